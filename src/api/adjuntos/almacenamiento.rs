@@ -0,0 +1,103 @@
+use std::{io, path::{Path, PathBuf}, sync::Arc};
+
+use rocket::{figment::Figment, tokio::{fs::{self, File}, io::{self as tokio_io, AsyncRead, AsyncReadExt}}};
+
+const MAX_BYTES_DEFAULT:u64=10 * 1024 * 1024;
+const CUOTA_BYTES_DEFAULT:u64=100 * 1024 * 1024;
+
+pub type AlmacenamientoAdjuntos = Arc<dyn Almacenamiento>;
+
+/// donde se guarda el contenido de los adjuntos, la metadata vive en la tabla `adjuntos`
+#[rocket::async_trait]
+pub trait Almacenamiento: Send + Sync {
+    /// guarda el contenido bajo `clave` y devuelve los bytes escritos,
+    /// si supera `limite` no deja nada guardado y falla con `ErrorKind::FileTooLarge`
+    async fn guardar(&self,clave:&str,contenido:&mut (dyn AsyncRead + Unpin + Send),limite:u64)->io::Result<u64>;
+
+    async fn abrir(&self,clave:&str)->io::Result<Box<dyn AsyncRead + Unpin + Send>>;
+
+    /// no falla si la clave no existe
+    async fn eliminar(&self,clave:&str)->io::Result<()>;
+}
+
+/// configuracion de adjuntos, se lee de `Rocket.toml`
+/// (`adjuntos_dir`, `adjuntos_max_bytes` y `adjuntos_cuota_bytes`)
+#[derive(Debug,Clone)]
+pub struct ConfigAdjuntos{
+    pub dir:PathBuf,
+    /// tamaño maximo de un archivo
+    pub max_bytes:u64,
+    /// espacio total por usuario
+    pub cuota_bytes:u64,
+}
+
+impl ConfigAdjuntos {
+    pub fn from_figment(figment:&Figment)->ConfigAdjuntos{
+        let dir=figment.extract_inner::<PathBuf>("adjuntos_dir")
+            .or_else(|_| figment.extract_inner::<PathBuf>("temp_dir").map(|d| d.join("tareas_adjuntos")))
+            .unwrap_or_else(|_| std::env::temp_dir().join("tareas_adjuntos"));
+        ConfigAdjuntos {
+            dir,
+            max_bytes: figment.extract_inner("adjuntos_max_bytes").unwrap_or(MAX_BYTES_DEFAULT),
+            cuota_bytes: figment.extract_inner("adjuntos_cuota_bytes").unwrap_or(CUOTA_BYTES_DEFAULT),
+        }
+    }
+}
+
+/// guarda los adjuntos como archivos bajo un directorio local
+pub struct AlmacenamientoLocal{
+    dir:PathBuf,
+}
+
+impl AlmacenamientoLocal {
+    pub fn new(dir:PathBuf)->AlmacenamientoLocal{
+        AlmacenamientoLocal { dir }
+    }
+
+    /// las claves solo pueden tener caracteres alfanumericos, `-`, `_` y `/`
+    fn ruta(&self,clave:&str)->io::Result<PathBuf>{
+        let valida=!clave.is_empty()
+            && clave.split('/').all(|parte| !parte.is_empty() && parte.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        if !valida {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("clave de adjunto invalida {}",clave)));
+        }
+        Ok(self.dir.join(Path::new(clave)))
+    }
+}
+
+#[rocket::async_trait]
+impl Almacenamiento for AlmacenamientoLocal {
+    async fn guardar(&self,clave:&str,contenido:&mut (dyn AsyncRead + Unpin + Send),limite:u64)->io::Result<u64>{
+        let ruta=self.ruta(clave)?;
+        if let Some(padre) = ruta.parent() {
+            fs::create_dir_all(padre).await?;
+        }
+        let mut archivo=File::create(&ruta).await?;
+        // se lee un byte mas del limite para detectar archivos demasiado grandes
+        let escritos=match tokio_io::copy(&mut contenido.take(limite + 1), &mut archivo).await {
+            Ok(escritos)=>escritos,
+            Err(e)=>{
+                let _ = fs::remove_file(&ruta).await;
+                return Err(e);
+            }
+        };
+        if escritos > limite {
+            let _ = fs::remove_file(&ruta).await;
+            return Err(io::Error::new(io::ErrorKind::FileTooLarge, "el adjunto supera el limite"));
+        }
+        archivo.sync_all().await?;
+        Ok(escritos)
+    }
+
+    async fn abrir(&self,clave:&str)->io::Result<Box<dyn AsyncRead + Unpin + Send>>{
+        let archivo=File::open(self.ruta(clave)?).await?;
+        Ok(Box::new(archivo))
+    }
+
+    async fn eliminar(&self,clave:&str)->io::Result<()>{
+        match fs::remove_file(self.ruta(clave)?).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound=>Err(e),
+            _=>Ok(())
+        }
+    }
+}
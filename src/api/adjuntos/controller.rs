@@ -0,0 +1,354 @@
+use rocket::{data::ToByteUnit, form::Form, fs::TempFile, http::{ContentType, Header, Status}, request::Request, response::{self, status::{self, Custom}, Responder, Response}, serde::json::Json, tokio::{io::AsyncRead, pin}, Data, State};
+use unreql::Session;
+
+use crate::{api::{tarea_con_permiso, AdduserResponse, Permiso, Tareas}, utils::Utils, ClientesSockets, JwtGuard};
+
+use super::{AdjuntoCrudo, Adjuntos, AdjuntoUpload, AlmacenamientoAdjuntos, ConfigAdjuntos, ErrorAdjunto};
+
+#[derive(FromForm)]
+pub struct AdjuntoForm<'r>{
+    pub archivo:TempFile<'r>,
+}
+
+/// respuesta de descarga con el content type y el nombre original del archivo
+pub struct DescargaAdjunto{
+    contenido:Box<dyn AsyncRead + Unpin + Send>,
+    adjunto:Adjuntos,
+}
+
+impl<'r> Responder<'r,'static> for DescargaAdjunto {
+    fn respond_to(self, _request: &'r Request<'_>) -> response::Result<'static> {
+        let content_type=ContentType::parse_flexible(&self.adjunto.content_type).unwrap_or(ContentType::Binary);
+        let nombre:String=self.adjunto.nombre.chars()
+            .filter(|c| !c.is_control() && *c != '"' && *c != '\\')
+            .collect();
+        Response::build()
+            .header(content_type)
+            .header(Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", nombre)))
+            .streamed_body(self.contenido)
+            .ok()
+    }
+}
+
+async fn responder_subida(res:Result<Adjuntos,ErrorAdjunto>,tarea:&Tareas,db:&Session,clients:&ClientesSockets)->Result<Custom<Json<Adjuntos>>,status::Custom<Json<AdduserResponse>>>{
+    match res {
+        Ok(adjunto)=>{
+            Utils::send_ws_to(clients, &tarea.destinatarios(db).await, "attachment").await;
+            Ok(
+                Custom(
+                    Status::Created, 
+                    Json(adjunto)
+                )
+            )
+        },
+        Err(ErrorAdjunto::Tamanio)=>{
+            Err(
+                Custom(
+                    Status::PayloadTooLarge,
+                    Json(
+                        AdduserResponse{
+                            error:5
+                        }
+                    )
+                )
+            )
+        },
+        Err(ErrorAdjunto::Cuota)=>{
+            Err(
+                Custom(
+                    Status::PayloadTooLarge,
+                    Json(
+                        AdduserResponse{
+                            error:6
+                        }
+                    )
+                )
+            )
+        },
+        Err(ErrorAdjunto::Almacenamiento(e))=>{
+            println!("error almacenamiento adjunto {}",e);
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        },
+        Err(ErrorAdjunto::Db(e))=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/tareas/{id}/adjuntos",
+    responses(
+        (status = 200, description = "Adjuntos de la tarea", body = Vec<Adjuntos>),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="adjuntos"
+)]
+#[get("/<id>/adjuntos")]
+pub async fn get_adjuntos(userClaims:JwtGuard,db:&State<Session>,id:&str)->Result<Custom<Json<Vec<Adjuntos>>>,status::Custom<Json<AdduserResponse>>>{
+    let tarea=tarea_con_permiso(id,&userClaims,Permiso::Lector,db).await?;
+    match Adjuntos::get_by_tarea(&tarea.id,db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok, 
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/tareas/{id}/adjuntos",
+    responses(
+        (status = 201, description = "Adjunto guardado", body = Adjuntos),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 403, description = "Sin permiso de edicion sobre la tarea", body = AdduserResponse),
+        (status = 413, description = "El archivo supera el tamaño maximo (error 5) o la cuota del usuario (error 6)", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea")
+    ),
+    request_body(content = AdjuntoUpload, content_type = "multipart/form-data", description = "archivo en el campo `archivo`"),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="adjuntos"
+)]
+#[post("/<id>/adjuntos",format="multipart/form-data",data="<form>")]
+pub async fn add_adjunto(db:&State<Session>,id:&str,userClaims:JwtGuard,form:Form<AdjuntoForm<'_>>,config:&State<ConfigAdjuntos>,almacenamiento:&State<AlmacenamientoAdjuntos>,clients:&State<ClientesSockets>)->Result<Custom<Json<Adjuntos>>,status::Custom<Json<AdduserResponse>>>{
+    let tarea=tarea_con_permiso(id,&userClaims,Permiso::Editor,db).await?;
+    let archivo=&form.archivo;
+    let nombre=archivo.raw_name()
+        .map(|n| n.dangerous_unsafe_unsanitized_raw().as_str().to_string())
+        .unwrap_or_else(|| "archivo".to_string());
+    let content_type=archivo.content_type().cloned().unwrap_or(ContentType::Binary).to_string();
+    let contenido=match archivo.open().await {
+        Ok(contenido)=>contenido,
+        Err(e)=>{
+            println!("error abrir adjunto {}",e);
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+    pin!(contenido);
+    let res=Adjuntos::subir(&tarea.id, &userClaims.id_usuario, &nombre, &content_type, &mut contenido, config, almacenamiento, db).await;
+    responder_subida(res, &tarea, db, clients).await
+}
+
+/// misma subida que `add_adjunto` pero con el archivo como cuerpo crudo
+///
+/// comparte ruta con `add_adjunto`; OpenAPI admite una sola operacion por metodo, asi que
+/// esta documenta ambos cuerpos y va antes en `ApiDoc`
+#[utoipa::path(
+    post,
+    path = "/tareas/{id}/adjuntos",
+    responses(
+        (status = 201, description = "Adjunto guardado", body = Adjuntos),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 403, description = "Sin permiso de edicion sobre la tarea", body = AdduserResponse),
+        (status = 413, description = "El archivo supera el tamaño maximo (error 5) o la cuota del usuario (error 6)", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea"),
+        ("nombre" = Option<String>, Query, description = "nombre del archivo cuando se envia el contenido crudo en lugar de multipart")
+    ),
+    request_body(
+        content(
+            (AdjuntoUpload = "multipart/form-data"),
+            (AdjuntoCrudo = "application/octet-stream")
+        ),
+        description = "archivo en el campo `archivo`, o el contenido crudo con su Content-Type"
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="adjuntos"
+)]
+#[post("/<id>/adjuntos?<nombre>",data="<data>",rank=2)]
+pub async fn add_adjunto_raw(db:&State<Session>,id:&str,nombre:Option<&str>,userClaims:JwtGuard,content_type:Option<&ContentType>,data:Data<'_>,config:&State<ConfigAdjuntos>,almacenamiento:&State<AlmacenamientoAdjuntos>,clients:&State<ClientesSockets>)->Result<Custom<Json<Adjuntos>>,status::Custom<Json<AdduserResponse>>>{
+    let tarea=tarea_con_permiso(id,&userClaims,Permiso::Editor,db).await?;
+    let content_type=content_type.cloned().unwrap_or(ContentType::Binary).to_string();
+    // un byte mas que el maximo para que el almacenamiento detecte el exceso
+    let contenido=data.open((config.max_bytes + 1).bytes());
+    pin!(contenido);
+    let res=Adjuntos::subir(&tarea.id, &userClaims.id_usuario, nombre.unwrap_or("archivo"), &content_type, &mut contenido, config, almacenamiento, db).await;
+    responder_subida(res, &tarea, db, clients).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/tareas/{id}/adjuntos/{id_adjunto}",
+    responses(
+        (status = 200, description = "Contenido del adjunto con su Content-Type"),
+        (status = 404, description = "Tarea o adjunto no encontrado", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea"),
+        ("id_adjunto" =String, Path, description = "id del adjunto")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="adjuntos"
+)]
+#[get("/<id>/adjuntos/<id_adjunto>")]
+pub async fn download_adjunto(userClaims:JwtGuard,db:&State<Session>,id:&str,id_adjunto:&str,almacenamiento:&State<AlmacenamientoAdjuntos>)->Result<DescargaAdjunto,status::Custom<Json<AdduserResponse>>>{
+    let tarea=tarea_con_permiso(id,&userClaims,Permiso::Lector,db).await?;
+    let adjunto=match Adjuntos::get_by_id(id_adjunto,&tarea.id,db).await {
+        Ok(Some(adjunto))=>adjunto,
+        Ok(None)=>{
+            return Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            );
+        },
+        Err(e)=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+    match almacenamiento.abrir(&adjunto.clave).await {
+        Ok(contenido)=>Ok(DescargaAdjunto { contenido, adjunto }),
+        Err(e)=>{
+            println!("error abrir adjunto {} {}",adjunto.clave,e);
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/tareas/{id}/adjuntos/{id_adjunto}",
+    responses(
+        (status = 200, description = "Adjunto eliminado", body = String),
+        (status = 404, description = "Tarea o adjunto no encontrado", body = AdduserResponse),
+        (status = 403, description = "Sin permiso de edicion sobre la tarea", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea"),
+        ("id_adjunto" =String, Path, description = "id del adjunto")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="adjuntos"
+)]
+#[delete("/<id>/adjuntos/<id_adjunto>")]
+pub async fn delete_adjunto(userClaims:JwtGuard,db:&State<Session>,id:&str,id_adjunto:&str,almacenamiento:&State<AlmacenamientoAdjuntos>,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    let tarea=tarea_con_permiso(id,&userClaims,Permiso::Editor,db).await?;
+    match Adjuntos::delete(id_adjunto,&tarea.id,almacenamiento,db).await {
+        Ok(res)=>{
+            if res{
+                Utils::send_ws_to(clients, &tarea.destinatarios(db).await, "attachment_delete").await;
+                return Ok(
+                    Custom(
+                        Status::Ok, 
+                        "Ok ".to_string()
+                    )
+                );
+            }
+            Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
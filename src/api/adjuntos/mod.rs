@@ -0,0 +1,8 @@
+mod controller;
+pub use controller::{*};
+
+mod model;
+pub use model::{*};
+
+mod almacenamiento;
+pub use almacenamiento::{*};
\ No newline at end of file
@@ -0,0 +1,240 @@
+use std::io;
+
+use rand::{distr::Alphanumeric, Rng};
+use rocket::{serde::{Deserialize, Serialize}, tokio::io::AsyncRead};
+use serde_json::Value;
+use unreql::{cmd::options::{DeleteOptions, InsertOptions}, r, rjson, types::WriteStatus, Session};
+use utoipa::ToSchema;
+
+use crate::utils::Utils;
+
+use super::{AlmacenamientoAdjuntos, ConfigAdjuntos};
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Adjuntos{
+    pub id:String,
+    pub id_tarea:String,
+    /// usuario que subio el archivo, se le descuenta de su cuota
+    pub id_usuario:String,
+    pub nombre:String,
+    pub content_type:String,
+    pub tamanio:u64,
+    /// clave del archivo en el almacenamiento
+    #[serde(skip_serializing)]
+    pub clave:String,
+    pub create_at:i64,
+}
+
+/// solo para documentar el formulario multipart en swagger
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct AdjuntoUpload{
+    #[schema(value_type = String, format = Binary)]
+    pub archivo:Vec<u8>,
+}
+
+/// solo para documentar la subida con el archivo como cuerpo crudo
+#[derive(ToSchema)]
+#[allow(dead_code)]
+#[schema(value_type = String, format = Binary)]
+pub struct AdjuntoCrudo(pub Vec<u8>);
+
+#[derive(Debug)]
+pub enum ErrorAdjunto{
+    /// el archivo supera `adjuntos_max_bytes`
+    Tamanio,
+    /// el usuario no tiene cuota suficiente
+    Cuota,
+    Almacenamiento(io::Error),
+    Db(unreql::Error),
+}
+
+impl Adjuntos {
+    pub async fn create_indexes(db:&Session)->Result<(),unreql::Error>{
+        Utils::create_table_if_missing("adjuntos", db).await?;
+        let existentes=r.db("tareas").table("adjuntos")
+        .index_list()
+        .exec::<_,Vec<String>>(db)
+        .await?;
+        for nombre in ["id_tarea","id_usuario"] {
+            if existentes.iter().any(|i| i == nombre) {
+                continue;
+            }
+            let query = r.db("tareas").table("adjuntos")
+            .index_create(nombre)
+            .exec::<_,Value>(db);
+            if let Err(e) = query.await {
+                println!("error create_indexes adjuntos {} {}",nombre,e);
+                return Err(e);
+            }
+        }
+        r.db("tareas").table("adjuntos").index_wait(()).exec::<_,Value>(db).await?;
+        Ok(())
+    }
+
+    /// bytes ocupados por los adjuntos que subio el usuario
+    pub async fn uso_by_usuario(id_usuario:&str,db:&Session)->Result<u64,unreql::Error>{
+        let query = r.db("tareas").table("adjuntos")
+        .get_all(r.with_opt(id_usuario.to_string(), r.index("id_usuario")))
+        .sum("tamanio")
+        .exec::<_,u64>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res)
+            },
+            Err(e)=>{
+                println!("error uso_by_usuario {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn get_by_tarea(id_tarea:&str,db:&Session)->Result<Vec<Adjuntos>,unreql::Error>{
+        let query = r.db("tareas").table("adjuntos")
+        .get_all(r.with_opt(id_tarea.to_string(), r.index("id_tarea")))
+        .order_by("create_at")
+        .exec_to_vec::<_,Adjuntos>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res)
+            },
+            Err(e)=>{
+                println!("error get_by_tarea adjuntos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn get_by_id(id:&str,id_tarea:&str,db:&Session)->Result<Option<Adjuntos>,unreql::Error>{
+        let query = r.db("tareas").table("adjuntos")
+        .get(id.to_string())
+        .exec_to_vec::<_,Adjuntos>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().find(|a| a.id_tarea == id_tarea))
+            },
+            Err(e)=>{
+                println!("error get_by_id adjuntos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// guarda el archivo respetando el tamaño maximo y la cuota del usuario
+    #[allow(clippy::too_many_arguments)]
+    pub async fn subir(
+        id_tarea:&str,
+        id_usuario:&str,
+        nombre:&str,
+        content_type:&str,
+        contenido:&mut (dyn AsyncRead + Unpin + Send),
+        config:&ConfigAdjuntos,
+        almacenamiento:&AlmacenamientoAdjuntos,
+        db:&Session
+    )->Result<Adjuntos,ErrorAdjunto>{
+        let usado=Self::uso_by_usuario(id_usuario, db).await.map_err(ErrorAdjunto::Db)?;
+        let restante=config.cuota_bytes.saturating_sub(usado);
+        if restante == 0 {
+            return Err(ErrorAdjunto::Cuota);
+        }
+        let limite=config.max_bytes.min(restante);
+
+        let sufijo:String=rand::rng().sample_iter(&Alphanumeric).take(32).map(char::from).collect();
+        let clave=format!("{}/{}",id_usuario,sufijo);
+        let tamanio=match almacenamiento.guardar(&clave, contenido, limite).await {
+            Ok(tamanio)=>tamanio,
+            Err(e) if e.kind() == io::ErrorKind::FileTooLarge=>{
+                if limite < config.max_bytes {
+                    return Err(ErrorAdjunto::Cuota);
+                }
+                return Err(ErrorAdjunto::Tamanio);
+            },
+            Err(e)=>{
+                println!("error subir adjunto {}",e);
+                return Err(ErrorAdjunto::Almacenamiento(e));
+            }
+        };
+
+        let query = r.db("tareas").table("adjuntos")
+        .insert(
+            r.with_opt(
+                rjson!({
+                    "id_tarea":id_tarea.to_string(),
+                    "id_usuario":id_usuario.to_string(),
+                    "nombre":nombre.to_string(),
+                    "content_type":content_type.to_string(),
+                    "tamanio":tamanio,
+                    "clave":clave.clone(),
+                    "create_at":Utils::current_timestamp(),
+                }),
+                InsertOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                for change in res.changes.unwrap_or_default() {
+                    if let Some(new_val) = change.new_val {
+                        let adjunto: Adjuntos = Deserialize::deserialize(new_val).map_err(|e| ErrorAdjunto::Db(e.into()))?;
+                        return Ok(adjunto);
+                    }
+                }
+                let _ = almacenamiento.eliminar(&clave).await;
+                Err(ErrorAdjunto::Almacenamiento(io::Error::other("adjunto sin cambios")))
+            },
+            Err(e)=>{
+                println!("error subir adjunto {}",e);
+                let _ = almacenamiento.eliminar(&clave).await;
+                Err(ErrorAdjunto::Db(e))
+            }
+        }
+    }
+
+    pub async fn delete(id:&str,id_tarea:&str,almacenamiento:&AlmacenamientoAdjuntos,db:&Session)->Result<bool,unreql::Error>{
+        let query = r.db("tareas").table("adjuntos")
+        .get_all(id.to_string())
+        .filter(rjson!({"id_tarea":id_tarea.to_string()}))
+        .delete(DeleteOptions {return_changes: Some(true.into()), ..Default::default() })
+        .exec::<_,WriteStatus<Adjuntos>>(db);
+        match query.await {
+            Ok(res)=>{
+                Self::eliminar_archivos(&res, almacenamiento).await;
+                Ok(res.deleted > 0)
+            },
+            Err(e)=>{
+                println!("error delete adjunto {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// elimina los adjuntos de tareas que se purgaron
+    pub async fn delete_by_tareas(ids_tareas:&[String],almacenamiento:&AlmacenamientoAdjuntos,db:&Session)->Result<u32,unreql::Error>{
+        if ids_tareas.is_empty() {
+            return Ok(0);
+        }
+        let query = r.db("tareas").table("adjuntos")
+        .get_all(r.with_opt(r.args(ids_tareas.to_vec()), r.index("id_tarea")))
+        .delete(DeleteOptions {return_changes: Some(true.into()), ..Default::default() })
+        .exec::<_,WriteStatus<Adjuntos>>(db);
+        match query.await {
+            Ok(res)=>{
+                Self::eliminar_archivos(&res, almacenamiento).await;
+                Ok(res.deleted)
+            },
+            Err(e)=>{
+                println!("error delete_by_tareas {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn eliminar_archivos(res:&WriteStatus<Adjuntos>,almacenamiento:&AlmacenamientoAdjuntos){
+        for adjunto in res.changes.iter().flatten().filter_map(|c| c.old_val.as_ref()) {
+            if let Err(e) = almacenamiento.eliminar(&adjunto.clave).await {
+                println!("error eliminar archivo {} {}",adjunto.clave,e);
+            }
+        }
+    }
+}
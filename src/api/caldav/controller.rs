@@ -0,0 +1,449 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rocket::{http::{hyper::{body::HttpBody, server::conn::Http, service::service_fn, Body, Request, Response}, Status}, response::status::{self, Custom}, serde::json::Json, tokio::{self, net::TcpListener}};
+use unreql::Session;
+use validator::Validate;
+
+use crate::{api::{comprobar_version, etag_json, etag_tarea, mover_a_etapa, tarea_con_permiso, tarea_ics, AdduserResponse, Espacios, Flujos, IfMatch, IfNoneMatch, Permiso, Tareas}, utils::Utils, ClientesSockets, JwtGuard};
+
+use super::{hrefs_xml, href_de_tarea, id_cliente_valido, id_de_href, leer_vtodo, props_pedidas, raiz_xml, texto_xml, ConfigCaldav, CredencialesCaldav, Multistatus, NombreXml, RutaDav, TodoIcal, CALDAV_MAX_BYTES, NS_CALDAV, NS_CS, NS_DAV, RUTA_CALDAV, RUTA_COLECCION};
+
+const METODOS_DAV:&str="OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, REPORT";
+
+/// recurso del que se devuelven propiedades
+enum RecursoDav<'a>{
+    Cuenta,
+    /// la coleccion con su ctag, que cambia con cualquier tarea
+    Coleccion(&'a str),
+    Tarea(&'a Tareas),
+}
+
+/// servidor CalDAV en su propio puerto: Rocket no enruta PROPFIND ni REPORT
+pub async fn caldav_job(config:ConfigCaldav,db:Session,clients:ClientesSockets){
+    let direccion=SocketAddr::new(config.direccion, config.puerto);
+    let listener=match TcpListener::bind(direccion).await {
+        Ok(listener)=>listener,
+        Err(e)=>{
+            println!("error caldav_job {}",e);
+            return;
+        }
+    };
+    println!("📆 CalDAV en http://{}{}",direccion,RUTA_CALDAV);
+    loop {
+        let (conexion,_)=match listener.accept().await {
+            Ok(res)=>res,
+            Err(e)=>{
+                println!("error caldav_job {}",e);
+                continue;
+            }
+        };
+        let (db,clients)=(db.clone(),clients.clone());
+        tokio::spawn(async move {
+            let servicio=service_fn(move |req| atender(req, db.clone(), clients.clone()));
+            if let Err(e) = Http::new().http1_only(true).serve_connection(conexion, servicio).await {
+                println!("error conexion caldav {}",e);
+            }
+        });
+    }
+}
+
+fn respuesta(status:u16)->Response<Body>{
+    Response::builder()
+    .status(status)
+    .header("DAV", "1, 3, calendar-access")
+    .body(Body::empty())
+    .unwrap_or_default()
+}
+
+fn respuesta_xml(status:u16,xml:String)->Response<Body>{
+    let mut res=respuesta(status);
+    res.headers_mut().insert("Content-Type", "application/xml; charset=utf-8".parse().unwrap());
+    *res.body_mut()=Body::from(xml);
+    res
+}
+
+/// los errores de los helpers de la API con su status y el mismo cuerpo json
+fn respuesta_error(error:status::Custom<Json<AdduserResponse>>)->Response<Body>{
+    let mut res=respuesta(error.0.code);
+    res.headers_mut().insert("Content-Type", "application/json".parse().unwrap());
+    *res.body_mut()=Body::from(serde_json::to_string(&error.1.0).unwrap_or_default());
+    res
+}
+
+fn error_dav(status:Status,error:i8)->status::Custom<Json<AdduserResponse>>{
+    Custom(
+        status,
+        Json(
+            AdduserResponse{
+                error
+            }
+        )
+    )
+}
+
+fn cabecera<'a>(req:&'a Request<Body>,nombre:&str)->Option<&'a str>{
+    req.headers().get(nombre).and_then(|valor| valor.to_str().ok())
+}
+
+async fn atender(req:Request<Body>,db:Session,clients:ClientesSockets)->Result<Response<Body>,Infallible>{
+    let ruta=RutaDav::desde(req.uri().path());
+    let metodo=req.method().as_str().to_string();
+    match (metodo.as_str(),&ruta) {
+        (_,RutaDav::WellKnown)=>{
+            let mut res=respuesta(301);
+            res.headers_mut().insert("Location", RUTA_CALDAV.parse().unwrap());
+            return Ok(res);
+        },
+        ("OPTIONS",_)=>{
+            let mut res=respuesta(200);
+            res.headers_mut().insert("Allow", METODOS_DAV.parse().unwrap());
+            return Ok(res);
+        },
+        (_,RutaDav::Otra)=>return Ok(respuesta(404)),
+        _=>{},
+    }
+    let userClaims=match autenticar(&req,&db).await {
+        Ok(Some(userClaims))=>userClaims,
+        Ok(None)=>{
+            let mut res=respuesta(401);
+            res.headers_mut().insert("WWW-Authenticate", "Basic realm=\"Tareas\"".parse().unwrap());
+            return Ok(res);
+        },
+        Err(e)=>return Ok(respuesta(500)),
+    };
+    let res=match (metodo.as_str(),ruta) {
+        ("PROPFIND",ruta)=>propfind(req,ruta,&userClaims,&db).await,
+        ("REPORT",RutaDav::Coleccion)=>report(req,&userClaims,&db).await,
+        ("GET",RutaDav::Tarea(id))=>get_tarea(&req,&id,false,&userClaims,&db).await,
+        ("HEAD",RutaDav::Tarea(id))=>get_tarea(&req,&id,true,&userClaims,&db).await,
+        ("PUT",RutaDav::Tarea(id))=>put_tarea(req,&id,&userClaims,&db,&clients).await,
+        ("DELETE",RutaDav::Tarea(id))=>delete_tarea(&req,&id,&userClaims,&db,&clients).await,
+        _=>{
+            let mut res=respuesta(405);
+            res.headers_mut().insert("Allow", METODOS_DAV.parse().unwrap());
+            Ok(res)
+        }
+    };
+    Ok(res.unwrap_or_else(respuesta_error))
+}
+
+/// Basic con la contraseña de `POST /calendario/caldav`; el usuario no se comprueba. El token
+/// del calendario no sirve porque es de solo lectura. Da acceso al alcance de la contraseña:
+/// las tareas personales o las del espacio
+async fn autenticar(req:&Request<Body>,db:&Session)->Result<Option<JwtGuard>,unreql::Error>{
+    let clave=cabecera(req, "Authorization")
+        .and_then(|valor| valor.split_once(' '))
+        .filter(|(esquema,_)| esquema.eq_ignore_ascii_case("basic"))
+        .and_then(|(_,credenciales)| STANDARD.decode(credenciales.trim()).ok())
+        .and_then(|credenciales| String::from_utf8(credenciales).ok())
+        .and_then(|credenciales| credenciales.split_once(':').map(|(_,clave)| clave.to_string()));
+    let Some(clave) = clave else {
+        return Ok(None);
+    };
+    let Some(credenciales) = CredencialesCaldav::get_by_clave(&clave, db).await? else {
+        return Ok(None);
+    };
+    // quien ya no es miembro del espacio pierde el acceso aunque la contraseña siga guardada
+    let rol=match credenciales.id_espacio.as_deref() {
+        Some(id_espacio)=>match Espacios::rol_de(id_espacio, &credenciales.id_usuario, db).await? {
+            Some(rol)=>Some(rol),
+            None=>return Ok(None),
+        },
+        None=>None,
+    };
+    Ok(Some(JwtGuard { id_usuario: credenciales.id_usuario, id_espacio: credenciales.id_espacio, rol }))
+}
+
+/// cuerpo como texto, 413 si pasa de `CALDAV_MAX_BYTES`
+async fn leer_cuerpo(req:Request<Body>)->Result<String,status::Custom<Json<AdduserResponse>>>{
+    let mut cuerpo=req.into_body();
+    let mut bytes=Vec::new();
+    while let Some(parte) = cuerpo.data().await {
+        let Ok(parte) = parte else {
+            return Err(error_dav(Status::BadRequest, 3));
+        };
+        if bytes.len() + parte.len() > CALDAV_MAX_BYTES {
+            return Err(error_dav(Status::PayloadTooLarge, 5));
+        }
+        bytes.extend_from_slice(&parte);
+    }
+    String::from_utf8(bytes).map_err(|_| error_dav(Status::BadRequest, 3))
+}
+
+async fn tareas_visibles(userClaims:&JwtGuard,db:&Session)->Result<Vec<Tareas>,status::Custom<Json<AdduserResponse>>>{
+    match Tareas::get_todas_visibles(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(mut tareas)=>{
+            tareas.sort_by(|a,b| a.id.cmp(&b.id));
+            Ok(tareas)
+        },
+        Err(e)=>Err(error_dav(Status::InternalServerError, 2))
+    }
+}
+
+/// ctag de la coleccion: cambia si se crea, edita o borra cualquier tarea visible
+fn ctag(tareas:&[Tareas])->String{
+    let versiones:Vec<(&str,i64)>=tareas.iter().map(|t| (t.id.as_str(),t.version)).collect();
+    etag_json(&versiones)
+}
+
+fn href_xml(href:&str)->String{
+    format!("<d:href>{}</d:href>",texto_xml(href))
+}
+
+/// valor ya escapado de la propiedad, `None` si el recurso no la tiene
+fn valor_prop(recurso:&RecursoDav,prop:&NombreXml)->Option<String>{
+    match (prop.ns.as_str(),prop.local.as_str(),recurso) {
+        (NS_DAV,"resourcetype",RecursoDav::Cuenta)=>Some("<d:collection/>".to_string()),
+        (NS_DAV,"resourcetype",RecursoDav::Coleccion(_))=>Some("<d:collection/><c:calendar/>".to_string()),
+        (NS_DAV,"resourcetype",RecursoDav::Tarea(_))=>Some(String::new()),
+        (NS_DAV,"displayname",RecursoDav::Tarea(tarea))=>Some(texto_xml(&tarea.nombre)),
+        (NS_DAV,"displayname",_)=>Some("Tareas".to_string()),
+        (NS_DAV,"current-user-principal" | "principal-URL" | "owner",_)=>Some(href_xml(RUTA_CALDAV)),
+        (NS_CALDAV,"calendar-home-set",_)=>Some(href_xml(RUTA_CALDAV)),
+        (NS_DAV,"current-user-privilege-set",_)=>Some(
+            ["read","write","write-content","write-properties","bind","unbind"].iter()
+            .map(|p| format!("<d:privilege><d:{}/></d:privilege>",p))
+            .collect()
+        ),
+        (NS_CALDAV,"supported-calendar-component-set",RecursoDav::Coleccion(_))=>Some("<c:comp name=\"VTODO\"/>".to_string()),
+        (NS_DAV,"supported-report-set",RecursoDav::Coleccion(_))=>Some(
+            "<d:supported-report><d:report><c:calendar-query/></d:report></d:supported-report><d:supported-report><d:report><c:calendar-multiget/></d:report></d:supported-report>".to_string()
+        ),
+        (NS_CS,"getctag",RecursoDav::Coleccion(ctag)) | (NS_DAV,"getetag",RecursoDav::Coleccion(ctag))=>Some(texto_xml(ctag)),
+        (NS_DAV,"getetag",RecursoDav::Tarea(tarea))=>Some(texto_xml(&etag_tarea(tarea.version))),
+        (NS_DAV,"getcontenttype",RecursoDav::Tarea(_))=>Some("text/calendar; charset=utf-8; component=VTODO".to_string()),
+        (NS_CALDAV,"calendar-data",RecursoDav::Tarea(tarea))=>Some(texto_xml(&tarea_ics(tarea))),
+        _=>None,
+    }
+}
+
+/// agrega el recurso con las propiedades pedidas; con `allprop` solo van las que tiene
+fn agregar_recurso(multistatus:&mut Multistatus,href:&str,recurso:&RecursoDav,pedidas:&Option<Vec<NombreXml>>){
+    let props=pedidas.clone().unwrap_or_else(|| vec![
+        NombreXml::nuevo(NS_DAV, "resourcetype"),
+        NombreXml::nuevo(NS_DAV, "displayname"),
+        NombreXml::nuevo(NS_DAV, "getetag"),
+        NombreXml::nuevo(NS_DAV, "getcontenttype"),
+        NombreXml::nuevo(NS_CS, "getctag"),
+    ]);
+    let mut encontradas=Vec::new();
+    let mut faltantes=Vec::new();
+    for prop in props {
+        match valor_prop(recurso, &prop) {
+            Some(valor)=>encontradas.push((prop,valor)),
+            None if pedidas.is_some()=>faltantes.push(prop),
+            None=>{},
+        }
+    }
+    multistatus.recurso(href, &encontradas, &faltantes);
+}
+
+/// propiedades de la cuenta, la coleccion o una tarea; `Depth: 0` no incluye los hijos
+async fn propfind(req:Request<Body>,ruta:RutaDav,userClaims:&JwtGuard,db:&Session)->Result<Response<Body>,status::Custom<Json<AdduserResponse>>>{
+    let con_hijos=cabecera(&req, "Depth").map(|d| d.trim() != "0").unwrap_or(true);
+    let cuerpo=leer_cuerpo(req).await?;
+    let pedidas=props_pedidas(&cuerpo);
+    let mut multistatus=Multistatus::nuevo();
+    match ruta {
+        RutaDav::Cuenta=>{
+            agregar_recurso(&mut multistatus, RUTA_CALDAV, &RecursoDav::Cuenta, &pedidas);
+            if con_hijos {
+                let tareas=tareas_visibles(userClaims, db).await?;
+                agregar_recurso(&mut multistatus, RUTA_COLECCION, &RecursoDav::Coleccion(&ctag(&tareas)), &pedidas);
+            }
+        },
+        RutaDav::Coleccion=>{
+            let tareas=tareas_visibles(userClaims, db).await?;
+            agregar_recurso(&mut multistatus, RUTA_COLECCION, &RecursoDav::Coleccion(&ctag(&tareas)), &pedidas);
+            if con_hijos {
+                for tarea in tareas.iter() {
+                    agregar_recurso(&mut multistatus, &href_de_tarea(&tarea.id), &RecursoDav::Tarea(tarea), &pedidas);
+                }
+            }
+        },
+        RutaDav::Tarea(id)=>{
+            let tarea=tarea_con_permiso(&id,userClaims,Permiso::Lector,db).await?;
+            agregar_recurso(&mut multistatus, &href_de_tarea(&tarea.id), &RecursoDav::Tarea(&tarea), &pedidas);
+        },
+        RutaDav::WellKnown | RutaDav::Otra=>return Err(error_dav(Status::NotFound, 1)),
+    }
+    Ok(respuesta_xml(207, multistatus.cerrar()))
+}
+
+/// `calendar-query` devuelve todas las tareas, los filtros de fecha no se aplican;
+/// `calendar-multiget` las de los href pedidos
+async fn report(req:Request<Body>,userClaims:&JwtGuard,db:&Session)->Result<Response<Body>,status::Custom<Json<AdduserResponse>>>{
+    let cuerpo=leer_cuerpo(req).await?;
+    let pedidas=props_pedidas(&cuerpo).or_else(|| Some(vec![
+        NombreXml::nuevo(NS_DAV, "getetag"),
+        NombreXml::nuevo(NS_CALDAV, "calendar-data"),
+    ]));
+    let mut multistatus=Multistatus::nuevo();
+    match raiz_xml(&cuerpo) {
+        Some(raiz) if raiz.es(NS_CALDAV, "calendar-query")=>{
+            // en la coleccion solo hay VTODO, una consulta de eventos no trae nada
+            if cuerpo.contains("VTODO") || !cuerpo.contains("VEVENT") {
+                for tarea in tareas_visibles(userClaims, db).await?.iter() {
+                    agregar_recurso(&mut multistatus, &href_de_tarea(&tarea.id), &RecursoDav::Tarea(tarea), &pedidas);
+                }
+            }
+        },
+        Some(raiz) if raiz.es(NS_CALDAV, "calendar-multiget")=>{
+            for href in hrefs_xml(&cuerpo) {
+                let Some(id) = id_de_href(&href) else {
+                    multistatus.no_encontrado(&href);
+                    continue;
+                };
+                match Tareas::get_visible(&id,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+                    Ok(Some(tarea))=>agregar_recurso(&mut multistatus, &href, &RecursoDav::Tarea(&tarea), &pedidas),
+                    Ok(None)=>multistatus.no_encontrado(&href),
+                    Err(e)=>return Err(error_dav(Status::InternalServerError, 2)),
+                }
+            }
+        },
+        _=>{
+            return Ok(respuesta_xml(403, format!("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<d:error xmlns:d=\"{}\"><d:supported-report/></d:error>",NS_DAV)));
+        }
+    }
+    Ok(respuesta_xml(207, multistatus.cerrar()))
+}
+
+async fn get_tarea(req:&Request<Body>,id:&str,solo_cabeceras:bool,userClaims:&JwtGuard,db:&Session)->Result<Response<Body>,status::Custom<Json<AdduserResponse>>>{
+    let tarea=tarea_con_permiso(id,userClaims,Permiso::Lector,db).await?;
+    let etag=etag_tarea(tarea.version);
+    let if_none_match=IfNoneMatch(cabecera(req, "If-None-Match").map(str::to_string));
+    let mut res=respuesta(if if_none_match.coincide(&etag) { 304 } else { 200 });
+    res.headers_mut().insert("ETag", etag.parse().unwrap());
+    if res.status() == 200 {
+        res.headers_mut().insert("Content-Type", "text/calendar; charset=utf-8".parse().unwrap());
+        if !solo_cabeceras {
+            *res.body_mut()=Body::from(tarea_ics(&tarea));
+        }
+    }
+    Ok(res)
+}
+
+/// crea o actualiza la tarea desde el `VTODO`. No devuelve `ETag` porque la tarea guardada no
+/// es igual al iCalendar del cliente (RFC 4791, 5.3.4), que la vuelve a pedir
+async fn put_tarea(req:Request<Body>,id:&str,userClaims:&JwtGuard,db:&Session,clients:&ClientesSockets)->Result<Response<Body>,status::Custom<Json<AdduserResponse>>>{
+    let if_match=IfMatch::desde(cabecera(&req, "If-Match"));
+    let solo_crear=cabecera(&req, "If-None-Match").map(|v| v.trim() == "*").unwrap_or(false);
+    let cuerpo=leer_cuerpo(req).await?;
+    let Some(todo) = leer_vtodo(&cuerpo).filter(|todo| todo.tarea.validate().is_ok()) else {
+        return Err(error_dav(Status::BadRequest, 3));
+    };
+    match Tareas::get_con_permiso(id,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(Some((tarea,permiso)))=>{
+            if solo_crear {
+                return Err(error_dav(Status::PreconditionFailed, 13));
+            }
+            if permiso < Permiso::Editor {
+                return Err(error_dav(Status::Forbidden, 16));
+            }
+            comprobar_version(&tarea,&if_match)?;
+            editar_tarea(tarea,todo,if_match.0,userClaims,db,clients).await?;
+            Ok(respuesta(204))
+        },
+        Ok(None)=>{
+            if if_match.0.is_some() {
+                return Err(error_dav(Status::PreconditionFailed, 13));
+            }
+            crear_tarea(id,todo,userClaims,db,clients).await?;
+            Ok(respuesta(201))
+        },
+        Err(e)=>Err(error_dav(Status::InternalServerError, 2))
+    }
+}
+
+/// tarea nueva con el nombre de recurso del cliente como id, para que su href no cambie
+async fn crear_tarea(id:&str,todo:TodoIcal,userClaims:&JwtGuard,db:&Session,clients:&ClientesSockets)->Result<(),status::Custom<Json<AdduserResponse>>>{
+    if !id_cliente_valido(id) {
+        return Err(error_dav(Status::Forbidden, 16));
+    }
+    let flujo=match Flujos::get_para(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(flujo)=>flujo,
+        Err(e)=>return Err(error_dav(Status::InternalServerError, 2)),
+    };
+    let uid=todo.uid.clone().unwrap_or_else(|| id.to_string());
+    let nueva=match Tareas::add_tarea_ical(&todo.tarea,id,&uid,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),&flujo.inicial().clave,db).await {
+        Ok(res)=>match res.into_iter().next() {
+            Some(nueva)=>nueva,
+            // el id es de una tarea que no puede ver, de otro alcance o en la papelera
+            None=>return Err(error_dav(Status::Forbidden, 16)),
+        },
+        Err(e)=>return Err(error_dav(Status::InternalServerError, 2)),
+    };
+    Utils::send_ws_to(clients, &nueva.destinatarios(db).await, "new").await;
+    if todo.completada {
+        // si el flujo no deja completarla queda pendiente y el cliente lo ve al sincronizar
+        mover_a_etapa(&nueva,None,false,&userClaims.id_usuario,None,db,clients).await.ok();
+    }
+    Ok(())
+}
+
+/// aplica los campos que cambiaron y el estado, con las mismas reglas del flujo que la API
+async fn editar_tarea(tarea:Tareas,mut todo:TodoIcal,esperado:Option<i64>,userClaims:&JwtGuard,db:&Session,clients:&ClientesSockets)->Result<(),status::Custom<Json<AdduserResponse>>>{
+    let mut esperado=esperado;
+    let mut editada=false;
+    // DUE va en segundos, si no cambio se conserva el vencimiento con sus ms
+    if todo.tarea.vence.map(|v| v.div_euclid(1000)) == tarea.vence.map(|v| v.div_euclid(1000)) {
+        todo.tarea.vence=tarea.vence;
+    }
+    let cambia=todo.tarea.nombre != tarea.nombre
+        || todo.tarea.vence != tarea.vence
+        || todo.tarea.etiquetas_limpias() != tarea.etiquetas
+        || todo.tarea.prioridad != tarea.prioridad;
+    if cambia {
+        match Tareas::editar(&tarea.id,&todo.tarea,&userClaims.id_usuario,esperado,db).await {
+            Ok(true)=>{
+                editada=true;
+                esperado=None;
+            },
+            Ok(false) if esperado.is_some()=>return Err(error_dav(Status::PreconditionFailed, 13)),
+            Ok(false)=>return Err(error_dav(Status::InternalServerError, 2)),
+            Err(e)=>return Err(error_dav(Status::InternalServerError, 2)),
+        }
+    }
+    if todo.completada != tarea.estado {
+        // el `update` de mover_a_etapa ya avisa la edicion; si no se mueve se avisa aca
+        let res=cambiar_estado(&tarea,todo.completada,esperado,userClaims,db,clients).await;
+        if res.is_err() && editada {
+            Utils::send_ws_to(clients, &tarea.destinatarios(db).await, "update").await;
+        }
+        return res;
+    }
+    if editada {
+        Utils::send_ws_to(clients, &tarea.destinatarios(db).await, "update").await;
+    }
+    Ok(())
+}
+
+/// completa la tarea o la vuelve al estado inicial del flujo
+async fn cambiar_estado(tarea:&Tareas,completada:bool,esperado:Option<i64>,userClaims:&JwtGuard,db:&Session,clients:&ClientesSockets)->Result<(),status::Custom<Json<AdduserResponse>>>{
+    let destino=if completada {
+        None
+    }else{
+        match Flujos::get_para(&tarea.id_usuario,tarea.id_espacio.as_deref(),db).await {
+            Ok(flujo)=>Some(flujo.inicial().clave.clone()),
+            Err(e)=>return Err(error_dav(Status::InternalServerError, 2)),
+        }
+    };
+    mover_a_etapa(tarea,destino.as_deref(),false,&userClaims.id_usuario,esperado,db,clients).await?;
+    Ok(())
+}
+
+async fn delete_tarea(req:&Request<Body>,id:&str,userClaims:&JwtGuard,db:&Session,clients:&ClientesSockets)->Result<Response<Body>,status::Custom<Json<AdduserResponse>>>{
+    let if_match=IfMatch::desde(cabecera(req, "If-Match"));
+    let tarea=tarea_con_permiso(id,userClaims,Permiso::Editor,db).await?;
+    comprobar_version(&tarea,&if_match)?;
+    match Tareas::delete_tarea_by_id(id,&userClaims.id_usuario,if_match.0,db).await {
+        Ok(true)=>{
+            Utils::send_ws_to(clients, &tarea.destinatarios(db).await, "trash").await;
+            Ok(respuesta(204))
+        },
+        Ok(false) if if_match.0.is_some()=>Err(error_dav(Status::PreconditionFailed, 13)),
+        Ok(false)=>Err(error_dav(Status::InternalServerError, 2)),
+        Err(e)=>Err(error_dav(Status::InternalServerError, 2)),
+    }
+}
@@ -0,0 +1,664 @@
+use std::{collections::HashMap, net::{IpAddr, Ipv4Addr}};
+
+use rocket::{figment::Figment, serde::{Deserialize, Serialize}};
+use serde_json::Value;
+use unreql::{r, rjson, types::WriteStatus, Session};
+use utoipa::ToSchema;
+
+use crate::{api::{Flujos, Prioridad, TareasHttp}, utils::{Utils, DIA_MS}};
+
+/// ruta de la cuenta: es el principal y el calendar home a la vez
+pub const RUTA_CALDAV:&str="/caldav/";
+/// la unica coleccion de la cuenta, con las tareas del alcance de la contraseña
+pub const RUTA_COLECCION:&str="/caldav/tareas/";
+/// cuerpo maximo de PUT, PROPFIND y REPORT
+pub const CALDAV_MAX_BYTES:usize=1024 * 1024;
+/// largo maximo del nombre de recurso que elige el cliente, el de una clave primaria de RethinkDB
+const ID_CLIENTE_MAX:usize=127;
+/// caracteres de la contraseña de aplicacion
+const LARGO_CLAVE:usize=40;
+
+pub const NS_DAV:&str="DAV:";
+pub const NS_CALDAV:&str="urn:ietf:params:xml:ns:caldav";
+pub const NS_CS:&str="http://calendarserver.org/ns/";
+
+/// configuracion del servidor CalDAV, se lee de `Rocket.toml` (`caldav_puerto`);
+/// escucha en la misma `address` que la API
+#[derive(Debug,Clone,Copy)]
+pub struct ConfigCaldav{
+    pub direccion:IpAddr,
+    pub puerto:u16,
+}
+
+impl ConfigCaldav {
+    /// `None` si no hay `caldav_puerto` y el servidor queda apagado
+    pub fn from_figment(figment:&Figment)->Option<ConfigCaldav>{
+        let puerto=figment.extract_inner::<u16>("caldav_puerto").ok()?;
+        Some(ConfigCaldav {
+            direccion: figment.extract_inner("address").unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            puerto,
+        })
+    }
+}
+
+/// contraseña de aplicacion de CalDAV, aparte del token del calendario que es de solo lectura
+/// y se comparte. El id es el sha-256 de la contraseña, que solo se muestra al crearla
+#[derive(Debug,Clone,Deserialize,Serialize)]
+#[serde(crate="rocket::serde")]
+pub struct CredencialesCaldav{
+    pub id:String,
+    /// `usuario:<id>` o `espacio:<id>`, igual que el id de los flujos
+    pub alcance:String,
+    pub id_usuario:String,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub id_espacio:Option<String>,
+    pub create_at:i64,
+}
+
+impl CredencialesCaldav {
+    pub async fn create_indexes(db:&Session)->Result<(),unreql::Error>{
+        Utils::create_table_if_missing("credenciales_caldav", db).await?;
+        let existentes=r.db("tareas").table("credenciales_caldav")
+        .index_list()
+        .exec::<_,Vec<String>>(db)
+        .await?;
+        if !existentes.iter().any(|i| i == "id_usuario") {
+            let query = r.db("tareas").table("credenciales_caldav")
+            .index_create("id_usuario")
+            .exec::<_,Value>(db);
+            if let Err(e) = query.await {
+                println!("error create_indexes credenciales_caldav {}",e);
+                return Err(e);
+            }
+        }
+        r.db("tareas").table("credenciales_caldav").index_wait(()).exec::<_,Value>(db).await?;
+        Ok(())
+    }
+
+    pub async fn get_by_clave(clave:&str,db:&Session)->Result<Option<CredencialesCaldav>,unreql::Error>{
+        let query = r.db("tareas").table("credenciales_caldav")
+        .get(Utils::sha256_hex(clave.as_bytes()))
+        .exec_to_vec::<_,CredencialesCaldav>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().next())
+            },
+            Err(e)=>{
+                println!("error get_by_clave credenciales_caldav {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// contraseña nueva para el espacio activo o las tareas personales; la anterior deja de servir
+    pub async fn renovar(id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<ClaveCaldav,unreql::Error>{
+        Self::revocar(id_usuario, id_espacio, db).await?;
+        let clave=Utils::generar_token(LARGO_CLAVE);
+        let credenciales=CredencialesCaldav {
+            id: Utils::sha256_hex(clave.as_bytes()),
+            alcance: Flujos::id_para(id_usuario, id_espacio),
+            id_usuario: id_usuario.to_string(),
+            id_espacio: id_espacio.map(str::to_string),
+            create_at: Utils::current_timestamp(),
+        };
+        let query = r.db("tareas").table("credenciales_caldav")
+        .insert(credenciales.clone())
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(_)=>{
+                Ok(ClaveCaldav {
+                    clave,
+                    create_at: credenciales.create_at,
+                })
+            },
+            Err(e)=>{
+                println!("error renovar credenciales_caldav {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// borra la contraseña del espacio activo o de las tareas personales; `false` si no habia
+    pub async fn revocar(id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<bool,unreql::Error>{
+        let query = r.db("tareas").table("credenciales_caldav")
+        .get_all(r.with_opt(id_usuario.to_string(), r.index("id_usuario")))
+        .filter(rjson!({"alcance":Flujos::id_para(id_usuario, id_espacio)}))
+        .delete(())
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.deleted > 0)
+            },
+            Err(e)=>{
+                println!("error revocar credenciales_caldav {}",e);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// contraseña de aplicacion recien creada, no se puede volver a consultar
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct ClaveCaldav{
+    /// contraseña para la app de CalDAV, con cualquier usuario
+    pub clave:String,
+    pub create_at:i64,
+}
+
+/// recurso de una ruta del servidor
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum RutaDav{
+    /// `/.well-known/caldav`, redirige a la cuenta
+    WellKnown,
+    Cuenta,
+    Coleccion,
+    /// `/caldav/tareas/<id>.ics`
+    Tarea(String),
+    Otra,
+}
+
+impl RutaDav {
+    pub fn desde(ruta:&str)->RutaDav{
+        match ruta.trim_end_matches('/') {
+            "/.well-known/caldav"=>RutaDav::WellKnown,
+            "" | "/caldav"=>RutaDav::Cuenta,
+            "/caldav/tareas"=>RutaDav::Coleccion,
+            ruta=>match id_de_href(ruta) {
+                Some(id)=>RutaDav::Tarea(id),
+                None=>RutaDav::Otra,
+            }
+        }
+    }
+}
+
+/// id de la tarea de un href de la coleccion, con o sin esquema y host
+pub fn id_de_href(href:&str)->Option<String>{
+    let (_,nombre)=href.split_once(RUTA_COLECCION)?;
+    let nombre=nombre.strip_suffix(".ics")?;
+    if nombre.is_empty() || nombre.contains('/') {
+        return None;
+    }
+    sin_porcentajes(nombre)
+}
+
+pub fn href_de_tarea(id:&str)->String{
+    format!("{}{}.ics",RUTA_COLECCION,id)
+}
+
+/// decodifica `%XX`; `None` si la secuencia o el utf-8 no son validos
+fn sin_porcentajes(valor:&str)->Option<String>{
+    let bytes=valor.as_bytes();
+    let mut res=Vec::with_capacity(bytes.len());
+    let mut i=0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex=valor.get(i + 1..i + 3)?;
+            res.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        }else{
+            res.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(res).ok()
+}
+
+/// nombre de recurso que se puede usar como id de una tarea nueva
+pub fn id_cliente_valido(id:&str)->bool{
+    !id.is_empty() && id.len() <= ID_CLIENTE_MAX && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '@'))
+}
+
+/// tarea leida del `VTODO` que manda el cliente
+#[derive(Debug,Clone)]
+pub struct TodoIcal{
+    pub uid:Option<String>,
+    pub tarea:TareasHttp,
+    pub completada:bool,
+}
+
+/// texto de iCalendar sin los escapes de `\`
+fn texto_sin_escapes(texto:&str)->String{
+    let mut res=String::with_capacity(texto.len());
+    let mut chars=texto.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N')=>res.push('\n'),
+            Some(c)=>res.push(c),
+            None=>{},
+        }
+    }
+    res
+}
+
+/// separa una lista de iCalendar por las comas que no estan escapadas
+fn lista_ics(valor:&str)->Vec<String>{
+    let mut res=Vec::new();
+    let mut actual=String::new();
+    let mut escapado=false;
+    for c in valor.chars() {
+        if c == ',' && !escapado {
+            res.push(texto_sin_escapes(&actual));
+            actual.clear();
+            continue;
+        }
+        escapado=c == '\\' && !escapado;
+        actual.push(c);
+    }
+    res.push(texto_sin_escapes(&actual));
+    res
+}
+
+/// fecha `AAAAMMDD`, `AAAAMMDDTHHMMSS` o `AAAAMMDDTHHMMSSZ` en ms; las fechas sin hora vencen
+/// al final del dia y las horas locales se toman como UTC
+fn fecha_ics(valor:&str)->Option<i64>{
+    let numero=|desde:usize,hasta:usize| valor.get(desde..hasta).filter(|v| v.bytes().all(|b| b.is_ascii_digit()))?.parse::<i64>().ok();
+    let dia=Utils::dia_de_fecha(numero(0,4)?, numero(4,6)?, numero(6,8)?)?;
+    if valor.len() == 8 {
+        return Some(dia * DIA_MS + DIA_MS - 1000);
+    }
+    if valor.get(8..9) != Some("T") || !matches!(valor.len(), 15 | 16) {
+        return None;
+    }
+    let (hora,minuto,segundo)=(numero(9,11)?, numero(11,13)?, numero(13,15)?);
+    if hora > 23 || minuto > 59 || segundo > 60 {
+        return None;
+    }
+    Some(dia * DIA_MS + ((hora * 60 + minuto) * 60 + segundo.min(59)) * 1000)
+}
+
+/// prioridad de iCalendar (1 la mas alta, 9 la mas baja, 0 sin prioridad)
+fn prioridad_ics(valor:&str)->Option<Prioridad>{
+    match valor.trim().parse::<u8>().ok()? {
+        1..=2=>Some(Prioridad::Urgente),
+        3..=4=>Some(Prioridad::Alta),
+        5=>Some(Prioridad::Media),
+        6..=9=>Some(Prioridad::Baja),
+        _=>None,
+    }
+}
+
+/// lineas desplegadas (RFC 5545, 3.1) separadas en nombre en mayusculas y valor
+fn propiedades_ics(ics:&str)->Vec<(String,String)>{
+    let mut lineas:Vec<String>=Vec::new();
+    for linea in ics.split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l)) {
+        match (linea.strip_prefix(' ').or_else(|| linea.strip_prefix('\t')),lineas.last_mut()) {
+            (Some(resto),Some(anterior))=>anterior.push_str(resto),
+            _=>lineas.push(linea.to_string()),
+        }
+    }
+    let mut res=Vec::with_capacity(lineas.len());
+    for linea in lineas {
+        // los `:` de los parametros entre comillas no separan el valor
+        let mut comillas=false;
+        let Some(dos_puntos) = linea.char_indices().find(|(_,c)| {
+            if *c == '"' {
+                comillas = !comillas;
+            }
+            *c == ':' && !comillas
+        }).map(|(i,_)| i) else {
+            continue;
+        };
+        let nombre=linea[..dos_puntos].split(';').next().unwrap_or_default().trim().to_uppercase();
+        res.push((nombre,linea[dos_puntos + 1..].to_string()));
+    }
+    res
+}
+
+/// el primer `VTODO` del calendario, `None` si no hay o no tiene `SUMMARY`
+pub fn leer_vtodo(ics:&str)->Option<TodoIcal>{
+    let mut dentro=false;
+    let mut anidado=0;
+    let mut uid=None;
+    let mut nombre=None;
+    let mut tarea=TareasHttp::default();
+    let mut estado=None;
+    let mut completada_at=false;
+    for (propiedad,valor) in propiedades_ics(ics) {
+        match (propiedad.as_str(),dentro,anidado) {
+            ("BEGIN",false,_) if valor.eq_ignore_ascii_case("VTODO")=>dentro=true,
+            ("BEGIN",true,_)=>anidado += 1,
+            ("END",true,0)=>break,
+            ("END",true,_)=>anidado -= 1,
+            // las propiedades de un VALARM no son de la tarea
+            (_,true,0)=>match propiedad.as_str() {
+                "UID"=>uid=Some(valor.trim().to_string()),
+                "SUMMARY"=>nombre=Some(texto_sin_escapes(&valor).trim().to_string()),
+                "DUE"=>tarea.vence=fecha_ics(valor.trim()),
+                "STATUS"=>estado=Some(valor.trim().to_uppercase()),
+                "COMPLETED"=>completada_at=true,
+                "PRIORITY"=>tarea.prioridad=prioridad_ics(&valor),
+                "CATEGORIES"=>tarea.etiquetas.extend(lista_ics(&valor)),
+                _=>{},
+            },
+            _=>{},
+        }
+    }
+    if !dentro {
+        return None;
+    }
+    tarea.nombre=nombre?;
+    let completada=match estado.as_deref() {
+        Some(estado)=>estado == "COMPLETED",
+        None=>completada_at,
+    };
+    Some(TodoIcal { uid, tarea, completada })
+}
+
+/// nombre de un elemento XML con el espacio de nombres resuelto
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct NombreXml{
+    pub ns:String,
+    pub local:String,
+}
+
+impl NombreXml {
+    pub fn nuevo(ns:&str,local:&str)->NombreXml{
+        NombreXml { ns: ns.to_string(), local: local.to_string() }
+    }
+
+    pub fn es(&self,ns:&str,local:&str)->bool{
+        self.ns == ns && self.local == local
+    }
+}
+
+#[derive(Debug,Clone)]
+enum EventoXml{
+    Abre(NombreXml),
+    Cierra,
+    Texto(String),
+}
+
+fn sin_entidades(texto:&str)->String{
+    texto.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// escapa texto para el contenido o los atributos de un elemento
+pub fn texto_xml(texto:&str)->String{
+    texto.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// etiquetas y texto del cuerpo; alcanza para las peticiones de CalDAV, que no usan DTD ni CDATA
+fn eventos_xml(cuerpo:&str)->Vec<EventoXml>{
+    let mut eventos=Vec::new();
+    let mut espacios:Vec<HashMap<String,String>>=Vec::new();
+    let mut resto=cuerpo;
+    while let Some(inicio) = resto.find('<') {
+        let texto=resto[..inicio].trim();
+        if !texto.is_empty() {
+            eventos.push(EventoXml::Texto(sin_entidades(texto)));
+        }
+        resto=&resto[inicio..];
+        if resto.starts_with("<?") || resto.starts_with("<!") {
+            let fin=if resto.starts_with("<!--") { resto.find("-->").map(|i| i + 3) } else { resto.find('>').map(|i| i + 1) };
+            resto=&resto[fin.unwrap_or(resto.len())..];
+            continue;
+        }
+        // el `>` que cierra la etiqueta, salteando los que esten entre comillas
+        let mut comillas=None;
+        let Some(fin) = resto.char_indices().find(|(_,c)| {
+            match (*c,comillas) {
+                ('"' | '\'',None)=>comillas=Some(*c),
+                (c,Some(abierta)) if c == abierta=>comillas=None,
+                _=>{},
+            }
+            *c == '>' && comillas.is_none()
+        }).map(|(i,_)| i) else {
+            break;
+        };
+        let etiqueta=&resto[1..fin];
+        resto=&resto[fin + 1..];
+        if etiqueta.starts_with('/') {
+            espacios.pop();
+            eventos.push(EventoXml::Cierra);
+            continue;
+        }
+        let vacia=etiqueta.ends_with('/');
+        let etiqueta=etiqueta.trim_end_matches('/');
+        let (nombre,atributos)=etiqueta.split_once(char::is_whitespace).unwrap_or((etiqueta,""));
+        let mut declarados=espacios.last().cloned().unwrap_or_default();
+        let mut atributos=atributos.trim();
+        while let Some((clave,valor)) = atributos.split_once('=') {
+            let valor=valor.trim_start();
+            let Some(comilla) = valor.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+                break;
+            };
+            let Some(cierre) = valor[1..].find(comilla) else {
+                break;
+            };
+            let clave=clave.trim();
+            if clave == "xmlns" {
+                declarados.insert(String::new(), sin_entidades(&valor[1..cierre + 1]));
+            }else if let Some(prefijo) = clave.strip_prefix("xmlns:") {
+                declarados.insert(prefijo.to_string(), sin_entidades(&valor[1..cierre + 1]));
+            }
+            atributos=valor[cierre + 2..].trim_start();
+        }
+        let (prefijo,local)=nombre.split_once(':').unwrap_or(("",nombre));
+        let ns=declarados.get(prefijo).cloned().unwrap_or_default();
+        eventos.push(EventoXml::Abre(NombreXml { ns, local: local.to_string() }));
+        if vacia {
+            eventos.push(EventoXml::Cierra);
+        }else{
+            espacios.push(declarados);
+        }
+    }
+    eventos
+}
+
+/// elemento raiz del cuerpo, el tipo de REPORT
+pub fn raiz_xml(cuerpo:&str)->Option<NombreXml>{
+    eventos_xml(cuerpo).into_iter().find_map(|evento| match evento {
+        EventoXml::Abre(nombre)=>Some(nombre),
+        _=>None,
+    })
+}
+
+/// propiedades pedidas en `<D:prop>`; `None` con `allprop`, `propname` o sin cuerpo
+pub fn props_pedidas(cuerpo:&str)->Option<Vec<NombreXml>>{
+    let mut res:Option<Vec<NombreXml>>=None;
+    // profundidad dentro de `<D:prop>`, sus hijos directos son las propiedades
+    let mut profundidad=0;
+    for evento in eventos_xml(cuerpo) {
+        match (evento,res.as_mut()) {
+            (EventoXml::Abre(nombre),None) if nombre.es(NS_DAV, "prop")=>{
+                res=Some(Vec::new());
+            },
+            (EventoXml::Abre(nombre),Some(props))=>{
+                if profundidad == 0 {
+                    props.push(nombre);
+                }
+                profundidad += 1;
+            },
+            (EventoXml::Cierra,Some(_))=>{
+                if profundidad == 0 {
+                    break;
+                }
+                profundidad -= 1;
+            },
+            _=>{},
+        }
+    }
+    res
+}
+
+/// contenido de los `<D:href>` del cuerpo
+pub fn hrefs_xml(cuerpo:&str)->Vec<String>{
+    let mut res=Vec::new();
+    let mut en_href=false;
+    for evento in eventos_xml(cuerpo) {
+        match evento {
+            EventoXml::Abre(nombre)=>en_href=nombre.es(NS_DAV, "href"),
+            EventoXml::Texto(texto) if en_href=>res.push(texto),
+            _=>en_href=false,
+        }
+    }
+    res
+}
+
+/// elemento vacio o con `contenido` ya escapado; los espacios conocidos usan el prefijo de la raiz
+pub fn elemento_xml(nombre:&NombreXml,contenido:&str)->String{
+    let (etiqueta,declaracion)=match nombre.ns.as_str() {
+        NS_DAV=>(format!("d:{}",nombre.local),String::new()),
+        NS_CALDAV=>(format!("c:{}",nombre.local),String::new()),
+        NS_CS=>(format!("cs:{}",nombre.local),String::new()),
+        ""=>(nombre.local.clone(),String::new()),
+        ns=>(format!("x:{}",nombre.local),format!(" xmlns:x=\"{}\"",texto_xml(ns))),
+    };
+    if contenido.is_empty() {
+        format!("<{}{}/>",etiqueta,declaracion)
+    }else{
+        format!("<{}{}>{}</{}>",etiqueta,declaracion,contenido,etiqueta)
+    }
+}
+
+/// respuesta 207 de PROPFIND y REPORT
+pub struct Multistatus{
+    xml:String,
+}
+
+impl Multistatus {
+    pub fn nuevo()->Multistatus{
+        Multistatus {
+            xml: format!("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<d:multistatus xmlns:d=\"{}\" xmlns:c=\"{}\" xmlns:cs=\"{}\">",NS_DAV,NS_CALDAV,NS_CS),
+        }
+    }
+
+    /// un recurso con las propiedades que tiene (200) y las que no (404)
+    pub fn recurso(&mut self,href:&str,encontradas:&[(NombreXml,String)],faltantes:&[NombreXml]){
+        self.xml.push_str(&format!("<d:response><d:href>{}</d:href>",texto_xml(href)));
+        if !encontradas.is_empty() {
+            self.xml.push_str("<d:propstat><d:prop>");
+            for (nombre,valor) in encontradas {
+                self.xml.push_str(&elemento_xml(nombre, valor));
+            }
+            self.xml.push_str("</d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat>");
+        }
+        if !faltantes.is_empty() {
+            self.xml.push_str("<d:propstat><d:prop>");
+            for nombre in faltantes {
+                self.xml.push_str(&elemento_xml(nombre, ""));
+            }
+            self.xml.push_str("</d:prop><d:status>HTTP/1.1 404 Not Found</d:status></d:propstat>");
+        }
+        self.xml.push_str("</d:response>");
+    }
+
+    /// un href de `calendar-multiget` que no existe o no es visible
+    pub fn no_encontrado(&mut self,href:&str){
+        self.xml.push_str(&format!("<d:response><d:href>{}</d:href><d:status>HTTP/1.1 404 Not Found</d:status></d:response>",texto_xml(href)));
+    }
+
+    pub fn cerrar(mut self)->String{
+        self.xml.push_str("</d:multistatus>");
+        self.xml
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vtodo_con_lineas_plegadas_y_escapes() {
+        let ics="BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VTODO\r\nUID:abc@app\r\n\
+            SUMMARY:Pagar\\, luz\\; agua y\r\n  gas\\nya\r\nDUE;VALUE=DATE:20261019\r\nPRIORITY:1\r\n\
+            CATEGORIES:casa,x\\,y\r\nSTATUS:COMPLETED\r\nBEGIN:VALARM\r\nSUMMARY:alarma\r\nPRIORITY:9\r\nEND:VALARM\r\n\
+            END:VTODO\r\nEND:VCALENDAR\r\n";
+        let todo=leer_vtodo(ics).unwrap();
+        assert_eq!(todo.uid.as_deref(), Some("abc@app"));
+        // la linea plegada se une sin el espacio inicial
+        assert_eq!(todo.tarea.nombre, "Pagar, luz; agua y gas\nya");
+        assert_eq!(todo.tarea.vence, Some(Utils::dia_de_fecha(2026, 10, 19).unwrap() * DIA_MS + DIA_MS - 1000));
+        // el VALARM no pisa la prioridad de la tarea
+        assert_eq!(todo.tarea.prioridad, Some(Prioridad::Urgente));
+        assert_eq!(todo.tarea.etiquetas, vec!["casa","x,y"]);
+        assert!(todo.completada);
+    }
+
+    #[test]
+    fn vtodo_sin_status_ni_resumen() {
+        let todo=leer_vtodo("BEGIN:VTODO\nSUMMARY: Llamar \nDUE:20261019T101530Z\nCOMPLETED:20261019T110000Z\nEND:VTODO\n").unwrap();
+        assert_eq!(todo.tarea.nombre, "Llamar");
+        assert_eq!(todo.tarea.vence, Some(Utils::dia_de_fecha(2026, 10, 19).unwrap() * DIA_MS + ((10 * 60 + 15) * 60 + 30) * 1000));
+        assert_eq!(todo.uid, None);
+        // sin STATUS cuenta COMPLETED
+        assert!(todo.completada);
+        assert!(!leer_vtodo("BEGIN:VTODO\nSUMMARY:x\nSTATUS:NEEDS-ACTION\nCOMPLETED:20261019T110000Z\nEND:VTODO\n").unwrap().completada);
+
+        assert!(leer_vtodo("BEGIN:VTODO\nDUE:20261019\nEND:VTODO\n").is_none());
+        assert!(leer_vtodo("BEGIN:VEVENT\nSUMMARY:evento\nEND:VEVENT\n").is_none());
+        // una fecha invalida no impide leer la tarea
+        assert_eq!(leer_vtodo("BEGIN:VTODO\nSUMMARY:x\nDUE:2026-10-19\nEND:VTODO\n").unwrap().tarea.vence, None);
+    }
+
+    #[test]
+    fn hrefs_con_porcentajes() {
+        assert_eq!(id_de_href("/caldav/tareas/abc.ics").as_deref(), Some("abc"));
+        assert_eq!(id_de_href("https://host:8001/caldav/tareas/a%40b%20c.ics").as_deref(), Some("a@b c"));
+        assert_eq!(id_de_href("/caldav/tareas/%C3%B1.ics").as_deref(), Some("ñ"));
+        for invalido in ["/caldav/tareas/.ics","/caldav/tareas/sub/x.ics","/caldav/tareas/x","/caldav/tareas/x%zz.ics","/caldav/tareas/x%4.ics","/caldav/tareas/%FF.ics","/otra/x.ics"] {
+            assert_eq!(id_de_href(invalido), None, "{}",invalido);
+        }
+        assert_eq!(href_de_tarea("abc"), "/caldav/tareas/abc.ics");
+
+        assert_eq!(RutaDav::desde("/.well-known/caldav"), RutaDav::WellKnown);
+        assert_eq!(RutaDav::desde("/"), RutaDav::Cuenta);
+        assert_eq!(RutaDav::desde("/caldav/tareas"), RutaDav::Coleccion);
+        assert_eq!(RutaDav::desde("/caldav/tareas/a%2Db.ics"), RutaDav::Tarea("a-b".to_string()));
+        assert_eq!(RutaDav::desde("/caldav/otra"), RutaDav::Otra);
+    }
+
+    #[test]
+    fn propfind_con_y_sin_prop() {
+        let cuerpo="<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:propfind xmlns:D=\"DAV:\" xmlns:C=\"urn:ietf:params:xml:ns:caldav\">\
+            <!-- <D:prop><D:ignorada/></D:prop> --><D:prop><D:getetag/>\
+            <C:calendar-data><C:comp name=\"VCALENDAR\"/></C:calendar-data>\
+            <x:calendar-color xmlns:x='http://apple.com/ns/ical/'/></D:prop></D:propfind>";
+        assert_eq!(props_pedidas(cuerpo), Some(vec![
+            NombreXml::nuevo(NS_DAV, "getetag"),
+            NombreXml::nuevo(NS_CALDAV, "calendar-data"),
+            NombreXml::nuevo("http://apple.com/ns/ical/", "calendar-color"),
+        ]));
+        // el espacio por defecto tambien cuenta
+        assert_eq!(props_pedidas("<propfind xmlns=\"DAV:\"><prop><displayname/></prop></propfind>"), Some(vec![NombreXml::nuevo(NS_DAV, "displayname")]));
+        assert_eq!(props_pedidas("<propfind xmlns=\"DAV:\"><allprop/></propfind>"), None);
+        assert_eq!(props_pedidas(""), None);
+        // un `prop` de otro espacio no es el de DAV
+        assert_eq!(props_pedidas("<propfind xmlns=\"DAV:\" xmlns:o=\"otro\"><o:prop><o:a/></o:prop></propfind>"), None);
+    }
+
+    #[test]
+    fn multiget_con_hrefs() {
+        let cuerpo="<C:calendar-multiget xmlns:D=\"DAV:\" xmlns:C=\"urn:ietf:params:xml:ns:caldav\" data-x=\"a>b\">\
+            <D:prop><D:getetag/></D:prop>\
+            <D:href>/caldav/tareas/a.ics</D:href>\n  <D:href> /caldav/tareas/b&amp;c.ics </D:href></C:calendar-multiget>";
+        assert_eq!(raiz_xml(cuerpo), Some(NombreXml::nuevo(NS_CALDAV, "calendar-multiget")));
+        assert_eq!(hrefs_xml(cuerpo), vec!["/caldav/tareas/a.ics","/caldav/tareas/b&c.ics"]);
+        assert!(hrefs_xml("<D:propfind xmlns:D=\"DAV:\"><D:prop/></D:propfind>").is_empty());
+    }
+
+    #[test]
+    fn multistatus_escapa_y_declara_espacios() {
+        let mut multistatus=Multistatus::nuevo();
+        multistatus.recurso("/caldav/tareas/a&b.ics", &[(NombreXml::nuevo(NS_DAV, "getetag"), texto_xml("\"1\""))], &[NombreXml::nuevo("otro", "x")]);
+        multistatus.no_encontrado("/caldav/tareas/c.ics");
+        let xml=multistatus.cerrar();
+        assert!(xml.contains("<d:href>/caldav/tareas/a&amp;b.ics</d:href>"));
+        assert!(xml.contains("<d:getetag>&quot;1&quot;</d:getetag>"));
+        assert!(xml.contains("<x:x xmlns:x=\"otro\"/>"));
+        assert!(xml.contains("<d:href>/caldav/tareas/c.ics</d:href><d:status>HTTP/1.1 404 Not Found</d:status>"));
+        assert!(xml.ends_with("</d:multistatus>"));
+    }
+}
@@ -0,0 +1,298 @@
+use rocket::{http::{ContentType, Status}, response::status::{self, Custom}, serde::json::Json, State};
+use unreql::Session;
+
+use crate::{api::{etag_json, AdduserResponse, ClaveCaldav, ConEtag, CredencialesCaldav, Espacios, IfNoneMatch}, JwtGuard, API_URL};
+
+use super::{calendario_ics, Calendarios, ComponenteIcs, SuscripcionCalendario};
+
+#[utoipa::path(
+    post,
+    path = "/calendario/token",
+    responses(
+        (status = 200, description = "Token nuevo de la suscripcion del espacio activo o de las tareas personales; el anterior deja de servir", body = SuscripcionCalendario),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="calendario"
+)]
+#[post("/token")]
+pub async fn renovar_token_calendario(userClaims:JwtGuard,db:&State<Session>)->Result<Custom<Json<SuscripcionCalendario>>,status::Custom<Json<AdduserResponse>>>{
+    match Calendarios::renovar(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(SuscripcionCalendario::desde(&res, API_URL))
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/calendario/token",
+    responses(
+        (status = 200, description = "Token revocado, la url del calendario deja de responder", body = String),
+        (status = 404, description = "No habia token", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="calendario"
+)]
+#[delete("/token")]
+pub async fn revocar_token_calendario(userClaims:JwtGuard,db:&State<Session>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    match Calendarios::revocar(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(true)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    "Ok ".to_string()
+                )
+            )
+        },
+        Ok(false)=>{
+            Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/calendario/caldav",
+    responses(
+        (status = 200, description = "Contraseña nueva de CalDAV del espacio activo o de las tareas personales; se muestra una sola vez y la anterior deja de servir", body = ClaveCaldav),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="calendario"
+)]
+#[post("/caldav")]
+pub async fn renovar_clave_caldav(userClaims:JwtGuard,db:&State<Session>)->Result<Custom<Json<ClaveCaldav>>,status::Custom<Json<AdduserResponse>>>{
+    match CredencialesCaldav::renovar(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/calendario/caldav",
+    responses(
+        (status = 200, description = "Contraseña revocada, las apps de CalDAV dejan de sincronizar", body = String),
+        (status = 404, description = "No habia contraseña", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="calendario"
+)]
+#[delete("/caldav")]
+pub async fn revocar_clave_caldav(userClaims:JwtGuard,db:&State<Session>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    match CredencialesCaldav::revocar(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(true)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    "Ok ".to_string()
+                )
+            )
+        },
+        Ok(false)=>{
+            Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/calendario/{token}.ics",
+    responses(
+        (status = 200, description = "iCalendar con las tareas que tienen vencimiento, con header `ETag`", content_type = "text/calendar"),
+        (status = 304, description = "El calendario no cambio desde la etag de `If-None-Match`"),
+        (status = 400, description = "Componente invalido", body = AdduserResponse),
+        (status = 404, description = "Token inexistente o revocado", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse)
+    ),
+    params(
+        ("token" = String, Path, description = "token de `POST /calendario/token`"),
+        ("componente" = Option<String>, Query, description = "`vtodo` (por defecto) o `vevent` para apps que no muestran tareas")
+    ),
+    tag="calendario"
+)]
+#[get("/<archivo>?<componente>")]
+pub async fn get_calendario(db:&State<Session>,archivo:&str,componente:Option<&str>,if_none_match:IfNoneMatch)->Result<ConEtag<(ContentType,String)>,status::Custom<Json<AdduserResponse>>>{
+    let Some(componente) = ComponenteIcs::desde(componente) else {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse{
+                        error:3
+                    }
+                )
+            )
+        );
+    };
+    let token=archivo.strip_suffix(".ics").unwrap_or(archivo);
+    let calendario=match Calendarios::get_by_token(token,db).await {
+        Ok(Some(calendario))=>calendario,
+        Ok(None)=>{
+            return Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            );
+        },
+        Err(e)=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+    // quien ya no es miembro del espacio deja de ver sus tareas
+    if let Some(id_espacio) = calendario.id_espacio.as_deref() {
+        match Espacios::rol_de(id_espacio,&calendario.id_usuario,db).await {
+            Ok(Some(_))=>{},
+            Ok(None)=>{
+                return Err(
+                    Custom(
+                        Status::NotFound,
+                        Json(
+                            AdduserResponse{
+                                error:1
+                            }
+                        )
+                    )
+                );
+            },
+            Err(e)=>{
+                return Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                );
+            }
+        }
+    }
+
+    match calendario.get_tareas(db).await {
+        Ok(tareas)=>{
+            let ics=calendario_ics(&tareas, componente);
+            let etag=etag_json(&ics);
+            Ok(ConEtag::nueva((ContentType::Calendar,ics),etag,&if_none_match))
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
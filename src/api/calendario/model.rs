@@ -0,0 +1,357 @@
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::Value;
+use unreql::{r, rjson, types::WriteStatus, Session};
+use utoipa::ToSchema;
+
+use crate::{api::{Flujos, Prioridad, Tareas}, utils::{Utils, DIA_MS}};
+
+/// caracteres del token de la suscripcion
+const LARGO_TOKEN:usize=40;
+/// octetos por linea del iCalendar antes de plegarla (RFC 5545, 3.1)
+const LARGO_LINEA_ICS:usize=75;
+
+/// token de la suscripcion al calendario; es el id del documento y no sirve para la API
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Calendarios{
+    pub id:String,
+    /// `usuario:<id>` o `espacio:<id>`, igual que el id de los flujos
+    pub alcance:String,
+    pub id_usuario:String,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub id_espacio:Option<String>,
+    pub create_at:i64,
+}
+
+/// tipo de componente con que se escriben las tareas
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ComponenteIcs{
+    /// `VTODO`, con `DUE` y `STATUS`
+    Vtodo,
+    /// `VEVENT` en el momento del vencimiento, para apps que no muestran tareas
+    Vevent,
+}
+
+impl ComponenteIcs {
+    pub fn desde(valor:Option<&str>)->Option<ComponenteIcs>{
+        match valor.map(str::to_lowercase).as_deref() {
+            None | Some("vtodo")=>Some(ComponenteIcs::Vtodo),
+            Some("vevent")=>Some(ComponenteIcs::Vevent),
+            _=>None,
+        }
+    }
+}
+
+/// fecha en UTC con el formato `AAAAMMDDTHHMMSSZ`
+fn fecha_ics(ms:i64)->String{
+    let dia=ms.div_euclid(DIA_MS);
+    let segundos=ms.rem_euclid(DIA_MS) / 1000;
+    format!("{}T{:02}{:02}{:02}Z",Utils::fecha_de_dia(dia).replace('-', ""),segundos / 3600,segundos / 60 % 60,segundos % 60)
+}
+
+/// texto con `\`, `;`, `,` y saltos de linea escapados
+fn texto_ics(texto:&str)->String{
+    let mut res=String::with_capacity(texto.len());
+    for c in texto.chars() {
+        match c {
+            '\\' | ';' | ','=>{
+                res.push('\\');
+                res.push(c);
+            },
+            '\n'=>res.push_str("\\n"),
+            '\r'=>{},
+            _=>res.push(c),
+        }
+    }
+    res
+}
+
+/// agrega la linea terminada en CRLF, plegada cada 75 octetos sin cortar caracteres
+fn linea_ics(ics:&mut String,linea:&str){
+    let mut largo=0;
+    for c in linea.chars() {
+        if largo + c.len_utf8() > LARGO_LINEA_ICS {
+            ics.push_str("\r\n ");
+            largo=1;
+        }
+        ics.push(c);
+        largo += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+/// prioridad de iCalendar: 1 la mas alta, 9 la mas baja
+fn prioridad_ics(prioridad:Prioridad)->u8{
+    match prioridad {
+        Prioridad::Urgente=>1,
+        Prioridad::Alta=>3,
+        Prioridad::Media=>5,
+        Prioridad::Baja=>9,
+    }
+}
+
+/// escribe la tarea como `VTODO`, o como `VEVENT` si tiene vencimiento
+fn componente_ics(ics:&mut String,tarea:&Tareas,componente:ComponenteIcs){
+    let nombre=match componente {
+        ComponenteIcs::Vtodo=>"VTODO",
+        ComponenteIcs::Vevent=>"VEVENT",
+    };
+    linea_ics(ics, &format!("BEGIN:{}",nombre));
+    match tarea.uid_ical.as_deref() {
+        Some(uid)=>linea_ics(ics, &format!("UID:{}",texto_ics(uid))),
+        None=>linea_ics(ics, &format!("UID:{}@tareas",tarea.id)),
+    }
+    linea_ics(ics, &format!("DTSTAMP:{}",fecha_ics(tarea.update_at)));
+    linea_ics(ics, &format!("CREATED:{}",fecha_ics(tarea.create_at)));
+    linea_ics(ics, &format!("LAST-MODIFIED:{}",fecha_ics(tarea.update_at)));
+    linea_ics(ics, &format!("SUMMARY:{}",texto_ics(&tarea.nombre)));
+    match componente {
+        ComponenteIcs::Vtodo=>{
+            if let Some(vence) = tarea.vence {
+                linea_ics(ics, &format!("DUE:{}",fecha_ics(vence)));
+            }
+            if tarea.estado {
+                linea_ics(ics, "STATUS:COMPLETED");
+                if let Some(completada_at) = tarea.completada_at {
+                    linea_ics(ics, &format!("COMPLETED:{}",fecha_ics(completada_at)));
+                }
+            }else{
+                linea_ics(ics, "STATUS:NEEDS-ACTION");
+            }
+        },
+        ComponenteIcs::Vevent=>{
+            if let Some(vence) = tarea.vence {
+                linea_ics(ics, &format!("DTSTART:{}",fecha_ics(vence)));
+            }
+            linea_ics(ics, "TRANSP:TRANSPARENT");
+            linea_ics(ics, if tarea.estado { "STATUS:CANCELLED" } else { "STATUS:CONFIRMED" });
+        }
+    }
+    if let Some(prioridad) = tarea.prioridad {
+        linea_ics(ics, &format!("PRIORITY:{}",prioridad_ics(prioridad)));
+    }
+    if !tarea.etiquetas.is_empty() {
+        let etiquetas:Vec<String>=tarea.etiquetas.iter().map(|e| texto_ics(e)).collect();
+        linea_ics(ics, &format!("CATEGORIES:{}",etiquetas.join(",")));
+    }
+    if let Some(id_padre) = tarea.id_padre.as_deref() {
+        linea_ics(ics, &format!("RELATED-TO:{}@tareas",id_padre));
+    }
+    linea_ics(ics, &format!("END:{}",nombre));
+}
+
+fn inicio_ics(ics:&mut String){
+    linea_ics(ics, "BEGIN:VCALENDAR");
+    linea_ics(ics, "VERSION:2.0");
+    linea_ics(ics, "PRODID:-//Tareas api//Tareas//ES");
+    linea_ics(ics, "CALSCALE:GREGORIAN");
+}
+
+/// calendario con las tareas que vencen; las fechas van en UTC y cada app las muestra en su zona
+pub fn calendario_ics(tareas:&[Tareas],componente:ComponenteIcs)->String{
+    let mut ics=String::new();
+    inicio_ics(&mut ics);
+    linea_ics(&mut ics, "X-WR-CALNAME:Tareas");
+    for tarea in tareas.iter().filter(|t| t.vence.is_some()) {
+        componente_ics(&mut ics, tarea, componente);
+    }
+    linea_ics(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+/// la tarea sola como `VTODO`, tenga o no vencimiento; es el recurso de CalDAV
+pub fn tarea_ics(tarea:&Tareas)->String{
+    let mut ics=String::new();
+    inicio_ics(&mut ics);
+    componente_ics(&mut ics, tarea, ComponenteIcs::Vtodo);
+    linea_ics(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+impl Calendarios {
+    pub async fn create_indexes(db:&Session)->Result<(),unreql::Error>{
+        Utils::create_table_if_missing("calendarios", db).await?;
+        let existentes=r.db("tareas").table("calendarios")
+        .index_list()
+        .exec::<_,Vec<String>>(db)
+        .await?;
+        if !existentes.iter().any(|i| i == "id_usuario") {
+            let query = r.db("tareas").table("calendarios")
+            .index_create("id_usuario")
+            .exec::<_,Value>(db);
+            if let Err(e) = query.await {
+                println!("error create_indexes calendarios {}",e);
+                return Err(e);
+            }
+        }
+        r.db("tareas").table("calendarios").index_wait(()).exec::<_,Value>(db).await?;
+        Ok(())
+    }
+
+    pub async fn get_by_token(token:&str,db:&Session)->Result<Option<Calendarios>,unreql::Error>{
+        let query = r.db("tareas").table("calendarios")
+        .get(token.to_string())
+        .exec_to_vec::<_,Calendarios>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().next())
+            },
+            Err(e)=>{
+                println!("error get_by_token calendario {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// token nuevo para el espacio activo o las tareas personales; el anterior deja de servir
+    pub async fn renovar(id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Calendarios,unreql::Error>{
+        Self::revocar(id_usuario, id_espacio, db).await?;
+        let calendario=Calendarios {
+            id: Utils::generar_token(LARGO_TOKEN),
+            alcance: Flujos::id_para(id_usuario, id_espacio),
+            id_usuario: id_usuario.to_string(),
+            id_espacio: id_espacio.map(str::to_string),
+            create_at: Utils::current_timestamp(),
+        };
+        let query = r.db("tareas").table("calendarios")
+        .insert(calendario.clone())
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(_)=>{
+                Ok(calendario)
+            },
+            Err(e)=>{
+                println!("error renovar calendario {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// borra el token del espacio activo o de las tareas personales; `false` si no habia
+    pub async fn revocar(id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<bool,unreql::Error>{
+        let query = r.db("tareas").table("calendarios")
+        .get_all(r.with_opt(id_usuario.to_string(), r.index("id_usuario")))
+        .filter(rjson!({"alcance":Flujos::id_para(id_usuario, id_espacio)}))
+        .delete(())
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.deleted > 0)
+            },
+            Err(e)=>{
+                println!("error revocar calendario {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// tareas con vencimiento del alcance del token, sin las de la papelera; las que no
+    /// tienen `vence` no estan en el indice
+    pub async fn get_tareas(&self,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
+        let query = Tareas::rango_por_indice(&self.id_usuario, self.id_espacio.as_deref(), "vence", r.minval(), r.maxval())
+        .exec_to_vec::<_,Tareas>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res)
+            },
+            Err(e)=>{
+                println!("error get_tareas calendario {}",e);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// token de la suscripcion con la ruta del calendario
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct SuscripcionCalendario{
+    pub token:String,
+    /// ruta del `.ics` para agregar en la app de calendario
+    pub url:String,
+    pub create_at:i64,
+}
+
+impl SuscripcionCalendario {
+    pub fn desde(calendario:&Calendarios,base:&str)->SuscripcionCalendario{
+        SuscripcionCalendario {
+            token: calendario.id.clone(),
+            url: format!("{}/calendario/{}.ics",base,calendario.id),
+            create_at: calendario.create_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn tarea(campos:Value)->Tareas{
+        let mut base=json!({
+            "id":"t1",
+            "nombre":"tarea",
+            "id_usuario":"u",
+            "estado":false,
+            "create_at":0,
+            "update_at":0,
+        });
+        base.as_object_mut().unwrap().extend(campos.as_object().unwrap().clone());
+        serde_json::from_value(base).unwrap()
+    }
+
+    #[test]
+    fn pliega_cada_75_octetos_sin_cortar_caracteres() {
+        let mut ics=String::new();
+        let linea=format!("SUMMARY:{}","ñ".repeat(60));
+        linea_ics(&mut ics, &linea);
+        let partes:Vec<&str>=ics.strip_suffix("\r\n").unwrap().split("\r\n").collect();
+        assert!(partes.len() > 1);
+        assert!(partes.iter().all(|p| p.len() <= LARGO_LINEA_ICS));
+        assert!(partes[1..].iter().all(|p| p.starts_with(' ')));
+        // al desplegar vuelve la linea original
+        assert_eq!(partes.iter().enumerate().map(|(i,p)| if i == 0 { *p } else { &p[1..] }).collect::<String>(), linea);
+
+        let mut corta=String::new();
+        linea_ics(&mut corta, "END:VTODO");
+        assert_eq!(corta, "END:VTODO\r\n");
+    }
+
+    #[test]
+    fn escapa_texto() {
+        assert_eq!(texto_ics("a;b,c\\d\r\nfin"), "a\\;b\\,c\\\\d\\nfin");
+    }
+
+    #[test]
+    fn fecha_en_utc() {
+        assert_eq!(fecha_ics(0), "19700101T000000Z");
+        assert_eq!(fecha_ics(Utils::dia_de_fecha(2026, 10, 19).unwrap() * DIA_MS + 3_723_000), "20261019T010203Z");
+    }
+
+    #[test]
+    fn calendario_solo_con_las_que_vencen() {
+        let tareas=[
+            tarea(json!({"id":"a","nombre":"Pagar; luz","vence":0,"prioridad":"urgente","etiquetas":["casa","x,y"]})),
+            tarea(json!({"id":"b","nombre":"sin fecha"})),
+            tarea(json!({"id":"c","nombre":"hecha","vence":0,"estado":true,"completada_at":1000,"uid_ical":"externo@app"})),
+        ];
+        let ics=calendario_ics(&tareas, ComponenteIcs::Vtodo);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VTODO").count(), 2);
+        assert!(ics.contains("UID:a@tareas\r\n"));
+        assert!(ics.contains("SUMMARY:Pagar\\; luz\r\n"));
+        assert!(ics.contains("PRIORITY:1\r\n"));
+        assert!(ics.contains("CATEGORIES:casa,x\\,y\r\n"));
+        assert!(ics.contains("UID:externo@app\r\n"));
+        assert!(ics.contains("STATUS:COMPLETED\r\nCOMPLETED:19700101T000001Z\r\n"));
+        assert!(!ics.contains("sin fecha"));
+
+        let eventos=calendario_ics(&tareas, ComponenteIcs::Vevent);
+        assert_eq!(eventos.matches("BEGIN:VEVENT").count(), 2);
+        assert!(eventos.contains("DTSTART:19700101T000000Z\r\n"));
+        // la tarea sola se escribe aunque no venza
+        assert!(tarea_ics(&tareas[1]).contains("SUMMARY:sin fecha\r\n"));
+    }
+}
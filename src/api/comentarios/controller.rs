@@ -0,0 +1,345 @@
+use rocket::{http::Status, response::status::{self, Custom}, serde::json::Json, State};
+use unreql::Session;
+use validator::Validate;
+
+use crate::{api::{AdduserResponse, Tareas}, utils::Utils, ClientesSockets, JwtGuard};
+
+use super::{ComentarioEdit, Comentarios, ComentariosHttp, ComentariosPage, ComentariosQuery};
+
+/// avisa por websocket a todos los que pueden ver la tarea
+async fn notificar(id_tarea:&str,event:&str,db:&Session,clients:&ClientesSockets){
+    if let Ok(tareas) = Tareas::get_tarea_by_id(id_tarea, db).await {
+        if let Some(tarea) = tareas.first() {
+            Utils::send_ws_to(clients, &tarea.destinatarios(db).await, event).await;
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/tareas/{id}/comentarios",
+    responses(
+        (status = 200, description = "Comentarios de la tarea en orden cronologico", body = ComentariosPage),
+        (status = 400, description = "Cursor invalido", body = AdduserResponse),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea"),
+        ComentariosQuery
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="comentarios"
+)]
+#[get("/<id>/comentarios?<params..>")]
+pub async fn get_comentarios(userClaims:JwtGuard,db:&State<Session>,id:&str,params:ComentariosQuery)->Result<Custom<Json<ComentariosPage>>,status::Custom<Json<AdduserResponse>>>{
+    if matches!(params.posicion_cursor(),Some(Err(()))) {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse{
+                        error:3
+                    }
+                )
+            )
+        );
+    }
+
+    match Tareas::get_visible(id,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(Some(_))=>{},
+        Ok(None)=>{
+            return Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            );
+        },
+        Err(e)=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    }
+
+    match Comentarios::get_page_by_tarea(id,&params,db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok, 
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/tareas/{id}/comentarios",
+    responses(
+        (status = 201, description = "Comentario creado", body = Comentarios),
+        (status = 400, description = "Datos invalidos o comentario padre inexistente", body = AdduserResponse),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea")
+    ),
+    request_body=ComentariosHttp,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="comentarios"
+)]
+#[post("/<id>/comentarios",data="<comentario>")]
+pub async fn add_comentario(db:&State<Session>,id:&str,userClaims:JwtGuard,comentario:Json<ComentariosHttp>,clients:&State<ClientesSockets>)->Result<Custom<Json<Comentarios>>,status::Custom<Json<AdduserResponse>>>{
+    if let Err(errors) = comentario.validate(){
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse { error: 4 }
+                )
+            )
+        );
+    }
+
+    let tarea=match Tareas::get_visible(id,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(Some(tarea))=>tarea,
+        Ok(None)=>{
+            return Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            );
+        },
+        Err(e)=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+
+    if let Some(id_padre) = comentario.id_padre.as_deref() {
+        let padre_valido=match Comentarios::get_by_id(id_padre,db).await {
+            Ok(padre)=>padre.is_some_and(|p| p.id_tarea == tarea.id),
+            Err(e)=>false
+        };
+        if !padre_valido {
+            return Err(
+                Custom(
+                    Status::BadRequest,
+                    Json(
+                        AdduserResponse { error: 3 }
+                    )
+                )
+            );
+        }
+    }
+
+    match Comentarios::add(&comentario,&tarea.id,&userClaims.id_usuario,db).await {
+        Ok(Some(res))=>{
+            Utils::send_ws_to(clients, &tarea.destinatarios(db).await, "comment").await;
+            Ok(
+                Custom(
+                    Status::Created, 
+                    Json(res)
+                )
+            )
+        },
+        Ok(None)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/comentarios/{id}",
+    responses(
+        (status = 200, description = "Comentario editado", body = String),
+        (status = 400, description = "Datos invalidos", body = AdduserResponse),
+        (status = 404, description = "Comentario no encontrado o no es del usuario", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id del comentario")
+    ),
+    request_body=ComentarioEdit,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="comentarios"
+)]
+#[put("/<id>",data="<comentario>")]
+pub async fn edit_comentario(db:&State<Session>,id:&str,userClaims:JwtGuard,comentario:Json<ComentarioEdit>,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    if let Err(errors) = comentario.validate(){
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse { error: 4 }
+                )
+            )
+        );
+    }
+
+    match Comentarios::edit(id,&comentario.texto,&userClaims.id_usuario,db).await {
+        Ok(res)=>{
+            if res{
+                if let Ok(Some(editado)) = Comentarios::get_by_id(id,db).await {
+                    notificar(&editado.id_tarea, "comment_update", db, clients).await;
+                }
+                return Ok(
+                    Custom(
+                        Status::Ok, 
+                        "Ok ".to_string()
+                    )
+                );
+            }
+            Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/comentarios/{id}",
+    responses(
+        (status = 200, description = "Comentario eliminado", body = String),
+        (status = 404, description = "Comentario no encontrado o no es del usuario", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id del comentario")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="comentarios"
+)]
+#[delete("/<id>")]
+pub async fn delete_comentario(db:&State<Session>,id:&str,userClaims:JwtGuard,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    match Comentarios::delete(id,&userClaims.id_usuario,db).await {
+        Ok(res)=>{
+            if res{
+                if let Ok(Some(eliminado)) = Comentarios::get_by_id(id,db).await {
+                    notificar(&eliminado.id_tarea, "comment_delete", db, clients).await;
+                }
+                return Ok(
+                    Custom(
+                        Status::Ok, 
+                        "Ok ".to_string()
+                    )
+                );
+            }
+            Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use rocket::{serde::{Deserialize, Serialize}, FromForm};
+use serde_json::Value;
+use unreql::{cmd::options::{BetweenOptions, DeleteOptions, InsertOptions, Status}, func, r, rjson, types::WriteStatus, Session};
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+use crate::utils::Utils;
+
+pub const COMENTARIOS_LIMIT_DEFAULT:u32=50;
+pub const COMENTARIOS_LIMIT_MAX:u32=200;
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct ComentariosHttp{
+    #[validate(length(min = 1, max = 4000))]
+    pub texto:String,
+    /// comentario al que responde, `null` para un comentario de primer nivel
+    #[serde(default)]
+    pub id_padre:Option<String>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct ComentarioEdit{
+    #[validate(length(min = 1, max = 4000))]
+    pub texto:String,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Comentarios{
+    pub id:String,
+    pub id_tarea:String,
+    #[serde(default)]
+    pub id_padre:Option<String>,
+    pub id_autor:String,
+    /// vacio si el comentario fue eliminado
+    pub texto:String,
+    pub create_at:i64,
+    pub update_at:i64,
+    /// los comentarios eliminados se conservan para no romper el hilo
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub deleted_at:Option<i64>,
+}
+
+#[derive(Debug,Clone,FromForm,IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ComentariosQuery{
+    /// cursor devuelto en `next_cursor` por la pagina anterior
+    pub cursor:Option<String>,
+    /// cantidad de comentarios por pagina (1-200, por defecto 50)
+    pub limit:Option<u32>,
+}
+
+impl ComentariosQuery {
+    pub fn limite(&self)->u32{
+        self.limit.unwrap_or(COMENTARIOS_LIMIT_DEFAULT).clamp(1, COMENTARIOS_LIMIT_MAX)
+    }
+
+    /// `[create_at, id]` del ultimo comentario de la pagina anterior
+    pub fn posicion_cursor(&self)->Option<Result<(i64,String),()>>{
+        let cursor=self.cursor.as_ref()?;
+        let posicion=Utils::decode_cursor(cursor)
+            .and_then(|v| match v {
+                Value::Array(partes) if partes.len() == 2 => {
+                    Some((partes[0].as_i64()?,partes[1].as_str()?.to_string()))
+                },
+                _=>None
+            });
+        Some(posicion.ok_or(()))
+    }
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct ComentariosPage{
+    /// comentarios en orden cronologico, el hilo se arma con `id_padre`
+    pub comentarios:Vec<Comentarios>,
+    /// cursor para pedir la siguiente pagina, `null` si no hay mas
+    pub next_cursor:Option<String>,
+}
+
+impl Comentarios {
+    pub async fn create_indexes(db:&Session)->Result<(),unreql::Error>{
+        Utils::create_table_if_missing("comentarios", db).await?;
+        let existentes=r.db("tareas").table("comentarios")
+        .index_list()
+        .exec::<_,Vec<String>>(db)
+        .await?;
+        if !existentes.iter().any(|i| i == "tarea_create_at") {
+            let query = r.db("tareas").table("comentarios")
+            .index_create(r.args((
+                "tarea_create_at",
+                func!(|c| [c.clone().g("id_tarea"), c.clone().g("create_at"), c.g("id")])
+            )))
+            .exec::<_,Value>(db);
+            if let Err(e) = query.await {
+                println!("error create_indexes comentarios {}",e);
+                return Err(e);
+            }
+        }
+        r.db("tareas").table("comentarios").index_wait(()).exec::<_,Value>(db).await?;
+        Ok(())
+    }
+
+    pub async fn get_by_id(id:&str,db:&Session)->Result<Option<Comentarios>,unreql::Error>{
+        let query = r.db("tareas").table("comentarios")
+        .get(id.to_string())
+        .exec_to_vec::<_,Comentarios>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().next())
+            },
+            Err(e)=>{
+                println!("error get_by_id comentarios {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn get_page_by_tarea(id_tarea:&str,params:&ComentariosQuery,db:&Session)->Result<ComentariosPage,unreql::Error>{
+        let limite=params.limite();
+        let mut lower=rjson!([id_tarea.to_string(), r.minval()]);
+        let mut opts=BetweenOptions { index: Some("tarea_create_at".to_string()), ..Default::default() };
+        if let Some(Ok((create_at,id))) = params.posicion_cursor() {
+            lower=rjson!([id_tarea.to_string(), create_at, id]);
+            opts.left_bound=Some(Status::Open);
+        }
+        // se pide uno extra para saber si hay otra pagina
+        let query = r.db("tareas").table("comentarios")
+        .between(lower, rjson!([id_tarea.to_string(), r.maxval()]), opts)
+        .order_by(r.index("tarea_create_at"))
+        .limit(limite + 1)
+        .exec_to_vec::<_,Comentarios>(db);
+        match query.await {
+            Ok(mut res)=>{
+                let mut next_cursor=None;
+                if res.len() > limite as usize {
+                    res.truncate(limite as usize);
+                    if let Some(ultimo) = res.last() {
+                        next_cursor=Some(Utils::encode_cursor(&Value::Array(vec![Value::from(ultimo.create_at),Value::from(ultimo.id.clone())])));
+                    }
+                }
+                Ok(ComentariosPage { comentarios: res, next_cursor })
+            },
+            Err(e)=>{
+                println!("error get_page_by_tarea {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn add(comentario:&ComentariosHttp,id_tarea:&str,id_autor:&str,db:&Session)->Result<Option<Comentarios>,unreql::Error>{
+        let query = r.db("tareas").table("comentarios")
+        .insert(
+            r.with_opt(
+                rjson!({
+                    "id_tarea":id_tarea.to_string(),
+                    "id_padre":comentario.id_padre.clone(),
+                    "id_autor":id_autor.to_string(),
+                    "texto":comentario.texto.clone(),
+                    "create_at":Utils::current_timestamp(),
+                    "update_at":Utils::current_timestamp(),
+                }),
+                InsertOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                for change in res.changes.unwrap_or_default() {
+                    if let Some(new_val) = change.new_val {
+                        let comentario: Comentarios = Deserialize::deserialize(new_val)?;
+                        return Ok(Some(comentario));
+                    }
+                }
+                Ok(None)
+            },
+            Err(e)=>{
+                println!("error add comentario {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// solo el autor puede editar y no se editan comentarios eliminados
+    pub async fn edit(id:&str,texto:&str,id_autor:&str,db:&Session)->Result<bool,unreql::Error>{
+        let id_autor=id_autor.to_string();
+        let query = r.db("tareas").table("comentarios")
+        .get_all(id.to_string())
+        .filter(func!(|c| c.clone().g("id_autor").eq(id_autor).and(c.has_fields("deleted_at").not())))
+        .update(rjson!({
+            "texto":texto.to_string(),
+            "update_at":Utils::current_timestamp(),
+        }))
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.replaced > 0)
+            },
+            Err(e)=>{
+                println!("error edit comentario {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// borra el texto y marca el comentario como eliminado, las respuestas se conservan
+    pub async fn delete(id:&str,id_autor:&str,db:&Session)->Result<bool,unreql::Error>{
+        let id_autor=id_autor.to_string();
+        let query = r.db("tareas").table("comentarios")
+        .get_all(id.to_string())
+        .filter(func!(|c| c.clone().g("id_autor").eq(id_autor).and(c.has_fields("deleted_at").not())))
+        .update(rjson!({
+            "texto":"",
+            "deleted_at":Utils::current_timestamp(),
+            "update_at":Utils::current_timestamp(),
+        }))
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.replaced > 0)
+            },
+            Err(e)=>{
+                println!("error delete comentario {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// borra los comentarios de las tareas eliminadas definitivamente
+    pub async fn delete_by_tareas(ids_tareas:&[String],db:&Session)->Result<u32,unreql::Error>{
+        if ids_tareas.is_empty() {
+            return Ok(0);
+        }
+        let ids=ids_tareas.to_vec();
+        let query = r.db("tareas").table("comentarios")
+        .filter(func!(|c| r.expr(ids).contains(c.g("id_tarea"))))
+        .delete(DeleteOptions::default())
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.deleted)
+            },
+            Err(e)=>{
+                println!("error delete_by_tareas comentarios {}",e);
+                Err(e)
+            }
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use rocket::{http::Status, response::status::{self, Custom}, serde::json::Json, State};
+use unreql::Session;
+use validator::Validate;
+
+use crate::{api::{AdduserResponse, Permiso, Tareas, Usuarios}, utils::Utils, ClientesSockets, JwtGuard};
+
+use super::{CompartidoEdit, Compartidos, CompartidosHttp, CompartidosList};
+
+/// avisa por websocket al dueño y al usuario que recibio el acceso
+async fn notificar(compartido:&Compartidos,event:&str,clients:&ClientesSockets){
+    Utils::send_ws_to(clients, &[compartido.id_duenio.clone(),compartido.id_usuario.clone()], event).await;
+}
+
+#[utoipa::path(
+    get,
+    path = "/compartidos/",
+    responses(
+        (status = 200, description = "Accesos otorgados y recibidos por el usuario", body = CompartidosList),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="compartidos"
+)]
+#[get("/")]
+pub async fn get_compartidos(userClaims:JwtGuard,db:&State<Session>)->Result<Custom<Json<CompartidosList>>,status::Custom<Json<AdduserResponse>>>{
+    let otorgados=Compartidos::get_otorgados(&userClaims.id_usuario,db).await;
+    let recibidos=Compartidos::get_recibidos(&userClaims.id_usuario,db).await;
+    match (otorgados,recibidos) {
+        (Ok(otorgados),Ok(recibidos))=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(CompartidosList { otorgados, recibidos })
+                )
+            )
+        },
+        _=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/compartidos/",
+    responses(
+        (status = 201, description = "Acceso creado, o actualizado si ya existia para el mismo usuario y tarea", body = Compartidos),
+        (status = 400, description = "Datos invalidos, permiso `duenio` o compartir consigo mismo", body = AdduserResponse),
+        (status = 404, description = "Usuario o tarea no encontrados", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    request_body=CompartidosHttp,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="compartidos"
+)]
+#[post("/",data="<compartido>")]
+pub async fn add_compartido(db:&State<Session>,userClaims:JwtGuard,compartido:Json<CompartidosHttp>,clients:&State<ClientesSockets>)->Result<Custom<Json<Compartidos>>,status::Custom<Json<AdduserResponse>>>{
+    if compartido.validate().is_err() || compartido.permiso == Permiso::Duenio {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse { error: 3 }
+                )
+            )
+        );
+    }
+
+    // solo el dueño puede compartir una tarea
+    if let Some(id_tarea) = compartido.id_tarea.as_deref() {
+        let es_duenio=match Tareas::get_con_permiso(id_tarea,&userClaims.id_usuario,None,db).await {
+            Ok(res)=>res.is_some_and(|(_,permiso)| permiso == Permiso::Duenio),
+            Err(e)=>false
+        };
+        if !es_duenio {
+            return Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            );
+        }
+    }
+
+    let destino=Usuarios::filter_by_username(&compartido.username,db).await;
+    let duenio=Usuarios::get_by_id(&userClaims.id_usuario,db).await;
+    let (destino,duenio)=match (destino,duenio) {
+        (Ok(destino),Ok(Some(duenio)))=>{
+            match destino.into_iter().next() {
+                Some(destino)=>(destino,duenio),
+                None=>{
+                    return Err(
+                        Custom(
+                            Status::NotFound,
+                            Json(
+                                AdduserResponse{
+                                    error:1
+                                }
+                            )
+                        )
+                    );
+                }
+            }
+        },
+        _=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+    if destino.id == duenio.id {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse { error: 3 }
+                )
+            )
+        );
+    }
+
+    match Compartidos::upsert(&duenio.id,&duenio.username,&destino.id,&compartido,db).await {
+        Ok(Some(res))=>{
+            notificar(&res, "share", clients).await;
+            Ok(
+                Custom(
+                    Status::Created,
+                    Json(res)
+                )
+            )
+        },
+        _=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/compartidos/{id}",
+    responses(
+        (status = 200, description = "Permiso cambiado", body = String),
+        (status = 400, description = "Permiso invalido", body = AdduserResponse),
+        (status = 404, description = "Acceso no encontrado o no lo otorgo el usuario", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id del acceso compartido")
+    ),
+    request_body=CompartidoEdit,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="compartidos"
+)]
+#[put("/<id>",data="<edit>")]
+pub async fn change_permiso(db:&State<Session>,id:&str,userClaims:JwtGuard,edit:Json<CompartidoEdit>,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    if edit.permiso == Permiso::Duenio {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse { error: 3 }
+                )
+            )
+        );
+    }
+
+    let compartido=match Compartidos::get_by_id(id,db).await {
+        Ok(Some(compartido)) if compartido.id_duenio == userClaims.id_usuario=>compartido,
+        Ok(_)=>{
+            return Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            );
+        },
+        Err(e)=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+
+    match Compartidos::change_permiso(&compartido.id,edit.permiso,db).await {
+        Ok(true)=>{
+            notificar(&compartido, "share_update", clients).await;
+            Ok(
+                Custom(
+                    Status::Ok,
+                    "Ok ".to_string()
+                )
+            )
+        },
+        _=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/compartidos/{id}",
+    responses(
+        (status = 200, description = "Acceso revocado por el dueño o abandonado por quien lo recibio", body = String),
+        (status = 404, description = "Acceso no encontrado", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id del acceso compartido")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="compartidos"
+)]
+#[delete("/<id>")]
+pub async fn delete_compartido(db:&State<Session>,id:&str,userClaims:JwtGuard,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    let compartido=match Compartidos::get_by_id(id,db).await {
+        Ok(Some(compartido)) if compartido.id_duenio == userClaims.id_usuario || compartido.id_usuario == userClaims.id_usuario=>compartido,
+        Ok(_)=>{
+            return Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            );
+        },
+        Err(e)=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+
+    match Compartidos::delete(&compartido.id,db).await {
+        Ok(true)=>{
+            notificar(&compartido, "share_revoke", clients).await;
+            Ok(
+                Custom(
+                    Status::Ok,
+                    "Ok ".to_string()
+                )
+            )
+        },
+        _=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::Value;
+use unreql::{cmd::options::{DeleteOptions, InsertOptions}, func, r, rjson, types::WriteStatus, Session};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::utils::Utils;
+
+/// nivel de acceso sobre una tarea, ordenado de menor a mayor
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde",rename_all="lowercase")]
+pub enum Permiso{
+    /// puede ver la tarea, su historial, comentarios y adjuntos, y comentar
+    Lector,
+    /// ademas puede cambiar el estado, subir adjuntos y moverla a la papelera
+    Editor,
+    /// solo el creador de la tarea, no se puede otorgar
+    Duenio,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct CompartidosHttp{
+    /// usuario con quien se comparte
+    #[validate(length(min = 1))]
+    pub username:String,
+    pub permiso:Permiso,
+    /// tarea a compartir, `null` para compartir todas las tareas del usuario
+    #[serde(default)]
+    pub id_tarea:Option<String>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct CompartidoEdit{
+    pub permiso:Permiso,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Compartidos{
+    pub id:String,
+    /// dueño de las tareas compartidas
+    pub id_duenio:String,
+    pub username_duenio:String,
+    /// usuario que recibe el acceso
+    pub id_usuario:String,
+    pub username:String,
+    /// `null` cuando se comparten todas las tareas del dueño
+    #[serde(default)]
+    pub id_tarea:Option<String>,
+    pub permiso:Permiso,
+    pub create_at:i64,
+    pub update_at:i64,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct CompartidosList{
+    /// accesos que el usuario dio a otros
+    pub otorgados:Vec<Compartidos>,
+    /// accesos que otros le dieron al usuario
+    pub recibidos:Vec<Compartidos>,
+}
+
+impl Compartidos {
+    pub async fn create_indexes(db:&Session)->Result<(),unreql::Error>{
+        Utils::create_table_if_missing("compartidos", db).await?;
+        let existentes=r.db("tareas").table("compartidos")
+        .index_list()
+        .exec::<_,Vec<String>>(db)
+        .await?;
+        for nombre in ["id_duenio","id_usuario"] {
+            if existentes.iter().any(|i| i == nombre) {
+                continue;
+            }
+            let query = r.db("tareas").table("compartidos")
+            .index_create(nombre)
+            .exec::<_,Value>(db);
+            if let Err(e) = query.await {
+                println!("error create_indexes compartidos {} {}",nombre,e);
+                return Err(e);
+            }
+        }
+        if !existentes.iter().any(|i| i == "duenio_usuario") {
+            let query = r.db("tareas").table("compartidos")
+            .index_create(r.args((
+                "duenio_usuario",
+                func!(|c| [c.clone().g("id_duenio"), c.g("id_usuario")])
+            )))
+            .exec::<_,Value>(db);
+            if let Err(e) = query.await {
+                println!("error create_indexes compartidos duenio_usuario {}",e);
+                return Err(e);
+            }
+        }
+        r.db("tareas").table("compartidos").index_wait(()).exec::<_,Value>(db).await?;
+        Ok(())
+    }
+
+    pub async fn get_by_id(id:&str,db:&Session)->Result<Option<Compartidos>,unreql::Error>{
+        let query = r.db("tareas").table("compartidos")
+        .get(id.to_string())
+        .exec_to_vec::<_,Compartidos>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().next())
+            },
+            Err(e)=>{
+                println!("error get_by_id compartidos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn get_by_indice(indice:&str,id_usuario:&str,db:&Session)->Result<Vec<Compartidos>,unreql::Error>{
+        let query = r.db("tareas").table("compartidos")
+        .get_all(r.with_opt(id_usuario.to_string(), r.index(indice.to_string())))
+        .exec_to_vec::<_,Compartidos>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res)
+            },
+            Err(e)=>{
+                println!("error get_by_indice compartidos {} {}",indice,e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn get_otorgados(id_duenio:&str,db:&Session)->Result<Vec<Compartidos>,unreql::Error>{
+        Self::get_by_indice("id_duenio", id_duenio, db).await
+    }
+
+    pub async fn get_recibidos(id_usuario:&str,db:&Session)->Result<Vec<Compartidos>,unreql::Error>{
+        Self::get_by_indice("id_usuario", id_usuario, db).await
+    }
+
+    /// mayor permiso que `id_usuario` recibio sobre una tarea de `id_duenio`
+    pub async fn permiso_sobre(id_duenio:&str,id_tarea:&str,id_usuario:&str,db:&Session)->Result<Option<Permiso>,unreql::Error>{
+        let query = r.db("tareas").table("compartidos")
+        .get_all(r.with_opt(rjson!([id_duenio.to_string(), id_usuario.to_string()]), r.index("duenio_usuario")))
+        .exec_to_vec::<_,Compartidos>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(
+                    res.into_iter()
+                    .filter(|c| c.id_tarea.as_deref().is_none_or(|t| t == id_tarea))
+                    .map(|c| c.permiso)
+                    .max()
+                )
+            },
+            Err(e)=>{
+                println!("error permiso_sobre {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// usuarios (distintos del dueño) con acceso a la tarea
+    pub async fn usuarios_con_acceso(id_duenio:&str,id_tarea:&str,db:&Session)->Result<Vec<String>,unreql::Error>{
+        let mut usuarios:Vec<String>=Self::get_otorgados(id_duenio, db).await?
+            .into_iter()
+            .filter(|c| c.id_tarea.as_deref().is_none_or(|t| t == id_tarea))
+            .map(|c| c.id_usuario)
+            .collect();
+        usuarios.sort();
+        usuarios.dedup();
+        Ok(usuarios)
+    }
+
+    /// crea el acceso o actualiza el permiso si ya existia para el mismo usuario y alcance
+    pub async fn upsert(id_duenio:&str,username_duenio:&str,id_usuario:&str,compartido:&CompartidosHttp,db:&Session)->Result<Option<Compartidos>,unreql::Error>{
+        let existente=Self::get_by_indice("id_duenio", id_duenio, db).await?
+            .into_iter()
+            .find(|c| c.id_usuario == id_usuario && c.id_tarea == compartido.id_tarea);
+        if let Some(existente) = existente {
+            Self::change_permiso(&existente.id, compartido.permiso, db).await?;
+            return Self::get_by_id(&existente.id, db).await;
+        }
+        let query = r.db("tareas").table("compartidos")
+        .insert(
+            r.with_opt(
+                rjson!({
+                    "id_duenio":id_duenio.to_string(),
+                    "username_duenio":username_duenio.to_string(),
+                    "id_usuario":id_usuario.to_string(),
+                    "username":compartido.username.clone(),
+                    "id_tarea":compartido.id_tarea.clone(),
+                    "permiso":compartido.permiso,
+                    "create_at":Utils::current_timestamp(),
+                    "update_at":Utils::current_timestamp(),
+                }),
+                InsertOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                for change in res.changes.unwrap_or_default() {
+                    if let Some(new_val) = change.new_val {
+                        let compartido: Compartidos = Deserialize::deserialize(new_val)?;
+                        return Ok(Some(compartido));
+                    }
+                }
+                Ok(None)
+            },
+            Err(e)=>{
+                println!("error upsert compartidos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn change_permiso(id:&str,permiso:Permiso,db:&Session)->Result<bool,unreql::Error>{
+        let query = r.db("tareas").table("compartidos")
+        .get(id.to_string())
+        .update(rjson!({
+            "permiso":permiso,
+            "update_at":Utils::current_timestamp(),
+        }))
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.replaced > 0 || res.unchanged > 0)
+            },
+            Err(e)=>{
+                println!("error change_permiso {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn delete(id:&str,db:&Session)->Result<bool,unreql::Error>{
+        let query = r.db("tareas").table("compartidos")
+        .get(id.to_string())
+        .delete(())
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.deleted > 0)
+            },
+            Err(e)=>{
+                println!("error delete compartidos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// revoca los accesos a tareas que se purgaron
+    pub async fn delete_by_tareas(ids_tareas:&[String],db:&Session)->Result<u32,unreql::Error>{
+        if ids_tareas.is_empty() {
+            return Ok(0);
+        }
+        let ids=ids_tareas.to_vec();
+        let query = r.db("tareas").table("compartidos")
+        .filter(func!(|c| r.expr(ids).contains(c.g("id_tarea"))))
+        .delete(DeleteOptions::default())
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.deleted)
+            },
+            Err(e)=>{
+                println!("error delete_by_tareas compartidos {}",e);
+                Err(e)
+            }
+        }
+    }
+}
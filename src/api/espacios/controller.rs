@@ -0,0 +1,719 @@
+use rocket::{http::Status, response::status::{self, Custom}, serde::json::Json, State};
+use unreql::Session;
+use validator::Validate;
+
+use crate::{api::{AdduserResponse, Usuarios}, utils::Utils, ClientesSockets, JwtGuard};
+
+use super::{EspacioUsuario, Espacios, EspaciosHttp, Invitaciones, InvitacionesHttp, MiembroEdit, Miembros, Rol};
+
+/// rol del usuario en el espacio si alcanza `minimo`,
+/// 404 si no es miembro y 403 si su rol no alcanza
+async fn rol_en(id_espacio:&str,id_usuario:&str,minimo:Rol,db:&Session)->Result<Rol,status::Custom<Json<AdduserResponse>>>{
+    match Espacios::rol_de(id_espacio,id_usuario,db).await {
+        Ok(Some(rol)) if rol >= minimo=>Ok(rol),
+        Ok(Some(_))=>{
+            Err(
+                Custom(
+                    Status::Forbidden,
+                    Json(
+                        AdduserResponse{
+                            error:16
+                        }
+                    )
+                )
+            )
+        },
+        Ok(None)=>{
+            Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+/// avisa por websocket a todos los miembros del espacio y, si se indica, a un usuario mas
+async fn notificar(id_espacio:&str,extra:Option<&str>,event:&str,db:&Session,clients:&ClientesSockets){
+    let mut destinatarios=Espacios::ids_miembros(id_espacio, db).await.unwrap_or_default();
+    if let Some(extra) = extra {
+        destinatarios.push(extra.to_string());
+    }
+    Utils::send_ws_to(clients, &destinatarios, event).await;
+}
+
+#[utoipa::path(
+    get,
+    path = "/espacios/",
+    responses(
+        (status = 200, description = "Espacios de los que el usuario es miembro, con su rol", body = Vec<EspacioUsuario>),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="espacios"
+)]
+#[get("/")]
+pub async fn get_espacios(userClaims:JwtGuard,db:&State<Session>)->Result<Custom<Json<Vec<EspacioUsuario>>>,status::Custom<Json<AdduserResponse>>>{
+    match Espacios::get_by_usuario(&userClaims.id_usuario,db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/espacios/",
+    responses(
+        (status = 201, description = "Espacio creado con el usuario como dueño", body = Espacios),
+        (status = 400, description = "Datos invalidos", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    request_body=EspaciosHttp,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="espacios"
+)]
+#[post("/",data="<espacio>")]
+pub async fn add_espacio(db:&State<Session>,userClaims:JwtGuard,espacio:Json<EspaciosHttp>)->Result<Custom<Json<Espacios>>,status::Custom<Json<AdduserResponse>>>{
+    if let Err(errors) = espacio.validate(){
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse { error: 3 }
+                )
+            )
+        );
+    }
+
+    let usuario=match Usuarios::get_by_id(&userClaims.id_usuario,db).await {
+        Ok(Some(usuario))=>usuario,
+        _=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+
+    match Espacios::add(&espacio,&usuario.id,&usuario.username,db).await {
+        Ok(Some(res))=>{
+            Ok(
+                Custom(
+                    Status::Created,
+                    Json(res)
+                )
+            )
+        },
+        _=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/espacios/{id}/miembros",
+    responses(
+        (status = 200, description = "Miembros del espacio", body = Vec<Miembros>),
+        (status = 404, description = "Espacio no encontrado o el usuario no es miembro", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id del espacio")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="espacios"
+)]
+#[get("/<id>/miembros")]
+pub async fn get_miembros_espacio(userClaims:JwtGuard,db:&State<Session>,id:&str)->Result<Custom<Json<Vec<Miembros>>>,status::Custom<Json<AdduserResponse>>>{
+    rol_en(id,&userClaims.id_usuario,Rol::Miembro,db).await?;
+    match Espacios::get_miembros(id,db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/espacios/{id}/miembros/{id_usuario}",
+    responses(
+        (status = 200, description = "Rol cambiado", body = String),
+        (status = 400, description = "No se puede otorgar ni cambiar el rol de dueño", body = AdduserResponse),
+        (status = 403, description = "Solo admins y el dueño cambian roles", body = AdduserResponse),
+        (status = 404, description = "Espacio o miembro no encontrado", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id del espacio"),
+        ("id_usuario" =String, Path, description = "id del miembro")
+    ),
+    request_body=MiembroEdit,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="espacios"
+)]
+#[put("/<id>/miembros/<id_usuario>",data="<edit>")]
+pub async fn change_rol_miembro(db:&State<Session>,id:&str,id_usuario:&str,userClaims:JwtGuard,edit:Json<MiembroEdit>,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    rol_en(id,&userClaims.id_usuario,Rol::Admin,db).await?;
+    let actual=match Espacios::rol_de(id,id_usuario,db).await {
+        Ok(Some(rol))=>rol,
+        Ok(None)=>{
+            return Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            );
+        },
+        Err(e)=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+    if actual == Rol::Duenio || edit.rol == Rol::Duenio {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse { error: 3 }
+                )
+            )
+        );
+    }
+
+    match Espacios::change_rol(id,id_usuario,edit.rol,db).await {
+        Ok(true)=>{
+            notificar(id, None, "member_update", db, clients).await;
+            Ok(
+                Custom(
+                    Status::Ok,
+                    "Ok ".to_string()
+                )
+            )
+        },
+        _=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/espacios/{id}/miembros/{id_usuario}",
+    responses(
+        (status = 200, description = "Miembro quitado, o el usuario abandono el espacio", body = String),
+        (status = 400, description = "El dueño no puede salir del espacio", body = AdduserResponse),
+        (status = 403, description = "Solo admins y el dueño quitan a otros miembros", body = AdduserResponse),
+        (status = 404, description = "Espacio o miembro no encontrado", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id del espacio"),
+        ("id_usuario" =String, Path, description = "id del miembro")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="espacios"
+)]
+#[delete("/<id>/miembros/<id_usuario>")]
+pub async fn delete_miembro_espacio(db:&State<Session>,id:&str,id_usuario:&str,userClaims:JwtGuard,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    // cualquiera puede salir, solo admins y el dueño quitan a otros
+    let minimo=if id_usuario == userClaims.id_usuario { Rol::Miembro } else { Rol::Admin };
+    rol_en(id,&userClaims.id_usuario,minimo,db).await?;
+    match Espacios::rol_de(id,id_usuario,db).await {
+        Ok(Some(Rol::Duenio))=>{
+            return Err(
+                Custom(
+                    Status::BadRequest,
+                    Json(
+                        AdduserResponse { error: 3 }
+                    )
+                )
+            );
+        },
+        Ok(Some(_))=>{},
+        Ok(None)=>{
+            return Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            );
+        },
+        Err(e)=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    }
+
+    match Espacios::delete_miembro(id,id_usuario,db).await {
+        Ok(true)=>{
+            notificar(id, Some(id_usuario), "member_remove", db, clients).await;
+            Ok(
+                Custom(
+                    Status::Ok,
+                    "Ok ".to_string()
+                )
+            )
+        },
+        _=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/espacios/{id}/invitaciones",
+    responses(
+        (status = 200, description = "Invitaciones pendientes del espacio", body = Vec<Invitaciones>),
+        (status = 403, description = "Solo admins y el dueño ven las invitaciones", body = AdduserResponse),
+        (status = 404, description = "Espacio no encontrado o el usuario no es miembro", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id del espacio")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="espacios"
+)]
+#[get("/<id>/invitaciones")]
+pub async fn get_invitaciones_espacio(userClaims:JwtGuard,db:&State<Session>,id:&str)->Result<Custom<Json<Vec<Invitaciones>>>,status::Custom<Json<AdduserResponse>>>{
+    rol_en(id,&userClaims.id_usuario,Rol::Admin,db).await?;
+    match Invitaciones::get_by_indice("id_espacio",id,db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/espacios/{id}/invitaciones",
+    responses(
+        (status = 201, description = "Invitacion creada, o actualizada si ya habia una pendiente", body = Invitaciones),
+        (status = 400, description = "Datos invalidos, rol de dueño o el usuario ya es miembro", body = AdduserResponse),
+        (status = 403, description = "Solo admins y el dueño invitan", body = AdduserResponse),
+        (status = 404, description = "Espacio o usuario no encontrado", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id del espacio")
+    ),
+    request_body=InvitacionesHttp,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="espacios"
+)]
+#[post("/<id>/invitaciones",data="<invitacion>")]
+pub async fn add_invitacion(db:&State<Session>,id:&str,userClaims:JwtGuard,invitacion:Json<InvitacionesHttp>,clients:&State<ClientesSockets>)->Result<Custom<Json<Invitaciones>>,status::Custom<Json<AdduserResponse>>>{
+    if invitacion.validate().is_err() || invitacion.rol == Rol::Duenio {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse { error: 3 }
+                )
+            )
+        );
+    }
+    rol_en(id,&userClaims.id_usuario,Rol::Admin,db).await?;
+
+    let espacio=Espacios::get_by_id(id,db).await;
+    let invitado=Usuarios::filter_by_username(&invitacion.username,db).await;
+    let (espacio,invitado)=match (espacio,invitado) {
+        (Ok(Some(espacio)),Ok(invitado))=>{
+            match invitado.into_iter().next() {
+                Some(invitado)=>(espacio,invitado),
+                None=>{
+                    return Err(
+                        Custom(
+                            Status::NotFound,
+                            Json(
+                                AdduserResponse{
+                                    error:1
+                                }
+                            )
+                        )
+                    );
+                }
+            }
+        },
+        _=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+    if !matches!(Espacios::rol_de(id,&invitado.id,db).await, Ok(None)) {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse { error: 3 }
+                )
+            )
+        );
+    }
+
+    match Invitaciones::upsert(&espacio,&invitado.id,&invitacion,&userClaims.id_usuario,db).await {
+        Ok(Some(res))=>{
+            Utils::send_ws_to(clients, std::slice::from_ref(&invitado.id), "invite").await;
+            Ok(
+                Custom(
+                    Status::Created,
+                    Json(res)
+                )
+            )
+        },
+        _=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/espacios/invitaciones",
+    responses(
+        (status = 200, description = "Invitaciones pendientes recibidas por el usuario", body = Vec<Invitaciones>),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="espacios"
+)]
+#[get("/invitaciones")]
+pub async fn get_invitaciones(userClaims:JwtGuard,db:&State<Session>)->Result<Custom<Json<Vec<Invitaciones>>>,status::Custom<Json<AdduserResponse>>>{
+    match Invitaciones::get_by_indice("id_usuario",&userClaims.id_usuario,db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/espacios/invitaciones/{id}",
+    responses(
+        (status = 200, description = "Invitacion aceptada, el usuario ya es miembro", body = String),
+        (status = 404, description = "Invitacion no encontrada o no es del usuario", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la invitacion")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="espacios"
+)]
+#[put("/invitaciones/<id>")]
+pub async fn aceptar_invitacion(db:&State<Session>,id:&str,userClaims:JwtGuard,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    let invitacion=match Invitaciones::get_by_id(id,db).await {
+        Ok(Some(invitacion)) if invitacion.id_usuario == userClaims.id_usuario=>invitacion,
+        Ok(_)=>{
+            return Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            );
+        },
+        Err(e)=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+
+    match invitacion.aceptar(db).await {
+        Ok(_)=>{
+            notificar(&invitacion.id_espacio, None, "member_join", db, clients).await;
+            Ok(
+                Custom(
+                    Status::Ok,
+                    "Ok ".to_string()
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/espacios/invitaciones/{id}",
+    responses(
+        (status = 200, description = "Invitacion rechazada por el invitado o cancelada por un admin", body = String),
+        (status = 404, description = "Invitacion no encontrada", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la invitacion")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="espacios"
+)]
+#[delete("/invitaciones/<id>")]
+pub async fn delete_invitacion(db:&State<Session>,id:&str,userClaims:JwtGuard)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    let invitacion=match Invitaciones::get_by_id(id,db).await {
+        Ok(Some(invitacion))=>invitacion,
+        Ok(None)=>{
+            return Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            );
+        },
+        Err(e)=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+    if invitacion.id_usuario != userClaims.id_usuario {
+        rol_en(&invitacion.id_espacio,&userClaims.id_usuario,Rol::Admin,db).await?;
+    }
+
+    match Invitaciones::delete(&invitacion.id,db).await {
+        Ok(_)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    "Ok ".to_string()
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
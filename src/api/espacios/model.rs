@@ -0,0 +1,402 @@
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::Value;
+use unreql::{cmd::options::InsertOptions, func, r, rjson, types::WriteStatus, Session};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::utils::Utils;
+
+/// rol de un usuario dentro de un espacio, ordenado de menor a mayor
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde",rename_all="lowercase")]
+pub enum Rol{
+    /// ve y edita las tareas del espacio
+    Miembro,
+    /// ademas invita, cambia roles y quita miembros
+    Admin,
+    /// quien creo el espacio, no se puede otorgar ni quitar
+    Duenio,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct EspaciosHttp{
+    #[validate(length(min = 1, max = 100))]
+    pub nombre:String,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Espacios{
+    pub id:String,
+    pub nombre:String,
+    pub id_duenio:String,
+    pub create_at:i64,
+    pub update_at:i64,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct EspacioUsuario{
+    pub espacio:Espacios,
+    /// rol del usuario que consulta
+    pub rol:Rol,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Miembros{
+    pub id:String,
+    pub id_espacio:String,
+    pub id_usuario:String,
+    pub username:String,
+    pub rol:Rol,
+    pub create_at:i64,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct MiembroEdit{
+    pub rol:Rol,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct InvitacionesHttp{
+    #[validate(length(min = 1))]
+    pub username:String,
+    pub rol:Rol,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Invitaciones{
+    pub id:String,
+    pub id_espacio:String,
+    pub nombre_espacio:String,
+    /// usuario invitado
+    pub id_usuario:String,
+    pub username:String,
+    pub rol:Rol,
+    pub id_invitador:String,
+    pub create_at:i64,
+}
+
+impl Espacios {
+    pub async fn create_indexes(db:&Session)->Result<(),unreql::Error>{
+        Utils::create_table_if_missing("espacios", db).await?;
+        for tabla in ["espacios_miembros","espacios_invitaciones"] {
+            Utils::create_table_if_missing(tabla, db).await?;
+            let existentes=r.db("tareas").table(tabla)
+            .index_list()
+            .exec::<_,Vec<String>>(db)
+            .await?;
+            for nombre in ["id_espacio","id_usuario"] {
+                if existentes.iter().any(|i| i == nombre) {
+                    continue;
+                }
+                let query = r.db("tareas").table(tabla)
+                .index_create(nombre)
+                .exec::<_,Value>(db);
+                if let Err(e) = query.await {
+                    println!("error create_indexes {} {} {}",tabla,nombre,e);
+                    return Err(e);
+                }
+            }
+            if !existentes.iter().any(|i| i == "espacio_usuario") {
+                let query = r.db("tareas").table(tabla)
+                .index_create(r.args((
+                    "espacio_usuario",
+                    func!(|m| [m.clone().g("id_espacio"), m.g("id_usuario")])
+                )))
+                .exec::<_,Value>(db);
+                if let Err(e) = query.await {
+                    println!("error create_indexes {} espacio_usuario {}",tabla,e);
+                    return Err(e);
+                }
+            }
+            r.db("tareas").table(tabla).index_wait(()).exec::<_,Value>(db).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_by_id(id:&str,db:&Session)->Result<Option<Espacios>,unreql::Error>{
+        let query = r.db("tareas").table("espacios")
+        .get(id.to_string())
+        .exec_to_vec::<_,Espacios>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().next())
+            },
+            Err(e)=>{
+                println!("error get_by_id espacios {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// crea el espacio con el usuario como dueño
+    pub async fn add(espacio:&EspaciosHttp,id_usuario:&str,username:&str,db:&Session)->Result<Option<Espacios>,unreql::Error>{
+        let query = r.db("tareas").table("espacios")
+        .insert(
+            r.with_opt(
+                rjson!({
+                    "nombre":espacio.nombre.clone(),
+                    "id_duenio":id_usuario.to_string(),
+                    "create_at":Utils::current_timestamp(),
+                    "update_at":Utils::current_timestamp(),
+                }),
+                InsertOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        let res = match query.await {
+            Ok(res)=>res,
+            Err(e)=>{
+                println!("error add espacios {}",e);
+                return Err(e);
+            }
+        };
+        for change in res.changes.unwrap_or_default() {
+            if let Some(new_val) = change.new_val {
+                let espacio: Espacios = Deserialize::deserialize(new_val)?;
+                Self::add_miembro(&espacio.id, id_usuario, username, Rol::Duenio, db).await?;
+                return Ok(Some(espacio));
+            }
+        }
+        Ok(None)
+    }
+
+    /// espacios de los que el usuario es miembro, con su rol
+    pub async fn get_by_usuario(id_usuario:&str,db:&Session)->Result<Vec<EspacioUsuario>,unreql::Error>{
+        let miembros=r.db("tareas").table("espacios_miembros")
+        .get_all(r.with_opt(id_usuario.to_string(), r.index("id_usuario")))
+        .exec_to_vec::<_,Miembros>(db);
+        let miembros = match miembros.await {
+            Ok(res)=>res,
+            Err(e)=>{
+                println!("error get_by_usuario espacios {}",e);
+                return Err(e);
+            }
+        };
+        if miembros.is_empty() {
+            return Ok(vec![]);
+        }
+        let ids:Vec<String>=miembros.iter().map(|m| m.id_espacio.clone()).collect();
+        let query = r.db("tareas").table("espacios")
+        .get_all(r.args(ids))
+        .exec_to_vec::<_,Espacios>(db);
+        match query.await {
+            Ok(espacios)=>{
+                let mut res:Vec<EspacioUsuario>=espacios.into_iter()
+                    .filter_map(|espacio| {
+                        let rol=miembros.iter().find(|m| m.id_espacio == espacio.id)?.rol;
+                        Some(EspacioUsuario { espacio, rol })
+                    })
+                    .collect();
+                res.sort_by(|a,b| a.espacio.nombre.cmp(&b.espacio.nombre));
+                Ok(res)
+            },
+            Err(e)=>{
+                println!("error get_by_usuario espacios {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn get_miembro(id_espacio:&str,id_usuario:&str,db:&Session)->Result<Option<Miembros>,unreql::Error>{
+        let query = r.db("tareas").table("espacios_miembros")
+        .get_all(r.with_opt(rjson!([id_espacio.to_string(), id_usuario.to_string()]), r.index("espacio_usuario")))
+        .exec_to_vec::<_,Miembros>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().next())
+            },
+            Err(e)=>{
+                println!("error get_miembro {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// rol del usuario en el espacio, `None` si no es miembro
+    pub async fn rol_de(id_espacio:&str,id_usuario:&str,db:&Session)->Result<Option<Rol>,unreql::Error>{
+        Ok(Self::get_miembro(id_espacio, id_usuario, db).await?.map(|m| m.rol))
+    }
+
+    pub async fn get_miembros(id_espacio:&str,db:&Session)->Result<Vec<Miembros>,unreql::Error>{
+        let query = r.db("tareas").table("espacios_miembros")
+        .get_all(r.with_opt(id_espacio.to_string(), r.index("id_espacio")))
+        .order_by("create_at")
+        .exec_to_vec::<_,Miembros>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res)
+            },
+            Err(e)=>{
+                println!("error get_miembros {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// ids de todos los miembros, para las notificaciones del espacio
+    pub async fn ids_miembros(id_espacio:&str,db:&Session)->Result<Vec<String>,unreql::Error>{
+        Ok(Self::get_miembros(id_espacio, db).await?.into_iter().map(|m| m.id_usuario).collect())
+    }
+
+    async fn add_miembro(id_espacio:&str,id_usuario:&str,username:&str,rol:Rol,db:&Session)->Result<bool,unreql::Error>{
+        let query = r.db("tareas").table("espacios_miembros")
+        .insert(rjson!({
+            "id_espacio":id_espacio.to_string(),
+            "id_usuario":id_usuario.to_string(),
+            "username":username.to_string(),
+            "rol":rol,
+            "create_at":Utils::current_timestamp(),
+        }))
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.inserted > 0)
+            },
+            Err(e)=>{
+                println!("error add_miembro {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn change_rol(id_espacio:&str,id_usuario:&str,rol:Rol,db:&Session)->Result<bool,unreql::Error>{
+        let query = r.db("tareas").table("espacios_miembros")
+        .get_all(r.with_opt(rjson!([id_espacio.to_string(), id_usuario.to_string()]), r.index("espacio_usuario")))
+        .update(rjson!({
+            "rol":rol,
+        }))
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.replaced > 0 || res.unchanged > 0)
+            },
+            Err(e)=>{
+                println!("error change_rol {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn delete_miembro(id_espacio:&str,id_usuario:&str,db:&Session)->Result<bool,unreql::Error>{
+        let query = r.db("tareas").table("espacios_miembros")
+        .get_all(r.with_opt(rjson!([id_espacio.to_string(), id_usuario.to_string()]), r.index("espacio_usuario")))
+        .delete(())
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.deleted > 0)
+            },
+            Err(e)=>{
+                println!("error delete_miembro {}",e);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Invitaciones {
+    pub async fn get_by_id(id:&str,db:&Session)->Result<Option<Invitaciones>,unreql::Error>{
+        let query = r.db("tareas").table("espacios_invitaciones")
+        .get(id.to_string())
+        .exec_to_vec::<_,Invitaciones>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().next())
+            },
+            Err(e)=>{
+                println!("error get_by_id invitaciones {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn get_by_indice(indice:&str,valor:&str,db:&Session)->Result<Vec<Invitaciones>,unreql::Error>{
+        let query = r.db("tareas").table("espacios_invitaciones")
+        .get_all(r.with_opt(valor.to_string(), r.index(indice.to_string())))
+        .order_by("create_at")
+        .exec_to_vec::<_,Invitaciones>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res)
+            },
+            Err(e)=>{
+                println!("error get_by_indice invitaciones {} {}",indice,e);
+                Err(e)
+            }
+        }
+    }
+
+    /// crea la invitacion o actualiza el rol si ya habia una pendiente
+    pub async fn upsert(espacio:&Espacios,id_usuario:&str,invitacion:&InvitacionesHttp,id_invitador:&str,db:&Session)->Result<Option<Invitaciones>,unreql::Error>{
+        let query = r.db("tareas").table("espacios_invitaciones")
+        .get_all(r.with_opt(rjson!([espacio.id.clone(), id_usuario.to_string()]), r.index("espacio_usuario")))
+        .delete(())
+        .exec::<_,WriteStatus>(db);
+        if let Err(e) = query.await {
+            println!("error upsert invitaciones {}",e);
+            return Err(e);
+        }
+        let query = r.db("tareas").table("espacios_invitaciones")
+        .insert(
+            r.with_opt(
+                rjson!({
+                    "id_espacio":espacio.id.clone(),
+                    "nombre_espacio":espacio.nombre.clone(),
+                    "id_usuario":id_usuario.to_string(),
+                    "username":invitacion.username.clone(),
+                    "rol":invitacion.rol,
+                    "id_invitador":id_invitador.to_string(),
+                    "create_at":Utils::current_timestamp(),
+                }),
+                InsertOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                for change in res.changes.unwrap_or_default() {
+                    if let Some(new_val) = change.new_val {
+                        let invitacion: Invitaciones = Deserialize::deserialize(new_val)?;
+                        return Ok(Some(invitacion));
+                    }
+                }
+                Ok(None)
+            },
+            Err(e)=>{
+                println!("error upsert invitaciones {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// agrega al invitado como miembro y borra la invitacion
+    pub async fn aceptar(&self,db:&Session)->Result<bool,unreql::Error>{
+        if Espacios::get_miembro(&self.id_espacio, &self.id_usuario, db).await?.is_none() {
+            Espacios::add_miembro(&self.id_espacio, &self.id_usuario, &self.username, self.rol, db).await?;
+        }
+        Self::delete(&self.id, db).await
+    }
+
+    pub async fn delete(id:&str,db:&Session)->Result<bool,unreql::Error>{
+        let query = r.db("tareas").table("espacios_invitaciones")
+        .get(id.to_string())
+        .delete(())
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.deleted > 0)
+            },
+            Err(e)=>{
+                println!("error delete invitaciones {}",e);
+                Err(e)
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use rocket::{http::Status, response::status::{self, Custom}, serde::json::Json, State};
+use unreql::Session;
+
+use crate::{api::{AdduserResponse, Espacios, Rol}, utils::Utils, ClientesSockets, JwtGuard};
+
+use super::{Flujos, FlujosHttp};
+
+#[utoipa::path(
+    get,
+    path = "/flujo/",
+    responses(
+        (status = 200, description = "Flujo del espacio activo o el personal del usuario; el de por defecto si no se definio uno", body = Flujos),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="flujos"
+)]
+#[get("/")]
+pub async fn get_flujo(userClaims:JwtGuard,db:&State<Session>)->Result<Custom<Json<Flujos>>,status::Custom<Json<AdduserResponse>>>{
+    match Flujos::get_para(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/flujo/",
+    responses(
+        (status = 200, description = "Flujo guardado", body = Flujos),
+        (status = 400, description = "Estados invalidos, claves repetidas o transiciones a estados inexistentes", body = AdduserResponse),
+        (status = 403, description = "En un espacio solo admin o dueño pueden cambiar el flujo", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    request_body=FlujosHttp,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="flujos"
+)]
+#[put("/",data="<flujo>")]
+pub async fn set_flujo(db:&State<Session>,userClaims:JwtGuard,flujo:Json<FlujosHttp>,clients:&State<ClientesSockets>)->Result<Custom<Json<Flujos>>,status::Custom<Json<AdduserResponse>>>{
+    if !flujo.is_valid() {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse { error: 3 }
+                )
+            )
+        );
+    }
+    if userClaims.id_espacio.is_some() && userClaims.rol < Some(Rol::Admin) {
+        return Err(
+            Custom(
+                Status::Forbidden,
+                Json(
+                    AdduserResponse{
+                        error:16
+                    }
+                )
+            )
+        );
+    }
+
+    match Flujos::guardar(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),&flujo,db).await {
+        Ok(res)=>{
+            let destinatarios=match userClaims.id_espacio.as_deref() {
+                Some(id_espacio)=>Espacios::ids_miembros(id_espacio,db).await.unwrap_or_default(),
+                None=>vec![userClaims.id_usuario.clone()],
+            };
+            Utils::send_ws_to(clients, &destinatarios, "workflow").await;
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
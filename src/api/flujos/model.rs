@@ -0,0 +1,226 @@
+use std::collections::HashSet;
+
+use rocket::serde::{Deserialize, Serialize};
+use unreql::{cmd::options::{Conflict, InsertOptions}, r, rjson, types::WriteStatus, Session};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{api::Tareas, utils::Utils};
+
+/// estados del flujo por defecto, el primero es el de las tareas nuevas y el ultimo el de las completadas
+const ESTADOS_DEFAULT:[(&str,&str);4]=[
+    ("backlog","Backlog"),
+    ("doing","En curso"),
+    ("review","En revision"),
+    ("done","Hecho"),
+];
+
+#[derive(Debug,Clone,PartialEq,Eq,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct EstadoFlujo{
+    /// identificador que se guarda en `etapa` de las tareas
+    #[validate(length(min = 1, max = 40))]
+    pub clave:String,
+    #[validate(length(min = 1, max = 100))]
+    pub nombre:String,
+    /// maximo de tareas activas en este estado, sin limite si es `null`
+    #[serde(default)]
+    pub limite_wip:Option<u32>,
+}
+
+#[derive(Debug,Clone,PartialEq,Eq,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct TransicionFlujo{
+    pub desde:String,
+    pub hasta:String,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct FlujosHttp{
+    /// estados en orden; el primero es el inicial y el ultimo el de tarea completada
+    #[validate(length(min = 2, max = 20), nested)]
+    pub estados:Vec<EstadoFlujo>,
+    /// transiciones permitidas, vacio para permitir cualquiera
+    #[serde(default)]
+    pub transiciones:Vec<TransicionFlujo>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct EtapaHttp{
+    /// clave del estado del flujo al que se mueve la tarea
+    pub etapa:String,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Flujos{
+    /// `usuario:<id>` para el flujo personal o `espacio:<id>` para el de un espacio
+    pub id:String,
+    pub estados:Vec<EstadoFlujo>,
+    #[serde(default)]
+    pub transiciones:Vec<TransicionFlujo>,
+    pub update_at:i64,
+}
+
+impl FlujosHttp {
+    /// claves unicas y transiciones entre estados que existen
+    pub fn is_valid(&self)->bool{
+        if self.validate().is_err() {
+            return false;
+        }
+        let mut claves=HashSet::new();
+        if !self.estados.iter().all(|e| claves.insert(e.clave.as_str())) {
+            return false;
+        }
+        self.transiciones.iter().all(|t| claves.contains(t.desde.as_str()) && claves.contains(t.hasta.as_str()))
+    }
+}
+
+impl Flujos {
+    pub fn id_para(id_usuario:&str,id_espacio:Option<&str>)->String{
+        match id_espacio {
+            Some(id_espacio)=>format!("espacio:{}",id_espacio),
+            None=>format!("usuario:{}",id_usuario),
+        }
+    }
+
+    pub fn default_para(id:String)->Flujos{
+        Flujos {
+            id,
+            estados: ESTADOS_DEFAULT.iter()
+                .map(|(clave,nombre)| EstadoFlujo { clave: clave.to_string(), nombre: nombre.to_string(), limite_wip: None })
+                .collect(),
+            transiciones: vec![],
+            update_at: 0,
+        }
+    }
+
+    /// clave del estado inicial del flujo por defecto, la de las tareas migradas con `estado: false`
+    pub fn etapa_inicial_default()->&'static str{
+        ESTADOS_DEFAULT[0].0
+    }
+
+    /// clave del estado final del flujo por defecto, la de las tareas migradas con `estado: true`
+    pub fn etapa_final_default()->&'static str{
+        ESTADOS_DEFAULT[ESTADOS_DEFAULT.len() - 1].0
+    }
+
+    pub fn inicial(&self)->&EstadoFlujo{
+        &self.estados[0]
+    }
+
+    pub fn final_(&self)->&EstadoFlujo{
+        &self.estados[self.estados.len() - 1]
+    }
+
+    pub fn estado(&self,clave:&str)->Option<&EstadoFlujo>{
+        self.estados.iter().find(|e| e.clave == clave)
+    }
+
+    /// etapa de una tarea dentro de este flujo; si la etapa no existe en el
+    /// flujo se usa el estado inicial o el final segun `completada`
+    pub fn etapa_de<'a>(&'a self,etapa:Option<&str>,completada:bool)->&'a EstadoFlujo{
+        match etapa.and_then(|e| self.estado(e)) {
+            Some(estado)=>estado,
+            None if completada=>self.final_(),
+            None=>self.inicial(),
+        }
+    }
+
+    pub fn permite(&self,desde:&str,hasta:&str)->bool{
+        desde == hasta
+            || self.transiciones.is_empty()
+            || self.transiciones.iter().any(|t| t.desde == desde && t.hasta == hasta)
+    }
+
+    pub async fn create_indexes(db:&Session)->Result<(),unreql::Error>{
+        Utils::create_table_if_missing("flujos", db).await
+    }
+
+    /// flujo del espacio activo o el personal del usuario, el de por defecto si no definio uno
+    pub async fn get_para(id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Flujos,unreql::Error>{
+        let id=Self::id_para(id_usuario, id_espacio);
+        let query = r.db("tareas").table("flujos")
+        .get(id.clone())
+        .exec_to_vec::<_,Flujos>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().next().unwrap_or_else(|| Self::default_para(id)))
+            },
+            Err(e)=>{
+                println!("error get_para flujos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn guardar(id_usuario:&str,id_espacio:Option<&str>,flujo:&FlujosHttp,db:&Session)->Result<Flujos,unreql::Error>{
+        let flujo=Flujos {
+            id: Self::id_para(id_usuario, id_espacio),
+            estados: flujo.estados.clone(),
+            transiciones: flujo.transiciones.clone(),
+            update_at: Utils::current_timestamp(),
+        };
+        let query = r.db("tareas").table("flujos")
+        .insert(
+            r.with_opt(
+                rjson!({
+                    "id":flujo.id.clone(),
+                    "estados":flujo.estados.clone(),
+                    "transiciones":flujo.transiciones.clone(),
+                    "update_at":flujo.update_at,
+                }),
+                InsertOptions { conflict: Some(Conflict::Replace), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(_)=>{
+                Ok(flujo)
+            },
+            Err(e)=>{
+                println!("error guardar flujos {}",e);
+                Err(e)
+            }
+        }
+    }
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct ColumnaTablero{
+    pub estado:EstadoFlujo,
+    pub total:usize,
+    /// tareas de la columna en el orden manual
+    pub tareas:Vec<Tareas>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Tablero{
+    pub flujo:Flujos,
+    pub columnas:Vec<ColumnaTablero>,
+}
+
+impl Tablero {
+    /// agrupa las tareas por estado en el orden del flujo; las que estan en un estado
+    /// que el flujo no tiene van a la primera o ultima columna segun `estado`
+    pub fn construir(flujo:Flujos,tareas:Vec<Tareas>)->Tablero{
+        let mut columnas:Vec<ColumnaTablero>=flujo.estados.iter()
+            .map(|estado| ColumnaTablero { estado: estado.clone(), total: 0, tareas: vec![] })
+            .collect();
+        for tarea in tareas {
+            let clave=&flujo.etapa_de(tarea.etapa.as_deref(), tarea.estado).clave;
+            if let Some(columna) = columnas.iter_mut().find(|c| &c.estado.clave == clave) {
+                columna.tareas.push(tarea);
+            }
+        }
+        for columna in columnas.iter_mut() {
+            columna.tareas.sort_by(|a,b| a.posicion.cmp(&b.posicion).then_with(|| a.id.cmp(&b.id)));
+            columna.total=columna.tareas.len();
+        }
+        Tablero { flujo, columnas }
+    }
+}
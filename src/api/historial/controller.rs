@@ -0,0 +1,80 @@
+use rocket::{http::Status, response::status::{self, Custom}, serde::json::Json, State};
+use unreql::Session;
+
+use crate::{api::{AdduserResponse, Tareas}, JwtGuard};
+
+use super::Historial;
+
+#[utoipa::path(
+    get,
+    path = "/tareas/{id}/historial",
+    responses(
+        (status = 200, description = "Historial de cambios de la tarea, del mas antiguo al mas reciente", body = Vec<Historial>),
+        (status = 404, description = "La tarea no tiene historial", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[get("/<id>/historial")]
+pub async fn get_historial_tarea(userClaims:JwtGuard,db:&State<Session>,id:&str)->Result<Custom<Json<Vec<Historial>>>,status::Custom<Json<AdduserResponse>>>{
+    // el historial se guarda a nombre del dueño, aunque lo consulte alguien con quien se compartio;
+    // las tareas en la papelera o purgadas solo las consulta su dueño fuera de los espacios
+    let id_duenio=match Tareas::get_visible(id,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(Some(tarea))=>tarea.id_usuario,
+        _ if userClaims.id_espacio.is_none()=>userClaims.id_usuario.clone(),
+        _=>{
+            return Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            );
+        }
+    };
+    match Historial::get_by_tarea(id,&id_duenio,db).await {
+        Ok(res)=>{
+            if !res.is_empty() {
+                return Ok(
+                    Custom(
+                        Status::Ok, 
+                        Json(res)
+                    )
+                );
+            }
+
+            Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
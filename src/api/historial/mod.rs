@@ -0,0 +1,5 @@
+mod controller;
+pub use controller::{*};
+
+mod model;
+pub use model::{*};
\ No newline at end of file
@@ -0,0 +1,152 @@
+use std::collections::BTreeSet;
+
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use unreql::{cmd::options::BetweenOptions, func, r, rjson, types::{Change, WriteStatus}, Session};
+use utoipa::ToSchema;
+
+use crate::utils::Utils;
+
+/// actor usado para los cambios hechos por tareas en segundo plano
+pub const ACTOR_SISTEMA:&str="sistema";
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct CambioCampo{
+    pub campo:String,
+    /// valor anterior, `null` si el campo no existia
+    #[schema(value_type = Object)]
+    pub antes:Value,
+    /// valor nuevo, `null` si el campo se elimino
+    #[schema(value_type = Object)]
+    pub despues:Value,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Historial{
+    pub id:String,
+    pub id_tarea:String,
+    /// dueño de la tarea al momento del cambio
+    pub id_usuario:String,
+    /// usuario que hizo el cambio o `sistema`
+    pub actor:String,
+    /// crear, editar, estado, mover, asignar, dependencias, lote, papelera, restaurar o purgar
+    pub operacion:String,
+    pub cambios:Vec<CambioCampo>,
+    pub create_at:i64,
+}
+
+impl Historial {
+    pub async fn create_indexes(db:&Session)->Result<(),unreql::Error>{
+        Utils::create_table_if_missing("tareas_historial", db).await?;
+        let existentes=r.db("tareas").table("tareas_historial")
+        .index_list()
+        .exec::<_,Vec<String>>(db)
+        .await?;
+        if !existentes.iter().any(|i| i == "tarea_create_at") {
+            let query = r.db("tareas").table("tareas_historial")
+            .index_create(r.args((
+                "tarea_create_at",
+                func!(|h| [h.clone().g("id_tarea"), h.g("create_at")])
+            )))
+            .exec::<_,Value>(db);
+            if let Err(e) = query.await {
+                println!("error create_indexes tareas_historial {}",e);
+                return Err(e);
+            }
+        }
+        r.db("tareas").table("tareas_historial").index_wait(()).exec::<_,Value>(db).await?;
+        Ok(())
+    }
+
+    /// diferencias campo a campo entre dos versiones de un documento
+    pub fn diff(antes:Option<&Value>,despues:Option<&Value>)->Vec<CambioCampo>{
+        let vacio=serde_json::Map::new();
+        let antes=antes.and_then(Value::as_object).unwrap_or(&vacio);
+        let despues=despues.and_then(Value::as_object).unwrap_or(&vacio);
+        let campos:BTreeSet<&String>=antes.keys().chain(despues.keys()).collect();
+        campos.into_iter()
+        .filter(|campo| campo.as_str() != "id")
+        .filter_map(|campo| {
+            let a=antes.get(campo).cloned().unwrap_or(Value::Null);
+            let d=despues.get(campo).cloned().unwrap_or(Value::Null);
+            if a == d {
+                return None;
+            }
+            Some(CambioCampo { campo: campo.clone(), antes: a, despues: d })
+        })
+        .collect()
+    }
+
+    /// guarda una entrada por cada documento modificado en `changes`
+    /// (el resultado de `return_changes` de una escritura sobre tareas)
+    pub async fn add_from_changes(changes:&[Change],actor:&str,operacion:&str,db:&Session)->Result<u32,unreql::Error>{
+        let ahora=Utils::current_timestamp();
+        let entradas:Vec<Value>=changes.iter()
+            .filter_map(|change| {
+                let doc=change.new_val.as_ref().or(change.old_val.as_ref())?;
+                let id_tarea=doc.get("id")?.as_str()?;
+                let id_usuario=doc.get("id_usuario").and_then(Value::as_str).unwrap_or_default();
+                let cambios=Self::diff(change.old_val.as_ref(), change.new_val.as_ref());
+                if cambios.is_empty() {
+                    return None;
+                }
+                Some(json!({
+                    "id_tarea":id_tarea,
+                    "id_usuario":id_usuario,
+                    "actor":actor,
+                    "operacion":operacion,
+                    "cambios":cambios,
+                    "create_at":ahora,
+                }))
+            })
+            .collect();
+        if entradas.is_empty() {
+            return Ok(0);
+        }
+        let query = r.db("tareas").table("tareas_historial")
+        .insert(Value::Array(entradas))
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.inserted)
+            },
+            Err(e)=>{
+                println!("error add_from_changes {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// igual que `add_from_changes` pero solo registra el error,
+    /// para no hacer fallar la escritura que ya se realizo
+    pub async fn registrar(changes:&[Change],actor:&str,operacion:&str,db:&Session){
+        if let Err(e) = Self::add_from_changes(changes, actor, operacion, db).await {
+            println!("error registrar historial {} {}",operacion,e);
+        }
+    }
+
+    pub async fn get_by_tarea(id_tarea:&str,id_usuario:&str,db:&Session)->Result<Vec<Historial>,unreql::Error>{
+        let query = r.db("tareas").table("tareas_historial")
+        .between(
+            rjson!([id_tarea.to_string(), r.minval()]),
+            rjson!([id_tarea.to_string(), r.maxval()]),
+            BetweenOptions { index: Some("tarea_create_at".to_string()), ..Default::default() }
+        )
+        .order_by(r.index("tarea_create_at"))
+        .filter(rjson!({
+            "id_usuario":id_usuario.to_string(),
+        }))
+        .exec_to_vec::<_,Historial>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res)
+            },
+            Err(e)=>{
+                println!("error get_by_tarea {}",e);
+                Err(e)
+            }
+        }
+    }
+}
@@ -0,0 +1,2 @@
+mod model;
+pub use model::{*};
\ No newline at end of file
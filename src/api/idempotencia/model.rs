@@ -0,0 +1,325 @@
+use std::{future::Future, time::Duration};
+
+use rocket::{figment::Figment, http::Status, request::{self, FromRequest, Outcome, Request}, response::status::Custom, serde::{de::DeserializeOwned, json::Json, Deserialize, Serialize}, tokio::time::interval};
+use serde_json::Value;
+use unreql::{cmd::options::BetweenOptions, func, r, rjson, types::WriteStatus, Session};
+
+use crate::{api::AdduserResponse, utils::Utils};
+
+const VENTANA_SEGS_DEFAULT:u64=86400;
+const RESERVA_SEGS_DEFAULT:u64=120;
+const LARGO_MAXIMO_CLAVE:usize=255;
+
+/// header con la clave que identifica una peticion que el cliente puede reintentar
+pub const HEADER_IDEMPOTENCIA: &str = "Idempotency-Key";
+
+/// configuracion de las claves de idempotencia, se lee de `Rocket.toml`
+/// (`idempotencia_ventana_segs` e `idempotencia_reserva_segs`)
+#[derive(Debug,Clone,Copy)]
+pub struct ConfigIdempotencia{
+    pub ventana_segs:u64,
+    /// cuanto dura la reserva de una clave cuya peticion no termino, por ejemplo porque el
+    /// proceso se cayo; pasado ese tiempo la clave se puede volver a usar
+    pub reserva_segs:u64,
+}
+
+impl ConfigIdempotencia {
+    pub fn from_figment(figment:&Figment)->ConfigIdempotencia{
+        ConfigIdempotencia {
+            ventana_segs: figment.extract_inner::<u64>("idempotencia_ventana_segs").unwrap_or(VENTANA_SEGS_DEFAULT).max(1),
+            reserva_segs: figment.extract_inner::<u64>("idempotencia_reserva_segs").unwrap_or(RESERVA_SEGS_DEFAULT).max(1),
+        }
+    }
+
+    pub fn ventana_ms(&self)->i64{
+        (self.ventana_segs * 1000) as i64
+    }
+
+    pub fn reserva_ms(&self)->i64{
+        (self.reserva_segs * 1000) as i64
+    }
+}
+
+/// respuesta guardada para una clave de idempotencia
+#[derive(Debug,Clone,Deserialize,Serialize)]
+#[serde(crate="rocket::serde")]
+pub struct Idempotencia{
+    /// `<id_usuario>:<clave>`
+    pub id:String,
+    /// sha-256 del metodo, la ruta y el json canonico del cuerpo de la peticion original
+    pub huella:String,
+    /// `null` mientras la peticion original sigue en curso
+    #[serde(default)]
+    pub status:Option<u16>,
+    #[serde(default)]
+    pub cuerpo:Value,
+    /// fin de la reserva mientras la peticion sigue en curso, despues fin de la ventana
+    pub expira:i64,
+}
+
+/// cuerpos de respuesta que se pueden guardar y reconstruir al repetir una peticion
+pub trait CuerpoGuardado: Sized {
+    fn a_valor(&self)->Value;
+    fn desde_valor(valor:Value)->Option<Self>;
+}
+
+impl<T:Serialize+DeserializeOwned> CuerpoGuardado for Json<T> {
+    fn a_valor(&self)->Value{
+        serde_json::to_value(&self.0).unwrap_or(Value::Null)
+    }
+
+    fn desde_valor(valor:Value)->Option<Self>{
+        serde_json::from_value(valor).ok().map(Json)
+    }
+}
+
+impl CuerpoGuardado for String {
+    fn a_valor(&self)->Value{
+        Value::String(self.clone())
+    }
+
+    fn desde_valor(valor:Value)->Option<Self>{
+        valor.as_str().map(str::to_string)
+    }
+}
+
+type Respuesta<T>=Result<Custom<T>,Custom<Json<AdduserResponse>>>;
+
+/// header `Idempotency-Key` junto con la peticion a la que se aplica
+pub struct ClaveIdempotencia{
+    pub clave:Option<String>,
+    /// metodo y uri, forman parte de la huella para que la clave no sirva en otra ruta
+    ruta:String,
+    ventana_ms:i64,
+    reserva_ms:i64,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClaveIdempotencia {
+    type Error = ();
+
+    async fn from_request(request:&'r Request<'_>)->request::Outcome<Self,Self::Error>{
+        let clave=request.headers().get_one(HEADER_IDEMPOTENCIA).map(|c| c.trim().to_string());
+        if clave.as_ref().is_some_and(|c| c.is_empty() || c.len() > LARGO_MAXIMO_CLAVE) {
+            return Outcome::Error((Status::BadRequest,()));
+        }
+        let config=request.rocket().state::<ConfigIdempotencia>().copied()
+            .unwrap_or(ConfigIdempotencia { ventana_segs: VENTANA_SEGS_DEFAULT, reserva_segs: RESERVA_SEGS_DEFAULT });
+        Outcome::Success(ClaveIdempotencia {
+            clave,
+            ruta: format!("{} {}",request.method(),request.uri()),
+            ventana_ms: config.ventana_ms(),
+            reserva_ms: config.reserva_ms(),
+        })
+    }
+}
+
+impl ClaveIdempotencia {
+    fn huella(&self,cuerpo:&impl Serialize)->String{
+        let cuerpo=serde_json::to_value(cuerpo).unwrap_or(Value::Null);
+        Utils::sha256_hex(format!("{}\n{}",self.ruta,Utils::json_canonico(&cuerpo)).as_bytes())
+    }
+
+    /// ejecuta `operacion` una sola vez por clave. Si la clave ya se uso con la misma peticion
+    /// devuelve la respuesta guardada sin volver a escribir; con otra peticion responde `422`.
+    /// Las respuestas `5xx` no se guardan para que el cliente pueda reintentar
+    pub async fn ejecutar<T:CuerpoGuardado,F:Future<Output=Respuesta<T>>>(&self,id_usuario:&str,cuerpo:&impl Serialize,db:&Session,operacion:F)->Respuesta<T>{
+        let Some(clave) = self.clave.as_deref() else {
+            return operacion.await;
+        };
+        let id=format!("{}:{}",id_usuario,clave);
+        let huella=self.huella(cuerpo);
+        match Idempotencia::reservar(&id,&huella,self.reserva_ms,db).await {
+            Ok(None)=>{},
+            Ok(Some(previa))=>{
+                return previa.repetir(&huella);
+            },
+            Err(e)=>{
+                return Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                );
+            }
+        }
+
+        let res=operacion.await;
+        let (status,valor)=match &res {
+            Ok(Custom(status,cuerpo))=>(*status,cuerpo.a_valor()),
+            Err(Custom(status,Json(error)))=>(*status,serde_json::to_value(error).unwrap_or(Value::Null)),
+        };
+        if status.code >= 500 {
+            Idempotencia::liberar(&id,db).await;
+        }else{
+            Idempotencia::completar(&id,status.code,valor,self.ventana_ms,db).await;
+        }
+        res
+    }
+}
+
+impl Idempotencia {
+    pub async fn create_indexes(db:&Session)->Result<(),unreql::Error>{
+        Utils::create_table_if_missing("idempotencia", db).await?;
+        let existentes=r.db("tareas").table("idempotencia")
+        .index_list()
+        .exec::<_,Vec<String>>(db)
+        .await?;
+        if !existentes.iter().any(|i| i == "expira") {
+            let query = r.db("tareas").table("idempotencia")
+            .index_create("expira")
+            .exec::<_,Value>(db);
+            if let Err(e) = query.await {
+                println!("error create_indexes idempotencia {}",e);
+                return Err(e);
+            }
+        }
+        r.db("tareas").table("idempotencia").index_wait(()).exec::<_,Value>(db).await?;
+        Ok(())
+    }
+
+    /// respuesta guardada convertida al tipo de la ruta; `409` si la peticion original no termino
+    fn repetir<T:CuerpoGuardado>(self,huella:&str)->Respuesta<T>{
+        if self.huella != huella {
+            return Err(
+                Custom(
+                    Status::UnprocessableEntity,
+                    Json(
+                        AdduserResponse{
+                            error:14
+                        }
+                    )
+                )
+            );
+        }
+        let Some(status) = self.status.and_then(Status::from_code) else {
+            return Err(
+                Custom(
+                    Status::Conflict,
+                    Json(
+                        AdduserResponse{
+                            error:15
+                        }
+                    )
+                )
+            );
+        };
+        let res=if status.class().is_success() {
+            T::desde_valor(self.cuerpo).map(|cuerpo| Ok(Custom(status,cuerpo)))
+        }else{
+            serde_json::from_value::<AdduserResponse>(self.cuerpo).ok().map(|error| Err(Custom(status,Json(error))))
+        };
+        res.unwrap_or(
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        )
+    }
+
+    /// reserva la clave por `reserva_ms` para una peticion nueva; si ya estaba reservada o tiene
+    /// una respuesta guardada devuelve la entrada existente. Una entrada vencida se descarta como
+    /// si no existiera
+    async fn reservar(id:&str,huella:&str,reserva_ms:i64,db:&Session)->Result<Option<Idempotencia>,unreql::Error>{
+        let ahora=Utils::current_timestamp();
+        let query = r.db("tareas").table("idempotencia")
+        .get_all(id.to_string())
+        .filter(func!(|i| i.g("expira").lt(ahora)))
+        .delete(())
+        .exec::<_,WriteStatus>(db);
+        if let Err(e) = query.await {
+            println!("error reservar idempotencia {}",e);
+            return Err(e);
+        }
+
+        // el insert falla si la clave ya existe, asi que de dos peticiones simultaneas solo una la reserva
+        let query = r.db("tareas").table("idempotencia")
+        .insert(rjson!({
+            "id":id.to_string(),
+            "huella":huella.to_string(),
+            "expira":ahora + reserva_ms,
+        }))
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res) if res.inserted > 0=>{
+                Ok(None)
+            },
+            Ok(_)=>{
+                let query = r.db("tareas").table("idempotencia")
+                .get(id.to_string())
+                .exec_to_vec::<_,Idempotencia>(db);
+                Ok(query.await?.into_iter().next())
+            },
+            Err(e)=>{
+                println!("error reservar idempotencia {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// guarda la respuesta durante `ventana_ms` desde ahora
+    async fn completar(id:&str,status:u16,cuerpo:Value,ventana_ms:i64,db:&Session){
+        let query = r.db("tareas").table("idempotencia")
+        .get(id.to_string())
+        .update(rjson!({
+            "status":status,
+            "cuerpo":cuerpo,
+            "expira":Utils::current_timestamp() + ventana_ms,
+        }))
+        .exec::<_,WriteStatus>(db);
+        if let Err(e) = query.await {
+            println!("error completar idempotencia {}",e);
+        }
+    }
+
+    async fn liberar(id:&str,db:&Session){
+        let query = r.db("tareas").table("idempotencia")
+        .get(id.to_string())
+        .delete(())
+        .exec::<_,WriteStatus>(db);
+        if let Err(e) = query.await {
+            println!("error liberar idempotencia {}",e);
+        }
+    }
+
+    pub async fn purge_expired(antes_de:i64,db:&Session)->Result<u32,unreql::Error>{
+        let query = r.db("tareas").table("idempotencia")
+        .between(
+            r.minval(),
+            antes_de,
+            BetweenOptions { index: Some("expira".to_string()), ..Default::default() }
+        )
+        .delete(())
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.deleted)
+            },
+            Err(e)=>{
+                println!("error purge_expired idempotencia {}",e);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// tarea en segundo plano que borra las claves vencidas
+pub async fn purge_idempotencia_job(config:ConfigIdempotencia,db:Session){
+    let mut intervalo=interval(Duration::from_secs(config.ventana_segs));
+    loop {
+        intervalo.tick().await;
+        if let Err(e) = Idempotencia::purge_expired(Utils::current_timestamp(), &db).await {
+            println!("error purge_idempotencia_job {}",e);
+        }
+    }
+}
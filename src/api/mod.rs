@@ -5,4 +5,35 @@ mod tareas;
 pub use tareas::{*};
 
 mod web_sockets;
-pub use web_sockets::{*};
\ No newline at end of file
+pub use web_sockets::{*};
+
+mod historial;
+pub use historial::{*};
+
+mod comentarios;
+pub use comentarios::{*};
+
+mod adjuntos;
+pub use adjuntos::{*};
+
+mod compartidos;
+pub use compartidos::{*};
+
+mod espacios;
+pub use espacios::{*};
+
+mod flujos;
+pub use flujos::{*};
+
+mod idempotencia;
+pub use idempotencia::{*};
+
+
+mod tiempos;
+pub use tiempos::{*};
+mod plantillas;
+pub use plantillas::{*};
+mod calendario;
+pub use calendario::{*};
+mod caldav;
+pub use caldav::{*};
@@ -0,0 +1,405 @@
+use rocket::{http::Status, response::status::{self, Custom}, serde::json::Json, State};
+use unreql::Session;
+use validator::Validate;
+
+use crate::{api::{tarea_con_permiso, AdduserResponse, ClaveIdempotencia, Flujos, Permiso, Rol, Tareas}, utils::Utils, ClientesSockets, JwtGuard};
+
+use super::{InstanciarHttp, PlantillaDesdeTarea, PlantillaTarea, Plantillas, PlantillasHttp};
+
+#[utoipa::path(
+    get,
+    path = "/plantillas/",
+    responses(
+        (status = 200, description = "Plantillas del espacio activo o las personales, por nombre", body = Vec<Plantillas>),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="plantillas"
+)]
+#[get("/")]
+pub async fn get_plantillas(userClaims:JwtGuard,db:&State<Session>)->Result<Custom<Json<Vec<Plantillas>>>,status::Custom<Json<AdduserResponse>>>{
+    match Plantillas::get_para(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/plantillas/{id}",
+    responses(
+        (status = 200, description = "Plantilla encontrada", body = Plantillas),
+        (status = 404, description = "Plantilla no encontrada", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" = String, Path, description = "id de la plantilla")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="plantillas"
+)]
+#[get("/<id>")]
+pub async fn get_plantilla(userClaims:JwtGuard,db:&State<Session>,id:&str)->Result<Custom<Json<Plantillas>>,status::Custom<Json<AdduserResponse>>>{
+    match Plantillas::get_visible(id,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(Some(res))=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Ok(None)=>{
+            Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/plantillas/",
+    responses(
+        (status = 200, description = "Plantilla guardada", body = Plantillas),
+        (status = 400, description = "Datos invalidos o mas de 100 tareas o 5 niveles de subtareas", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    request_body=PlantillasHttp,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="plantillas"
+)]
+#[post("/",data="<plantilla>")]
+pub async fn add_plantilla(db:&State<Session>,userClaims:JwtGuard,plantilla:Json<PlantillasHttp>)->Result<Custom<Json<Plantillas>>,status::Custom<Json<AdduserResponse>>>{
+    if !plantilla.is_valid() {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse{
+                        error:3
+                    }
+                )
+            )
+        );
+    }
+
+    match Plantillas::add(&plantilla.nombre,&plantilla.tarea,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(Some(res))=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        _=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/tareas/{id}/plantilla",
+    responses(
+        (status = 200, description = "Plantilla con la tarea, sus subtareas, etiquetas, prioridad y vencimientos relativos", body = Plantillas),
+        (status = 400, description = "Nombre invalido o la tarea tiene demasiadas subtareas", body = AdduserResponse),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" = String, Path, description = "id de la tarea a guardar como plantilla")
+    ),
+    request_body=PlantillaDesdeTarea,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="plantillas"
+)]
+#[post("/<id>/plantilla",data="<plantilla>")]
+pub async fn add_plantilla_desde_tarea(db:&State<Session>,userClaims:JwtGuard,id:&str,plantilla:Json<PlantillaDesdeTarea>)->Result<Custom<Json<Plantillas>>,status::Custom<Json<AdduserResponse>>>{
+    if plantilla.validate().is_err() {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse{
+                        error:3
+                    }
+                )
+            )
+        );
+    }
+    let tarea=tarea_con_permiso(id,&userClaims,Permiso::Lector,db).await?;
+    let arbol=match tarea.get_arbol(db).await {
+        Ok(arbol)=>arbol,
+        Err(e)=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+    let nueva=PlantillasHttp { nombre: plantilla.nombre.clone(), tarea: PlantillaTarea::desde_arbol(&arbol) };
+    if !nueva.is_valid() {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse{
+                        error:3
+                    }
+                )
+            )
+        );
+    }
+
+    match Plantillas::add(&nueva.nombre,&nueva.tarea,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(Some(res))=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        _=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/plantillas/{id}/instanciar",
+    responses(
+        (status = 200, description = "Tareas creadas, la principal primero y despues sus subtareas", body = Vec<Tareas>),
+        (status = 400, description = "Zona horaria invalida", body = AdduserResponse),
+        (status = 404, description = "Plantilla no encontrada", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" = String, Path, description = "id de la plantilla")
+    ),
+    request_body=InstanciarHttp,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="plantillas"
+)]
+#[post("/<id>/instanciar",data="<instanciar>")]
+pub async fn instanciar_plantilla(db:&State<Session>,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,id:&str,instanciar:Json<InstanciarHttp>,clients:&State<ClientesSockets>)->Result<Custom<Json<Vec<Tareas>>>,status::Custom<Json<AdduserResponse>>>{
+    idempotencia.ejecutar(&userClaims.id_usuario,&*instanciar,db,async {
+        let ahora=Utils::current_timestamp();
+        let Some(variables) = instanciar.variables(ahora) else {
+            return Err(
+                Custom(
+                    Status::BadRequest,
+                    Json(
+                        AdduserResponse{
+                            error:3
+                        }
+                    )
+                )
+            );
+        };
+        let plantilla=match Plantillas::get_visible(id,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+            Ok(Some(plantilla))=>plantilla,
+            Ok(None)=>{
+                return Err(
+                    Custom(
+                        Status::NotFound,
+                        Json(
+                            AdduserResponse{
+                                error:1
+                            }
+                        )
+                    )
+                );
+            },
+            Err(e)=>{
+                return Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                );
+            }
+        };
+        let flujo=match Flujos::get_para(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+            Ok(flujo)=>flujo,
+            Err(e)=>{
+                return Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                );
+            }
+        };
+        let nodo=plantilla.tarea.instanciar(&variables, ahora, None);
+        match Tareas::add_arbol(&nodo, &userClaims.id_usuario, userClaims.id_espacio.as_deref(), &flujo.inicial().clave, db).await {
+            Ok(res)=>{
+                Utils::send_ws_to(clients, &Tareas::destinatarios_de(&res, db).await, "new").await;
+                Ok(
+                    Custom(
+                        Status::Ok,
+                        Json(res)
+                    )
+                )
+            },
+            Err(e)=>{
+                Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                )
+            }
+        }
+    }).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/plantillas/{id}",
+    responses(
+        (status = 200, description = "Plantilla eliminada", body = String),
+        (status = 404, description = "Plantilla no encontrada o no es del usuario", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" = String, Path, description = "id de la plantilla")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="plantillas"
+)]
+#[delete("/<id>")]
+pub async fn delete_plantilla(db:&State<Session>,userClaims:JwtGuard,id:&str)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    // en un espacio admin y dueño pueden borrar las plantillas de cualquiera
+    let id_autor=if userClaims.rol >= Some(Rol::Admin) { None } else { Some(userClaims.id_usuario.as_str()) };
+    match Plantillas::delete(id,&Flujos::id_para(&userClaims.id_usuario,userClaims.id_espacio.as_deref()),id_autor,db).await {
+        Ok(true)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    "Ok ".to_string()
+                )
+            )
+        },
+        Ok(false)=>{
+            Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
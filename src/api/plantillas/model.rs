@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::Value;
+use unreql::{cmd::options::InsertOptions, r, rjson, types::WriteStatus, Session};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{api::{ArbolTareas, Flujos, NodoTarea, Prioridad, TareasHttp, ARBOL_MAX_TAREAS, ARBOL_PROFUNDIDAD_MAX}, utils::{Utils, DIA_MS}};
+
+/// tarea de una plantilla; `nombre` y `etiquetas` pueden tener variables como `{fecha}`
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct PlantillaTarea{
+    #[validate(length(min = 1, max = 500))]
+    pub nombre:String,
+    #[serde(default)]
+    pub etiquetas:Vec<String>,
+    #[serde(default)]
+    pub prioridad:Option<Prioridad>,
+    /// vencimiento en ms contados desde que se crea la tarea a partir de la plantilla
+    #[serde(default)]
+    pub vence_en:Option<i64>,
+    #[serde(default)]
+    #[validate(nested)]
+    #[schema(no_recursion)]
+    pub subtareas:Vec<PlantillaTarea>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct PlantillasHttp{
+    #[validate(length(min = 1, max = 100))]
+    pub nombre:String,
+    #[validate(nested)]
+    pub tarea:PlantillaTarea,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct PlantillaDesdeTarea{
+    #[validate(length(min = 1, max = 100))]
+    pub nombre:String,
+}
+
+#[derive(Debug,Clone,Default,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct InstanciarHttp{
+    /// valores para las variables `{clave}`; `{fecha}` es el dia de hoy si no se manda
+    #[serde(default)]
+    pub variables:HashMap<String,String>,
+    /// zona horaria de `{fecha}`, en minutos como en `GET /tiempos/resumen`
+    #[serde(default)]
+    pub zona:Option<i64>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Plantillas{
+    pub id:String,
+    /// `usuario:<id>` o `espacio:<id>`, igual que el id de los flujos
+    pub alcance:String,
+    pub id_usuario:String,
+    pub nombre:String,
+    pub tarea:PlantillaTarea,
+    pub create_at:i64,
+    pub update_at:i64,
+}
+
+impl PlantillaTarea {
+    pub fn cantidad(&self)->usize{
+        1 + self.subtareas.iter().map(PlantillaTarea::cantidad).sum::<usize>()
+    }
+
+    pub fn profundidad(&self)->usize{
+        1 + self.subtareas.iter().map(PlantillaTarea::profundidad).max().unwrap_or(0)
+    }
+
+    /// plantilla de una tarea guardada; los vencimientos quedan relativos a la creacion de cada tarea
+    pub fn desde_arbol(arbol:&ArbolTareas)->PlantillaTarea{
+        PlantillaTarea {
+            nombre: arbol.tarea.nombre.clone(),
+            etiquetas: arbol.tarea.etiquetas.clone(),
+            prioridad: arbol.tarea.prioridad,
+            vence_en: arbol.tarea.vence.map(|vence| (vence - arbol.tarea.create_at).max(0)),
+            subtareas: arbol.subtareas.iter().map(PlantillaTarea::desde_arbol).collect(),
+        }
+    }
+
+    /// tareas a crear, con las variables reemplazadas y los vencimientos contados desde `ahora`
+    pub fn instanciar(&self,variables:&HashMap<String,String>,ahora:i64,id_padre:Option<String>)->NodoTarea{
+        NodoTarea {
+            tarea: TareasHttp {
+                nombre: reemplazar(&self.nombre, variables),
+                vence: self.vence_en.map(|ms| ahora + ms),
+                etiquetas: self.etiquetas.iter().map(|e| reemplazar(e, variables)).collect(),
+                prioridad: self.prioridad,
+                id_padre,
+            },
+            subtareas: self.subtareas.iter().map(|s| s.instanciar(variables, ahora, None)).collect(),
+        }
+    }
+}
+
+impl PlantillasHttp {
+    pub fn is_valid(&self)->bool{
+        self.validate().is_ok() && self.tarea.cantidad() <= ARBOL_MAX_TAREAS && self.tarea.profundidad() <= ARBOL_PROFUNDIDAD_MAX + 1
+    }
+}
+
+impl InstanciarHttp {
+    /// variables con `{fecha}` agregada, `None` si la zona no es valida
+    pub fn variables(&self,ahora:i64)->Option<HashMap<String,String>>{
+        let zona=Utils::zona_valida(self.zona)?;
+        let mut variables=self.variables.clone();
+        variables.entry("fecha".to_string())
+            .or_insert_with(|| Utils::fecha_de_dia((ahora + zona * 60 * 1000).div_euclid(DIA_MS)));
+        Some(variables)
+    }
+}
+
+/// reemplaza cada `{clave}` por su valor; las variables sin valor quedan como estan
+fn reemplazar(texto:&str,variables:&HashMap<String,String>)->String{
+    let mut res=String::with_capacity(texto.len());
+    let mut resto=texto;
+    while let Some(inicio) = resto.find('{') {
+        res.push_str(&resto[..inicio]);
+        let variable=&resto[inicio..];
+        match variable.find('}').and_then(|fin| Some((variables.get(&variable[1..fin])?,fin))) {
+            Some((valor,fin))=>{
+                res.push_str(valor);
+                resto=&variable[fin + 1..];
+            },
+            None=>{
+                res.push('{');
+                resto=&variable[1..];
+            }
+        }
+    }
+    res.push_str(resto);
+    res
+}
+
+impl Plantillas {
+    pub async fn create_indexes(db:&Session)->Result<(),unreql::Error>{
+        Utils::create_table_if_missing("plantillas", db).await?;
+        let existentes=r.db("tareas").table("plantillas")
+        .index_list()
+        .exec::<_,Vec<String>>(db)
+        .await?;
+        if !existentes.iter().any(|i| i == "alcance") {
+            let query = r.db("tareas").table("plantillas")
+            .index_create("alcance")
+            .exec::<_,Value>(db);
+            if let Err(e) = query.await {
+                println!("error create_indexes plantillas {}",e);
+                return Err(e);
+            }
+        }
+        r.db("tareas").table("plantillas").index_wait(()).exec::<_,Value>(db).await?;
+        Ok(())
+    }
+
+    /// plantillas del espacio activo o las personales del usuario, por nombre
+    pub async fn get_para(id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Vec<Plantillas>,unreql::Error>{
+        let query = r.db("tareas").table("plantillas")
+        .get_all(r.with_opt(Flujos::id_para(id_usuario, id_espacio), r.index("alcance")))
+        .order_by("nombre")
+        .exec_to_vec::<_,Plantillas>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res)
+            },
+            Err(e)=>{
+                println!("error get_para plantillas {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// la plantilla si es del espacio activo o personal del usuario
+    pub async fn get_visible(id:&str,id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Option<Plantillas>,unreql::Error>{
+        let alcance=Flujos::id_para(id_usuario, id_espacio);
+        let query = r.db("tareas").table("plantillas")
+        .get_all(id.to_string())
+        .filter(rjson!({"alcance":alcance}))
+        .exec_to_vec::<_,Plantillas>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().next())
+            },
+            Err(e)=>{
+                println!("error get_visible plantillas {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn add(nombre:&str,tarea:&PlantillaTarea,id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Option<Plantillas>,unreql::Error>{
+        let query = r.db("tareas").table("plantillas")
+        .insert(
+            r.with_opt(
+                rjson!({
+                    "alcance":Flujos::id_para(id_usuario, id_espacio),
+                    "id_usuario":id_usuario.to_string(),
+                    "nombre":nombre.to_string(),
+                    "tarea":tarea.clone(),
+                    "create_at":Utils::current_timestamp(),
+                    "update_at":Utils::current_timestamp(),
+                }),
+                InsertOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                for change in res.changes.unwrap_or_default() {
+                    if let Some(new_val) = change.new_val {
+                        let plantilla: Plantillas = Deserialize::deserialize(new_val)?;
+                        return Ok(Some(plantilla));
+                    }
+                }
+                Ok(None)
+            },
+            Err(e)=>{
+                println!("error add plantilla {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// solo quien la creo, o cualquiera si `id_autor` es `None`
+    pub async fn delete(id:&str,alcance:&str,id_autor:Option<&str>,db:&Session)->Result<bool,unreql::Error>{
+        let filtro=match id_autor {
+            Some(id_autor)=>rjson!({"alcance":alcance.to_string(),"id_usuario":id_autor.to_string()}),
+            None=>rjson!({"alcance":alcance.to_string()}),
+        };
+        let query = r.db("tareas").table("plantillas")
+        .get_all(id.to_string())
+        .filter(filtro)
+        .delete(())
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.deleted > 0)
+            },
+            Err(e)=>{
+                println!("error delete plantilla {}",e);
+                Err(e)
+            }
+        }
+    }
+}
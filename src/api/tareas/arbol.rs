@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use rocket::serde::Deserialize;
+use serde_json::{json, Value};
+use unreql::{cmd::options::InsertOptions, func, r, types::WriteStatus, Session};
+
+use crate::{api::Historial, utils::Utils};
+
+use super::{posicion_entre, IndiceBusqueda, Tareas, TareasHttp};
+
+/// niveles de subtareas que se copian al clonar o guardar una plantilla
+pub const ARBOL_PROFUNDIDAD_MAX:usize=5;
+/// tareas que se crean como mucho de una vez, contando las subtareas
+pub const ARBOL_MAX_TAREAS:usize=100;
+/// documentos por insert al crear muchas tareas juntas
+pub const LOTE_INSERCION:usize=200;
+
+/// tarea guardada con sus subtareas activas
+#[derive(Debug,Clone)]
+pub struct ArbolTareas{
+    pub tarea:Tareas,
+    pub subtareas:Vec<ArbolTareas>,
+}
+
+/// tarea a crear con sus subtareas
+#[derive(Debug,Clone)]
+pub struct NodoTarea{
+    pub tarea:TareasHttp,
+    pub subtareas:Vec<NodoTarea>,
+}
+
+impl ArbolTareas {
+    fn construir(tarea:Tareas,hijas:&mut HashMap<String,Vec<Tareas>>)->ArbolTareas{
+        let subtareas=hijas.remove(&tarea.id).unwrap_or_default()
+            .into_iter()
+            .map(|hija| ArbolTareas::construir(hija, hijas))
+            .collect();
+        ArbolTareas { tarea, subtareas }
+    }
+
+    /// copia para volver a crear, con `id_padre` como padre de la raiz
+    pub fn a_nodo(&self,id_padre:Option<String>)->NodoTarea{
+        NodoTarea {
+            tarea: TareasHttp {
+                nombre: self.tarea.nombre.clone(),
+                vence: self.tarea.vence,
+                etiquetas: self.tarea.etiquetas.clone(),
+                prioridad: self.tarea.prioridad,
+                id_padre,
+            },
+            subtareas: self.subtareas.iter().map(|s| s.a_nodo(None)).collect(),
+        }
+    }
+}
+
+impl NodoTarea {
+    pub fn cantidad(&self)->usize{
+        1 + self.subtareas.iter().map(NodoTarea::cantidad).sum::<usize>()
+    }
+}
+
+impl Tareas {
+    /// la tarea con sus subtareas activas hasta `ARBOL_PROFUNDIDAD_MAX` niveles, una consulta por nivel
+    pub async fn get_arbol(self,db:&Session)->Result<ArbolTareas,unreql::Error>{
+        let mut hijas:HashMap<String,Vec<Tareas>>=HashMap::new();
+        let mut nivel=vec![self.id.clone()];
+        for _ in 0..ARBOL_PROFUNDIDAD_MAX {
+            if nivel.is_empty() {
+                break;
+            }
+            let query = r.db("tareas").table("tareas")
+            .get_all(r.with_opt(r.args(nivel), r.index("id_padre")))
+            .filter(func!(|t| t.has_fields("deleted_at").not()))
+            .order_by("posicion")
+            .exec_to_vec::<_,Tareas>(db);
+            let subtareas=match query.await {
+                Ok(res)=>res,
+                Err(e)=>{
+                    println!("error get_arbol {}",e);
+                    return Err(e);
+                }
+            };
+            nivel=subtareas.iter().map(|t| t.id.clone()).collect();
+            for subtarea in subtareas {
+                if let Some(id_padre) = subtarea.id_padre.clone() {
+                    hijas.entry(id_padre).or_default().push(subtarea);
+                }
+            }
+        }
+        Ok(ArbolTareas::construir(self, &mut hijas))
+    }
+
+    /// crea la tarea y sus subtareas con un insert por nivel. Quedan al principio del orden
+    /// manual, cada una seguida de sus subtareas
+    pub async fn add_arbol(raiz:&NodoTarea,id_usuario:&str,id_espacio:Option<&str>,etapa:&str,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
+        let (_,mut creadas)=Self::add_arboles(std::slice::from_ref(raiz), id_usuario, id_espacio, etapa, db).await?;
+        // la raiz primero y despues las subtareas en el orden manual
+        creadas.sort_by(|a,b| a.posicion.cmp(&b.posicion));
+        Ok(creadas)
+    }
+
+    /// crea varias tareas con sus subtareas, un nivel por vez y de a `LOTE_INSERCION` por insert.
+    /// Quedan al principio del orden manual en el orden de `raices`, cada una seguida de sus
+    /// subtareas. Devuelve el id de cada tarea en preorden, `None` si no se creo, y las creadas
+    pub async fn add_arboles(raices:&[NodoTarea],id_usuario:&str,id_espacio:Option<&str>,etapa:&str,db:&Session)->Result<(Vec<Option<String>>,Vec<Tareas>),unreql::Error>{
+        // en preorden: cada tarea con su nivel y el indice de su padre
+        fn aplanar<'a>(nodo:&'a NodoTarea,nivel:usize,padre:Option<usize>,res:&mut Vec<(&'a NodoTarea,usize,Option<usize>)>){
+            let indice=res.len();
+            res.push((nodo,nivel,padre));
+            for subtarea in nodo.subtareas.iter() {
+                aplanar(subtarea, nivel + 1, Some(indice), res);
+            }
+        }
+        let mut nodos=Vec::new();
+        for raiz in raices {
+            aplanar(raiz, 0, None, &mut nodos);
+        }
+
+        // posiciones generadas desde la ultima hacia arriba
+        let mut siguiente=Self::primera_posicion(id_usuario, id_espacio, db).await?;
+        let mut posiciones=vec![String::new();nodos.len()];
+        for indice in (0..nodos.len()).rev() {
+            let posicion=posicion_entre(None, siguiente.as_deref())
+                .or_else(|| posicion_entre(None, None))
+                .unwrap_or_default();
+            posiciones[indice]=posicion.clone();
+            siguiente=Some(posicion);
+        }
+
+        let ahora=Utils::current_timestamp();
+        let mut ids:Vec<Option<String>>=vec![None;nodos.len()];
+        let mut creadas:Vec<Tareas>=Vec::new();
+        let profundidad=nodos.iter().map(|(_,nivel,_)| *nivel).max().unwrap_or(0);
+        for nivel in 0..=profundidad {
+            // las subtareas de una tarea que no se pudo crear tampoco se crean
+            let indices:Vec<(usize,Option<String>)>=nodos.iter().enumerate()
+                .filter(|(_,(_,n,_))| *n == nivel)
+                .filter_map(|(indice,(_,_,padre))| match padre {
+                    Some(padre)=>ids[*padre].clone().map(|id| (indice,Some(id))),
+                    None=>Some((indice,nodos[indice].0.tarea.id_padre.clone())),
+                })
+                .collect();
+            if indices.is_empty() {
+                break;
+            }
+            for lote in indices.chunks(LOTE_INSERCION) {
+                let documentos:Vec<Value>=lote.iter().map(|(indice,id_padre)| {
+                    let tarea=&nodos[*indice].0.tarea;
+                    let mut documento=json!({
+                        "nombre":tarea.nombre,
+                        "id_usuario":id_usuario,
+                        "estado":false,
+                        "etapa":etapa,
+                        "posicion":posiciones[*indice],
+                        "create_at":ahora,
+                        "update_at":ahora,
+                    });
+                    if let Some(id_espacio) = id_espacio {
+                        documento["id_espacio"]=Value::from(id_espacio);
+                    }
+                    if let Some(id_padre) = id_padre {
+                        documento["id_padre"]=Value::from(id_padre.as_str());
+                    }
+                    if let Some(vence) = tarea.vence {
+                        documento["vence"]=Value::from(vence);
+                    }
+                    let etiquetas=tarea.etiquetas_limpias();
+                    if !etiquetas.is_empty() {
+                        documento["etiquetas"]=json!(etiquetas);
+                    }
+                    if let Some(prioridad) = tarea.prioridad {
+                        documento["prioridad"]=json!(prioridad);
+                    }
+                    documento
+                }).collect();
+                let query = r.db("tareas").table("tareas")
+                .insert(
+                    r.with_opt(
+                        Value::Array(documentos),
+                        InsertOptions {return_changes: Some(true.into()), ..Default::default() }
+                    )
+                )
+                .exec::<_,WriteStatus>(db);
+                let res=match query.await {
+                    Ok(res)=>res,
+                    Err(e)=>{
+                        println!("error add_arboles {}",e);
+                        return Err(e);
+                    }
+                };
+                // las claves generadas vienen en el mismo orden que los documentos
+                let claves=res.generated_keys.unwrap_or_default();
+                for ((indice,_),clave) in lote.iter().zip(claves.iter()) {
+                    ids[*indice]=Some(clave.to_string());
+                }
+                let changes=res.changes.unwrap_or_default();
+                Historial::registrar(&changes, id_usuario, "crear", db).await;
+                let mut indice=IndiceBusqueda::write();
+                for tarea in changes.iter().filter_map(|c| Tareas::deserialize(c.new_val.clone()?).ok()) {
+                    indice.indexar(&tarea);
+                    creadas.push(tarea);
+                }
+            }
+        }
+        Ok((ids,creadas))
+    }
+}
@@ -0,0 +1,257 @@
+use std::{collections::{HashMap, HashSet}, sync::{LazyLock, RwLock, RwLockReadGuard, RwLockWriteGuard}};
+
+use rocket::serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::Tareas;
+
+pub const BUSQUEDA_LIMIT_DEFAULT:usize=20;
+pub const BUSQUEDA_LIMIT_MAX:usize=100;
+
+/// peso de una coincidencia por prefijo frente a una palabra completa
+const PESO_PREFIJO:f64=0.5;
+
+static INDICE:LazyLock<RwLock<IndiceBusqueda>>=LazyLock::new(|| RwLock::new(IndiceBusqueda::default()));
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct TareaEncontrada{
+    pub tarea:Tareas,
+    pub puntaje:f64,
+    /// nombre con las palabras encontradas entre `<mark>` y `</mark>`
+    pub resaltado:String,
+}
+
+#[derive(Debug,Clone)]
+struct Documento{
+    id_usuario:String,
+    id_espacio:Option<String>,
+    terminos:usize,
+}
+
+/// indice invertido en memoria sobre el nombre de las tareas,
+/// se mantiene sincronizado desde las escrituras del modelo `Tareas`
+#[derive(Debug,Default)]
+pub struct IndiceBusqueda{
+    documentos:HashMap<String,Documento>,
+    /// termino normalizado -> (id tarea -> frecuencia)
+    terminos:HashMap<String,HashMap<String,usize>>,
+}
+
+impl IndiceBusqueda {
+    pub fn read()->RwLockReadGuard<'static,IndiceBusqueda>{
+        INDICE.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn write()->RwLockWriteGuard<'static,IndiceBusqueda>{
+        INDICE.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// pasa a minusculas y quita tildes, dieresis y la virgulilla de la ñ
+    pub fn normalizar(texto:&str)->String{
+        texto.chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            'á'|'à'|'â'|'ä'|'ã'=>'a',
+            'é'|'è'|'ê'|'ë'=>'e',
+            'í'|'ì'|'î'|'ï'=>'i',
+            'ó'|'ò'|'ô'|'ö'|'õ'=>'o',
+            'ú'|'ù'|'û'|'ü'=>'u',
+            'ñ'=>'n',
+            'ç'=>'c',
+            _=>c
+        })
+        .collect()
+    }
+
+    /// palabras del texto con su posicion en bytes dentro del original
+    fn palabras(texto:&str)->Vec<(usize,&str)>{
+        let mut res=Vec::new();
+        let mut inicio:Option<usize>=None;
+        for (i,c) in texto.char_indices() {
+            match (c.is_alphanumeric(),inicio) {
+                (true,None)=>inicio=Some(i),
+                (false,Some(s))=>{
+                    res.push((s,&texto[s..i]));
+                    inicio=None;
+                },
+                _=>{}
+            }
+        }
+        if let Some(s) = inicio {
+            res.push((s,&texto[s..]));
+        }
+        res
+    }
+
+    pub fn tokenizar(texto:&str)->Vec<String>{
+        Self::palabras(texto).into_iter().map(|(_,p)| Self::normalizar(p)).collect()
+    }
+
+    pub fn indexar(&mut self,tarea:&Tareas){
+        self.eliminar(&tarea.id);
+        let tokens=Self::tokenizar(&tarea.nombre);
+        for token in tokens.iter() {
+            *self.terminos.entry(token.clone()).or_default().entry(tarea.id.clone()).or_insert(0) += 1;
+        }
+        self.documentos.insert(tarea.id.clone(), Documento { id_usuario: tarea.id_usuario.clone(), id_espacio: tarea.id_espacio.clone(), terminos: tokens.len() });
+    }
+
+    pub fn eliminar(&mut self,id:&str){
+        if self.documentos.remove(id).is_none() {
+            return;
+        }
+        self.terminos.retain(|_,docs| {
+            docs.remove(id);
+            !docs.is_empty()
+        });
+    }
+
+    pub fn clear(&mut self){
+        self.documentos.clear();
+        self.terminos.clear();
+    }
+
+    /// ids de las tareas para las que `visible(id, id_usuario dueño, id_espacio)` es verdadero y que
+    /// contienen todos los terminos de la consulta (el ultimo tambien como prefijo), ordenados por puntaje
+    pub fn buscar(&self,visible:impl Fn(&str,&str,Option<&str>)->bool,consulta:&str,limit:usize)->Vec<(String,f64)>{
+        let terminos=Self::tokenizar(consulta);
+        if terminos.is_empty() {
+            return vec![];
+        }
+        let total_docs=self.documentos.len().max(1) as f64;
+        let mut puntajes:Option<HashMap<String,f64>>=None;
+        for (i,termino) in terminos.iter().enumerate() {
+            let es_ultimo=i == terminos.len() - 1;
+            let mut encontrados:HashMap<String,f64>=HashMap::new();
+            for (clave,docs) in self.terminos.iter() {
+                let peso=if clave == termino {
+                    1.0
+                } else if es_ultimo && clave.starts_with(termino.as_str()) {
+                    PESO_PREFIJO
+                } else {
+                    continue;
+                };
+                let idf=(1.0 + total_docs / docs.len() as f64).ln();
+                for (id,frecuencia) in docs.iter() {
+                    let Some(doc) = self.documentos.get(id) else { continue };
+                    if !visible(id, &doc.id_usuario, doc.id_espacio.as_deref()) {
+                        continue;
+                    }
+                    let tf=*frecuencia as f64 / doc.terminos.max(1) as f64;
+                    let puntaje=encontrados.entry(id.clone()).or_insert(0.0);
+                    *puntaje=puntaje.max(peso * tf * idf);
+                }
+            }
+            puntajes=Some(match puntajes {
+                None=>encontrados,
+                Some(previos)=>previos.into_iter()
+                    .filter_map(|(id,p)| encontrados.get(&id).map(|n| (id,p + n)))
+                    .collect()
+            });
+        }
+        let mut res:Vec<(String,f64)>=puntajes.unwrap_or_default().into_iter().collect();
+        res.sort_by(|a,b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        res.truncate(limit);
+        res
+    }
+
+    /// envuelve en `<mark>` las palabras del texto que coinciden con la consulta
+    pub fn resaltar(texto:&str,consulta:&str)->String{
+        let terminos:HashSet<String>=Self::tokenizar(consulta).into_iter().collect();
+        let mut res=String::with_capacity(texto.len());
+        let mut ultimo=0;
+        for (inicio,palabra) in Self::palabras(texto) {
+            let normalizada=Self::normalizar(palabra);
+            if !terminos.iter().any(|t| normalizada.starts_with(t.as_str())) {
+                continue;
+            }
+            res.push_str(&Self::escapar(&texto[ultimo..inicio]));
+            res.push_str("<mark>");
+            res.push_str(&Self::escapar(palabra));
+            res.push_str("</mark>");
+            ultimo=inicio + palabra.len();
+        }
+        res.push_str(&Self::escapar(&texto[ultimo..]));
+        res
+    }
+
+    fn escapar(texto:&str)->String{
+        texto.replace('&',"&amp;").replace('<',"&lt;").replace('>',"&gt;")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn tarea(id:&str,nombre:&str,id_usuario:&str)->Tareas{
+        serde_json::from_value(json!({
+            "id":id,
+            "nombre":nombre,
+            "id_usuario":id_usuario,
+            "estado":false,
+            "create_at":0,
+            "update_at":0,
+        })).unwrap()
+    }
+
+    fn indice(tareas:&[Tareas])->IndiceBusqueda{
+        let mut indice=IndiceBusqueda::default();
+        for tarea in tareas {
+            indice.indexar(tarea);
+        }
+        indice
+    }
+
+    #[test]
+    fn normaliza_tildes_y_mayusculas() {
+        assert_eq!(IndiceBusqueda::normalizar("Canción ÑANDÚ Pingüino"), "cancion nandu pinguino");
+        assert_eq!(IndiceBusqueda::tokenizar("¡Revisar el PR-42, ya!"), vec!["revisar","el","pr","42","ya"]);
+    }
+
+    #[test]
+    fn busca_sin_tildes_y_por_prefijo() {
+        let indice=indice(&[tarea("1","Reunión con diseño","u"),tarea("2","Comprar pan","u")]);
+        let todas=|_:&str,_:&str,_:Option<&str>| true;
+        assert_eq!(indice.buscar(todas, "reunion", 10).iter().map(|r| r.0.as_str()).collect::<Vec<_>>(), vec!["1"]);
+        assert_eq!(indice.buscar(todas, "REUNIÓN dise", 10).len(), 1);
+        // solo el ultimo termino se busca como prefijo
+        assert!(indice.buscar(todas, "reu diseño", 10).is_empty());
+        assert!(indice.buscar(todas, "", 10).is_empty());
+    }
+
+    #[test]
+    fn ordena_por_puntaje() {
+        let indice=indice(&[
+            tarea("largo","informe trimestral de ventas para el directorio","u"),
+            tarea("corto","informe","u"),
+            tarea("prefijo","informes","u"),
+        ]);
+        let res=indice.buscar(|_,_,_| true, "informe", 10);
+        let ids:Vec<&str>=res.iter().map(|r| r.0.as_str()).collect();
+        assert_eq!(ids, vec!["corto","prefijo","largo"]);
+        assert!(res.windows(2).all(|par| par[0].1 >= par[1].1));
+        assert_eq!(indice.buscar(|_,_,_| true, "informe", 1).len(), 1);
+    }
+
+    #[test]
+    fn filtra_por_visibilidad_y_elimina() {
+        let mut indice=indice(&[tarea("1","pagar luz","ana"),tarea("2","pagar gas","beto")]);
+        let de_ana=|_:&str,duenio:&str,_:Option<&str>| duenio == "ana";
+        assert_eq!(indice.buscar(de_ana, "pagar", 10).iter().map(|r| r.0.as_str()).collect::<Vec<_>>(), vec!["1"]);
+        indice.eliminar("1");
+        assert!(indice.buscar(de_ana, "pagar", 10).is_empty());
+        // reindexar reemplaza los terminos anteriores
+        indice.indexar(&tarea("2","cobrar gas","beto"));
+        assert!(indice.buscar(|_,_,_| true, "pagar", 10).is_empty());
+    }
+
+    #[test]
+    fn resalta_y_escapa() {
+        assert_eq!(IndiceBusqueda::resaltar("Reunión <equipo> & diseño", "reunion dis"), "<mark>Reunión</mark> &lt;equipo&gt; &amp; <mark>diseño</mark>");
+        assert_eq!(IndiceBusqueda::resaltar("sin coincidencias", "otro"), "sin coincidencias");
+    }
+}
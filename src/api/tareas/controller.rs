@@ -1,49 +1,184 @@
-use std::{collections::HashMap, sync::Arc};
+use std::collections::{HashMap, HashSet};
 
-use rocket::{futures::lock::Mutex, http::Status, response::status::{self, Custom}, serde::json::Json, tokio::sync::broadcast::Sender, State};
+use rocket::{data::{Data, Limits, ToByteUnit}, http::{ContentType, Status}, response::status::{self, Custom}, serde::json::Json, State};
 use unreql::Session;
+use validator::Validate;
 
-use crate::{api::{AdduserResponse, Tareas}, utils::Utils, ClientesSockets, JwtGuard};
+use crate::{api::{AdduserResponse, AlmacenamientoAdjuntos, ClaveIdempotencia, EtapaHttp, Flujos, Permiso, Tablero, Tareas, Usuarios}, utils::Utils, ClientesSockets, JwtGuard};
 
-use super::TareasHttp;
+use super::{etag_json, ARBOL_MAX_TAREAS, etag_tarea, posicion_entre, AsignacionHttp, ConEtag, IfMatch, IfMatchListado, IfNoneMatch, DependenciasHttp, Estadisticas, EstadisticasQuery, ErrorExportacion, ErrorImportacion, Exportacion, ExportarQuery, ResultadoImportacion, OrigenImportacion, IMPORTAR_MAX_BYTES, IMPORTAR_MAX_FILAS, AccionTodoTxt, LineaTodoTxt, ResultadoTodoTxt, TareaTodoTxt, leer_linea_todo_txt, leer_todo_txt, todo_txt, GrafoDependencias, LoteHttp, LoteResultado, MoverHttp, TareaEncontrada, TareaRapida, TareaRapidaHttp, TareasHttp, TareasPage, TareasQuery, interpretar, BUSQUEDA_LIMIT_DEFAULT, BUSQUEDA_LIMIT_MAX};
+
+/// la tarea si el usuario tiene al menos el permiso `minimo` sobre ella,
+/// 404 si no puede verla y 403 si puede verla pero no alcanza el permiso
+pub async fn tarea_con_permiso(id:&str,userClaims:&JwtGuard,minimo:Permiso,db:&Session)->Result<Tareas,status::Custom<Json<AdduserResponse>>>{
+    match Tareas::get_con_permiso(id,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(Some((tarea,permiso)))=>{
+            if permiso >= minimo {
+                return Ok(tarea);
+            }
+            Err(
+                Custom(
+                    Status::Forbidden,
+                    Json(
+                        AdduserResponse{
+                            error:16
+                        }
+                    )
+                )
+            )
+        },
+        Ok(None)=>{
+            Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+/// 412 si el cliente mando `If-Match` con una version de la tarea que ya no es la actual;
+/// la escritura vuelve a comprobarlo dentro de RethinkDB por si cambia entre la lectura y el update
+pub fn comprobar_version(tarea:&Tareas,if_match:&IfMatch)->Result<(),status::Custom<Json<AdduserResponse>>>{
+    match if_match.0 {
+        Some(esperado) if esperado != tarea.version=>{
+            Err(
+                Custom(
+                    Status::PreconditionFailed,
+                    Json(
+                        AdduserResponse{
+                            error:13
+                        }
+                    )
+                )
+            )
+        },
+        _=>Ok(())
+    }
+}
 
 #[utoipa::path(
     get,
     path = "/tareas/",
     responses(
-        (status = 200, description = "Existen tareas", body = Vec<Tareas>),
-        (status = 404, description = "No tiene tareas", body =AdduserResponse),
+        (status = 200, description = "Pagina de tareas, vacia si no hay resultados", body = TareasPage),
+        (status = 400, description = "Parametros de listado invalidos", body = AdduserResponse),
+        (status = 304, description = "Sin cambios desde la etag de `If-None-Match`"),
         (status = 500, description = "Error interno del servidor", body = AdduserResponse),
         (status = 401, description = "No autorizado")
     ),
+    params(
+        TareasQuery
+    ),
     security(
         ("jwt_token" = [])
     ),
     tag="tareas"
 )]
-#[get("/")]
-pub async fn get_all_tareas(userClaims:JwtGuard,db:&State<Session>)->Result<Custom<Json<Vec<Tareas>>>,status::Custom<Json<AdduserResponse>>>{
-    match Tareas::get_all_tareas_by_usuario(&userClaims.id_usuario,&db).await {
+#[get("/?<params..>")]
+pub async fn get_all_tareas(userClaims:JwtGuard,if_none_match:IfNoneMatch,db:&State<Session>,params:TareasQuery)->Result<ConEtag<Custom<Json<TareasPage>>>,status::Custom<Json<AdduserResponse>>>{
+    if !params.is_valid() {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse{
+                        error:3
+                    }
+                )
+            )
+        );
+    }
+
+    match Tareas::get_page_by_usuario(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),&params,&db).await {
         Ok(res)=>{
-            if res.len() > 0 {
-                return Ok(
+            let etag=etag_json(&res);
+            Ok(
+                ConEtag::nueva(
                     Custom(
-                        Status::Ok, 
+                        Status::Ok,
                         Json(res)
-                    )
-                );
-            }
-
+                    ),
+                    etag,
+                    &if_none_match
+                )
+            )
+        },
+        Err(e)=>{
             Err(
                 Custom(
-                    Status::NotFound,
+                    Status::InternalServerError,
                     Json(
                         AdduserResponse{
-                            error:1
+                            error:2
                         }
                     )
                 )
             )
+        }
+    }
+}
+
+
+#[utoipa::path(
+    get,
+    path = "/tareas/search",
+    responses(
+        (status = 200, description = "Tareas encontradas ordenadas por relevancia", body = Vec<TareaEncontrada>),
+        (status = 400, description = "Consulta vacia", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("q" = String, Query, description = "palabras a buscar, sin distinguir tildes ni mayusculas"),
+        ("limit" = Option<usize>, Query, description = "maximo de resultados (1-100, por defecto 20)")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[get("/search?<q>&<limit>")]
+pub async fn search_tareas(userClaims:JwtGuard,db:&State<Session>,q:&str,limit:Option<usize>)->Result<Custom<Json<Vec<TareaEncontrada>>>,status::Custom<Json<AdduserResponse>>>{
+    if q.trim().is_empty() {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse{
+                        error:3
+                    }
+                )
+            )
+        );
+    }
+
+    let limit=limit.unwrap_or(BUSQUEDA_LIMIT_DEFAULT).clamp(1, BUSQUEDA_LIMIT_MAX);
+    match Tareas::search(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),q,limit,&db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok, 
+                    Json(res)
+                )
+            )
         },
         Err(e)=>{
             Err(
@@ -60,13 +195,13 @@ pub async fn get_all_tareas(userClaims:JwtGuard,db:&State<Session>)->Result<Cust
     }
 }
 
-
 #[utoipa::path(
     get,
     path = "/tareas/{id}",
     responses(
         (status = 200, description = "Tarea encontrada", body = Vec<Tareas>),
         (status = 404, description = "Tarea no encontrada", body =AdduserResponse),
+        (status = 304, description = "Sin cambios desde la etag de `If-None-Match`"),
         (status = 500, description = "Error interno del servidor", body = AdduserResponse),
         (status = 401, description = "No autorizado")
     ),
@@ -79,14 +214,20 @@ pub async fn get_all_tareas(userClaims:JwtGuard,db:&State<Session>)->Result<Cust
     tag="tareas"
 )]
 #[get("/<id>")]
-pub async fn get_tarea_by_id(userClaims:JwtGuard,db:&State<Session>,id:&str)->Result<Custom<Json<Vec<Tareas>>>,status::Custom<Json<AdduserResponse>>>{
+pub async fn get_tarea_by_id(userClaims:JwtGuard,if_none_match:IfNoneMatch,db:&State<Session>,id:&str)->Result<ConEtag<Custom<Json<Vec<Tareas>>>>,status::Custom<Json<AdduserResponse>>>{
+    tarea_con_permiso(id,&userClaims,Permiso::Lector,db).await?;
     match Tareas::get_tarea_by_id(&id,&db).await {
         Ok(res)=>{
             if res.len() > 0 {
+                let etag=etag_tarea(res[0].version);
                 return Ok(
-                    Custom(
-                        Status::Ok, 
-                        Json(res)
+                    ConEtag::nueva(
+                        Custom(
+                            Status::Ok,
+                            Json(res)
+                        ),
+                        etag,
+                        &if_none_match
                     )
                 );
             }
@@ -123,6 +264,10 @@ pub async fn get_tarea_by_id(userClaims:JwtGuard,db:&State<Session>,id:&str)->Re
     responses(
         (status = 201, description = "Tarea creada", body = String),
         (status = 400, description = "Datos inválidos", body = AdduserResponse),
+        (status = 403, description = "Sin permiso de edicion sobre la tarea `id_padre`", body = AdduserResponse),
+        (status = 404, description = "La tarea `id_padre` no existe o no es visible", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
         (status = 500, description = "Error interno del servidor", body = AdduserResponse),
         (status = 401, description = "No autorizado")
     ),
@@ -133,190 +278,1649 @@ pub async fn get_tarea_by_id(userClaims:JwtGuard,db:&State<Session>,id:&str)->Re
     tag="tareas"
 )]
 #[post("/",data="<tarea>")]
-pub async fn add_tarea(db:&State<Session>,userClaims:JwtGuard,tarea:Json<TareasHttp>,clients:&State<ClientesSockets>)->Result<Custom<Json<Vec<Tareas>>>,status::Custom<Json<AdduserResponse>>>{
-    match Tareas::add_tarea(&tarea, &userClaims.id_usuario, &db).await {
-        Ok(res)=>{
-            if res.len() > 0 {
-                let clients:Arc<Mutex<HashMap<String,Sender<String>>>>=clients.inner().clone();
-                Utils::send_ws(&clients,"new").await;
-                return Ok(
+pub async fn add_tarea(db:&State<Session>,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,tarea:Json<TareasHttp>,clients:&State<ClientesSockets>)->Result<Custom<Json<Vec<Tareas>>>,status::Custom<Json<AdduserResponse>>>{
+    idempotencia.ejecutar(&userClaims.id_usuario,&*tarea,db,async {
+        if tarea.validate().is_err() || tarea.nombre.trim().is_empty() {
+            return Err(
+                Custom(
+                    Status::BadRequest,
+                    Json(
+                        AdduserResponse{
+                            error:3
+                        }
+                    )
+                )
+            );
+        }
+        // solo se agregan subtareas a tareas que el usuario puede editar
+        if let Some(id_padre) = tarea.id_padre.as_deref() {
+            tarea_con_permiso(id_padre,&userClaims,Permiso::Editor,db).await?;
+        }
+        let flujo=match Flujos::get_para(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+            Ok(flujo)=>flujo,
+            Err(e)=>{
+                return Err(
                     Custom(
-                        Status::Ok, 
-                        Json(res)
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
                     )
                 );
-            }else{
+            }
+        };
+        match Tareas::add_tarea(&tarea, &userClaims.id_usuario, userClaims.id_espacio.as_deref(), &flujo.inicial().clave, &db).await {
+            Ok(res)=>{
+                if res.len() > 0 {
+                    Utils::send_ws_to(clients, &Tareas::destinatarios_de(&res, db).await, "new").await;
+                    return Ok(
+                        Custom(
+                            Status::Ok, 
+                            Json(res)
+                        )
+                    );
+                }else{
+                    Err(
+                        Custom(
+                            Status::BadRequest,
+                            Json(
+                                AdduserResponse{
+                                    error:1
+                                }
+                            )
+                        )
+                    )  
+                }
+            },
+            Err(e)=>{
                 Err(
                     Custom(
-                        Status::BadRequest,
+                        Status::InternalServerError,
                         Json(
                             AdduserResponse{
-                                error:1
+                                error:2
                             }
                         )
                     )
-                )  
-            }
-        },
-        Err(e)=>{
-            Err(
-                Custom(
-                    Status::InternalServerError,
-                    Json(
-                        AdduserResponse{
-                            error:2
-                        }
-                    )
                 )
-            )
+            }
         }
-    }
+    }).await
 }
 
 #[utoipa::path(
-    put,
-    path = "/tareas/{id}",
+    post,
+    path = "/tareas/rapida",
     responses(
-        (status = 201, description = "estado tarea cambiado", body = String),
-        (status = 400, description = "Id tarea no encontado", body = AdduserResponse),
+        (status = 200, description = "Lo que se entendio del texto y la tarea creada, sin crearla con `prueba=true`", body = TareaRapida),
+        (status = 400, description = "Texto vacio, sin nombre despues de interpretarlo o zona horaria invalida", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
         (status = 500, description = "Error interno del servidor", body = AdduserResponse),
         (status = 401, description = "No autorizado")
     ),
     params(
-        ("id" =String, Path, description = "id de la tarea  a obtener")
+        ("prueba" = Option<bool>, Query, description = "solo interpreta el texto, sin crear la tarea")
     ),
+    request_body=TareaRapidaHttp,
     security(
         ("jwt_token" = [])
     ),
     tag="tareas"
 )]
-#[put("/<id>")]
-pub async fn change_estado(db:&State<Session>,id:&str,userClaims:JwtGuard,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
-    match Tareas::change_estado(&true,id, &db).await {
-        Ok(res)=>{
-            if res{
-                let clients:Arc<Mutex<HashMap<String,Sender<String>>>>=clients.inner().clone();
-                Utils::send_ws(&clients,"update").await;
-                return Ok(
+#[post("/rapida?<prueba>",data="<rapida>")]
+pub async fn add_tarea_rapida(db:&State<Session>,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,rapida:Json<TareaRapidaHttp>,prueba:Option<bool>,clients:&State<ClientesSockets>)->Result<Custom<Json<TareaRapida>>,status::Custom<Json<AdduserResponse>>>{
+    let Some(zona) = Utils::zona_valida(rapida.zona).filter(|_| rapida.validate().is_ok()) else {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse{
+                        error:3
+                    }
+                )
+            )
+        );
+    };
+    let interpretacion=interpretar(&rapida.texto, Utils::current_timestamp(), zona * 60 * 1000);
+    if interpretacion.nombre.is_empty() {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse{
+                        error:3
+                    }
+                )
+            )
+        );
+    }
+    if prueba.unwrap_or(false) {
+        return Ok(
+            Custom(
+                Status::Ok,
+                Json(TareaRapida { interpretacion, tareas: vec![] })
+            )
+        );
+    }
+
+    idempotencia.ejecutar(&userClaims.id_usuario,&*rapida,db,async {
+        let flujo=match Flujos::get_para(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+            Ok(flujo)=>flujo,
+            Err(e)=>{
+                return Err(
                     Custom(
-                        Status::Ok, 
-                        "Ok ".to_string()
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
                     )
                 );
             }
-            Err(
-                Custom(
-                    Status::InternalServerError,
-                    Json(
-                        AdduserResponse{
-                            error:2
-                        }
+        };
+        match Tareas::add_tarea(&interpretacion.tarea(), &userClaims.id_usuario, userClaims.id_espacio.as_deref(), &flujo.inicial().clave, &db).await {
+            Ok(tareas)=>{
+                Utils::send_ws_to(clients, &Tareas::destinatarios_de(&tareas, db).await, "new").await;
+                Ok(
+                    Custom(
+                        Status::Ok,
+                        Json(TareaRapida { interpretacion, tareas })
                     )
                 )
-            )
-        },
-        Err(e)=>{
-            Err(
-                Custom(
-                    Status::BadRequest,
-                    Json(
-                        AdduserResponse{
-                            error:1
-                        }
+            },
+            Err(e)=>{
+                Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
                     )
                 )
-            )
-        }     
-    }
+            }
+        }
+    }).await
 }
 
 #[utoipa::path(
-    delete,
-    path = "/tareas/{id}",
+    post,
+    path = "/tareas/{id}/clonar",
     responses(
-        (status = 201, description = "tarea eliminada", body = String),
-        (status = 400, description = "Id tarea no encontado", body = AdduserResponse),
+        (status = 200, description = "Copia de la tarea y de sus subtareas, la copia de la tarea primero", body = Vec<Tareas>),
+        (status = 400, description = "La tarea tiene demasiadas subtareas para copiarla", body = AdduserResponse),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
         (status = 500, description = "Error interno del servidor", body = AdduserResponse),
         (status = 401, description = "No autorizado")
     ),
     params(
-        ("id" =String, Path, description = "id de la tarea  a obtener")
+        ("id" = String, Path, description = "id de la tarea a copiar")
     ),
     security(
         ("jwt_token" = [])
     ),
     tag="tareas"
 )]
-#[delete("/<id>")]
-pub async fn delete_tarea_by_id(db:&State<Session>,id:&str,userClaims:JwtGuard,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>> {
-    match Tareas::delete_tarea_by_id(&id, &db).await {
-        Ok(res)=>{
-            if res{
-
-                let clients:Arc<Mutex<HashMap<String,Sender<String>>>>=clients.inner().clone();
-                Utils::send_ws(&clients,"delete_one").await;
-                return Ok(
+#[post("/<id>/clonar")]
+pub async fn clonar_tarea(db:&State<Session>,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,id:&str,clients:&State<ClientesSockets>)->Result<Custom<Json<Vec<Tareas>>>,status::Custom<Json<AdduserResponse>>>{
+    idempotencia.ejecutar(&userClaims.id_usuario,&(),db,async {
+        let tarea=tarea_con_permiso(id,&userClaims,Permiso::Lector,db).await?;
+        let arbol=match tarea.get_arbol(db).await {
+            Ok(arbol)=>arbol,
+            Err(e)=>{
+                return Err(
                     Custom(
-                        Status::Ok, 
-                        "Ok ".to_string()
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
                     )
                 );
             }
-            Err(
+        };
+        let copia=arbol.a_nodo(arbol.tarea.id_padre.clone());
+        if copia.cantidad() > ARBOL_MAX_TAREAS {
+            return Err(
                 Custom(
-                    Status::InternalServerError,
+                    Status::BadRequest,
                     Json(
                         AdduserResponse{
-                            error:2
+                            error:3
                         }
                     )
                 )
-            )
-        },
-        Err(e)=>{
-            Err(
-                Custom(
-                    Status::BadRequest,
-                    Json(
-                        AdduserResponse{
-                            error:1
-                        }
+            );
+        }
+        let flujo=match Flujos::get_para(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+            Ok(flujo)=>flujo,
+            Err(e)=>{
+                return Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                );
+            }
+        };
+        match Tareas::add_arbol(&copia, &userClaims.id_usuario, userClaims.id_espacio.as_deref(), &flujo.inicial().clave, db).await {
+            Ok(res)=>{
+                Utils::send_ws_to(clients, &Tareas::destinatarios_de(&res, db).await, "new").await;
+                Ok(
+                    Custom(
+                        Status::Ok,
+                        Json(res)
                     )
                 )
-            )
-        }     
-    }
-    
+            },
+            Err(e)=>{
+                Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                )
+            }
+        }
+    }).await
 }
 
 #[utoipa::path(
-    delete,
-    path = "/tareas/",
+    post,
+    path = "/tareas/batch",
     responses(
-        (status = 201, description = "tarea eliminada", body = String),
-        (status = 400, description = "Id tarea no encontado", body = AdduserResponse),
+        (status = 200, description = "Resultado de cada operacion con su status y codigo de error", body = LoteResultado),
+        (status = 400, description = "Lote vacio o con mas de 100 operaciones", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
         (status = 500, description = "Error interno del servidor", body = AdduserResponse),
         (status = 401, description = "No autorizado")
     ),
+    request_body=LoteHttp,
     security(
         ("jwt_token" = [])
     ),
     tag="tareas"
 )]
-#[delete("/")]
-pub async fn delete_tareas_by_id_user(db:&State<Session>,userClaims:JwtGuard,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>> {
-    match Tareas::delete_all_tareas_by_usuario(&userClaims.id_usuario,&db).await {
-        Ok(res)=>{
-            if res{
-                let clients:Arc<Mutex<HashMap<String,Sender<String>>>>=clients.inner().clone();
-                Utils::send_ws(&clients,"delete_all").await;
-                return Ok(
-                    Custom(
-                        Status::Ok, 
-                        "Ok ".to_string()
-                    )
-                );
+#[post("/batch",data="<lote>")]
+pub async fn batch_tareas(db:&State<Session>,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,lote:Json<LoteHttp>,clients:&State<ClientesSockets>)->Result<Custom<Json<LoteResultado>>,status::Custom<Json<AdduserResponse>>>{
+    idempotencia.ejecutar(&userClaims.id_usuario,&*lote,db,async {
+        if lote.validate().is_err() {
+            return Err(
+                Custom(
+                    Status::BadRequest,
+                    Json(
+                        AdduserResponse { error: 3 }
+                    )
+                )
+            );
+        }
+
+        match Tareas::ejecutar_lote(&lote,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+            Ok((res,afectadas))=>{
+                // un solo evento para todo el lote, a todos los que ven alguna de las tareas
+                let mut destinatarios=Tareas::destinatarios_de(&afectadas, db).await;
+                if !destinatarios.contains(&userClaims.id_usuario) {
+                    destinatarios.push(userClaims.id_usuario.clone());
+                }
+                if res.aplicadas > 0 {
+                    Utils::send_ws_to(clients, &destinatarios, "batch").await;
+                }
+                Ok(
+                    Custom(
+                        Status::Ok,
+                        Json(res)
+                    )
+                )
+            },
+            Err(e)=>{
+                Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                )
+            }
+        }
+    }).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/tareas/{id}",
+    responses(
+        (status = 201, description = "estado tarea cambiado", body = String),
+        (status = 400, description = "Id tarea no encontado", body = AdduserResponse),
+        (status = 403, description = "Sin permiso de edicion sobre la tarea", body = AdduserResponse),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 409, description = "La tarea esta bloqueada por tareas pendientes", body = AdduserResponse),
+        (status = 412, description = "La tarea cambio desde la version de `If-Match`", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea  a obtener"),
+        ("forzar" = Option<bool>, Query, description = "completar aunque tenga bloqueos pendientes")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[put("/<id>?<forzar>")]
+pub async fn change_estado(db:&State<Session>,id:&str,forzar:Option<bool>,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,if_match:IfMatch,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    idempotencia.ejecutar(&userClaims.id_usuario,&(),db,async {
+        let tarea=tarea_con_permiso(id,&userClaims,Permiso::Editor,db).await?;
+        comprobar_version(&tarea,&if_match)?;
+        mover_a_etapa(&tarea,None,forzar.unwrap_or(false),&userClaims.id_usuario,if_match.0,db,clients).await
+    }).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/tareas/{id}/etapa",
+    responses(
+        (status = 200, description = "Tarea movida al estado", body = String),
+        (status = 400, description = "El estado no existe en el flujo de la tarea", body = AdduserResponse),
+        (status = 403, description = "Sin permiso de edicion sobre la tarea", body = AdduserResponse),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 409, description = "Transicion no permitida, limite WIP alcanzado o bloqueos pendientes", body = AdduserResponse),
+        (status = 412, description = "La tarea cambio desde la version de `If-Match`", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea"),
+        ("forzar" = Option<bool>, Query, description = "pasar al estado final aunque tenga bloqueos pendientes")
+    ),
+    request_body=EtapaHttp,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[put("/<id>/etapa?<forzar>",data="<etapa>")]
+pub async fn change_etapa(db:&State<Session>,id:&str,forzar:Option<bool>,etapa:Json<EtapaHttp>,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,if_match:IfMatch,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    idempotencia.ejecutar(&userClaims.id_usuario,&*etapa,db,async {
+        let tarea=tarea_con_permiso(id,&userClaims,Permiso::Editor,db).await?;
+        comprobar_version(&tarea,&if_match)?;
+        mover_a_etapa(&tarea,Some(&etapa.etapa),forzar.unwrap_or(false),&userClaims.id_usuario,if_match.0,db,clients).await
+    }).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/tareas/{id}/posicion",
+    responses(
+        (status = 200, description = "Tarea movida entre sus nuevos vecinos", body = String),
+        (status = 400, description = "Sin vecinos, vecino inexistente o la misma tarea", body = AdduserResponse),
+        (status = 403, description = "Sin permiso de edicion sobre la tarea", body = AdduserResponse),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 409, description = "Los vecinos ya no estan en ese orden, hay que volver a leer el listado", body = AdduserResponse),
+        (status = 412, description = "La tarea cambio desde la version de `If-Match`", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea a mover")
+    ),
+    request_body=MoverHttp,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[put("/<id>/posicion",data="<mover>")]
+pub async fn mover_tarea(db:&State<Session>,id:&str,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,if_match:IfMatch,mover:Json<MoverHttp>,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    idempotencia.ejecutar(&userClaims.id_usuario,&*mover,db,async {
+        let tarea=tarea_con_permiso(id,&userClaims,Permiso::Editor,db).await?;
+        comprobar_version(&tarea,&if_match)?;
+        let vecinos=[mover.antes.as_deref(),mover.despues.as_deref()];
+        if vecinos.iter().all(Option::is_none) || vecinos.contains(&Some(id)) {
+            return Err(
+                Custom(
+                    Status::BadRequest,
+                    Json(
+                        AdduserResponse { error: 3 }
+                    )
+                )
+            );
+        }
+
+        let mut posiciones=[None,None];
+        for (i,vecino) in vecinos.iter().enumerate() {
+            let Some(vecino) = vecino else { continue };
+            match Tareas::get_visible(vecino,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+                Ok(Some(vecino))=>posiciones[i]=Some(vecino.posicion),
+                Ok(None)=>{
+                    return Err(
+                        Custom(
+                            Status::BadRequest,
+                            Json(
+                                AdduserResponse { error: 3 }
+                            )
+                        )
+                    );
+                },
+                Err(e)=>{
+                    return Err(
+                        Custom(
+                            Status::InternalServerError,
+                            Json(
+                                AdduserResponse{
+                                    error:2
+                                }
+                            )
+                        )
+                    );
+                }
+            }
+        }
+
+        // los vecinos se leen recien ahora, si otro los movio y quedaron al reves se rechaza
+        let Some(posicion) = posicion_entre(posiciones[0].as_deref(),posiciones[1].as_deref()) else {
+            return Err(
+                Custom(
+                    Status::Conflict,
+                    Json(
+                        AdduserResponse{
+                            error:11
+                        }
+                    )
+                )
+            );
+        };
+        match Tareas::mover(&tarea.id,&posicion,&userClaims.id_usuario,if_match.0,db).await {
+            Ok(false) if if_match.0.is_some()=>{
+                Err(
+                    Custom(
+                        Status::PreconditionFailed,
+                        Json(
+                            AdduserResponse{
+                                error:13
+                            }
+                        )
+                    )
+                )
+            },
+            Ok(true)=>{
+                Utils::send_ws_to(clients, &tarea.destinatarios(db).await, "move").await;
+                Ok(
+                    Custom(
+                        Status::Ok,
+                        "Ok ".to_string()
+                    )
+                )
+            },
+            _=>{
+                Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                )
+            }
+        }
+    }).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/tareas/tablero",
+    responses(
+        (status = 200, description = "Tareas visibles agrupadas por estado del flujo activo", body = Tablero),
+        (status = 304, description = "Sin cambios desde la etag de `If-None-Match`"),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[get("/tablero")]
+pub async fn get_tablero(userClaims:JwtGuard,if_none_match:IfNoneMatch,db:&State<Session>)->Result<ConEtag<Custom<Json<Tablero>>>,status::Custom<Json<AdduserResponse>>>{
+    let flujo=Flujos::get_para(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await;
+    let tareas=Tareas::get_todas_visibles(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await;
+    match (flujo,tareas) {
+        (Ok(flujo),Ok(tareas))=>{
+            let res=Tablero::construir(flujo,tareas);
+            let etag=etag_json(&res);
+            Ok(
+                ConEtag::nueva(
+                    Custom(
+                        Status::Ok,
+                        Json(res)
+                    ),
+                    etag,
+                    &if_none_match
+                )
+            )
+        },
+        _=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/tareas/estadisticas",
+    responses(
+        (status = 200, description = "Creadas y completadas por periodo, tasa de completado, tiempo promedio, rachas y vencidas", body = Estadisticas),
+        (status = 400, description = "Rango, zona horaria o agrupacion invalidos", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        EstadisticasQuery
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[get("/estadisticas?<params..>")]
+pub async fn get_estadisticas(userClaims:JwtGuard,db:&State<Session>,params:EstadisticasQuery)->Result<Custom<Json<Estadisticas>>,status::Custom<Json<AdduserResponse>>>{
+    let Some(rango) = params.rango() else {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse{
+                        error:3
+                    }
+                )
+            )
+        );
+    };
+
+    match Tareas::estadisticas(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),rango,db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/tareas/export",
+    responses(
+        (status = 200, description = "ZIP con `tareas.json`, `tareas.csv` y `manifest.json` (fecha de exportacion y version del esquema)", content_type = "application/zip"),
+        (status = 400, description = "Rango de fechas invalido", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ExportarQuery
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[get("/export?<params..>")]
+pub async fn exportar_tareas(userClaims:JwtGuard,db:&State<Session>,params:ExportarQuery)->Result<Exportacion,status::Custom<Json<AdduserResponse>>>{
+    if !params.is_valid() {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse{
+                        error:3
+                    }
+                )
+            )
+        );
+    }
+
+    match Tareas::exportar(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),&params,db).await {
+        Ok(res)=>{
+            Ok(res)
+        },
+        Err(ErrorExportacion::Archivo(e))=>{
+            println!("error archivo exportacion {}",e);
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        },
+        Err(ErrorExportacion::Db(e))=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/tareas/import",
+    responses(
+        (status = 200, description = "Resultado por fila: las creadas con su id y las omitidas con sus errores", body = ResultadoImportacion),
+        (status = 400, description = "El archivo no tiene el formato de `origen`, o `origen` no es `tareas`, `todoist` ni `trello`", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 413, description = "El archivo o la cantidad de tareas superan el maximo", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("prueba" = Option<bool>, Query, description = "solo informa que se crearia y que se omitiria, sin guardar"),
+        ("origen" = Option<String>, Query, description = "`tareas` (default), `todoist` o `trello`")
+    ),
+    request_body(content = String, content_type = "text/csv", description = "Con `origen=tareas` un CSV con encabezado, arreglo JSON de tareas o el ZIP de `GET /tareas/export`; con `todoist` el CSV de un proyecto, el ZIP del respaldo o el JSON de sincronizacion; con `trello` el JSON o CSV de un tablero"),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[post("/import?<prueba>&<origen>",data="<data>")]
+pub async fn importar_tareas(db:&State<Session>,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,prueba:Option<bool>,origen:Option<&str>,data:Data<'_>,limits:&Limits,clients:&State<ClientesSockets>)->Result<Custom<Json<ResultadoImportacion>>,status::Custom<Json<AdduserResponse>>>{
+    let origen=match OrigenImportacion::desde(origen) {
+        Some(origen)=>origen,
+        None=>{
+            return Err(
+                Custom(
+                    Status::BadRequest,
+                    Json(
+                        AdduserResponse{
+                            error:3
+                        }
+                    )
+                )
+            );
+        }
+    };
+    let max_bytes=limits.get("importar").unwrap_or(IMPORTAR_MAX_BYTES.bytes());
+    let contenido=match data.open(max_bytes).into_bytes().await {
+        Ok(contenido) if contenido.is_complete()=>contenido.into_inner(),
+        Ok(_)=>{
+            return Err(
+                Custom(
+                    Status::PayloadTooLarge,
+                    Json(
+                        AdduserResponse{
+                            error:5
+                        }
+                    )
+                )
+            );
+        },
+        Err(e)=>{
+            println!("error leer importacion {}",e);
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+    // `origen` y `prueba` ya son parte de la huella por estar en la ruta
+    let huella=Utils::sha256_hex(&contenido);
+
+    idempotencia.ejecutar(&userClaims.id_usuario,&huella,db,async {
+        let filas=match origen.leer(&contenido,max_bytes.as_u64()) {
+            Ok(filas)=>filas,
+            Err(ErrorImportacion::Formato)=>{
+                return Err(
+                    Custom(
+                        Status::BadRequest,
+                        Json(
+                            AdduserResponse{
+                                error:3
+                            }
+                        )
+                    )
+                );
+            },
+            Err(ErrorImportacion::Tamanio)=>{
+                return Err(
+                    Custom(
+                        Status::PayloadTooLarge,
+                        Json(
+                            AdduserResponse{
+                                error:5
+                            }
+                        )
+                    )
+                );
+            }
+        };
+        let flujo=match Flujos::get_para(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+            Ok(flujo)=>flujo,
+            Err(e)=>{
+                return Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                );
+            }
+        };
+        let prueba=prueba.unwrap_or(false);
+        match Tareas::importar(filas, prueba, &userClaims.id_usuario, userClaims.id_espacio.as_deref(), &flujo.inicial().clave, &flujo.final_().clave, db).await {
+            Ok((res,creadas))=>{
+                // un solo aviso para todo el archivo
+                if !prueba && res.creadas > 0 {
+                    Utils::send_ws_to(clients, &Tareas::destinatarios_de(&creadas, db).await, "import").await;
+                }
+                Ok(
+                    Custom(
+                        Status::Ok,
+                        Json(res)
+                    )
+                )
+            },
+            Err(e)=>{
+                Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                )
+            }
+        }
+    }).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/tareas/todo.txt",
+    responses(
+        (status = 200, description = "Tareas en formato todo.txt, una por linea con su `id:`, con header `ETag`", content_type = "text/plain"),
+        (status = 304, description = "Sin cambios desde la etag de `If-None-Match`"),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("estado" = Option<bool>, Query, description = "solo las completadas (`true`) o las pendientes (`false`)")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[get("/todo.txt?<estado>")]
+pub async fn get_todo_txt(userClaims:JwtGuard,if_none_match:IfNoneMatch,db:&State<Session>,estado:Option<bool>)->Result<ConEtag<(ContentType,String)>,status::Custom<Json<AdduserResponse>>>{
+    match Tareas::get_todas_visibles(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(mut tareas)=>{
+            if let Some(estado) = estado {
+                tareas.retain(|t| t.estado == estado);
+            }
+            let texto=todo_txt(&tareas);
+            let etag=etag_json(&texto);
+            Ok(ConEtag::nueva((ContentType::Plain,texto),etag,&if_none_match))
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/tareas/todo.txt",
+    responses(
+        (status = 200, description = "Resultado por linea: creada, actualizada, sin cambios u omitida con sus errores", body = ResultadoTodoTxt),
+        (status = 400, description = "El cuerpo no es texto UTF-8", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 412, description = "Las tareas cambiaron desde la etag de `GET /tareas/todo.txt` enviada en `If-Match`", body = AdduserResponse),
+        (status = 413, description = "El archivo o la cantidad de lineas superan el maximo", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("prueba" = Option<bool>, Query, description = "solo informa que se crearia y que se actualizaria, sin guardar")
+    ),
+    request_body(content = String, content_type = "text/plain", description = "Archivo todo.txt, una tarea por linea; `id:` identifica las tareas que ya existen"),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[put("/todo.txt?<prueba>",data="<data>")]
+pub async fn put_todo_txt(db:&State<Session>,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,if_match:IfMatchListado,prueba:Option<bool>,data:Data<'_>,limits:&Limits,clients:&State<ClientesSockets>)->Result<Custom<Json<ResultadoTodoTxt>>,status::Custom<Json<AdduserResponse>>>{
+    let max_bytes=limits.get("importar").unwrap_or(IMPORTAR_MAX_BYTES.bytes());
+    let contenido=match data.open(max_bytes).into_bytes().await {
+        Ok(contenido) if contenido.is_complete()=>contenido.into_inner(),
+        Ok(_)=>{
+            return Err(
+                Custom(
+                    Status::PayloadTooLarge,
+                    Json(
+                        AdduserResponse{
+                            error:5
+                        }
+                    )
+                )
+            );
+        },
+        Err(e)=>{
+            println!("error leer todo.txt {}",e);
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+    // `prueba` ya es parte de la huella por estar en la ruta
+    let huella=Utils::sha256_hex(&contenido);
+
+    idempotencia.ejecutar(&userClaims.id_usuario,&huella,db,async {
+        let Ok(texto) = String::from_utf8(contenido) else {
+            return Err(
+                Custom(
+                    Status::BadRequest,
+                    Json(
+                        AdduserResponse{
+                            error:3
+                        }
+                    )
+                )
+            );
+        };
+        let lineas=leer_todo_txt(&texto);
+        if lineas.len() > IMPORTAR_MAX_FILAS {
+            return Err(
+                Custom(
+                    Status::PayloadTooLarge,
+                    Json(
+                        AdduserResponse{
+                            error:5
+                        }
+                    )
+                )
+            );
+        }
+        let prueba=prueba.unwrap_or(false);
+        let (res,tocadas)=sincronizar_todo_txt(lineas,prueba,&if_match,&userClaims,db).await?;
+        // un solo aviso para todo el archivo, tambien por los cambios de estado
+        if !tocadas.is_empty() {
+            Utils::send_ws_to(clients, &Tareas::destinatarios_de(&tocadas, db).await, "import").await;
+        }
+        Ok(
+            Custom(
+                Status::Ok,
+                Json(res)
+            )
+        )
+    }).await
+}
+
+/// aplica las lineas sobre las tareas visibles: la que trae `id:` actualiza esa tarea y la que no
+/// lo trae se busca por nombre entre las que ninguna linea nombra, asi volver a subir un archivo
+/// sin ids no duplica tareas; si no se encuentra se crea. Las tareas que no estan en el archivo
+/// no se tocan. Con `If-Match` el archivo tiene que venir de la etag actual de
+/// `GET /tareas/todo.txt` (`412`). Un error de la base en una linea queda en sus `errores` y
+/// sigue con las demas, asi las que ya se escribieron se avisan igual. No emite eventos, quien
+/// llama avisa una vez por todas las tareas tocadas
+async fn sincronizar_todo_txt(lineas:Vec<(usize,&str)>,prueba:bool,if_match:&IfMatchListado,userClaims:&JwtGuard,db:&Session)->Result<(ResultadoTodoTxt,Vec<Tareas>),status::Custom<Json<AdduserResponse>>>{
+    let error_interno=||{
+        Custom(
+            Status::InternalServerError,
+            Json(
+                AdduserResponse{
+                    error:2
+                }
+            )
+        )
+    };
+    let tareas=match Tareas::get_todas_visibles(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(tareas)=>tareas,
+        Err(e)=>return Err(error_interno()),
+    };
+    if !if_match.coincide(&etag_json(&todo_txt(&tareas))) {
+        return Err(
+            Custom(
+                Status::PreconditionFailed,
+                Json(
+                    AdduserResponse{
+                        error:13
+                    }
+                )
+            )
+        );
+    }
+    let flujo=match Flujos::get_para(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(flujo)=>flujo,
+        Err(e)=>return Err(error_interno()),
+    };
+    let leidas:Vec<(usize,Result<TareaTodoTxt,Vec<String>>,Vec<String>)>=lineas.into_iter()
+        .map(|(numero,linea)| {
+            let (leida,avisos)=leer_linea_todo_txt(linea);
+            (numero,leida,avisos)
+        })
+        .collect();
+    let nombradas:HashSet<&str>=leidas.iter()
+        .filter_map(|(_,leida,_)| leida.as_ref().ok()?.id.as_deref())
+        .collect();
+    let mut por_nombre:HashMap<String,Vec<&Tareas>>=HashMap::new();
+    for tarea in tareas.iter().filter(|t| !nombradas.contains(t.id.as_str())) {
+        let nombre=tarea.nombre.split_whitespace().collect::<Vec<_>>().join(" ");
+        por_nombre.entry(nombre).or_default().push(tarea);
+    }
+    let por_id:HashMap<&str,&Tareas>=tareas.iter().map(|t| (t.id.as_str(),t)).collect();
+    let mut usadas:HashSet<&str>=HashSet::new();
+
+    let mut resultados:Vec<LineaTodoTxt>=Vec::new();
+    // creadas y actualizadas, para avisar a quienes las ven
+    let mut tocadas:Vec<Tareas>=Vec::new();
+    for (numero,leida,avisos) in leidas.iter() {
+        let mut linea=LineaTodoTxt {
+            linea: *numero,
+            nombre: None,
+            accion: AccionTodoTxt::Omitir,
+            id: None,
+            errores: Vec::new(),
+            avisos: avisos.clone(),
+        };
+        let leida=match leida {
+            Ok(leida)=>leida,
+            Err(errores)=>{
+                linea.errores=errores.clone();
+                resultados.push(linea);
+                continue;
+            }
+        };
+        linea.nombre=Some(leida.tarea.nombre.clone());
+        let existente=match leida.id.as_deref() {
+            Some(id)=>match por_id.get(id) {
+                Some(tarea) if usadas.insert(id)=>Some(*tarea),
+                Some(_)=>{
+                    linea.errores.push(format!("id {} repetido, ya se aplico otra linea",id));
+                    resultados.push(linea);
+                    continue;
+                },
+                None=>{
+                    linea.errores.push(format!("id {} no es de una tarea visible",id));
+                    resultados.push(linea);
+                    continue;
+                }
+            },
+            None=>por_nombre.get_mut(&leida.tarea.nombre).and_then(|tareas| tareas.pop()),
+        };
+
+        let Some(tarea) = existente else {
+            linea.accion=AccionTodoTxt::Crear;
+            if !prueba {
+                let nueva=match Tareas::add_tarea(&leida.tarea,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),&flujo.inicial().clave,db).await {
+                    Ok(res)=>res.into_iter().next(),
+                    Err(e)=>{
+                        linea.accion=AccionTodoTxt::Omitir;
+                        linea.errores.push("no se pudo crear, error 2".to_string());
+                        resultados.push(linea);
+                        continue;
+                    }
+                };
+                if let Some(nueva) = nueva {
+                    linea.id=Some(nueva.id.clone());
+                    if leida.completada {
+                        if let Err(e) = cambiar_etapa(&nueva,None,false,&userClaims.id_usuario,None,db).await {
+                            linea.errores.push(format!("se creo pero no se pudo completar, error {}",e.1.error));
+                        }
+                    }
+                    tocadas.push(nueva);
+                }
+            }
+            resultados.push(linea);
+            continue;
+        };
+
+        linea.id=Some(tarea.id.clone());
+        let cambios=leida.cambios(tarea);
+        let cambia_estado=leida.completada != tarea.estado;
+        if cambios.is_none() && !cambia_estado {
+            linea.accion=AccionTodoTxt::SinCambios;
+            resultados.push(linea);
+            continue;
+        }
+        match tarea.permiso(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+            Ok(Some(permiso)) if permiso >= Permiso::Editor=>{},
+            Ok(_)=>{
+                linea.errores.push("sin permiso para editarla".to_string());
+                resultados.push(linea);
+                continue;
+            },
+            Err(e)=>{
+                linea.errores.push("no se pudo comprobar el permiso, error 2".to_string());
+                resultados.push(linea);
+                continue;
+            }
+        }
+        linea.accion=AccionTodoTxt::Actualizar;
+        if prueba {
+            resultados.push(linea);
+            continue;
+        }
+        if let Some(cambios) = cambios {
+            match Tareas::editar(&tarea.id,&cambios,&userClaims.id_usuario,Some(tarea.version),db).await {
+                Ok(true)=>{},
+                Ok(false)=>{
+                    linea.accion=AccionTodoTxt::Omitir;
+                    linea.errores.push("cambio mientras se sincronizaba, vuelve a pedir el archivo".to_string());
+                    resultados.push(linea);
+                    continue;
+                },
+                Err(e)=>{
+                    linea.accion=AccionTodoTxt::Omitir;
+                    linea.errores.push("no se pudo actualizar, error 2".to_string());
+                    resultados.push(linea);
+                    continue;
+                }
+            }
+        }
+        if cambia_estado {
+            let destino=if leida.completada { None } else { Some(flujo.inicial().clave.as_str()) };
+            if let Err(e) = cambiar_etapa(tarea,destino,false,&userClaims.id_usuario,None,db).await {
+                linea.errores.push(format!("no se pudo cambiar el estado, error {}",e.1.error));
+            }
+        }
+        tocadas.push(tarea.clone());
+        resultados.push(linea);
+    }
+
+    let contar=|accion:AccionTodoTxt| resultados.iter().filter(|l| l.accion == accion).count();
+    Ok((ResultadoTodoTxt {
+        prueba,
+        creadas: contar(AccionTodoTxt::Crear),
+        actualizadas: contar(AccionTodoTxt::Actualizar),
+        sin_cambios: contar(AccionTodoTxt::SinCambios),
+        omitidas: contar(AccionTodoTxt::Omitir),
+        lineas: resultados,
+    },tocadas))
+}
+
+/// mueve la tarea al estado `destino` de su flujo, o al final si es `None`, con las reglas de
+/// `cambiar_etapa`, y emite `update`
+pub async fn mover_a_etapa(tarea:&Tareas,destino:Option<&str>,forzar:bool,actor:&str,esperado:Option<i64>,db:&Session,clients:&ClientesSockets)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    let res=cambiar_etapa(tarea,destino,forzar,actor,esperado,db).await?;
+    Utils::send_ws_to(clients, &tarea.destinatarios(db).await, "update").await;
+    Ok(res)
+}
+
+/// mueve la tarea al estado `destino` de su flujo, o al final si es `None`, validando
+/// que exista (400), la transicion y el limite WIP (409) y los bloqueos si es el final (409).
+/// No emite eventos, para quien avisa una vez por varias tareas
+pub async fn cambiar_etapa(tarea:&Tareas,destino:Option<&str>,forzar:bool,actor:&str,esperado:Option<i64>,db:&Session)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    let flujo=match Flujos::get_para(&tarea.id_usuario,tarea.id_espacio.as_deref(),db).await {
+        Ok(flujo)=>flujo,
+        Err(e)=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+    let destino=match destino {
+        Some(clave)=>match flujo.estado(clave) {
+            Some(estado)=>estado,
+            None=>{
+                return Err(
+                    Custom(
+                        Status::BadRequest,
+                        Json(
+                            AdduserResponse{
+                                error:3
+                            }
+                        )
+                    )
+                );
+            }
+        },
+        None=>flujo.final_(),
+    };
+    let actual=flujo.etapa_de(tarea.etapa.as_deref(), tarea.estado);
+    if !flujo.permite(&actual.clave,&destino.clave) {
+        return Err(
+            Custom(
+                Status::Conflict,
+                Json(
+                    AdduserResponse{
+                        error:10
+                    }
+                )
+            )
+        );
+    }
+    if actual.clave != destino.clave {
+        if let Some(limite) = destino.limite_wip {
+            match tarea.count_en_etapa(&destino.clave,db).await {
+                Ok(n) if n >= limite as u64=>{
+                    return Err(
+                        Custom(
+                            Status::Conflict,
+                            Json(
+                                AdduserResponse{
+                                    error:9
+                                }
+                            )
+                        )
+                    );
+                },
+                Ok(_)=>{},
+                Err(e)=>{
+                    return Err(
+                        Custom(
+                            Status::InternalServerError,
+                            Json(
+                                AdduserResponse{
+                                    error:2
+                                }
+                            )
+                        )
+                    );
+                }
+            }
+        }
+    }
+    let completada=destino.clave == flujo.final_().clave;
+    if completada && !forzar {
+        match tarea.bloqueadoras_abiertas(db).await {
+            Ok(res) if !res.is_empty()=>{
+                return Err(
+                    Custom(
+                        Status::Conflict,
+                        Json(
+                            AdduserResponse{
+                                error:8
+                            }
+                        )
+                    )
+                );
+            },
+            Ok(_)=>{},
+            Err(e)=>{
+                return Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                );
+            }
+        }
+    }
+    match Tareas::change_estado(&completada,&destino.clave,&tarea.id,actor,esperado,db).await {
+        Ok(false) if esperado.is_some()=>{
+            Err(
+                Custom(
+                    Status::PreconditionFailed,
+                    Json(
+                        AdduserResponse{
+                            error:13
+                        }
+                    )
+                )
+            )
+        },
+        Ok(res)=>{
+            if res{
+                return Ok(
+                    Custom(
+                        Status::Ok, 
+                        "Ok ".to_string()
+                    )
+                );
+            }
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::BadRequest,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        }     
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/tareas/{id}",
+    responses(
+        (status = 201, description = "tarea movida a la papelera", body = String),
+        (status = 400, description = "Id tarea no encontado", body = AdduserResponse),
+        (status = 403, description = "Sin permiso de edicion sobre la tarea", body = AdduserResponse),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 412, description = "La tarea cambio desde la version de `If-Match`", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea  a obtener")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[delete("/<id>")]
+pub async fn delete_tarea_by_id(db:&State<Session>,id:&str,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,if_match:IfMatch,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>> {
+    idempotencia.ejecutar(&userClaims.id_usuario,&(),db,async {
+        let tarea=tarea_con_permiso(id,&userClaims,Permiso::Editor,db).await?;
+        comprobar_version(&tarea,&if_match)?;
+        match Tareas::delete_tarea_by_id(&id,&userClaims.id_usuario,if_match.0,&db).await {
+            Ok(false) if if_match.0.is_some()=>{
+                Err(
+                    Custom(
+                        Status::PreconditionFailed,
+                        Json(
+                            AdduserResponse{
+                                error:13
+                            }
+                        )
+                    )
+                )
+            },
+            Ok(res)=>{
+                if res{
+                    Utils::send_ws_to(clients, &tarea.destinatarios(db).await, "trash").await;
+                    return Ok(
+                        Custom(
+                            Status::Ok, 
+                            "Ok ".to_string()
+                        )
+                    );
+                }
+                Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                )
+            },
+            Err(e)=>{
+                Err(
+                    Custom(
+                        Status::BadRequest,
+                        Json(
+                            AdduserResponse{
+                                error:1
+                            }
+                        )
+                    )
+                )
+            }     
+        }
+    
+    }).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/tareas/",
+    responses(
+        (status = 201, description = "tareas movidas a la papelera", body = String),
+        (status = 400, description = "Id tarea no encontado", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[delete("/")]
+pub async fn delete_tareas_by_id_user(db:&State<Session>,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>> {
+    idempotencia.ejecutar(&userClaims.id_usuario,&(),db,async {
+        match Tareas::delete_all_tareas_by_usuario(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),&db).await {
+            Ok(res)=>{
+                if !res.is_empty() {
+                    Utils::send_ws_to(clients, &Tareas::destinatarios_de(&res, db).await, "trash_all").await;
+                    return Ok(
+                        Custom(
+                            Status::Ok, 
+                            "Ok ".to_string()
+                        )
+                    );
+                }
+                Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                )
+            },
+            Err(e)=>{
+                Err(
+                    Custom(
+                        Status::BadRequest,
+                        Json(
+                            AdduserResponse{
+                                error:1
+                            }
+                        )
+                    )
+                )
+            }     
+        }
+    
+    }).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/tareas/dependencias",
+    responses(
+        (status = 200, description = "Grafo de dependencias de las tareas visibles y orden sugerido", body = GrafoDependencias),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[get("/dependencias")]
+pub async fn get_dependencias(userClaims:JwtGuard,db:&State<Session>)->Result<Custom<Json<GrafoDependencias>>,status::Custom<Json<AdduserResponse>>>{
+    match Tareas::get_todas_visibles(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok, 
+                    Json(GrafoDependencias::construir(&res))
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/tareas/{id}/dependencias",
+    responses(
+        (status = 200, description = "Dependencias reemplazadas", body = String),
+        (status = 400, description = "Alguna tarea bloqueadora no existe o no es visible", body = AdduserResponse),
+        (status = 403, description = "Sin permiso de edicion sobre la tarea", body = AdduserResponse),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 409, description = "Las dependencias formarian un ciclo", body = AdduserResponse),
+        (status = 412, description = "La tarea cambio desde la version de `If-Match`", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea bloqueada")
+    ),
+    request_body=DependenciasHttp,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[put("/<id>/dependencias",data="<dependencias>")]
+pub async fn set_dependencias(db:&State<Session>,id:&str,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,if_match:IfMatch,dependencias:Json<DependenciasHttp>,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    idempotencia.ejecutar(&userClaims.id_usuario,&*dependencias,db,async {
+        let tarea=tarea_con_permiso(id,&userClaims,Permiso::Editor,db).await?;
+        comprobar_version(&tarea,&if_match)?;
+        let mut bloqueada_por=dependencias.bloqueada_por.clone();
+        bloqueada_por.sort();
+        bloqueada_por.dedup();
+        let mut validas=dependencias.validate().is_ok();
+        for bloqueadora in bloqueada_por.iter() {
+            if !validas {
+                break;
+            }
+            validas=matches!(Tareas::get_visible(bloqueadora,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await, Ok(Some(_)));
+        }
+        if !validas {
+            return Err(
+                Custom(
+                    Status::BadRequest,
+                    Json(
+                        AdduserResponse{
+                            error:3
+                        }
+                    )
+                )
+            );
+        }
+
+        let _turno=Tareas::edicion_dependencias().await;
+        match Tareas::crea_ciclo(&tarea.id,&bloqueada_por,db).await {
+            Ok(false)=>{},
+            Ok(true)=>{
+                return Err(
+                    Custom(
+                        Status::Conflict,
+                        Json(
+                            AdduserResponse{
+                                error:7
+                            }
+                        )
+                    )
+                );
+            },
+            Err(e)=>{
+                return Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                );
+            }
+        }
+
+        match Tareas::set_dependencias(&tarea.id,&bloqueada_por,&userClaims.id_usuario,if_match.0,db).await {
+            Ok(false) if if_match.0.is_some()=>{
+                Err(
+                    Custom(
+                        Status::PreconditionFailed,
+                        Json(
+                            AdduserResponse{
+                                error:13
+                            }
+                        )
+                    )
+                )
+            },
+            Ok(true)=>{
+                Utils::send_ws_to(clients, &tarea.destinatarios(db).await, "dependencies").await;
+                Ok(
+                    Custom(
+                        Status::Ok, 
+                        "Ok ".to_string()
+                    )
+                )
+            },
+            _=>{
+                Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                )
             }
+        }
+    }).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/tareas/asignadas",
+    responses(
+        (status = 200, description = "Tareas asignadas al usuario en el espacio activo", body = Vec<Tareas>),
+        (status = 304, description = "Sin cambios desde la etag de `If-None-Match`"),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[get("/asignadas")]
+pub async fn get_asignadas(userClaims:JwtGuard,if_none_match:IfNoneMatch,db:&State<Session>)->Result<ConEtag<Custom<Json<Vec<Tareas>>>>,status::Custom<Json<AdduserResponse>>>{
+    match Tareas::get_asignadas(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(res)=>{
+            let etag=etag_json(&res);
+            Ok(
+                ConEtag::nueva(
+                    Custom(
+                        Status::Ok,
+                        Json(res)
+                    ),
+                    etag,
+                    &if_none_match
+                )
+            )
+        },
+        Err(e)=>{
             Err(
                 Custom(
                     Status::InternalServerError,
@@ -327,19 +1931,350 @@ pub async fn delete_tareas_by_id_user(db:&State<Session>,userClaims:JwtGuard,cli
                     )
                 )
             )
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/tareas/{id}/asignado",
+    responses(
+        (status = 200, description = "Tarea asignada, o sin asignar si `username` es null", body = String),
+        (status = 400, description = "El usuario no puede ver la tarea", body = AdduserResponse),
+        (status = 403, description = "Sin permiso de edicion sobre la tarea", body = AdduserResponse),
+        (status = 404, description = "Tarea o usuario no encontrado", body = AdduserResponse),
+        (status = 412, description = "La tarea cambio desde la version de `If-Match`", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea")
+    ),
+    request_body=AsignacionHttp,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tareas"
+)]
+#[put("/<id>/asignado",data="<asignacion>")]
+pub async fn asignar_tarea(db:&State<Session>,id:&str,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,if_match:IfMatch,asignacion:Json<AsignacionHttp>,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    idempotencia.ejecutar(&userClaims.id_usuario,&*asignacion,db,async {
+        let tarea=tarea_con_permiso(id,&userClaims,Permiso::Editor,db).await?;
+        comprobar_version(&tarea,&if_match)?;
+
+        // solo se asigna a quien puede ver la tarea: el dueño, un miembro del espacio o alguien con quien se compartio
+        let asignado=match asignacion.username.as_deref() {
+            None=>None,
+            Some(username)=>{
+                let usuario=match Usuarios::filter_by_username(username,db).await {
+                    Ok(res)=>res.into_iter().next(),
+                    Err(e)=>{
+                        return Err(
+                            Custom(
+                                Status::InternalServerError,
+                                Json(
+                                    AdduserResponse{
+                                        error:2
+                                    }
+                                )
+                            )
+                        );
+                    }
+                };
+                let Some(usuario) = usuario else {
+                    return Err(
+                        Custom(
+                            Status::NotFound,
+                            Json(
+                                AdduserResponse{
+                                    error:1
+                                }
+                            )
+                        )
+                    );
+                };
+                if !matches!(tarea.permiso(&usuario.id,tarea.id_espacio.as_deref(),db).await, Ok(Some(_))) {
+                    return Err(
+                        Custom(
+                            Status::BadRequest,
+                            Json(
+                                AdduserResponse{
+                                    error:3
+                                }
+                            )
+                        )
+                    );
+                }
+                Some(usuario.id)
+            }
+        };
+
+        match Tareas::asignar(&tarea.id,asignado.as_deref(),&userClaims.id_usuario,if_match.0,db).await {
+            Ok(false) if if_match.0.is_some()=>{
+                Err(
+                    Custom(
+                        Status::PreconditionFailed,
+                        Json(
+                            AdduserResponse{
+                                error:13
+                            }
+                        )
+                    )
+                )
+            },
+            Ok(true)=>{
+                if tarea.asignado_a != asignado {
+                    if let Some(nuevo) = asignado {
+                        Utils::send_ws_to(clients, &[nuevo], "assigned").await;
+                    }
+                    if let Some(anterior) = tarea.asignado_a {
+                        Utils::send_ws_to(clients, &[anterior], "unassigned").await;
+                    }
+                }
+                Ok(
+                    Custom(
+                        Status::Ok, 
+                        "Ok ".to_string()
+                    )
+                )
+            },
+            Ok(false)=>{
+                Err(
+                    Custom(
+                        Status::NotFound,
+                        Json(
+                            AdduserResponse{
+                                error:1
+                            }
+                        )
+                    )
+                )
+            },
+            Err(e)=>{
+                Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                )
+            }
+        }
+    }).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/tareas/papelera",
+    responses(
+        (status = 200, description = "Tareas en la papelera", body = Vec<Tareas>),
+        (status = 304, description = "Sin cambios desde la etag de `If-None-Match`"),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="papelera"
+)]
+#[get("/papelera")]
+pub async fn get_papelera(userClaims:JwtGuard,if_none_match:IfNoneMatch,db:&State<Session>)->Result<ConEtag<Custom<Json<Vec<Tareas>>>>,status::Custom<Json<AdduserResponse>>>{
+    match Tareas::get_papelera_by_usuario(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),&db).await {
+        Ok(res)=>{
+            let etag=etag_json(&res);
+            Ok(
+                ConEtag::nueva(
+                    Custom(
+                        Status::Ok,
+                        Json(res)
+                    ),
+                    etag,
+                    &if_none_match
+                )
+            )
         },
         Err(e)=>{
             Err(
                 Custom(
-                    Status::BadRequest,
+                    Status::InternalServerError,
                     Json(
                         AdduserResponse{
-                            error:1
+                            error:2
                         }
                     )
                 )
             )
-        }     
+        }
     }
-    
-}
\ No newline at end of file
+}
+
+#[utoipa::path(
+    put,
+    path = "/tareas/papelera/{id}",
+    responses(
+        (status = 200, description = "tarea restaurada", body = String),
+        (status = 404, description = "La tarea no esta en la papelera", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea a restaurar")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="papelera"
+)]
+#[put("/papelera/<id>")]
+pub async fn restore_tarea(db:&State<Session>,id:&str,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    idempotencia.ejecutar(&userClaims.id_usuario,&(),db,async {
+        match Tareas::restore_tarea(id,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),&db).await {
+            Ok(res)=>{
+                if let Some(tarea) = res {
+                    Utils::send_ws_to(clients, &tarea.destinatarios(db).await, "restore").await;
+                    return Ok(
+                        Custom(
+                            Status::Ok, 
+                            "Ok ".to_string()
+                        )
+                    );
+                }
+                Err(
+                    Custom(
+                        Status::NotFound,
+                        Json(
+                            AdduserResponse{
+                                error:1
+                            }
+                        )
+                    )
+                )
+            },
+            Err(e)=>{
+                Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                )
+            }
+        }
+    }).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/tareas/papelera/{id}",
+    responses(
+        (status = 200, description = "tarea eliminada definitivamente", body = String),
+        (status = 404, description = "La tarea no esta en la papelera", body = AdduserResponse),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea a eliminar")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="papelera"
+)]
+#[delete("/papelera/<id>")]
+pub async fn purge_tarea(db:&State<Session>,id:&str,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,almacenamiento:&State<AlmacenamientoAdjuntos>,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    idempotencia.ejecutar(&userClaims.id_usuario,&(),db,async {
+        match Tareas::purge_tarea(id,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),almacenamiento,&db).await {
+            Ok(res)=>{
+                if let Some((_,destinatarios)) = res {
+                    Utils::send_ws_to(clients, &destinatarios, "purge").await;
+                    return Ok(
+                        Custom(
+                            Status::Ok, 
+                            "Ok ".to_string()
+                        )
+                    );
+                }
+                Err(
+                    Custom(
+                        Status::NotFound,
+                        Json(
+                            AdduserResponse{
+                                error:1
+                            }
+                        )
+                    )
+                )
+            },
+            Err(e)=>{
+                Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                )
+            }
+        }
+    }).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/tareas/papelera",
+    responses(
+        (status = 200, description = "papelera vaciada, devuelve la cantidad eliminada", body = u32),
+        (status = 409, description = "Otra peticion con la misma `Idempotency-Key` sigue en curso", body = AdduserResponse),
+        (status = 422, description = "La `Idempotency-Key` ya se uso con otra peticion", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="papelera"
+)]
+#[delete("/papelera")]
+pub async fn purge_papelera(db:&State<Session>,userClaims:JwtGuard,idempotencia:ClaveIdempotencia,almacenamiento:&State<AlmacenamientoAdjuntos>,clients:&State<ClientesSockets>)->Result<Custom<Json<u32>>,status::Custom<Json<AdduserResponse>>>{
+    idempotencia.ejecutar(&userClaims.id_usuario,&(),db,async {
+        match Tareas::purge_papelera_by_usuario(&userClaims.id_usuario,userClaims.id_espacio.as_deref(),almacenamiento,&db).await {
+            Ok((res,destinatarios))=>{
+                if !res.is_empty() {
+                    Utils::send_ws_to(clients, &destinatarios, "purge_all").await;
+                }
+                Ok(
+                    Custom(
+                        Status::Ok, 
+                        Json(res.len() as u32)
+                    )
+                )
+            },
+            Err(e)=>{
+                Err(
+                    Custom(
+                        Status::InternalServerError,
+                        Json(
+                            AdduserResponse{
+                                error:2
+                            }
+                        )
+                    )
+                )
+            }
+        }
+    }).await
+}
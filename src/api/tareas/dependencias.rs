@@ -0,0 +1,100 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use rocket::serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use super::Tareas;
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct DependenciasHttp{
+    /// ids de las tareas que bloquean a esta, reemplaza la lista anterior
+    #[validate(length(max = 100))]
+    pub bloqueada_por:Vec<String>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct NodoDependencia{
+    pub id:String,
+    pub nombre:String,
+    pub estado:bool,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct AristaDependencia{
+    /// tarea que tiene que completarse primero
+    pub bloqueadora:String,
+    pub bloqueada:String,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct GrafoDependencias{
+    /// tareas que bloquean o estan bloqueadas por otra
+    pub nodos:Vec<NodoDependencia>,
+    pub aristas:Vec<AristaDependencia>,
+    /// tareas pendientes en un orden que respeta las dependencias
+    pub orden:Vec<String>,
+    /// tareas pendientes sin bloqueos abiertos, en el mismo orden
+    pub disponibles:Vec<String>,
+}
+
+impl GrafoDependencias {
+    /// arma el grafo con las dependencias entre las tareas dadas; las tareas
+    /// bloqueadoras que no estan en la lista se ignoran
+    pub fn construir(tareas:&[Tareas])->GrafoDependencias{
+        let por_id:HashMap<&str,&Tareas>=tareas.iter().map(|t| (t.id.as_str(),t)).collect();
+        let aristas:Vec<AristaDependencia>=tareas.iter()
+            .flat_map(|t| {
+                t.bloqueada_por.iter()
+                .filter(|b| por_id.contains_key(b.as_str()))
+                .map(|b| AristaDependencia { bloqueadora: b.clone(), bloqueada: t.id.clone() })
+            })
+            .collect();
+
+        let en_aristas:HashSet<&str>=aristas.iter()
+            .flat_map(|a| [a.bloqueadora.as_str(),a.bloqueada.as_str()])
+            .collect();
+        let nodos:Vec<NodoDependencia>=tareas.iter()
+            .filter(|t| en_aristas.contains(t.id.as_str()))
+            .map(|t| NodoDependencia { id: t.id.clone(), nombre: t.nombre.clone(), estado: t.estado })
+            .collect();
+
+        // Kahn sobre las tareas pendientes, desempatando por fecha de creacion
+        let mut bloqueos:HashMap<&str,usize>=tareas.iter()
+            .filter(|t| !t.estado)
+            .map(|t| (t.id.as_str(),0))
+            .collect();
+        let mut bloquea_a:HashMap<&str,Vec<&str>>=HashMap::new();
+        for arista in aristas.iter() {
+            let pendiente=por_id.get(arista.bloqueadora.as_str()).is_some_and(|t| !t.estado);
+            if pendiente && bloqueos.contains_key(arista.bloqueada.as_str()) {
+                *bloqueos.entry(arista.bloqueada.as_str()).or_insert(0) += 1;
+                bloquea_a.entry(arista.bloqueadora.as_str()).or_default().push(arista.bloqueada.as_str());
+            }
+        }
+        let clave=|id:&str| (por_id[id].create_at,id.to_string());
+        let mut listas:BTreeSet<(i64,String)>=bloqueos.iter()
+            .filter(|(_,n)| **n == 0)
+            .map(|(id,_)| clave(id))
+            .collect();
+        let disponibles:Vec<String>=listas.iter().map(|(_,id)| id.clone()).collect();
+        let mut orden=Vec::with_capacity(bloqueos.len());
+        while let Some((_,id)) = listas.pop_first() {
+            for siguiente in bloquea_a.get(id.as_str()).cloned().unwrap_or_default() {
+                if let Some(n) = bloqueos.get_mut(siguiente) {
+                    *n -= 1;
+                    if *n == 0 {
+                        listas.insert(clave(siguiente));
+                    }
+                }
+            }
+            orden.push(id);
+        }
+
+        GrafoDependencias { nodos, aristas, orden, disponibles }
+    }
+}
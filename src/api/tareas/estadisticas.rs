@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use rocket::{serde::{Deserialize, Serialize}, FromForm};
+use serde_json::Value;
+use unreql::{cmd::options::BetweenOptions, func, r, rjson, Command, Session};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::utils::{Utils, DIA_MS};
+
+use super::Tareas;
+
+const ESTADISTICAS_DIAS_DEFAULT:i64=30;
+const ESTADISTICAS_DIAS_MAX:i64=366;
+
+#[derive(Debug,Clone,FromForm,IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EstadisticasQuery{
+    /// inicio del rango en milisegundos, por defecto 30 dias antes de `hasta`
+    pub desde:Option<i64>,
+    /// fin del rango en milisegundos, por defecto ahora
+    pub hasta:Option<i64>,
+    /// zona horaria con que se cortan los dias, en minutos como en `GET /tiempos/resumen`
+    pub zona:Option<i64>,
+    /// dia (por defecto) o semana; las semanas empiezan el lunes
+    pub agrupar:Option<String>,
+}
+
+/// rango ya validado de una consulta de estadisticas
+#[derive(Debug,Clone,Copy)]
+pub struct RangoEstadisticas{
+    pub desde:i64,
+    pub hasta:i64,
+    pub zona_ms:i64,
+    pub semanal:bool,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct PeriodoEstadisticas{
+    /// primer dia del periodo, `AAAA-MM-DD` en la zona pedida
+    pub inicio:String,
+    pub creadas:u64,
+    pub completadas:u64,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Estadisticas{
+    /// `dia` o `semana`
+    pub agrupar:String,
+    /// todos los periodos del rango, tambien los vacios
+    pub periodos:Vec<PeriodoEstadisticas>,
+    pub creadas:u64,
+    pub completadas:u64,
+    /// fraccion de las tareas creadas en el rango que ya estan completadas, de 0 a 1
+    pub tasa_completado:f64,
+    /// promedio entre `create_at` y `completada_at` de las completadas en el rango, `null` si no hay
+    pub promedio_completar_ms:Option<i64>,
+    /// dias seguidos con alguna tarea completada hasta hoy (o hasta ayer si hoy todavia no)
+    pub racha_actual:u32,
+    pub racha_maxima:u32,
+    /// tareas pendientes con `vence` ya pasado
+    pub vencidas:u64,
+}
+
+impl EstadisticasQuery {
+    /// `None` si el rango, la zona o la agrupacion no son validos
+    pub fn rango(&self)->Option<RangoEstadisticas>{
+        let (desde,hasta)=Utils::rango_dias(self.desde, self.hasta, ESTADISTICAS_DIAS_DEFAULT, ESTADISTICAS_DIAS_MAX)?;
+        let zona=Utils::zona_valida(self.zona)?;
+        let semanal=match self.agrupar.as_deref().unwrap_or("dia") {
+            "dia"=>false,
+            "semana"=>true,
+            _=>return None,
+        };
+        Some(RangoEstadisticas { desde, hasta, zona_ms: zona * 60 * 1000, semanal })
+    }
+}
+
+impl RangoEstadisticas {
+    /// dia local (desde epoch) de un timestamp
+    fn dia(&self,ms:i64)->i64{
+        (ms + self.zona_ms).div_euclid(DIA_MS)
+    }
+
+    /// numero de periodo de un timestamp; el dia 0 fue jueves, asi que `+ 3` hace que las
+    /// semanas empiecen el lunes
+    fn periodo(&self,ms:i64)->i64{
+        let dia=self.dia(ms);
+        if self.semanal { (dia + 3).div_euclid(7) } else { dia }
+    }
+
+    /// la misma cuenta que `periodo` pero dentro de la consulta
+    fn periodo_query(&self,ms:Command)->Command{
+        let dia=ms.add(self.zona_ms).div(DIA_MS).floor();
+        if self.semanal { dia.add(3).div(7).floor() } else { dia }
+    }
+
+    fn inicio_periodo(&self,periodo:i64)->String{
+        Utils::fecha_de_dia(if self.semanal { periodo * 7 - 3 } else { periodo })
+    }
+}
+
+/// `[{group, reduction}]` de un `group().ungroup()` como mapa de grupo numerico a reduccion
+fn por_grupo(filas:Vec<Value>)->BTreeMap<i64,Value>{
+    filas.into_iter()
+        .filter_map(|fila| Some((fila.get("group")?.as_f64()? as i64,fila.get("reduction")?.clone())))
+        .collect()
+}
+
+fn campo(valor:&Value,nombre:&str)->i64{
+    valor.get(nombre).and_then(Value::as_f64).unwrap_or(0.0) as i64
+}
+
+/// `(actual, maxima)` de una serie de dias con alguna tarea completada
+fn rachas(dias:&BTreeSet<i64>,hoy:i64)->(u32,u32){
+    let mut maxima=0;
+    let mut racha=0;
+    let mut anterior:Option<i64>=None;
+    for dia in dias.iter().copied() {
+        racha=if anterior == Some(dia - 1) { racha + 1 } else { 1 };
+        maxima=maxima.max(racha);
+        anterior=Some(dia);
+    }
+    let actual=match anterior {
+        Some(ultimo) if ultimo == hoy || ultimo == hoy - 1=>racha,
+        _=>0,
+    };
+    (actual,maxima)
+}
+
+impl Tareas {
+    /// tareas del espacio activo o las personales del usuario, por el indice `<alcance>_<campo>`
+    /// entre `desde` y `hasta`, sin las de la papelera
+    pub fn rango_por_indice(id_usuario:&str,id_espacio:Option<&str>,campo:&str,desde:Command,hasta:Command)->Command{
+        let (indice,id_alcance)=match id_espacio {
+            Some(id_espacio)=>(format!("espacio_{}",campo),id_espacio),
+            None=>(format!("usuario_{}",campo),id_usuario),
+        };
+        Self::en_espacio(
+            r.db("tareas").table("tareas")
+            .between(
+                rjson!([id_alcance.to_string(), desde]),
+                rjson!([id_alcance.to_string(), hasta, r.maxval()]),
+                BetweenOptions { index: Some(indice), ..Default::default() }
+            ),
+            id_espacio
+        )
+        .filter(func!(|t| t.has_fields("deleted_at").not()))
+    }
+
+    /// estadisticas calculadas con group/reduce en RethinkDB; solo los grupos vuelven al servidor
+    pub async fn estadisticas(id_usuario:&str,id_espacio:Option<&str>,rango:RangoEstadisticas,db:&Session)->Result<Estadisticas,unreql::Error>{
+        let ahora=Utils::current_timestamp();
+
+        let creadas = Self::rango_por_indice(id_usuario, id_espacio, "create_at", r.expr(rango.desde), r.expr(rango.hasta))
+        .group(func!(|t| rango.periodo_query(t.g("create_at"))))
+        .map(func!(|t| rjson!({"total":1,"completadas":r.branch(t.g("estado"), 1, 0)})))
+        .reduce(func!(|a,b| rjson!({
+            "total":a.clone().g("total").add(b.clone().g("total")),
+            "completadas":a.g("completadas").add(b.g("completadas")),
+        })))
+        .ungroup()
+        .exec::<_,Vec<Value>>(db);
+        let creadas=match creadas.await {
+            Ok(res)=>por_grupo(res),
+            Err(e)=>{
+                println!("error estadisticas creadas {}",e);
+                return Err(e);
+            }
+        };
+
+        let completadas = Self::rango_por_indice(id_usuario, id_espacio, "completada_at", r.expr(rango.desde), r.expr(rango.hasta))
+        .group(func!(|t| rango.periodo_query(t.g("completada_at"))))
+        .map(func!(|t| rjson!({"total":1,"ms":t.clone().g("completada_at").sub(t.g("create_at"))})))
+        .reduce(func!(|a,b| rjson!({
+            "total":a.clone().g("total").add(b.clone().g("total")),
+            "ms":a.g("ms").add(b.g("ms")),
+        })))
+        .ungroup()
+        .exec::<_,Vec<Value>>(db);
+        let completadas=match completadas.await {
+            Ok(res)=>por_grupo(res),
+            Err(e)=>{
+                println!("error estadisticas completadas {}",e);
+                return Err(e);
+            }
+        };
+
+        // las rachas se cuentan sobre todo el historial, no solo el rango; el limite inferior
+        // numerico deja afuera las que tienen `completada_at` en `null`
+        let zona_ms=rango.zona_ms;
+        let dias = Self::rango_por_indice(id_usuario, id_espacio, "completada_at", r.expr(0), r.maxval())
+        .group(func!(|t| t.g("completada_at").add(zona_ms).div(DIA_MS).floor()))
+        .count(())
+        .ungroup()
+        .exec::<_,Vec<Value>>(db);
+        let dias:BTreeSet<i64>=match dias.await {
+            Ok(res)=>por_grupo(res).into_keys().collect(),
+            Err(e)=>{
+                println!("error estadisticas rachas {}",e);
+                return Err(e);
+            }
+        };
+
+        let vencidas = Self::rango_por_indice(id_usuario, id_espacio, "vence", r.expr(0), r.expr(ahora))
+        .filter(rjson!({"estado":false}))
+        .count(())
+        .exec::<_,u64>(db);
+        let vencidas=match vencidas.await {
+            Ok(res)=>res,
+            Err(e)=>{
+                println!("error estadisticas vencidas {}",e);
+                return Err(e);
+            }
+        };
+
+        let periodos=(rango.periodo(rango.desde)..=rango.periodo(rango.hasta))
+            .map(|periodo| PeriodoEstadisticas {
+                inicio: rango.inicio_periodo(periodo),
+                creadas: creadas.get(&periodo).map(|v| campo(v, "total")).unwrap_or(0) as u64,
+                completadas: completadas.get(&periodo).map(|v| campo(v, "total")).unwrap_or(0) as u64,
+            })
+            .collect();
+        let total_creadas:i64=creadas.values().map(|v| campo(v, "total")).sum();
+        let creadas_completadas:i64=creadas.values().map(|v| campo(v, "completadas")).sum();
+        let total_completadas:i64=completadas.values().map(|v| campo(v, "total")).sum();
+        let ms_completar:i64=completadas.values().map(|v| campo(v, "ms")).sum();
+        let (racha_actual,racha_maxima)=rachas(&dias, rango.dia(ahora));
+
+        Ok(Estadisticas {
+            agrupar: if rango.semanal { "semana" } else { "dia" }.to_string(),
+            periodos,
+            creadas: total_creadas as u64,
+            completadas: total_completadas as u64,
+            tasa_completado: if total_creadas > 0 { creadas_completadas as f64 / total_creadas as f64 } else { 0.0 },
+            promedio_completar_ms: (total_completadas > 0).then(|| ms_completar / total_completadas),
+            racha_actual,
+            racha_maxima,
+            vencidas,
+        })
+    }
+}
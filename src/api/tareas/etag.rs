@@ -0,0 +1,115 @@
+use rocket::{http::{Header, Status}, request::{self, FromRequest, Outcome, Request}, response::{self, Responder, Response}, serde::Serialize};
+
+use crate::utils::Utils;
+
+/// etag de una tarea, su `version`, que cambia con cada escritura
+pub fn etag_tarea(version:i64)->String{
+    format!("\"{}\"",version)
+}
+
+/// etag de un listado, el sha-256 del cuerpo que se devolveria; es el mismo en todas las
+/// instancias y despues de reiniciar
+pub fn etag_json(cuerpo:&impl Serialize)->String{
+    format!("\"{}\"",Utils::sha256_hex(&serde_json::to_vec(cuerpo).unwrap_or_default()))
+}
+
+/// valor de la etag sin comillas ni el prefijo `W/` de las etags debiles
+fn sin_comillas(etag:&str)->&str{
+    let etag=etag.trim();
+    let etag=etag.strip_prefix("W/").unwrap_or(etag);
+    etag.strip_prefix('"').and_then(|e| e.strip_suffix('"')).unwrap_or(etag)
+}
+
+/// header `If-Match`: `version` que el cliente espera que tenga la tarea. Sin header o con `*`
+/// no se comprueba; una etag que no es de una tarea nunca coincide
+pub struct IfMatch(pub Option<i64>);
+
+impl IfMatch {
+    pub fn desde(valor:Option<&str>)->IfMatch{
+        IfMatch(
+            valor.filter(|valor| valor.trim() != "*")
+            .map(|valor| sin_comillas(valor).parse::<i64>().unwrap_or(-1))
+        )
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = ();
+
+    async fn from_request(request:&'r Request<'_>)->request::Outcome<Self,Self::Error>{
+        Outcome::Success(IfMatch::desde(request.headers().get_one("If-Match")))
+    }
+}
+
+/// header `If-Match` con la etag de un listado, como la de `GET /tareas/todo.txt`. Sin header
+/// o con `*` coincide con cualquiera
+pub struct IfMatchListado(pub Option<String>);
+
+impl IfMatchListado {
+    pub fn coincide(&self,etag:&str)->bool{
+        let Some(valor) = self.0.as_deref() else {
+            return true;
+        };
+        valor.trim() == "*" || valor.split(',').any(|e| sin_comillas(e) == sin_comillas(etag))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatchListado {
+    type Error = ();
+
+    async fn from_request(request:&'r Request<'_>)->request::Outcome<Self,Self::Error>{
+        Outcome::Success(IfMatchListado(request.headers().get_one("If-Match").map(str::to_string)))
+    }
+}
+
+/// header `If-None-Match`: etags que el cliente ya tiene
+pub struct IfNoneMatch(pub Option<String>);
+
+impl IfNoneMatch {
+    pub fn coincide(&self,etag:&str)->bool{
+        let Some(valor) = self.0.as_deref() else {
+            return false;
+        };
+        valor.trim() == "*" || valor.split(',').any(|e| sin_comillas(e) == sin_comillas(etag))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfNoneMatch {
+    type Error = ();
+
+    async fn from_request(request:&'r Request<'_>)->request::Outcome<Self,Self::Error>{
+        Outcome::Success(IfNoneMatch(request.headers().get_one("If-None-Match").map(str::to_string)))
+    }
+}
+
+/// respuesta con header `ETag`; `304` sin cuerpo si el cliente ya tiene esa version
+pub struct ConEtag<T>{
+    pub cuerpo:T,
+    pub etag:String,
+    pub no_modificado:bool,
+}
+
+impl<T> ConEtag<T> {
+    pub fn nueva(cuerpo:T,etag:String,if_none_match:&IfNoneMatch)->ConEtag<T>{
+        let no_modificado=if_none_match.coincide(&etag);
+        ConEtag { cuerpo, etag, no_modificado }
+    }
+}
+
+impl<'r,'o:'r,T:Responder<'r,'o>> Responder<'r,'o> for ConEtag<T> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let etag=Header::new("ETag", self.etag);
+        if self.no_modificado {
+            return Response::build()
+                .status(Status::NotModified)
+                .header(etag)
+                .ok();
+        }
+        Response::build_from(self.cuerpo.respond_to(request)?)
+            .header(etag)
+            .ok()
+    }
+}
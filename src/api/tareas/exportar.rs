@@ -0,0 +1,207 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, BufWriter, Seek, SeekFrom, Write}, path::Path};
+
+use rocket::{http::{ContentType, Header}, request::Request, response::{self, Responder, Response}, tokio::{self, task::spawn_blocking}, FromForm};
+use serde_json::{json, Value};
+use unreql::Session;
+use utoipa::IntoParams;
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use crate::utils::{Utils, DIA_MS};
+
+use super::{Tareas, TareasQuery};
+
+/// version del formato de `tareas.json` y `tareas.csv`, cambia si cambian sus campos
+pub const VERSION_ESQUEMA_EXPORTACION:u32=1;
+/// tareas que se leen de la base por vez
+const PAGINA_EXPORTACION:u32=200;
+const COLUMNAS_CSV:[&str;13]=["id","nombre","estado","etapa","prioridad","etiquetas","vence","completada_at","asignado_a","id_padre","id_espacio","create_at","update_at"];
+
+#[derive(Debug,Clone,Default,FromForm,IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportarQuery{
+    pub estado:Option<bool>,
+    /// creadas desde este timestamp en ms, inclusivo
+    pub desde:Option<i64>,
+    /// creadas hasta este timestamp en ms, inclusivo
+    pub hasta:Option<i64>,
+}
+
+pub enum ErrorExportacion{
+    Db(unreql::Error),
+    Archivo(io::Error),
+}
+
+/// zip ya armado, se envia leyendo del archivo temporal
+pub struct Exportacion{
+    archivo:tokio::fs::File,
+    nombre:String,
+}
+
+impl<'r> Responder<'r,'static> for Exportacion {
+    fn respond_to(self, _request: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .header(ContentType::ZIP)
+            .header(Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", self.nombre)))
+            .streamed_body(self.archivo)
+            .ok()
+    }
+}
+
+impl ExportarQuery {
+    pub fn is_valid(&self)->bool{
+        !matches!((self.desde,self.hasta),(Some(desde),Some(hasta)) if desde > hasta)
+    }
+
+    /// listado por fecha de creacion con los mismos filtros
+    fn listado(&self,cursor:Option<String>)->TareasQuery{
+        TareasQuery {
+            cursor,
+            limit: Some(PAGINA_EXPORTACION),
+            orden: Some("create_at".to_string()),
+            dir: Some("asc".to_string()),
+            estado: self.estado,
+            creado_desde: self.desde,
+            creado_hasta: self.hasta,
+            ..Default::default()
+        }
+    }
+}
+
+fn campo_csv(valor:&str)->String{
+    if valor.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"",valor.replace('"', "\"\""))
+    }else{
+        valor.to_string()
+    }
+}
+
+fn fila_csv(tarea:&Tareas)->String{
+    let opcional=|valor:Option<i64>| valor.map(|v| v.to_string()).unwrap_or_default();
+    let prioridad=tarea.prioridad.and_then(|p| serde_json::to_value(p).ok()).and_then(|p| p.as_str().map(str::to_string)).unwrap_or_default();
+    let campos=[
+        tarea.id.clone(),
+        tarea.nombre.clone(),
+        tarea.estado.to_string(),
+        tarea.etapa.clone().unwrap_or_default(),
+        prioridad,
+        tarea.etiquetas.join(";"),
+        opcional(tarea.vence),
+        opcional(tarea.completada_at),
+        tarea.asignado_a.clone().unwrap_or_default(),
+        tarea.id_padre.clone().unwrap_or_default(),
+        tarea.id_espacio.clone().unwrap_or_default(),
+        tarea.create_at.to_string(),
+        tarea.update_at.to_string(),
+    ];
+    let mut fila=campos.iter().map(|c| campo_csv(c)).collect::<Vec<_>>().join(",");
+    fila.push_str("\r\n");
+    fila
+}
+
+/// archivo nuevo para leer y escribir que se borra enseguida; en unix sigue disponible mientras
+/// este abierto, asi no quedan restos si la exportacion falla a la mitad
+fn archivo_temporal(ruta:&Path)->io::Result<File>{
+    let archivo=OpenOptions::new().read(true).write(true).create_new(true).open(ruta)?;
+    if let Err(e) = fs::remove_file(ruta) {
+        println!("error archivo_temporal {} {}",ruta.display(),e);
+    }
+    Ok(archivo)
+}
+
+/// zip que se arma en disco: `tareas.json` se escribe directo y `tareas.csv` en otro archivo
+/// que se copia al final, asi las tareas se leen de la base una sola vez
+struct ArmadoZip{
+    zip:ZipWriter<File>,
+    csv:BufWriter<File>,
+    escritas:u64,
+}
+
+impl ArmadoZip {
+    fn nuevo(prefijo:&str)->io::Result<ArmadoZip>{
+        let dir=std::env::temp_dir();
+        let mut zip=ZipWriter::new(archivo_temporal(&dir.join(format!("{}.zip",prefijo)))?);
+        let mut csv=BufWriter::new(archivo_temporal(&dir.join(format!("{}.csv",prefijo)))?);
+        zip.start_file("tareas.json", SimpleFileOptions::default()).map_err(io::Error::other)?;
+        zip.write_all(b"[")?;
+        csv.write_all(COLUMNAS_CSV.join(",").as_bytes())?;
+        csv.write_all(b"\r\n")?;
+        Ok(ArmadoZip { zip, csv, escritas: 0 })
+    }
+
+    fn agregar(&mut self,tareas:&[Tareas])->io::Result<()>{
+        for tarea in tareas {
+            self.zip.write_all(if self.escritas == 0 { b"\n" } else { b",\n" })?;
+            serde_json::to_writer(&mut self.zip, tarea)?;
+            self.csv.write_all(fila_csv(tarea).as_bytes())?;
+            self.escritas += 1;
+        }
+        Ok(())
+    }
+
+    fn terminar(mut self,manifiesto:&Value)->io::Result<File>{
+        self.zip.write_all(b"\n]\n")?;
+        let mut csv=self.csv.into_inner().map_err(|e| e.into_error())?;
+        csv.seek(SeekFrom::Start(0))?;
+        self.zip.start_file("tareas.csv", SimpleFileOptions::default()).map_err(io::Error::other)?;
+        io::copy(&mut csv, &mut self.zip)?;
+        self.zip.start_file("manifest.json", SimpleFileOptions::default()).map_err(io::Error::other)?;
+        serde_json::to_writer_pretty(&mut self.zip, manifiesto)?;
+        let mut archivo=self.zip.finish().map_err(io::Error::other)?;
+        archivo.seek(SeekFrom::Start(0))?;
+        Ok(archivo)
+    }
+}
+
+/// corre la escritura en un hilo aparte para no bloquear el runtime
+async fn escribir<T:Send+'static>(tarea:impl FnOnce()->io::Result<T>+Send+'static)->Result<T,ErrorExportacion>{
+    match spawn_blocking(tarea).await {
+        Ok(Ok(res))=>Ok(res),
+        Ok(Err(e))=>Err(ErrorExportacion::Archivo(e)),
+        Err(e)=>Err(ErrorExportacion::Archivo(io::Error::other(e))),
+    }
+}
+
+impl Tareas {
+    /// arma el zip con las tareas del listado leyendolas por paginas; en memoria solo queda
+    /// una pagina por vez
+    pub async fn exportar(id_usuario:&str,id_espacio:Option<&str>,params:&ExportarQuery,db:&Session)->Result<Exportacion,ErrorExportacion>{
+        let ahora=Utils::current_timestamp();
+        let prefijo=format!("tareas_export_{}_{}",ahora,Utils::generar_string(16));
+        let mut armado=escribir(move || ArmadoZip::nuevo(&prefijo)).await?;
+
+        let mut cursor=None;
+        loop {
+            let pagina=match Self::get_page_by_usuario(id_usuario, id_espacio, &params.listado(cursor), db).await {
+                Ok(pagina)=>pagina,
+                Err(e)=>{
+                    println!("error exportar {}",e);
+                    return Err(ErrorExportacion::Db(e));
+                }
+            };
+            cursor=pagina.next_cursor;
+            armado=escribir(move || armado.agregar(&pagina.tareas).map(|_| armado)).await?;
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        let manifiesto=json!({
+            "version_esquema":VERSION_ESQUEMA_EXPORTACION,
+            "exportado_at":ahora,
+            "total":armado.escritas,
+            "archivos":["tareas.json","tareas.csv"],
+            "columnas_csv":COLUMNAS_CSV,
+            "filtros":{
+                "estado":params.estado,
+                "desde":params.desde,
+                "hasta":params.hasta,
+            },
+            "id_espacio":id_espacio,
+        });
+        let archivo=escribir(move || armado.terminar(&manifiesto)).await?;
+        Ok(Exportacion {
+            archivo: tokio::fs::File::from_std(archivo),
+            nombre: format!("tareas-{}.zip",Utils::fecha_de_dia(ahora.div_euclid(DIA_MS))),
+        })
+    }
+}
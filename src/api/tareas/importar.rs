@@ -0,0 +1,491 @@
+use std::{collections::{hash_map::Entry, HashMap}, io::{Cursor, Read}};
+
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::Value;
+use unreql::{cmd::options::UpdateOptions, func, r, rjson, types::WriteStatus, Session};
+use utoipa::ToSchema;
+use validator::Validate;
+use zip::ZipArchive;
+
+use crate::{api::Historial, utils::Utils};
+
+use super::{NodoTarea, Prioridad, Tareas, TareasHttp, ARBOL_PROFUNDIDAD_MAX};
+
+/// tareas que se aceptan por importacion
+pub const IMPORTAR_MAX_FILAS:usize=5000;
+/// tamaño del cuerpo si `Rocket.toml` no define el limite `importar`
+pub const IMPORTAR_MAX_BYTES:u64=10 * 1024 * 1024;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde",rename_all="lowercase")]
+pub enum AccionImportacion{
+    Crear,
+    Omitir,
+}
+
+/// resultado de una tarea del archivo
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct FilaImportacion{
+    /// numero de la tarea en el archivo, desde 1 y sin contar el encabezado del CSV
+    pub fila:usize,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub nombre:Option<String>,
+    pub accion:AccionImportacion,
+    /// id de la tarea creada, no viene en la prueba
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub id:Option<String>,
+    /// por que se omite
+    #[serde(default,skip_serializing_if="Vec::is_empty")]
+    pub errores:Vec<String>,
+    /// cambios que se hicieron para poder crearla
+    #[serde(default,skip_serializing_if="Vec::is_empty")]
+    pub avisos:Vec<String>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct ResultadoImportacion{
+    pub prueba:bool,
+    /// creadas, o las que se crearian si es una prueba
+    pub creadas:usize,
+    pub omitidas:usize,
+    pub filas:Vec<FilaImportacion>,
+}
+
+pub enum ErrorImportacion{
+    /// no es un CSV, un arreglo JSON ni un ZIP con `tareas.json` o `tareas.csv`
+    Formato,
+    /// el contenido descomprimido o la cantidad de tareas superan el maximo
+    Tamanio,
+}
+
+/// tarea leida del archivo con el `id` que tenia, para resolver `id_padre` entre filas
+pub struct FilaLeida{
+    pub id:Option<String>,
+    pub tarea:Result<TareasHttp,Vec<String>>,
+    /// nombre que tenia en el archivo, para informar las omitidas
+    pub nombre:Option<String>,
+    /// se mueve al estado final del flujo despues de crearla
+    pub completada:bool,
+    /// cuando se completo en el origen; sin fecha se toma la de la importacion
+    pub completada_at:Option<i64>,
+    /// lo que no se pudo pasar al modelo de tareas
+    pub avisos:Vec<String>,
+}
+
+impl FilaLeida {
+    pub fn nueva(id:Option<String>,tarea:TareasHttp)->FilaLeida{
+        FilaLeida { id, tarea: validar(tarea), nombre: None, completada: false, completada_at: None, avisos: Vec::new() }
+    }
+
+    pub fn omitida(nombre:Option<String>,error:String)->FilaLeida{
+        FilaLeida { id: None, tarea: Err(vec![error]), nombre, completada: false, completada_at: None, avisos: Vec::new() }
+    }
+}
+
+/// objeto del JSON: los campos de `TareasHttp`, el `id` de la exportacion y el resto se ignora
+#[derive(Deserialize)]
+#[serde(crate="rocket::serde")]
+struct FilaJson{
+    #[serde(default)]
+    id:Option<String>,
+    #[serde(default)]
+    estado:bool,
+    #[serde(default)]
+    completada_at:Option<i64>,
+    #[serde(flatten)]
+    tarea:TareasHttp,
+}
+
+/// lee un ZIP de exportacion, un arreglo JSON o un CSV con encabezado, segun el contenido
+pub fn leer_importacion(contenido:&[u8],max_bytes:u64)->Result<Vec<FilaLeida>,ErrorImportacion>{
+    if contenido.starts_with(b"PK\x03\x04") {
+        return leer_zip(contenido, max_bytes);
+    }
+    let filas=match contenido.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'[')=>leer_json(contenido)?,
+        _=>leer_csv(contenido)?,
+    };
+    if filas.len() > IMPORTAR_MAX_FILAS {
+        return Err(ErrorImportacion::Tamanio);
+    }
+    Ok(filas)
+}
+
+/// usa `tareas.json` de la exportacion, o `tareas.csv` si no esta
+fn leer_zip(contenido:&[u8],max_bytes:u64)->Result<Vec<FilaLeida>,ErrorImportacion>{
+    let mut zip=ZipArchive::new(Cursor::new(contenido)).map_err(|_| ErrorImportacion::Formato)?;
+    for (nombre,leer) in [("tareas.json",leer_json as fn(&[u8])->Result<Vec<FilaLeida>,ErrorImportacion>),("tareas.csv",leer_csv)] {
+        let Ok(archivo) = zip.by_name(nombre) else {
+            continue;
+        };
+        // el tamaño declarado no es confiable, se corta al leer
+        let mut datos=Vec::new();
+        if archivo.take(max_bytes + 1).read_to_end(&mut datos).is_err() {
+            return Err(ErrorImportacion::Formato);
+        }
+        if datos.len() as u64 > max_bytes {
+            return Err(ErrorImportacion::Tamanio);
+        }
+        let filas=leer(&datos)?;
+        if filas.len() > IMPORTAR_MAX_FILAS {
+            return Err(ErrorImportacion::Tamanio);
+        }
+        return Ok(filas);
+    }
+    Err(ErrorImportacion::Formato)
+}
+
+fn leer_json(contenido:&[u8])->Result<Vec<FilaLeida>,ErrorImportacion>{
+    let valores:Vec<Value>=serde_json::from_slice(contenido).map_err(|_| ErrorImportacion::Formato)?;
+    Ok(valores.into_iter().map(|valor| match serde_json::from_value::<FilaJson>(valor) {
+        Ok(fila)=>{
+            let mut leida=FilaLeida::nueva(fila.id, fila.tarea);
+            leida.completada=fila.estado;
+            leida.completada_at=fila.completada_at.filter(|_| fila.estado);
+            leida
+        },
+        Err(e)=>FilaLeida::omitida(None, e.to_string()),
+    }).collect())
+}
+
+fn leer_csv(contenido:&[u8])->Result<Vec<FilaLeida>,ErrorImportacion>{
+    let texto=std::str::from_utf8(contenido).map_err(|_| ErrorImportacion::Formato)?;
+    let mut registros=registros_csv(texto.trim_start_matches('\u{feff}'))?.into_iter();
+    let encabezado:Vec<String>=registros.next().ok_or(ErrorImportacion::Formato)?
+        .iter()
+        .map(|c| c.trim().to_lowercase())
+        .collect();
+    let columna=|nombre:&str| encabezado.iter().position(|c| c == nombre);
+    let Some(col_nombre) = columna("nombre") else {
+        return Err(ErrorImportacion::Formato);
+    };
+    let (col_id,col_padre,col_vence,col_etiquetas,col_prioridad)=(columna("id"),columna("id_padre"),columna("vence"),columna("etiquetas"),columna("prioridad"));
+    let (col_estado,col_completada_at)=(columna("estado"),columna("completada_at"));
+
+    Ok(registros.map(|registro| {
+        let celda=|col:Option<usize>| col.and_then(|c| registro.get(c)).map(|v| v.trim()).filter(|v| !v.is_empty());
+        let mut errores=Vec::new();
+        let vence=match celda(col_vence).map(str::parse::<i64>) {
+            Some(Ok(vence))=>Some(vence),
+            Some(Err(_))=>{
+                errores.push("vence: se esperaba un timestamp en milisegundos".to_string());
+                None
+            },
+            None=>None,
+        };
+        let prioridad=match celda(col_prioridad).map(|p| serde_json::from_value::<Prioridad>(Value::from(p.to_lowercase()))) {
+            Some(Ok(prioridad))=>Some(prioridad),
+            Some(Err(_))=>{
+                errores.push("prioridad: se esperaba baja, media, alta o urgente".to_string());
+                None
+            },
+            None=>None,
+        };
+        let tarea=TareasHttp {
+            nombre: celda(Some(col_nombre)).unwrap_or_default().to_string(),
+            vence,
+            etiquetas: celda(col_etiquetas).map(|e| e.split(';').map(str::to_string).collect()).unwrap_or_default(),
+            prioridad,
+            id_padre: celda(col_padre).map(str::to_string),
+        };
+        let mut fila=FilaLeida::nueva(celda(col_id).map(str::to_string), tarea);
+        fila.completada=celda(col_estado).map(|e| e.eq_ignore_ascii_case("true")).unwrap_or(false);
+        fila.completada_at=celda(col_completada_at).and_then(|c| c.parse::<i64>().ok()).filter(|_| fila.completada);
+        if !errores.is_empty() {
+            fila.tarea=Err(errores);
+        }
+        fila
+    }).collect())
+}
+
+/// nombre sin espacios alrededor y las reglas de validacion de la creacion de tareas
+pub fn validar(mut tarea:TareasHttp)->Result<TareasHttp,Vec<String>>{
+    tarea.nombre=tarea.nombre.trim().to_string();
+    match tarea.validate() {
+        Ok(())=>Ok(tarea),
+        Err(e)=>{
+            let mut errores:Vec<String>=e.field_errors().iter()
+                .flat_map(|(campo,errores)| errores.iter().map(move |error| format!("{}: {}",campo,error.code)))
+                .collect();
+            errores.sort();
+            Err(errores)
+        }
+    }
+}
+
+/// registros de un CSV (RFC 4180), sin las lineas vacias
+pub fn registros_csv(texto:&str)->Result<Vec<Vec<String>>,ErrorImportacion>{
+    let mut registros=Vec::new();
+    let mut registro:Vec<String>=Vec::new();
+    let mut campo=String::new();
+    let mut comillas=false;
+    let mut caracteres=texto.chars().peekable();
+    while let Some(c) = caracteres.next() {
+        if comillas {
+            match c {
+                '"' if caracteres.peek() == Some(&'"') => {
+                    caracteres.next();
+                    campo.push('"');
+                },
+                '"'=>comillas=false,
+                _=>campo.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"'=>comillas=true,
+            ','=>registro.push(std::mem::take(&mut campo)),
+            '\r' | '\n'=>{
+                if c == '\r' && caracteres.peek() == Some(&'\n') {
+                    caracteres.next();
+                }
+                registro.push(std::mem::take(&mut campo));
+                if registro.iter().any(|c| !c.is_empty()) {
+                    registros.push(std::mem::take(&mut registro));
+                }
+                registro.clear();
+            },
+            _=>campo.push(c),
+        }
+    }
+    if comillas {
+        return Err(ErrorImportacion::Formato);
+    }
+    registro.push(campo);
+    if registro.iter().any(|c| !c.is_empty()) {
+        registros.push(registro);
+    }
+    Ok(registros)
+}
+
+impl Tareas {
+    /// crea las tareas validas del archivo en `etapa` y pasa las completadas a `etapa_final`; con
+    /// `prueba` solo informa que pasaria. `id_padre` se resuelve contra el `id` de otra fila del
+    /// mismo archivo, si no esta la tarea queda sin padre. Devuelve tambien las tareas creadas
+    pub async fn importar(filas:Vec<FilaLeida>,prueba:bool,id_usuario:&str,id_espacio:Option<&str>,etapa:&str,etapa_final:&str,db:&Session)->Result<(ResultadoImportacion,Vec<Tareas>),unreql::Error>{
+        let mut resultados:Vec<FilaImportacion>=Vec::with_capacity(filas.len());
+        let mut tareas:Vec<Option<TareasHttp>>=Vec::with_capacity(filas.len());
+        let mut completadas:Vec<Option<Option<i64>>>=Vec::with_capacity(filas.len());
+        let mut por_id:HashMap<String,usize>=HashMap::new();
+        for (indice,fila) in filas.into_iter().enumerate() {
+            let mut resultado=FilaImportacion {
+                fila: indice + 1,
+                nombre: fila.nombre,
+                accion: AccionImportacion::Crear,
+                id: None,
+                errores: Vec::new(),
+                avisos: fila.avisos,
+            };
+            completadas.push(Some(fila.completada_at).filter(|_| fila.completada));
+            match fila.tarea {
+                Ok(tarea)=>{
+                    resultado.nombre=Some(tarea.nombre.clone());
+                    if let Some(id) = fila.id {
+                        let aviso=format!("id {} repetido, sus subtareas van con la primera",id);
+                        if let Entry::Vacant(entrada) = por_id.entry(id) {
+                            entrada.insert(indice);
+                        }else{
+                            resultado.avisos.push(aviso);
+                        }
+                    }
+                    tareas.push(Some(tarea));
+                },
+                Err(errores)=>{
+                    resultado.accion=AccionImportacion::Omitir;
+                    resultado.errores=errores;
+                    tareas.push(None);
+                }
+            }
+            resultados.push(resultado);
+        }
+
+        let mut hijas:HashMap<usize,Vec<usize>>=HashMap::new();
+        let mut raices:Vec<usize>=Vec::new();
+        for (indice,tarea) in tareas.iter().enumerate() {
+            let Some(tarea) = tarea else {
+                continue;
+            };
+            match tarea.id_padre.as_ref().map(|id_padre| (id_padre,por_id.get(id_padre))) {
+                Some((_,Some(padre))) if *padre != indice=>hijas.entry(*padre).or_default().push(indice),
+                Some((id_padre,_))=>{
+                    resultados[indice].avisos.push(format!("id_padre {} no es una tarea valida del archivo, se importa sin padre",id_padre));
+                    raices.push(indice);
+                },
+                None=>raices.push(indice),
+            }
+        }
+
+        fn omitir_rama(indice:usize,hijas:&HashMap<usize,Vec<usize>>,resultados:&mut [FilaImportacion]){
+            resultados[indice].errores.push(format!("mas de {} niveles de subtareas",ARBOL_PROFUNDIDAD_MAX));
+            for hija in hijas.get(&indice).into_iter().flatten() {
+                omitir_rama(*hija, hijas, resultados);
+            }
+        }
+        // arboles en el orden del archivo; las filas que no se alcanzan desde una raiz estan en un ciclo
+        fn armar(indice:usize,nivel:usize,tareas:&[Option<TareasHttp>],hijas:&HashMap<usize,Vec<usize>>,preorden:&mut Vec<usize>,resultados:&mut [FilaImportacion])->NodoTarea{
+            preorden.push(indice);
+            let mut subtareas=Vec::new();
+            for hija in hijas.get(&indice).into_iter().flatten() {
+                if nivel < ARBOL_PROFUNDIDAD_MAX {
+                    subtareas.push(armar(*hija, nivel + 1, tareas, hijas, preorden, resultados));
+                }else{
+                    omitir_rama(*hija, hijas, resultados);
+                }
+            }
+            let mut tarea=tareas[indice].clone().unwrap_or_default();
+            tarea.id_padre=None;
+            NodoTarea { tarea, subtareas }
+        }
+        let mut preorden:Vec<usize>=Vec::new();
+        let nodos:Vec<NodoTarea>=raices.iter()
+            .map(|raiz| armar(*raiz, 0, &tareas, &hijas, &mut preorden, &mut resultados))
+            .collect();
+        let mut alcanzadas=vec![false;tareas.len()];
+        for indice in preorden.iter() {
+            alcanzadas[*indice]=true;
+        }
+        for (indice,resultado) in resultados.iter_mut().enumerate() {
+            if resultado.accion == AccionImportacion::Crear && !alcanzadas[indice] {
+                resultado.accion=AccionImportacion::Omitir;
+                if resultado.errores.is_empty() {
+                    resultado.errores.push("id_padre forma un ciclo".to_string());
+                }
+            }
+        }
+
+        let mut creadas_db=Vec::new();
+        if !prueba && !nodos.is_empty() {
+            let (ids,nuevas)=Self::add_arboles(&nodos, id_usuario, id_espacio, etapa, db).await?;
+            let mut a_completar=Vec::new();
+            for (indice,id) in preorden.iter().zip(ids) {
+                if let (Some(completada_at),Some(id)) = (completadas[*indice],id.as_ref()) {
+                    a_completar.push((id.clone(),completada_at));
+                }
+                resultados[*indice].id=id;
+            }
+            Self::completar_importadas(a_completar, id_usuario, etapa_final, db).await?;
+            creadas_db=nuevas;
+        }
+        let creadas=resultados.iter().filter(|fila| fila.accion == AccionImportacion::Crear).count();
+        Ok((ResultadoImportacion {
+            prueba,
+            creadas,
+            omitidas: resultados.len() - creadas,
+            filas: resultados,
+        },creadas_db))
+    }
+
+    /// pasa las tareas recien importadas que venian completadas al estado final del flujo,
+    /// con la fecha de completado del archivo o la actual si no la trae
+    async fn completar_importadas(completadas:Vec<(String,Option<i64>)>,actor:&str,etapa_final:&str,db:&Session)->Result<(),unreql::Error>{
+        if completadas.is_empty() {
+            return Ok(());
+        }
+        let ahora=Utils::current_timestamp();
+        let ids:Vec<String>=completadas.iter().map(|(id,_)| id.clone()).collect();
+        let fechas:serde_json::Map<String,Value>=completadas.into_iter()
+            .map(|(id,completada_at)| (id,Value::from(completada_at.unwrap_or(ahora))))
+            .collect();
+        let fechas=Value::Object(fechas);
+        let query = r.db("tareas").table("tareas")
+        .get_all(r.args(ids))
+        .update(
+            r.with_opt(
+                func!(|t| Self::con_version(t.clone(), rjson!({
+                    "estado":true,
+                    "etapa":etapa_final.to_string(),
+                    "completada_at":r.expr(fechas.clone()).g(t.g("id")),
+                    "update_at":ahora,
+                }))),
+                UpdateOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Historial::registrar(&res.changes.unwrap_or_default(), actor, "estado", db).await;
+                Ok(())
+            },
+            Err(e)=>{
+                println!("error completar_importadas {}",e);
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+
+    fn leer(contenido:&str)->Vec<FilaLeida>{
+        match leer_importacion(contenido.as_bytes(), IMPORTAR_MAX_BYTES) {
+            Ok(filas)=>filas,
+            Err(_)=>panic!("no se pudo leer {:?}",contenido),
+        }
+    }
+
+    #[test]
+    fn registros_con_comillas_y_saltos() {
+        let registros=registros_csv("a,\"b, c\",\"d \"\"e\"\"\"\r\n\r\n\"linea\nnueva\",,x\n").ok().unwrap();
+        assert_eq!(registros, vec![
+            vec!["a","b, c","d \"e\""],
+            vec!["linea\nnueva","","x"],
+        ]);
+        assert!(registros_csv("a,\"sin cerrar\n").is_err());
+    }
+
+    #[test]
+    fn csv_con_columnas_en_cualquier_orden() {
+        let filas=leer("\u{feff}Prioridad,NOMBRE,etiquetas,vence,id,id_padre,estado,completada_at\nalta, Comprar pan ,casa;super,1700000000000,7,,true,1690000000000\n,Sub,,,8,7,false,123\n");
+        assert_eq!(filas.len(), 2);
+        let tarea=filas[0].tarea.as_ref().unwrap();
+        assert_eq!(tarea.nombre, "Comprar pan");
+        assert_eq!(tarea.etiquetas, vec!["casa","super"]);
+        assert_eq!(tarea.vence, Some(1700000000000));
+        assert_eq!(tarea.prioridad, Some(Prioridad::Alta));
+        assert_eq!(filas[0].id.as_deref(), Some("7"));
+        assert!(filas[0].completada);
+        assert_eq!(filas[0].completada_at, Some(1690000000000));
+        assert_eq!(filas[1].tarea.as_ref().unwrap().id_padre.as_deref(), Some("7"));
+        // la fecha de completado solo cuenta si la tarea esta completada
+        assert_eq!(filas[1].completada_at, None);
+    }
+
+    #[test]
+    fn csv_informa_errores_por_fila() {
+        let filas=leer("nombre,vence,prioridad\n,,\n,1,\nbien,ayer,altisima\n");
+        // la fila vacia no cuenta
+        assert_eq!(filas.len(), 2);
+        assert_eq!(filas[0].tarea.as_ref().err().unwrap(), &vec!["nombre: length".to_string()]);
+        assert_eq!(filas[1].tarea.as_ref().err().unwrap().len(), 2);
+        assert!(leer_importacion(b"titulo\nsin columna nombre\n", IMPORTAR_MAX_BYTES).is_err());
+    }
+
+    #[test]
+    fn json_y_zip_de_exportacion() {
+        let json=r#"[{"id":"1","nombre":"a","estado":true,"completada_at":5,"etapa":"hecho"},{"nombre":""},3]"#;
+        let filas=leer(json);
+        assert_eq!(filas.len(), 3);
+        assert!(filas[0].completada);
+        assert_eq!(filas[0].completada_at, Some(5));
+        assert!(filas[1].tarea.is_err());
+        assert!(filas[2].tarea.is_err());
+
+        let mut zip=ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("tareas.csv", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"nombre\ndesde csv\n").unwrap();
+        zip.start_file("tareas.json", SimpleFileOptions::default()).unwrap();
+        zip.write_all(json.as_bytes()).unwrap();
+        let contenido=zip.finish().unwrap().into_inner();
+        // tareas.json tiene prioridad sobre tareas.csv
+        assert_eq!(leer_importacion(&contenido, IMPORTAR_MAX_BYTES).ok().unwrap().len(), 3);
+        assert!(matches!(leer_importacion(&contenido, 10), Err(ErrorImportacion::Tamanio)));
+    }
+}
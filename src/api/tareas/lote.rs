@@ -0,0 +1,448 @@
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use unreql::{cmd::options::{Conflict, InsertOptions, UpdateOptions}, func, r, types::{Change, WriteStatus}, Session};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{api::{Flujos, Historial, Permiso}, utils::Utils};
+
+use super::{posicion_entre, validar, IndiceBusqueda, Tareas, TareasHttp};
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde",tag="op",rename_all="lowercase")]
+pub enum OperacionLote{
+    /// crea una tarea en el espacio activo
+    Create{
+        /// de 1 a 500 caracteres, igual que en `POST /tareas`
+        nombre:String,
+    },
+    /// renombra la tarea y/o la mueve a otro estado del flujo
+    Update{
+        id:String,
+        #[serde(default)]
+        nombre:Option<String>,
+        #[serde(default)]
+        etapa:Option<String>,
+    },
+    /// pasa la tarea al ultimo estado del flujo, igual que `PUT /tareas/{id}`
+    Complete{
+        id:String,
+        #[serde(default)]
+        forzar:bool,
+    },
+    /// mueve la tarea a la papelera
+    Delete{
+        id:String,
+    },
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct LoteHttp{
+    /// operaciones en el orden en que se aplican
+    #[validate(length(min = 1, max = 100))]
+    pub operaciones:Vec<OperacionLote>,
+    /// si alguna operacion falla no se aplica ninguna: se valida todo antes de escribir y si
+    /// falla una escritura se deshacen las que ya se hicieron
+    #[serde(default)]
+    pub atomico:bool,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct ResultadoOperacion{
+    /// posicion de la operacion en `operaciones`
+    pub indice:usize,
+    /// codigo http que hubiera devuelto la operacion por separado
+    pub status:u16,
+    /// codigo de error, ausente si la operacion se aplico
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub error:Option<i8>,
+    /// id de la tarea afectada o creada
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub id:Option<String>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct LoteResultado{
+    pub resultados:Vec<ResultadoOperacion>,
+    /// operaciones que se aplicaron
+    pub aplicadas:usize,
+}
+
+/// estado, codigo de error
+type Rechazo=(u16,i8);
+
+/// nombre sin espacios alrededor si cumple las reglas de la creacion de tareas
+fn nombre_valido(nombre:&str)->Option<String>{
+    validar(TareasHttp { nombre: nombre.to_string(), ..Default::default() }).ok().map(|t| t.nombre)
+}
+
+impl OperacionLote {
+    fn id(&self)->Option<&str>{
+        match self {
+            OperacionLote::Create { .. }=>None,
+            OperacionLote::Update { id, .. } | OperacionLote::Complete { id, .. } | OperacionLote::Delete { id }=>Some(id),
+        }
+    }
+}
+
+/// lo que ya se sabe de las tareas del lote, leido una sola vez antes de validar
+struct Contexto{
+    tareas:HashMap<String,Tareas>,
+    permisos:HashMap<String,Permiso>,
+    flujos:HashMap<String,Flujos>,
+    /// tareas bloqueadoras que siguen pendientes
+    abiertas:HashSet<String>,
+    /// tareas activas por (flujo, etapa), se completa a medida que se necesita
+    wip:HashMap<(String,String),u64>,
+}
+
+impl Contexto {
+    async fn cargar(operaciones:&[OperacionLote],id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Contexto,unreql::Error>{
+        let mut ids:Vec<String>=operaciones.iter().filter_map(|op| op.id().map(str::to_string)).collect();
+        ids.sort();
+        ids.dedup();
+        let tareas:HashMap<String,Tareas>=if ids.is_empty() {
+            HashMap::new()
+        }else{
+            r.db("tareas").table("tareas")
+            .get_all(r.args(ids))
+            .filter(func!(|t| t.has_fields("deleted_at").not()))
+            .exec_to_vec::<_,Tareas>(db)
+            .await?
+            .into_iter()
+            .map(|t| (t.id.clone(),t))
+            .collect()
+        };
+
+        let mut permisos=HashMap::new();
+        let mut flujos=HashMap::new();
+        flujos.insert(Flujos::id_para(id_usuario, id_espacio), Flujos::get_para(id_usuario, id_espacio, db).await?);
+        for tarea in tareas.values() {
+            if let Some(permiso) = tarea.permiso(id_usuario, id_espacio, db).await? {
+                permisos.insert(tarea.id.clone(), permiso);
+            }
+            let id_flujo=Flujos::id_para(&tarea.id_usuario, tarea.id_espacio.as_deref());
+            if let Entry::Vacant(libre) = flujos.entry(id_flujo) {
+                libre.insert(Flujos::get_para(&tarea.id_usuario, tarea.id_espacio.as_deref(), db).await?);
+            }
+        }
+
+        let mut bloqueadoras:Vec<String>=tareas.values().flat_map(|t| t.bloqueada_por.iter().cloned()).collect();
+        bloqueadoras.sort();
+        bloqueadoras.dedup();
+        let abiertas:HashSet<String>=if bloqueadoras.is_empty() {
+            HashSet::new()
+        }else{
+            r.db("tareas").table("tareas")
+            .get_all(r.args(bloqueadoras))
+            .filter(func!(|t| t.clone().g("estado").eq(false).and(t.has_fields("deleted_at").not())))
+            .exec_to_vec::<_,Tareas>(db)
+            .await?
+            .into_iter()
+            .map(|t| t.id)
+            .collect()
+        };
+
+        Ok(Contexto { tareas, permisos, flujos, abiertas, wip: HashMap::new() })
+    }
+
+    /// valida pasar la tarea a `etapa` (o al estado final si es `None`) con las mismas reglas
+    /// que las rutas individuales y, si se puede, devuelve la clave destino y si es el final
+    async fn validar_movimiento(&mut self,tarea:&Tareas,etapa:Option<&str>,forzar:bool,db:&Session)->Result<Result<(String,bool),Rechazo>,unreql::Error>{
+        let id_flujo=Flujos::id_para(&tarea.id_usuario, tarea.id_espacio.as_deref());
+        let flujo=&self.flujos[&id_flujo];
+        let destino=match etapa {
+            Some(clave)=>match flujo.estado(clave) {
+                Some(estado)=>estado.clone(),
+                None=>return Ok(Err((400,3))),
+            },
+            None=>flujo.final_().clone(),
+        };
+        let actual=flujo.etapa_de(tarea.etapa.as_deref(), tarea.estado).clave.clone();
+        if !flujo.permite(&actual, &destino.clave) {
+            return Ok(Err((409,10)));
+        }
+        let completada=destino.clave == flujo.final_().clave;
+        if completada && !forzar && tarea.bloqueada_por.iter().any(|b| self.abiertas.contains(b)) {
+            return Ok(Err((409,8)));
+        }
+        if actual != destino.clave {
+            if let Some(limite) = destino.limite_wip {
+                let clave=(id_flujo.clone(),destino.clave.clone());
+                let en_etapa=match self.wip.get(&clave) {
+                    Some(n)=>*n,
+                    None=>tarea.count_en_etapa(&destino.clave, db).await?,
+                };
+                if en_etapa >= limite as u64 {
+                    return Ok(Err((409,9)));
+                }
+                self.wip.insert(clave, en_etapa + 1);
+            }
+            if let Some(n) = self.wip.get_mut(&(id_flujo,actual)) {
+                *n=n.saturating_sub(1);
+            }
+        }
+        Ok(Ok((destino.clave,completada)))
+    }
+}
+
+impl Tareas {
+    /// valida todas las operaciones contra el estado simulado de las tareas y despues escribe
+    /// todos los cambios de tareas existentes en un solo update y las nuevas en un solo insert;
+    /// con `atomico`, si una de las dos escrituras falla se deshace la otra. Devuelve el resultado por operacion y las tareas que cambiaron, para notificarlas juntas
+    pub async fn ejecutar_lote(lote:&LoteHttp,id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<(LoteResultado,Vec<Tareas>),unreql::Error>{
+        let mut contexto=Contexto::cargar(&lote.operaciones, id_usuario, id_espacio, db).await?;
+        let ahora=Utils::current_timestamp();
+        let mut resultados:Vec<ResultadoOperacion>=Vec::with_capacity(lote.operaciones.len());
+        let mut cambios:HashMap<String,Map<String,Value>>=HashMap::new();
+        let mut nuevas:Vec<(usize,String)>=Vec::new();
+
+        for (indice,op) in lote.operaciones.iter().enumerate() {
+            let resultado:Result<(),Rechazo>=match op {
+                OperacionLote::Create { nombre }=>{
+                    match nombre_valido(nombre) {
+                        Some(nombre)=>{
+                            nuevas.push((indice,nombre));
+                            Ok(())
+                        },
+                        None=>Err((400,3)),
+                    }
+                },
+                _=>{
+                    let id=op.id().unwrap_or_default();
+                    match (contexto.tareas.get(id).cloned(),contexto.permisos.get(id).copied()) {
+                        (Some(_),Some(permiso)) if permiso < Permiso::Editor=>Err((403,16)),
+                        (Some(mut tarea),Some(_)) if tarea.deleted_at.is_none()=>{
+                            let mut campos=Map::new();
+                            let aplicada=match op {
+                                OperacionLote::Update { nombre, etapa, .. }=>{
+                                    let valido=nombre.as_deref().map(nombre_valido);
+                                    if (valido.is_none() && etapa.is_none()) || valido == Some(None) {
+                                        Err((400,3))
+                                    }else{
+                                        let nombre=valido.flatten();
+                                        let movimiento=match etapa.as_deref() {
+                                            Some(etapa)=>contexto.validar_movimiento(&tarea, Some(etapa), false, db).await?.map(Some),
+                                            None=>Ok(None),
+                                        };
+                                        movimiento.inspect(|_| {
+                                            if let Some(nombre) = nombre.as_deref() {
+                                                tarea.nombre=nombre.to_string();
+                                                campos.insert("nombre".to_string(), Value::from(nombre));
+                                            }
+                                        })
+                                    }
+                                },
+                                OperacionLote::Complete { forzar, .. }=>{
+                                    contexto.validar_movimiento(&tarea, None, *forzar, db).await?.map(Some)
+                                },
+                                _=>{
+                                    tarea.deleted_at=Some(ahora);
+                                    campos.insert("deleted_at".to_string(), Value::from(ahora));
+                                    contexto.abiertas.remove(&tarea.id);
+                                    Ok(None)
+                                }
+                            };
+                            aplicada.map(|movimiento| {
+                                if let Some((etapa,completada)) = movimiento {
+                                    tarea.etapa=Some(etapa.clone());
+                                    tarea.estado=completada;
+                                    tarea.completada_at=if completada { tarea.completada_at.or(Some(ahora)) } else { None };
+                                    campos.insert("etapa".to_string(), Value::from(etapa));
+                                    campos.insert("estado".to_string(), Value::from(completada));
+                                    campos.insert("completada_at".to_string(), Value::from(tarea.completada_at));
+                                    if completada {
+                                        contexto.abiertas.remove(&tarea.id);
+                                    }else{
+                                        contexto.abiertas.insert(tarea.id.clone());
+                                    }
+                                }
+                                campos.insert("update_at".to_string(), Value::from(ahora));
+                                cambios.entry(tarea.id.clone()).or_default().extend(campos);
+                                contexto.tareas.insert(tarea.id.clone(), tarea);
+                            })
+                        },
+                        _=>Err((404,1)),
+                    }
+                }
+            };
+            resultados.push(match resultado {
+                Ok(())=>ResultadoOperacion { indice, status: if op.id().is_none() { 201 } else { 200 }, error: None, id: op.id().map(str::to_string) },
+                Err((status,error))=>ResultadoOperacion { indice, status, error: Some(error), id: op.id().map(str::to_string) },
+            });
+        }
+
+        if lote.atomico && resultados.iter().any(|res| res.error.is_some()) {
+            for resultado in resultados.iter_mut().filter(|res| res.error.is_none()) {
+                resultado.status=424;
+                resultado.error=Some(12);
+            }
+            return Ok((LoteResultado { resultados, aplicadas: 0 }, vec![]));
+        }
+
+        // con `atomico` cualquier escritura que falle deshace las otras
+        let mut fallo=false;
+        let mut editadas:Vec<Change>=Vec::new();
+        if !cambios.is_empty() {
+            let ids:Vec<String>=cambios.keys().cloned().collect();
+            let parches=Value::Object(cambios.into_iter().map(|(id,campos)| (id,Value::Object(campos))).collect());
+            let query = r.db("tareas").table("tareas")
+            .get_all(r.args(ids.clone()))
+            .filter(func!(|t| t.has_fields("deleted_at").not()))
+            .update(
+                r.with_opt(
+                    func!(|t| Self::con_version(t.clone(), r.expr(parches.clone()).g(t.g("id")))),
+                    UpdateOptions {return_changes: Some(true.into()), ..Default::default() }
+                )
+            )
+            .exec::<_,WriteStatus>(db);
+            let actualizadas:HashSet<String>=match query.await {
+                Ok(res)=>{
+                    editadas=res.changes.unwrap_or_default();
+                    editadas.iter()
+                        .filter_map(|c| c.new_val.as_ref()?.get("id")?.as_str().map(str::to_string))
+                        .collect()
+                },
+                Err(e)=>{
+                    println!("error ejecutar_lote update {}",e);
+                    HashSet::new()
+                }
+            };
+            // las que no se escribieron (error o movidas a la papelera mientras tanto)
+            for resultado in resultados.iter_mut().filter(|res| res.error.is_none()) {
+                if resultado.id.as_ref().is_some_and(|id| ids.contains(id) && !actualizadas.contains(id)) {
+                    resultado.status=500;
+                    resultado.error=Some(2);
+                    fallo=true;
+                }
+            }
+        }
+
+        let mut creadas:Vec<Change>=Vec::new();
+        if !nuevas.is_empty() && (!lote.atomico || !fallo) {
+            let flujo=&contexto.flujos[&Flujos::id_para(id_usuario, id_espacio)];
+            // como si se crearan una por una: la ultima queda primera en el orden manual
+            let mut siguiente=Self::primera_posicion(id_usuario, id_espacio, db).await?;
+            let mut documentos=Vec::with_capacity(nuevas.len());
+            for (_,nombre) in nuevas.iter() {
+                let posicion=posicion_entre(None, siguiente.as_deref())
+                    .or_else(|| posicion_entre(None, None))
+                    .unwrap_or_default();
+                let mut documento=json!({
+                    "nombre":nombre,
+                    "id_usuario":id_usuario,
+                    "estado":false,
+                    "etapa":flujo.inicial().clave,
+                    "posicion":posicion,
+                    "create_at":ahora,
+                    "update_at":ahora,
+                });
+                if let Some(id_espacio) = id_espacio {
+                    documento["id_espacio"]=Value::from(id_espacio);
+                }
+                documentos.push(documento);
+                siguiente=Some(posicion);
+            }
+            let query = r.db("tareas").table("tareas")
+            .insert(
+                r.with_opt(
+                    Value::Array(documentos),
+                    InsertOptions {return_changes: Some(true.into()), ..Default::default() }
+                )
+            )
+            .exec::<_,WriteStatus>(db);
+            match query.await {
+                Ok(res)=>{
+                    let claves:Vec<String>=res.generated_keys.unwrap_or_default().iter().map(|k| k.to_string()).collect();
+                    creadas=res.changes.unwrap_or_default();
+                    for (k,(indice,_)) in nuevas.iter().enumerate() {
+                        match claves.get(k) {
+                            Some(id)=>resultados[*indice].id=Some(id.clone()),
+                            None=>{
+                                resultados[*indice].status=500;
+                                resultados[*indice].error=Some(2);
+                                fallo=true;
+                            }
+                        }
+                    }
+                },
+                Err(e)=>{
+                    println!("error ejecutar_lote insert {}",e);
+                    for (indice,_) in nuevas.iter() {
+                        resultados[*indice].status=500;
+                        resultados[*indice].error=Some(2);
+                    }
+                    fallo=true;
+                }
+            }
+        }
+
+        if lote.atomico && fallo {
+            Self::deshacer_lote(&editadas, &creadas, db).await;
+            for resultado in resultados.iter_mut().filter(|res| res.error.is_none()) {
+                resultado.status=424;
+                resultado.error=Some(12);
+            }
+            return Ok((LoteResultado { resultados, aplicadas: 0 }, vec![]));
+        }
+
+        if !editadas.is_empty() {
+            Historial::registrar(&editadas, id_usuario, "lote", db).await;
+        }
+        if !creadas.is_empty() {
+            Historial::registrar(&creadas, id_usuario, "crear", db).await;
+        }
+        let mut afectadas:Vec<Tareas>=Vec::new();
+        {
+            let mut indice=IndiceBusqueda::write();
+            for tarea in editadas.iter().chain(creadas.iter()).filter_map(|c| Tareas::deserialize(c.new_val.clone()?).ok()) {
+                if tarea.deleted_at.is_some() {
+                    indice.eliminar(&tarea.id);
+                }else{
+                    indice.indexar(&tarea);
+                }
+                afectadas.push(tarea);
+            }
+        }
+
+        let aplicadas=resultados.iter().filter(|res| res.error.is_none()).count();
+        Ok((LoteResultado { resultados, aplicadas }, afectadas))
+    }
+
+    /// deshace un lote atomico que fallo a medias: las tareas editadas vuelven al documento que
+    /// tenian y las creadas se borran. Es una compensacion y no una transaccion, un cambio de otra
+    /// peticion sobre esas tareas entre medio tambien se pierde
+    async fn deshacer_lote(editadas:&[Change],creadas:&[Change],db:&Session){
+        let previas:Vec<Value>=editadas.iter().filter_map(|c| c.old_val.clone()).collect();
+        if !previas.is_empty() {
+            let query = r.db("tareas").table("tareas")
+            .insert(
+                r.with_opt(
+                    Value::Array(previas),
+                    InsertOptions {conflict: Some(Conflict::Replace), ..Default::default() }
+                )
+            )
+            .exec::<_,WriteStatus>(db);
+            if let Err(e) = query.await {
+                println!("error deshacer_lote update {}",e);
+            }
+        }
+        let ids:Vec<String>=creadas.iter()
+            .filter_map(|c| c.new_val.as_ref()?.get("id")?.as_str().map(str::to_string))
+            .collect();
+        if !ids.is_empty() {
+            let query = r.db("tareas").table("tareas")
+            .get_all(r.args(ids))
+            .delete(())
+            .exec::<_,WriteStatus>(db);
+            if let Err(e) = query.await {
+                println!("error deshacer_lote insert {}",e);
+            }
+        }
+    }
+}
@@ -0,0 +1,707 @@
+use std::{collections::HashMap, io::{Cursor, Read}};
+
+use rocket::serde::Deserialize;
+use serde_json::Value;
+use zip::ZipArchive;
+
+use crate::utils::{Utils, DIA_MS};
+
+use super::{leer_importacion, registros_csv, ErrorImportacion, FilaLeida, Prioridad, TareasHttp, IMPORTAR_MAX_FILAS};
+
+/// aplicacion de la que viene el archivo de `POST /tareas/import`
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum OrigenImportacion{
+    /// exportacion de esta API, CSV o arreglo JSON
+    Tareas,
+    /// CSV de un proyecto, el ZIP del respaldo con un CSV por proyecto o el JSON de la API de sincronizacion
+    Todoist,
+    /// JSON o CSV de un tablero
+    Trello,
+}
+
+impl OrigenImportacion {
+    pub fn desde(valor:Option<&str>)->Option<OrigenImportacion>{
+        match valor.map(str::to_lowercase).as_deref() {
+            None | Some("tareas")=>Some(OrigenImportacion::Tareas),
+            Some("todoist")=>Some(OrigenImportacion::Todoist),
+            Some("trello")=>Some(OrigenImportacion::Trello),
+            _=>None,
+        }
+    }
+
+    pub fn leer(&self,contenido:&[u8],max_bytes:u64)->Result<Vec<FilaLeida>,ErrorImportacion>{
+        let filas=match (self,contenido.starts_with(b"PK\x03\x04"),primer_caracter(contenido)) {
+            (OrigenImportacion::Tareas,_,_)=>return leer_importacion(contenido, max_bytes),
+            (OrigenImportacion::Todoist,true,_)=>leer_todoist_zip(contenido, max_bytes)?,
+            (OrigenImportacion::Todoist,false,Some(b'{'))=>leer_todoist_json(contenido)?,
+            (OrigenImportacion::Todoist,false,_)=>leer_todoist_csv(contenido, None, "")?,
+            (OrigenImportacion::Trello,false,Some(b'{'))=>leer_trello_json(contenido)?,
+            (OrigenImportacion::Trello,false,_)=>leer_trello_csv(contenido)?,
+            (OrigenImportacion::Trello,true,_)=>return Err(ErrorImportacion::Formato),
+        };
+        if filas.len() > IMPORTAR_MAX_FILAS {
+            return Err(ErrorImportacion::Tamanio);
+        }
+        Ok(filas)
+    }
+}
+
+fn primer_caracter(contenido:&[u8])->Option<u8>{
+    contenido.iter()
+        .skip(if contenido.starts_with("\u{feff}".as_bytes()) { 3 } else { 0 })
+        .find(|b| !b.is_ascii_whitespace())
+        .copied()
+}
+
+/// fecha `AAAA-MM-DD` o `AAAA-MM-DDTHH:MM[:SS[.mmm]]` con `Z` o `±HH:MM` opcionales, en ms;
+/// las fechas sin hora vencen al final del dia y las horas sin zona se toman como UTC
+fn fecha_externa(texto:&str)->Option<i64>{
+    let texto=texto.trim();
+    let numero=|desde:usize,hasta:usize| texto.get(desde..hasta).filter(|v| v.bytes().all(|b| b.is_ascii_digit()))?.parse::<i64>().ok();
+    if texto.get(4..5) != Some("-") || texto.get(7..8) != Some("-") {
+        return None;
+    }
+    let dia=Utils::dia_de_fecha(numero(0,4)?, numero(5,7)?, numero(8,10)?)?;
+    if texto.len() == 10 {
+        return Some(dia * DIA_MS + DIA_MS - 1000);
+    }
+    if !matches!(texto.get(10..11), Some("T") | Some(" ")) || texto.get(13..14) != Some(":") {
+        return None;
+    }
+    let (hora,minuto)=(numero(11,13)?, numero(14,16)?);
+    let mut resto=&texto[16..];
+    let mut segundos=0;
+    if let Some(s) = resto.strip_prefix(':') {
+        segundos=s.get(..2).filter(|v| v.bytes().all(|b| b.is_ascii_digit()))?.parse::<i64>().ok()?;
+        resto=&s[2..];
+    }
+    if let Some(s) = resto.strip_prefix('.') {
+        resto=s.trim_start_matches(|c:char| c.is_ascii_digit());
+    }
+    let zona=match resto {
+        "" | "Z"=>0,
+        _=>{
+            let signo=match resto.get(..1) {
+                Some("+")=>1,
+                Some("-")=>-1,
+                _=>return None,
+            };
+            let (h,m)=resto[1..].split_once(':').unwrap_or((resto.get(1..3)?,resto.get(3..).unwrap_or("00")));
+            signo * (h.parse::<i64>().ok()? * 60 + m.parse::<i64>().ok()?)
+        }
+    };
+    if hora > 23 || minuto > 59 || segundos > 60 {
+        return None;
+    }
+    Some(dia * DIA_MS + ((hora * 60 + minuto - zona) * 60 + segundos.min(59)) * 1000)
+}
+
+/// id de Todoist o Trello, que segun la version viene como texto o como numero
+fn texto_id(valor:&Value)->Option<String>{
+    match valor {
+        Value::String(id)=>Some(id.clone()),
+        Value::Number(id)=>Some(id.to_string()),
+        _=>None,
+    }
+}
+
+/// un ZIP con un CSV por proyecto; el nombre del proyecto sale del archivo, `Proyecto [123].csv`
+fn leer_todoist_zip(contenido:&[u8],max_bytes:u64)->Result<Vec<FilaLeida>,ErrorImportacion>{
+    let mut zip=ZipArchive::new(Cursor::new(contenido)).map_err(|_| ErrorImportacion::Formato)?;
+    let mut filas=Vec::new();
+    let mut leidos:u64=0;
+    for indice in 0..zip.len() {
+        let archivo=zip.by_index(indice).map_err(|_| ErrorImportacion::Formato)?;
+        let nombre=archivo.name().rsplit('/').next().unwrap_or_default().to_string();
+        let Some(proyecto) = nombre.strip_suffix(".csv") else {
+            continue;
+        };
+        let proyecto=match proyecto.rsplit_once(" [") {
+            Some((proyecto,id)) if id.ends_with(']')=>proyecto.to_string(),
+            _=>proyecto.to_string(),
+        };
+        // el tamaño declarado no es confiable, se corta al leer
+        let mut datos=Vec::new();
+        if archivo.take(max_bytes - leidos + 1).read_to_end(&mut datos).is_err() {
+            return Err(ErrorImportacion::Formato);
+        }
+        leidos += datos.len() as u64;
+        if leidos > max_bytes {
+            return Err(ErrorImportacion::Tamanio);
+        }
+        filas.extend(leer_todoist_csv(&datos, Some(&proyecto), &format!("{}:",indice))?);
+        if filas.len() > IMPORTAR_MAX_FILAS {
+            return Err(ErrorImportacion::Tamanio);
+        }
+    }
+    if filas.is_empty() {
+        return Err(ErrorImportacion::Formato);
+    }
+    Ok(filas)
+}
+
+/// CSV de un proyecto: `TYPE`, `CONTENT`, `PRIORITY` (1 la mas alta), `INDENT` y `DATE`. Las
+/// etiquetas van en el texto como `@etiqueta` y las secciones se pasan como etiqueta a sus tareas
+fn leer_todoist_csv(contenido:&[u8],proyecto:Option<&str>,prefijo:&str)->Result<Vec<FilaLeida>,ErrorImportacion>{
+    let texto=std::str::from_utf8(contenido).map_err(|_| ErrorImportacion::Formato)?;
+    let mut registros=registros_csv(texto.trim_start_matches('\u{feff}'))?.into_iter();
+    let encabezado:Vec<String>=registros.next().ok_or(ErrorImportacion::Formato)?
+        .iter()
+        .map(|c| c.trim().to_uppercase())
+        .collect();
+    let columna=|nombre:&str| encabezado.iter().position(|c| c == nombre);
+    let (Some(col_tipo),Some(col_contenido)) = (columna("TYPE"),columna("CONTENT")) else {
+        return Err(ErrorImportacion::Formato);
+    };
+    let (col_descripcion,col_prioridad,col_nivel,col_fecha)=(columna("DESCRIPTION"),columna("PRIORITY"),columna("INDENT"),columna("DATE"));
+
+    let mut filas=Vec::new();
+    let mut seccion:Option<String>=None;
+    // ultima tarea de cada nivel, para colgar las subtareas
+    let mut ultima_por_nivel:Vec<String>=Vec::new();
+    for (numero,registro) in registros.enumerate() {
+        let celda=|col:Option<usize>| col.and_then(|c| registro.get(c)).map(|v| v.trim()).filter(|v| !v.is_empty());
+        let contenido=celda(Some(col_contenido)).unwrap_or_default();
+        match celda(Some(col_tipo)).map(str::to_lowercase).as_deref() {
+            Some("task")=>{},
+            Some("section")=>{
+                seccion=Some(contenido.to_string());
+                ultima_por_nivel.clear();
+                continue;
+            },
+            Some("note")=>{
+                filas.push(FilaLeida::omitida(None, format!("comentario de Todoist, no se importa: {}",contenido)));
+                continue;
+            },
+            // `meta` guarda la vista del proyecto
+            _=>continue,
+        }
+        let mut avisos=Vec::new();
+        let mut etiquetas:Vec<String>=proyecto.into_iter().chain(seccion.as_deref()).map(str::to_string).collect();
+        let mut palabras=Vec::new();
+        for palabra in contenido.split(' ') {
+            match palabra.strip_prefix('@') {
+                Some(etiqueta) if !etiqueta.is_empty()=>etiquetas.push(etiqueta.to_string()),
+                _=>palabras.push(palabra),
+            }
+        }
+        let prioridad=match celda(col_prioridad) {
+            Some("1")=>Some(Prioridad::Urgente),
+            Some("2")=>Some(Prioridad::Alta),
+            Some("3")=>Some(Prioridad::Media),
+            _=>None,
+        };
+        let vence=match celda(col_fecha) {
+            Some(fecha)=>{
+                let vence=fecha_externa(fecha);
+                if vence.is_none() {
+                    avisos.push(format!("fecha \"{}\" no se reconoce, se importa sin vence",fecha));
+                }
+                vence
+            },
+            None=>None,
+        };
+        if celda(col_descripcion).is_some() {
+            avisos.push("la descripcion no se importa".to_string());
+        }
+        let nivel=celda(col_nivel).and_then(|n| n.parse::<usize>().ok()).unwrap_or(1).max(1);
+        ultima_por_nivel.truncate(nivel - 1);
+        let id_padre=if nivel > 1 { ultima_por_nivel.last().cloned() } else { None };
+        let id=format!("{}{}",prefijo,numero);
+        ultima_por_nivel.push(id.clone());
+        let mut fila=FilaLeida::nueva(Some(id), TareasHttp {
+            nombre: palabras.join(" "),
+            vence,
+            etiquetas,
+            prioridad,
+            id_padre,
+        });
+        fila.nombre=Some(contenido.to_string());
+        fila.avisos=avisos;
+        filas.push(fila);
+    }
+    Ok(filas)
+}
+
+#[derive(Deserialize)]
+#[serde(crate="rocket::serde")]
+struct ExportacionTodoist{
+    #[serde(default)]
+    projects:Vec<ProyectoTodoist>,
+    #[serde(default)]
+    sections:Vec<ProyectoTodoist>,
+    #[serde(default)]
+    labels:Vec<ProyectoTodoist>,
+    items:Vec<ItemTodoist>,
+}
+
+/// proyecto, seccion o etiqueta: solo interesa el nombre
+#[derive(Deserialize)]
+#[serde(crate="rocket::serde")]
+struct ProyectoTodoist{
+    id:Value,
+    name:String,
+}
+
+#[derive(Deserialize)]
+#[serde(crate="rocket::serde")]
+struct ItemTodoist{
+    id:Value,
+    content:String,
+    #[serde(default)]
+    description:String,
+    #[serde(default)]
+    project_id:Value,
+    #[serde(default)]
+    section_id:Value,
+    #[serde(default)]
+    parent_id:Value,
+    /// nombres, o ids de `labels` en las exportaciones viejas
+    #[serde(default)]
+    labels:Vec<Value>,
+    /// 4 la mas alta, 1 sin prioridad
+    #[serde(default)]
+    priority:u8,
+    #[serde(default)]
+    due:Option<VenceTodoist>,
+    #[serde(default)]
+    checked:bool,
+    #[serde(default)]
+    completed_at:Option<String>,
+    #[serde(default)]
+    is_deleted:bool,
+}
+
+#[derive(Deserialize)]
+#[serde(crate="rocket::serde")]
+struct VenceTodoist{
+    date:String,
+    #[serde(default)]
+    is_recurring:bool,
+    #[serde(default)]
+    string:String,
+}
+
+/// JSON de la API de sincronizacion, con `items`, `projects`, `sections` y `labels`
+fn leer_todoist_json(contenido:&[u8])->Result<Vec<FilaLeida>,ErrorImportacion>{
+    let exportacion:ExportacionTodoist=serde_json::from_slice(contenido).map_err(|_| ErrorImportacion::Formato)?;
+    let nombres=|lista:&[ProyectoTodoist]| -> HashMap<String,String> {
+        lista.iter().filter_map(|p| Some((texto_id(&p.id)?,p.name.clone()))).collect()
+    };
+    let (proyectos,secciones,etiquetas)=(nombres(&exportacion.projects),nombres(&exportacion.sections),nombres(&exportacion.labels));
+
+    Ok(exportacion.items.into_iter().map(|item| {
+        if item.is_deleted {
+            return FilaLeida::omitida(Some(item.content), "eliminada en Todoist".to_string());
+        }
+        let mut avisos=Vec::new();
+        let mut lista:Vec<String>=[proyectos.get(&texto_id(&item.project_id).unwrap_or_default()),secciones.get(&texto_id(&item.section_id).unwrap_or_default())]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        for etiqueta in item.labels.iter() {
+            match etiqueta {
+                Value::String(nombre)=>lista.push(nombre.clone()),
+                id=>match texto_id(id).and_then(|id| etiquetas.get(&id)) {
+                    Some(nombre)=>lista.push(nombre.clone()),
+                    None=>avisos.push(format!("etiqueta {} no esta en labels",id)),
+                }
+            }
+        }
+        let vence=match item.due.as_ref() {
+            Some(due)=>{
+                let vence=fecha_externa(&due.date);
+                if vence.is_none() {
+                    avisos.push(format!("fecha \"{}\" no se reconoce, se importa sin vence",due.date));
+                }
+                if due.is_recurring {
+                    avisos.push(format!("se repetia \"{}\", se importa solo el proximo vencimiento",due.string));
+                }
+                vence
+            },
+            None=>None,
+        };
+        if !item.description.trim().is_empty() {
+            avisos.push("la descripcion no se importa".to_string());
+        }
+        let prioridad=match item.priority {
+            4=>Some(Prioridad::Urgente),
+            3=>Some(Prioridad::Alta),
+            2=>Some(Prioridad::Media),
+            _=>None,
+        };
+        let mut fila=FilaLeida::nueva(texto_id(&item.id), TareasHttp {
+            nombre: item.content.clone(),
+            vence,
+            etiquetas: lista,
+            prioridad,
+            id_padre: texto_id(&item.parent_id),
+        });
+        fila.nombre=Some(item.content);
+        fila.completada=item.checked;
+        fila.completada_at=item.completed_at.as_deref().and_then(fecha_externa).filter(|_| item.checked);
+        fila.avisos=avisos;
+        fila
+    }).collect())
+}
+
+#[derive(Deserialize)]
+#[serde(crate="rocket::serde")]
+struct TableroTrello{
+    name:String,
+    #[serde(default)]
+    lists:Vec<ListaTrello>,
+    #[serde(default)]
+    labels:Vec<EtiquetaTrello>,
+    cards:Vec<TarjetaTrello>,
+    #[serde(default)]
+    checklists:Vec<ChecklistTrello>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate="rocket::serde")]
+struct ListaTrello{
+    id:String,
+    name:String,
+    #[serde(default)]
+    closed:bool,
+}
+
+#[derive(Deserialize)]
+#[serde(crate="rocket::serde")]
+struct EtiquetaTrello{
+    id:String,
+    #[serde(default)]
+    name:String,
+    /// las etiquetas sin nombre se importan con su color
+    #[serde(default)]
+    color:Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate="rocket::serde")]
+struct TarjetaTrello{
+    id:String,
+    name:String,
+    #[serde(default)]
+    desc:String,
+    #[serde(default)]
+    #[serde(rename="idList")]
+    id_list:String,
+    #[serde(default)]
+    #[serde(rename="idLabels")]
+    id_labels:Vec<String>,
+    #[serde(default)]
+    due:Option<String>,
+    #[serde(default)]
+    #[serde(rename="dueComplete")]
+    due_complete:bool,
+    #[serde(default)]
+    closed:bool,
+}
+
+#[derive(Deserialize)]
+#[serde(crate="rocket::serde")]
+struct ChecklistTrello{
+    #[serde(rename="idCard")]
+    id_card:String,
+    #[serde(default)]
+    #[serde(rename="checkItems")]
+    check_items:Vec<ItemChecklistTrello>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate="rocket::serde")]
+struct ItemChecklistTrello{
+    name:String,
+    /// `complete` o `incomplete`
+    state:String,
+    #[serde(default)]
+    due:Option<String>,
+}
+
+/// JSON de "Imprimir y exportar" de un tablero: cada tarjeta es una tarea con el tablero y la
+/// lista como etiquetas, y los elementos de sus checklists son subtareas
+fn leer_trello_json(contenido:&[u8])->Result<Vec<FilaLeida>,ErrorImportacion>{
+    let tablero:TableroTrello=serde_json::from_slice(contenido).map_err(|_| ErrorImportacion::Formato)?;
+    let listas:HashMap<&str,&ListaTrello>=tablero.lists.iter().map(|l| (l.id.as_str(),l)).collect();
+    let etiquetas:HashMap<&str,String>=tablero.labels.iter()
+        .filter_map(|e| {
+            let nombre=if e.name.trim().is_empty() { e.color.clone()? } else { e.name.clone() };
+            Some((e.id.as_str(),nombre))
+        })
+        .collect();
+    let mut checklists:HashMap<&str,Vec<&ItemChecklistTrello>>=HashMap::new();
+    for checklist in tablero.checklists.iter() {
+        checklists.entry(checklist.id_card.as_str()).or_default().extend(checklist.check_items.iter());
+    }
+
+    let mut filas=Vec::new();
+    for tarjeta in tablero.cards.iter() {
+        let lista=listas.get(tarjeta.id_list.as_str());
+        let items=checklists.remove(tarjeta.id.as_str()).unwrap_or_default();
+        if tarjeta.closed || lista.map(|l| l.closed).unwrap_or(false) {
+            filas.push(FilaLeida::omitida(Some(tarjeta.name.clone()), "archivada en Trello".to_string()));
+            for item in items {
+                filas.push(FilaLeida::omitida(Some(item.name.clone()), "su tarjeta esta archivada".to_string()));
+            }
+            continue;
+        }
+        let mut avisos=Vec::new();
+        let mut lista_etiquetas=vec![tablero.name.clone()];
+        lista_etiquetas.extend(lista.map(|l| l.name.clone()));
+        lista_etiquetas.extend(tarjeta.id_labels.iter().filter_map(|id| etiquetas.get(id.as_str()).cloned()));
+        let vence=fecha_trello(tarjeta.due.as_deref(), &mut avisos);
+        if !tarjeta.desc.trim().is_empty() {
+            avisos.push("la descripcion no se importa".to_string());
+        }
+        let mut fila=FilaLeida::nueva(Some(tarjeta.id.clone()), TareasHttp {
+            nombre: tarjeta.name.clone(),
+            vence,
+            etiquetas: lista_etiquetas,
+            prioridad: None,
+            id_padre: None,
+        });
+        fila.nombre=Some(tarjeta.name.clone());
+        fila.completada=tarjeta.due_complete;
+        fila.avisos=avisos;
+        filas.push(fila);
+        for item in items {
+            let mut avisos=Vec::new();
+            let vence=fecha_trello(item.due.as_deref(), &mut avisos);
+            let mut fila=FilaLeida::nueva(None, TareasHttp {
+                nombre: item.name.clone(),
+                vence,
+                id_padre: Some(tarjeta.id.clone()),
+                ..Default::default()
+            });
+            fila.nombre=Some(item.name.clone());
+            fila.completada=item.state == "complete";
+            fila.avisos=avisos;
+            filas.push(fila);
+        }
+    }
+    Ok(filas)
+}
+
+fn fecha_trello(fecha:Option<&str>,avisos:&mut Vec<String>)->Option<i64>{
+    let fecha=fecha.filter(|f| !f.trim().is_empty())?;
+    let vence=fecha_externa(fecha);
+    if vence.is_none() {
+        avisos.push(format!("fecha \"{}\" no se reconoce, se importa sin vence",fecha));
+    }
+    vence
+}
+
+/// CSV de un tablero: se requiere `Card Name` y se leen `Card ID`, `List Name`, `Board Name`,
+/// `Labels` (`nombre (color)` separadas por coma), `Due Date`, `Due Complete` y `Archived`
+fn leer_trello_csv(contenido:&[u8])->Result<Vec<FilaLeida>,ErrorImportacion>{
+    let texto=std::str::from_utf8(contenido).map_err(|_| ErrorImportacion::Formato)?;
+    let mut registros=registros_csv(texto.trim_start_matches('\u{feff}'))?.into_iter();
+    let encabezado:Vec<String>=registros.next().ok_or(ErrorImportacion::Formato)?
+        .iter()
+        .map(|c| c.trim().to_lowercase())
+        .collect();
+    let columna=|nombre:&str| encabezado.iter().position(|c| c == nombre);
+    let Some(col_nombre) = columna("card name") else {
+        return Err(ErrorImportacion::Formato);
+    };
+    let (col_id,col_lista,col_tablero,col_etiquetas,col_vence,col_completa,col_archivada,col_descripcion)=(
+        columna("card id"),columna("list name"),columna("board name"),columna("labels"),
+        columna("due date"),columna("due complete"),columna("archived"),columna("card description")
+    );
+
+    Ok(registros.map(|registro| {
+        let celda=|col:Option<usize>| col.and_then(|c| registro.get(c)).map(|v| v.trim()).filter(|v| !v.is_empty());
+        let verdadero=|col:Option<usize>| celda(col).map(|v| v.eq_ignore_ascii_case("true")).unwrap_or(false);
+        let nombre=celda(Some(col_nombre)).unwrap_or_default().to_string();
+        if verdadero(col_archivada) {
+            return FilaLeida::omitida(Some(nombre), "archivada en Trello".to_string());
+        }
+        let mut avisos=Vec::new();
+        let mut etiquetas:Vec<String>=[celda(col_tablero),celda(col_lista)].into_iter().flatten().map(str::to_string).collect();
+        for etiqueta in celda(col_etiquetas).unwrap_or_default().split(',') {
+            // `Urgente (red)`, o solo `(red)` si no tiene nombre
+            let etiqueta=etiqueta.trim();
+            let etiqueta=match etiqueta.rsplit_once(" (") {
+                Some((nombre,_)) if etiqueta.ends_with(')')=>nombre.trim(),
+                _=>etiqueta.trim_start_matches('(').trim_end_matches(')'),
+            };
+            if !etiqueta.is_empty() {
+                etiquetas.push(etiqueta.to_string());
+            }
+        }
+        let vence=fecha_trello(celda(col_vence), &mut avisos);
+        if celda(col_descripcion).is_some() {
+            avisos.push("la descripcion no se importa".to_string());
+        }
+        let mut fila=FilaLeida::nueva(celda(col_id).map(str::to_string), TareasHttp {
+            nombre: nombre.clone(),
+            vence,
+            etiquetas,
+            ..Default::default()
+        });
+        fila.nombre=Some(nombre);
+        fila.completada=verdadero(col_completa);
+        fila.avisos=avisos;
+        fila
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::IMPORTAR_MAX_BYTES;
+    use super::*;
+
+    fn tarea(fila:&FilaLeida)->&TareasHttp{
+        match fila.tarea.as_ref() {
+            Ok(tarea)=>tarea,
+            Err(errores)=>panic!("fila omitida {:?}",errores),
+        }
+    }
+
+    #[test]
+    fn fechas_con_hora_zona_y_segundos() {
+        let dia=Utils::dia_de_fecha(2026, 10, 19).unwrap() * DIA_MS;
+        let hora=|h:i64,m:i64,s:i64| dia + ((h * 60 + m) * 60 + s) * 1000;
+        // sin hora vence al final del dia
+        assert_eq!(fecha_externa("2026-10-19"), Some(dia + DIA_MS - 1000));
+        assert_eq!(fecha_externa(" 2026-10-19T10:30 "), Some(hora(10, 30, 0)));
+        assert_eq!(fecha_externa("2026-10-19T10:30:15Z"), Some(hora(10, 30, 15)));
+        assert_eq!(fecha_externa("2026-10-19T10:30:15.250+02:00"), Some(hora(8, 30, 15)));
+        assert_eq!(fecha_externa("2026-10-19 10:30-0300"), Some(hora(13, 30, 0)));
+        // el segundo intercalar queda en el 59
+        assert_eq!(fecha_externa("2026-10-19T23:59:60Z"), Some(hora(23, 59, 59)));
+        for invalida in ["19/10/2026","2026-13-01","2026-10-19T25:00","2026-10-19T10:3","2026-10-19T10:30X","mañana"] {
+            assert_eq!(fecha_externa(invalida), None, "{}",invalida);
+        }
+    }
+
+    #[test]
+    fn todoist_csv_anida_por_indent_con_prioridad_1_la_mas_alta() {
+        let csv="\u{feff}TYPE,CONTENT,DESCRIPTION,PRIORITY,INDENT,DATE\n\
+            task,Comprar @casa pan,,1,1,2026-10-19\n\
+            task,Sub tarea,,4,2,\n\
+            task,Nieta,,2,3,\n\
+            section,Trabajo,,,,\n\
+            task,Informe,una nota,3,1,mañana\n\
+            task,Anexo,,,2,\n\
+            note,un comentario,,,,\n\
+            meta,view_style=list,,,,\n";
+        let filas=leer_todoist_csv(csv.as_bytes(), Some("Proyecto"), "").ok().unwrap();
+        assert_eq!(filas.len(), 6);
+
+        let comprar=tarea(&filas[0]);
+        assert_eq!(comprar.nombre, "Comprar pan");
+        assert_eq!(comprar.etiquetas, vec!["Proyecto","casa"]);
+        // en el CSV 1 es la mas alta
+        assert_eq!(comprar.prioridad, Some(Prioridad::Urgente));
+        assert_eq!(comprar.vence, fecha_externa("2026-10-19"));
+        assert_eq!(filas[0].nombre.as_deref(), Some("Comprar @casa pan"));
+
+        assert_eq!(tarea(&filas[1]).id_padre, filas[0].id);
+        assert_eq!(tarea(&filas[1]).prioridad, None);
+        assert_eq!(tarea(&filas[2]).id_padre, filas[1].id);
+        assert_eq!(tarea(&filas[2]).prioridad, Some(Prioridad::Alta));
+
+        // la seccion corta el anidamiento y pasa como etiqueta
+        let informe=tarea(&filas[3]);
+        assert_eq!(informe.id_padre, None);
+        assert_eq!(informe.etiquetas, vec!["Proyecto","Trabajo"]);
+        assert_eq!(informe.prioridad, Some(Prioridad::Media));
+        assert_eq!(informe.vence, None);
+        assert_eq!(filas[3].avisos.len(), 2);
+        assert_eq!(tarea(&filas[4]).id_padre, filas[3].id);
+
+        assert!(filas[5].tarea.is_err());
+        assert_eq!(filas[5].nombre, None);
+
+        // en el ZIP los ids llevan el prefijo del archivo para no chocar entre proyectos
+        let zip=leer_todoist_csv(b"TYPE,CONTENT,INDENT\ntask,a,1\ntask,b,2\n", None, "3:").ok().unwrap();
+        assert_eq!(zip[0].id.as_deref(), Some("3:0"));
+        assert_eq!(tarea(&zip[1]).id_padre.as_deref(), Some("3:0"));
+
+        assert!(leer_todoist_csv(b"NOMBRE,FECHA\na,b\n", None, "").is_err());
+    }
+
+    #[test]
+    fn todoist_json_con_prioridad_al_reves() {
+        let json=r#"{
+            "projects":[{"id":"p","name":"Casa"}],
+            "labels":[{"id":9,"name":"viejo"}],
+            "items":[
+                {"id":"1","content":"Pagar","project_id":"p","priority":4,"labels":["luz",9],"due":{"date":"2026-10-19","is_recurring":true,"string":"cada mes"}},
+                {"id":2,"content":"Sub","parent_id":"1","priority":1,"checked":true,"completed_at":"2026-10-18T09:00:00Z"},
+                {"id":"3","content":"Borrada","is_deleted":true}
+            ]
+        }"#;
+        let filas=leer_todoist_json(json.as_bytes()).ok().unwrap();
+        assert_eq!(filas.len(), 3);
+        let pagar=tarea(&filas[0]);
+        assert_eq!(pagar.prioridad, Some(Prioridad::Urgente));
+        assert_eq!(pagar.etiquetas, vec!["Casa","luz","viejo"]);
+        assert_eq!(filas[0].avisos.len(), 1);
+        let sub=tarea(&filas[1]);
+        assert_eq!(sub.prioridad, None);
+        assert_eq!(sub.id_padre.as_deref(), Some("1"));
+        assert_eq!(filas[1].id.as_deref(), Some("2"));
+        assert!(filas[1].completada);
+        assert_eq!(filas[1].completada_at, fecha_externa("2026-10-18T09:00:00Z"));
+        assert!(filas[2].tarea.is_err());
+    }
+
+    #[test]
+    fn trello_json_pasa_checklists_a_subtareas() {
+        let json=r#"{
+            "name":"Tablero",
+            "lists":[{"id":"l1","name":"Pendiente"},{"id":"l2","name":"Viejo","closed":true}],
+            "labels":[{"id":"e1","name":"","color":"red"},{"id":"e2","name":"Urgente"}],
+            "cards":[
+                {"id":"c1","name":"Tarjeta","idList":"l1","idLabels":["e1","e2"],"due":"2026-10-19T12:00:00.000Z","desc":"detalle"},
+                {"id":"c2","name":"Archivada","idList":"l1","closed":true},
+                {"id":"c3","name":"En lista cerrada","idList":"l2"}
+            ],
+            "checklists":[
+                {"idCard":"c1","checkItems":[{"name":"Paso 1","state":"complete"},{"name":"Paso 2","state":"incomplete","due":"ayer"}]},
+                {"idCard":"c2","checkItems":[{"name":"Paso suelto","state":"incomplete"}]}
+            ]
+        }"#;
+        let filas=leer_trello_json(json.as_bytes()).ok().unwrap();
+        assert_eq!(filas.len(), 6);
+
+        let tarjeta=tarea(&filas[0]);
+        assert_eq!(filas[0].id.as_deref(), Some("c1"));
+        assert_eq!(tarjeta.etiquetas, vec!["Tablero","Pendiente","red","Urgente"]);
+        assert_eq!(tarjeta.vence, fecha_externa("2026-10-19T12:00:00Z"));
+        assert_eq!(filas[0].avisos, vec!["la descripcion no se importa"]);
+
+        let paso1=tarea(&filas[1]);
+        assert_eq!(paso1.nombre, "Paso 1");
+        assert_eq!(paso1.id_padre.as_deref(), Some("c1"));
+        assert!(filas[1].completada);
+        assert_eq!(filas[1].id, None);
+        assert!(!filas[2].completada);
+        assert_eq!(tarea(&filas[2]).vence, None);
+        assert_eq!(filas[2].avisos.len(), 1);
+
+        // la tarjeta archivada se informa con sus elementos
+        assert_eq!(filas[3].nombre.as_deref(), Some("Archivada"));
+        assert!(filas[3].tarea.is_err());
+        assert_eq!(filas[4].nombre.as_deref(), Some("Paso suelto"));
+        assert!(filas[4].tarea.is_err());
+        assert!(filas[5].tarea.is_err());
+    }
+
+    #[test]
+    fn trello_csv_con_etiquetas_y_archivadas() {
+        let csv="Card ID,Card Name,List Name,Board Name,Labels,Due Date,Due Complete,Archived\n\
+            a1,Uno,Hecho,Tablero,\"Urgente (red), (green)\",2026-10-19T12:00:00.000Z,true,false\n\
+            a2,Dos,Hecho,Tablero,,,false,true\n";
+        let filas=OrigenImportacion::Trello.leer(csv.as_bytes(), IMPORTAR_MAX_BYTES).ok().unwrap();
+        assert_eq!(filas.len(), 2);
+        assert_eq!(tarea(&filas[0]).etiquetas, vec!["Tablero","Hecho","Urgente","green"]);
+        assert!(filas[0].completada);
+        assert!(filas[1].tarea.is_err());
+    }
+}
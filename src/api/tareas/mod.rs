@@ -2,4 +2,43 @@ mod controller;
 pub use controller::{*};
 
 mod model;
-pub use model::{*};
\ No newline at end of file
+pub use model::{*};
+
+mod busqueda;
+pub use busqueda::{*};
+
+mod papelera;
+pub use papelera::{*};
+
+mod dependencias;
+pub use dependencias::{*};
+
+mod orden;
+pub use orden::{*};
+
+mod lote;
+pub use lote::{*};
+
+mod etag;
+pub use etag::{*};
+
+mod estadisticas;
+pub use estadisticas::{*};
+
+mod rapida;
+pub use rapida::{*};
+
+mod arbol;
+pub use arbol::{*};
+
+mod exportar;
+pub use exportar::{*};
+
+mod importar;
+pub use importar::{*};
+
+mod migracion;
+pub use migracion::{*};
+
+mod todotxt;
+pub use todotxt::{*};
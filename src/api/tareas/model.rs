@@ -1,14 +1,75 @@
-use rocket::serde::{Deserialize, Serialize};
-use unreql::{cmd::options::InsertOptions, r, rjson, types::WriteStatus, Session};
-use utoipa::ToSchema;
+use std::{cmp::Ordering, collections::{HashMap, HashSet}, sync::LazyLock};
+
+use rocket::{serde::{Deserialize, Serialize}, tokio::sync::{Mutex, MutexGuard}, FromForm};
+use serde_json::Value;
+use unreql::{cmd::options::{BetweenOptions, Conflict, DeleteOptions, InsertOptions, ReplaceOptions, Status, UpdateOptions}, func, r, rjson, types::{Change, WriteStatus}, Command, Session};
+use utoipa::{IntoParams, ToSchema};
 use validator::Validate;
 
-use crate::utils::Utils;
+use crate::{api::{Adjuntos, AlmacenamientoAdjuntos, Comentarios, Compartidos, Espacios, Flujos, Historial, Permiso, Tiempos, ACTOR_SISTEMA}, utils::Utils};
 
-#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+use super::{posicion_entre, posiciones_siguientes, IndiceBusqueda, TareaEncontrada};
+
+pub const LIMIT_DEFAULT:u32=50;
+pub const LIMIT_MAX:u32=200;
+
+/// indices secundarios de la tabla tareas: (nombre, campos de la clave compuesta)
+const INDICES_TAREAS:[(&str,[&str;3]);13]=[
+    ("usuario_create_at",["id_usuario","create_at","id"]),
+    ("usuario_update_at",["id_usuario","update_at","id"]),
+    ("usuario_nombre",["id_usuario","nombre","id"]),
+    ("usuario_posicion",["id_usuario","posicion","id"]),
+    ("usuario_deleted_at",["id_usuario","deleted_at","id"]),
+    ("usuario_completada_at",["id_usuario","completada_at","id"]),
+    ("usuario_vence",["id_usuario","vence","id"]),
+    ("espacio_create_at",["id_espacio","create_at","id"]),
+    ("espacio_update_at",["id_espacio","update_at","id"]),
+    ("espacio_nombre",["id_espacio","nombre","id"]),
+    ("espacio_posicion",["id_espacio","posicion","id"]),
+    ("espacio_completada_at",["id_espacio","completada_at","id"]),
+    ("espacio_vence",["id_espacio","vence","id"]),
+];
+
+/// indices secundarios simples (sobre un solo campo)
+const INDICES_SIMPLES:[&str;3]=["deleted_at","asignado_a","id_padre"];
+
+/// las ediciones de dependencias se hacen de a una, ver `Tareas::edicion_dependencias`
+static EDICION_DEPENDENCIAS:LazyLock<Mutex<()>>=LazyLock::new(|| Mutex::new(()));
+
+#[derive(Debug,Clone,Default,Deserialize,Serialize,ToSchema,Validate)]
 #[serde(crate="rocket::serde")]
 pub struct TareasHttp{
+    #[validate(length(min = 1, max = 500))]
     pub nombre:String,
+    /// fecha limite en ms
+    #[serde(default)]
+    #[validate(range(min = 0))]
+    pub vence:Option<i64>,
+    #[serde(default)]
+    pub etiquetas:Vec<String>,
+    #[serde(default)]
+    pub prioridad:Option<Prioridad>,
+    /// tarea de la que esta es subtarea
+    #[serde(default)]
+    pub id_padre:Option<String>,
+}
+
+/// prioridad de una tarea, ordenada de menor a mayor
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde",rename_all="lowercase")]
+pub enum Prioridad{
+    Baja,
+    Media,
+    Alta,
+    Urgente,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct AsignacionHttp{
+    /// usuario al que se asigna la tarea, `null` para quitar la asignacion
+    #[serde(default)]
+    pub username:Option<String>,
 }
 
 #[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
@@ -20,26 +81,463 @@ pub struct Tareas{
     pub estado:bool,
     pub create_at:i64,
     pub update_at:i64,
+    /// se incrementa con cada escritura de la tarea, incluido el orden manual; es su etag
+    #[serde(default)]
+    pub version:i64,
+    /// timestamp en que la tarea se movio a la papelera, ausente si no esta en ella
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub deleted_at:Option<i64>,
+    /// espacio de trabajo de la tarea, ausente en las tareas personales
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub id_espacio:Option<String>,
+    /// id del usuario responsable de la tarea, ausente si no esta asignada
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub asignado_a:Option<String>,
+    /// ids de las tareas que tienen que completarse antes que esta
+    #[serde(default,skip_serializing_if="Vec::is_empty")]
+    pub bloqueada_por:Vec<String>,
+    /// clave del estado del flujo de trabajo; `estado` es `true` solo en el ultimo estado del flujo
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub etapa:Option<String>,
+    /// posicion en el orden manual, las tareas se listan por este valor de menor a mayor
+    #[serde(default)]
+    pub posicion:String,
+    /// timestamp en que la tarea paso al ultimo estado del flujo, ausente o `null` si no esta completada
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub completada_at:Option<i64>,
+    /// fecha limite en ms, ausente si no tiene
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub vence:Option<i64>,
+    /// etiquetas en minusculas y sin repetir
+    #[serde(default,skip_serializing_if="Vec::is_empty")]
+    pub etiquetas:Vec<String>,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub prioridad:Option<Prioridad>,
+    /// tarea de la que esta es subtarea, ausente en las tareas de primer nivel
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub id_padre:Option<String>,
+    /// `UID` con que la creo un cliente CalDAV, ausente en las creadas por la API
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub uid_ical:Option<String>,
+}
+
+#[derive(Debug,Clone,Default,FromForm,IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TareasQuery{
+    /// cursor devuelto en `next_cursor` por la pagina anterior
+    pub cursor:Option<String>,
+    /// cantidad de tareas por pagina (1-200, por defecto 50)
+    pub limit:Option<u32>,
+    /// campo de orden: posicion (orden manual, por defecto), create_at, update_at o nombre
+    pub orden:Option<String>,
+    /// direccion del orden: asc o desc (por defecto asc para posicion y desc para el resto)
+    pub dir:Option<String>,
+    pub estado:Option<bool>,
+    /// clave del estado del flujo de trabajo
+    pub etapa:Option<String>,
+    /// timestamp en ms, inclusivo
+    pub creado_desde:Option<i64>,
+    /// timestamp en ms, inclusivo
+    pub creado_hasta:Option<i64>,
+    /// timestamp en ms, inclusivo
+    pub actualizado_desde:Option<i64>,
+    /// timestamp en ms, inclusivo
+    pub actualizado_hasta:Option<i64>,
+}
+
+impl TareasHttp {
+    /// etiquetas sin espacios alrededor, en minusculas y sin repetir
+    pub fn etiquetas_limpias(&self)->Vec<String>{
+        let mut etiquetas:Vec<String>=Vec::new();
+        for etiqueta in self.etiquetas.iter().map(|e| e.trim().to_lowercase()) {
+            if !etiqueta.is_empty() && !etiquetas.contains(&etiqueta) {
+                etiquetas.push(etiqueta);
+            }
+        }
+        etiquetas
+    }
+}
+
+impl TareasQuery {
+    pub fn campo_orden(&self)->Option<&'static str>{
+        match self.orden.as_deref().unwrap_or("posicion") {
+            "posicion"=>Some("posicion"),
+            "create_at"=>Some("create_at"),
+            "update_at"=>Some("update_at"),
+            "nombre"=>Some("nombre"),
+            _=>None
+        }
+    }
+
+    pub fn descendente(&self)->Option<bool>{
+        let por_defecto=if self.campo_orden() == Some("posicion") { "asc" } else { "desc" };
+        match self.dir.as_deref().unwrap_or(por_defecto) {
+            "asc"=>Some(false),
+            "desc"=>Some(true),
+            _=>None
+        }
+    }
+
+    pub fn limite(&self)->u32{
+        self.limit.unwrap_or(LIMIT_DEFAULT).clamp(1, LIMIT_MAX)
+    }
+
+    /// devuelve `[valor, id]` del ultimo elemento de la pagina anterior
+    pub fn posicion_cursor(&self)->Option<Result<(Value,String),()>>{
+        let cursor=self.cursor.as_ref()?;
+        let posicion=Utils::decode_cursor(cursor)
+            .and_then(|v| match v {
+                Value::Array(mut partes) if partes.len() == 2 => {
+                    let id=partes.pop()?.as_str()?.to_string();
+                    let valor=partes.pop()?;
+                    Some((valor,id))
+                },
+                _=>None
+            });
+        Some(posicion.ok_or(()))
+    }
+
+    pub fn is_valid(&self)->bool{
+        self.campo_orden().is_some()
+        && self.descendente().is_some()
+        && !matches!(self.posicion_cursor(),Some(Err(())))
+    }
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct TareasPage{
+    pub tareas:Vec<Tareas>,
+    /// cursor para pedir la siguiente pagina, `null` si no hay mas
+    pub next_cursor:Option<String>,
+    /// total de tareas que cumplen los filtros
+    pub total:u64,
 }
 
 impl Tareas {
-    pub async fn get_all_tareas_by_usuario(id_usuario:&str,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
+    pub async fn create_indexes(db:&Session)->Result<(),unreql::Error>{
+        let existentes=r.db("tareas").table("tareas")
+        .index_list()
+        .exec::<_,Vec<String>>(db)
+        .await?;
+        for (nombre,campos) in INDICES_TAREAS {
+            if existentes.iter().any(|i| i == nombre) {
+                continue;
+            }
+            let [usuario,campo,id]=campos;
+            let query = r.db("tareas").table("tareas")
+            .index_create(r.args((
+                nombre,
+                func!(|t| [t.clone().g(usuario), t.clone().g(campo), t.g(id)])
+            )))
+            .exec::<_,Value>(db);
+            if let Err(e) = query.await {
+                println!("error create_indexes {} {}",nombre,e);
+                return Err(e);
+            }
+        }
+        for nombre in INDICES_SIMPLES {
+            if existentes.iter().any(|i| i == nombre) {
+                continue;
+            }
+            let query = r.db("tareas").table("tareas")
+            .index_create(nombre)
+            .exec::<_,Value>(db);
+            if let Err(e) = query.await {
+                println!("error create_indexes {} {}",nombre,e);
+                return Err(e);
+            }
+        }
+        r.db("tareas").table("tareas").index_wait(()).exec::<_,Value>(db).await?;
+        Ok(())
+    }
+
+    /// carga en el indice de busqueda todas las tareas existentes
+    pub async fn load_search_index(db:&Session)->Result<usize,unreql::Error>{
         let query = r.db("tareas").table("tareas")
-        .filter(rjson!({
-            "id_usuario":id_usuario.to_string(),
-        }))
+        .exec_to_vec::<_,Tareas>(db);
+        match query.await {
+            Ok(res)=>{
+                let mut indice=IndiceBusqueda::write();
+                indice.clear();
+                for tarea in res.iter().filter(|t| t.deleted_at.is_none()) {
+                    indice.indexar(tarea);
+                }
+                Ok(res.len())
+            },
+            Err(e)=>{
+                println!("error load_search_index {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn search(id_usuario:&str,id_espacio:Option<&str>,q:&str,limit:usize,db:&Session)->Result<Vec<TareaEncontrada>,unreql::Error>{
+        let (duenios,sueltas)=match id_espacio {
+            Some(_)=>(vec![],vec![]),
+            None=>Self::fuentes_visibles(id_usuario, db).await?,
+        };
+        let visible=|id:&str,duenio:&str,espacio:Option<&str>| match id_espacio {
+            Some(_)=>espacio == id_espacio,
+            None=>espacio.is_none() && (duenios.iter().any(|d| d == duenio) || sueltas.iter().any(|s| s == id)),
+        };
+        let encontrados=IndiceBusqueda::read().buscar(visible, q, limit);
+        if encontrados.is_empty() {
+            return Ok(vec![]);
+        }
+        let ids:Vec<String>=encontrados.iter().map(|(id,_)| id.clone()).collect();
+        let query = r.db("tareas").table("tareas")
+        .get_all(r.args(ids))
+        .exec_to_vec::<_,Tareas>(db);
+        match query.await {
+            Ok(res)=>{
+                let mut tareas:HashMap<String,Tareas>=res.into_iter()
+                    .filter(|t| visible(&t.id, &t.id_usuario, t.id_espacio.as_deref()))
+                    .map(|t| (t.id.clone(),t))
+                    .collect();
+                Ok(
+                    encontrados.into_iter()
+                    .filter_map(|(id,puntaje)| {
+                        let tarea=tareas.remove(&id)?;
+                        let resaltado=IndiceBusqueda::resaltar(&tarea.nombre, q);
+                        Some(TareaEncontrada { tarea, puntaje, resaltado })
+                    })
+                    .collect()
+                )
+            },
+            Err(e)=>{
+                println!("error search {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// `nuevo` con la `version` siguiente a la de la tarea `t`; toda escritura de una tarea pasa por aca
+    pub fn con_version(t:Command,nuevo:Command)->Command{
+        nuevo.merge(rjson!({"version":t.g("version").default(0).add(1)}))
+    }
+
+    /// `nuevo` con la version siguiente si la tarea `t` sigue en la version `esperado` (la etag del
+    /// `If-Match`); si cambio mientras tanto la escritura falla dentro de RethinkDB y no modifica nada
+    fn si_coincide(t:Command,esperado:Option<i64>,nuevo:Command)->Command{
+        let nuevo=Self::con_version(t.clone(), nuevo);
+        match esperado {
+            Some(esperado)=>r.branch(t.g("version").default(0).eq(esperado), nuevo, r.error("etag")),
+            None=>nuevo,
+        }
+    }
+
+    /// limita la consulta a las tareas del espacio activo, o a las personales si no hay ninguno
+    pub fn en_espacio(query:Command,id_espacio:Option<&str>)->Command{
+        match id_espacio {
+            Some(id_espacio)=>query.filter(rjson!({"id_espacio":id_espacio.to_string()})),
+            None=>query.filter(func!(|t| t.has_fields("id_espacio").not())),
+        }
+    }
+
+    fn filtrar(mut query:Command,params:&TareasQuery)->Command{
+        query = query.filter(func!(|t| t.has_fields("deleted_at").not()));
+        if let Some(estado) = params.estado {
+            query = query.filter(rjson!({"estado":estado}));
+        }
+        if let Some(etapa) = params.etapa.clone() {
+            query = query.filter(rjson!({"etapa":etapa}));
+        }
+        let rangos=[
+            ("create_at",params.creado_desde,params.creado_hasta),
+            ("update_at",params.actualizado_desde,params.actualizado_hasta),
+        ];
+        for (campo,desde,hasta) in rangos {
+            if let Some(desde) = desde {
+                query = query.filter(func!(|t| t.g(campo).ge(desde)));
+            }
+            if let Some(hasta) = hasta {
+                query = query.filter(func!(|t| t.g(campo).le(hasta)));
+            }
+        }
+        query
+    }
+
+    /// fuentes de tareas visibles para el usuario: los dueños cuyas listas completas puede ver
+    /// (empezando por el mismo) y las tareas sueltas que le compartieron de otras listas
+    async fn fuentes_visibles(id_usuario:&str,db:&Session)->Result<(Vec<String>,Vec<String>),unreql::Error>{
+        let recibidos=Compartidos::get_recibidos(id_usuario, db).await?;
+        let mut duenios=vec![id_usuario.to_string()];
+        for compartido in recibidos.iter().filter(|c| c.id_tarea.is_none()) {
+            if !duenios.contains(&compartido.id_duenio) {
+                duenios.push(compartido.id_duenio.clone());
+            }
+        }
+        let mut sueltas:Vec<String>=recibidos.into_iter()
+            .filter(|c| !duenios.contains(&c.id_duenio))
+            .filter_map(|c| c.id_tarea)
+            .collect();
+        sueltas.sort();
+        sueltas.dedup();
+        Ok((duenios,sueltas))
+    }
+
+    /// valor del campo de orden, el mismo que se guarda en el cursor
+    fn valor_orden(&self,campo:&str)->Value{
+        match campo {
+            "update_at"=>Value::from(self.update_at),
+            "nombre"=>Value::from(self.nombre.clone()),
+            "posicion"=>Value::from(self.posicion.clone()),
+            _=>Value::from(self.create_at),
+        }
+    }
+
+    /// compara (valor, id) con el mismo criterio que los indices compuestos
+    fn comparar_orden(valor:&Value,id:&str,otro_valor:&Value,otro_id:&str)->Ordering{
+        let por_valor=match (valor.as_i64(),otro_valor.as_i64()) {
+            (Some(a),Some(b))=>a.cmp(&b),
+            _=>valor.as_str().unwrap_or_default().cmp(otro_valor.as_str().unwrap_or_default()),
+        };
+        por_valor.then_with(|| id.cmp(otro_id))
+    }
+
+    /// pagina de las tareas de un solo dueño (o de un espacio) y el total que cumple los filtros
+    async fn get_rango(id_duenio:&str,id_espacio:Option<&str>,params:&TareasQuery,cursor:Option<&(Value,String)>,db:&Session)->Result<(Vec<Tareas>,u64),unreql::Error>{
+        let campo=params.campo_orden().unwrap_or("create_at");
+        let indice=match id_espacio {
+            Some(_)=>format!("espacio_{}",campo),
+            None=>format!("usuario_{}",campo),
+        };
+        let id_duenio=id_espacio.unwrap_or(id_duenio);
+        let desc=params.descendente().unwrap_or(true);
+        let limite=params.limite();
+
+        let total = Self::filtrar(
+            Self::en_espacio(
+                r.db("tareas").table("tareas")
+                .between(
+                    rjson!([id_duenio.to_string(), r.minval()]),
+                    rjson!([id_duenio.to_string(), r.maxval()]),
+                    BetweenOptions { index: Some(indice.clone()), ..Default::default() }
+                ),
+                id_espacio
+            ),
+            params
+        )
+        .count(())
+        .exec::<_,u64>(db);
+        let total = match total.await {
+            Ok(res)=>res,
+            Err(e)=>{
+                println!("error get_rango count {}",e);
+                return Err(e);
+            }
+        };
+
+        let mut lower=rjson!([id_duenio.to_string(), r.minval()]);
+        let mut upper=rjson!([id_duenio.to_string(), r.maxval()]);
+        let mut opts=BetweenOptions { index: Some(indice.clone()), ..Default::default() };
+        if let Some((valor,id)) = cursor.cloned() {
+            if desc {
+                upper=rjson!([id_duenio.to_string(), valor, id]);
+            }else{
+                lower=rjson!([id_duenio.to_string(), valor, id]);
+                opts.left_bound=Some(Status::Open);
+            }
+        }
+        let orden=if desc { r.index(r.desc(indice)) } else { r.index(r.asc(indice)) };
+
+        // se pide uno extra para saber si hay otra pagina
+        let query = Self::filtrar(
+            Self::en_espacio(
+                r.db("tareas").table("tareas")
+                .between(lower, upper, opts)
+                .order_by(orden),
+                id_espacio
+            ),
+            params
+        )
+        .limit(limite + 1)
+        .exec_to_vec::<_,Tareas>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok((res,total))
+            },
+            Err(e)=>{
+                println!("error get_rango {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// tareas compartidas una por una que cumplen los filtros, sin paginar
+    async fn get_sueltas(ids:Vec<String>,params:&TareasQuery,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let query = Self::filtrar(
+            Self::en_espacio(
+                r.db("tareas").table("tareas")
+                .get_all(r.args(ids)),
+                None
+            ),
+            params
+        )
         .exec_to_vec::<_,Tareas>(db);
         match query.await {
             Ok(res)=>{
                 Ok(res)
             },
             Err(e)=>{
-                println!("error get_all_tareas_by_usuario {}",e);
+                println!("error get_sueltas {}",e);
                 Err(e)
             }
         }
     }
-    
+
+    /// pagina con las tareas del espacio activo o, sin espacio, con las propias y las compartidas
+    /// con el usuario, mezcladas con el mismo orden y cursor que el listado de un solo dueño
+    pub async fn get_page_by_usuario(id_usuario:&str,id_espacio:Option<&str>,params:&TareasQuery,db:&Session)->Result<TareasPage,unreql::Error>{
+        let campo=params.campo_orden().unwrap_or("create_at");
+        let desc=params.descendente().unwrap_or(true);
+        let limite=params.limite() as usize;
+        let cursor=params.posicion_cursor().and_then(Result::ok);
+
+        if id_espacio.is_some() {
+            let (mut tareas,total)=Self::get_rango(id_usuario, id_espacio, params, cursor.as_ref(), db).await?;
+            let next_cursor=Self::cortar_pagina(&mut tareas, campo, limite);
+            return Ok(TareasPage { tareas, next_cursor, total });
+        }
+
+        let (duenios,sueltas)=Self::fuentes_visibles(id_usuario, db).await?;
+        let mut tareas=Vec::new();
+        let mut total=0;
+        for duenio in duenios.iter() {
+            let (res,cantidad)=Self::get_rango(duenio, None, params, cursor.as_ref(), db).await?;
+            tareas.extend(res);
+            total += cantidad;
+        }
+        let sueltas=Self::get_sueltas(sueltas, params, db).await?;
+        total += sueltas.len() as u64;
+        tareas.extend(sueltas.into_iter().filter(|t| {
+            let Some((valor,id)) = cursor.as_ref() else { return true };
+            let orden=Self::comparar_orden(&t.valor_orden(campo), &t.id, valor, id);
+            if desc { orden.is_lt() } else { orden.is_gt() }
+        }));
+
+        tareas.sort_by(|a,b| {
+            let orden=Self::comparar_orden(&a.valor_orden(campo), &a.id, &b.valor_orden(campo), &b.id);
+            if desc { orden.reverse() } else { orden }
+        });
+        let next_cursor=Self::cortar_pagina(&mut tareas, campo, limite);
+        Ok(TareasPage { tareas, next_cursor, total })
+    }
+
+    /// deja la pagina en `limite` tareas y devuelve el cursor de la siguiente si sobraban
+    fn cortar_pagina(tareas:&mut Vec<Tareas>,campo:&str,limite:usize)->Option<String>{
+        if tareas.len() <= limite {
+            return None;
+        }
+        tareas.truncate(limite);
+        let ultima=tareas.last()?;
+        Some(Utils::encode_cursor(&Value::Array(vec![ultima.valor_orden(campo),Value::from(ultima.id.clone())])))
+    }
+
     pub async fn get_tarea_by_id(id:&str,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
         let query = r.db("tareas").table("tareas")
         .get(id.to_string())
@@ -55,17 +553,465 @@ impl Tareas {
         }
     }
 
-    pub async fn change_estado(estado:&bool,id:&str,db:&Session)->Result<bool,unreql::Error>{
+    /// permiso del usuario sobre la tarea desde el espacio activo, `None` si no tiene acceso;
+    /// las tareas de otro espacio nunca son visibles
+    pub async fn permiso(&self,id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Option<Permiso>,unreql::Error>{
+        if self.id_espacio.as_deref() != id_espacio {
+            return Ok(None);
+        }
+        if self.id_usuario == id_usuario {
+            return Ok(Some(Permiso::Duenio));
+        }
+        match self.id_espacio.as_deref() {
+            Some(id_espacio)=>Ok(Espacios::rol_de(id_espacio, id_usuario, db).await?.map(|_| Permiso::Editor)),
+            None=>Compartidos::permiso_sobre(&self.id_usuario, &self.id, id_usuario, db).await,
+        }
+    }
+
+    /// devuelve la tarea (fuera de la papelera) junto con el permiso del usuario sobre ella
+    pub async fn get_con_permiso(id:&str,id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Option<(Tareas,Permiso)>,unreql::Error>{
+        let Some(tarea) = Self::get_tarea_by_id(id, db).await?.into_iter().next() else {
+            return Ok(None);
+        };
+        if tarea.deleted_at.is_some() {
+            return Ok(None);
+        }
+        Ok(tarea.permiso(id_usuario, id_espacio, db).await?.map(|permiso| (tarea,permiso)))
+    }
+
+    /// devuelve la tarea si el usuario puede verla
+    pub async fn get_visible(id:&str,id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Option<Tareas>,unreql::Error>{
+        Ok(Self::get_con_permiso(id, id_usuario, id_espacio, db).await?.map(|(tarea,_)| tarea))
+    }
+
+    /// usuarios que pueden ver la tarea y deben recibir sus notificaciones
+    pub async fn destinatarios(&self,db:&Session)->Vec<String>{
+        let mut res=vec![self.id_usuario.clone()];
+        let otros=match self.id_espacio.as_deref() {
+            Some(id_espacio)=>Espacios::ids_miembros(id_espacio, db).await,
+            None=>Compartidos::usuarios_con_acceso(&self.id_usuario, &self.id, db).await,
+        };
+        match otros {
+            Ok(usuarios)=>res.extend(usuarios.into_iter().filter(|u| *u != self.id_usuario)),
+            Err(e)=>println!("error destinatarios {}",e),
+        }
+        if let Some(asignado_a) = self.asignado_a.as_ref() {
+            if !res.contains(asignado_a) {
+                res.push(asignado_a.clone());
+            }
+        }
+        res
+    }
+
+    /// usuarios que ven alguna de las tareas, sin repetir. Una consulta por espacio y por dueño
+    /// de tareas personales en vez de una por tarea
+    pub async fn destinatarios_de(tareas:&[Tareas],db:&Session)->Vec<String>{
+        let mut res:Vec<String>=Vec::new();
+        let mut espacios:HashSet<&str>=HashSet::new();
+        let mut personales:HashMap<&str,HashSet<&str>>=HashMap::new();
+        for tarea in tareas {
+            res.push(tarea.id_usuario.clone());
+            res.extend(tarea.asignado_a.clone());
+            match tarea.id_espacio.as_deref() {
+                Some(id_espacio)=>{
+                    espacios.insert(id_espacio);
+                },
+                None=>{
+                    personales.entry(tarea.id_usuario.as_str()).or_default().insert(tarea.id.as_str());
+                }
+            }
+        }
+        for id_espacio in espacios {
+            match Espacios::ids_miembros(id_espacio, db).await {
+                Ok(miembros)=>res.extend(miembros),
+                Err(e)=>println!("error destinatarios_de {}",e),
+            }
+        }
+        for (id_duenio,ids) in personales {
+            match Compartidos::get_otorgados(id_duenio, db).await {
+                Ok(otorgados)=>res.extend(
+                    otorgados.into_iter()
+                    .filter(|c| c.id_tarea.as_deref().is_none_or(|t| ids.contains(t)))
+                    .map(|c| c.id_usuario)
+                ),
+                Err(e)=>println!("error destinatarios_de {}",e),
+            }
+        }
+        res.sort();
+        res.dedup();
+        res
+    }
+
+    /// tareas como quedaron despues de los cambios, o como estaban si `nuevas` es false
+    fn tareas_de_cambios(changes:&[Change],nuevas:bool)->Vec<Tareas>{
+        changes.iter()
+            .filter_map(|c| if nuevas { c.new_val.clone() } else { c.old_val.clone() })
+            .filter_map(|v| Tareas::deserialize(v).ok())
+            .collect()
+    }
+
+    /// asigna la tarea a `asignado_a` o quita la asignacion con `None`
+    pub async fn asignar(id:&str,asignado_a:Option<&str>,actor:&str,esperado:Option<i64>,db:&Session)->Result<bool,unreql::Error>{
+        let mut cambios=rjson!({"update_at":Utils::current_timestamp()});
+        if let Some(asignado_a) = asignado_a {
+            cambios=cambios.merge(rjson!({"asignado_a":asignado_a.to_string()}));
+        }
+        let query = r.db("tareas").table("tareas")
+        .get_all(id.to_string())
+        .replace(
+            r.with_opt(
+                func!(|t| Self::si_coincide(t.clone(), esperado, t.without("asignado_a").merge(cambios))),
+                ReplaceOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                if res.replaced > 0 {
+                    Historial::registrar(&res.changes.unwrap_or_default(), actor, "asignar", db).await;
+                    return Ok(true);
+                }
+                Ok(false)
+            },
+            Err(e)=>{
+                println!("error asignar {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// todas las tareas activas visibles para el usuario en el espacio activo, sin paginar
+    pub async fn get_todas_visibles(id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
+        let (fuentes,sueltas)=match id_espacio {
+            Some(id_espacio)=>(vec![("espacio_create_at",id_espacio.to_string())],vec![]),
+            None=>{
+                let (duenios,sueltas)=Self::fuentes_visibles(id_usuario, db).await?;
+                (duenios.into_iter().map(|d| ("usuario_create_at",d)).collect(),sueltas)
+            }
+        };
+        let mut res=Vec::new();
+        for (indice,clave) in fuentes {
+            let query = Self::en_espacio(
+                r.db("tareas").table("tareas")
+                .between(
+                    rjson!([clave.clone(), r.minval()]),
+                    rjson!([clave, r.maxval()]),
+                    BetweenOptions { index: Some(indice.to_string()), ..Default::default() }
+                ),
+                id_espacio
+            )
+            .filter(func!(|t| t.has_fields("deleted_at").not()))
+            .exec_to_vec::<_,Tareas>(db);
+            match query.await {
+                Ok(tareas)=>res.extend(tareas),
+                Err(e)=>{
+                    println!("error get_todas_visibles {}",e);
+                    return Err(e);
+                }
+            }
+        }
+        res.extend(Self::get_sueltas(sueltas, &TareasQuery::default(), db).await?);
+        Ok(res)
+    }
+
+    /// turno para editar dependencias: mientras se tiene, ninguna otra edicion cambia el grafo entre
+    /// `crea_ciclo` y `set_dependencias`, asi dos ediciones simultaneas (A bloquea a B y B a A) no
+    /// forman un ciclo que ninguna de las dos vio
+    pub async fn edicion_dependencias()->MutexGuard<'static,()>{
+        EDICION_DEPENDENCIAS.lock().await
+    }
+
+    /// true si `id` se alcanza desde `bloqueadoras` siguiendo `bloqueada_por`,
+    /// es decir si bloquear `id` con ellas formaria un ciclo
+    pub async fn crea_ciclo(id:&str,bloqueadoras:&[String],db:&Session)->Result<bool,unreql::Error>{
+        let mut visitadas:HashSet<String>=HashSet::new();
+        let mut pendientes:Vec<String>=bloqueadoras.to_vec();
+        loop {
+            if pendientes.iter().any(|p| p == id) {
+                return Ok(true);
+            }
+            pendientes.retain(|p| visitadas.insert(p.clone()));
+            if pendientes.is_empty() {
+                return Ok(false);
+            }
+            let query = r.db("tareas").table("tareas")
+            .get_all(r.args(pendientes))
+            .exec_to_vec::<_,Tareas>(db);
+            pendientes = match query.await {
+                Ok(res)=>res.into_iter().flat_map(|t| t.bloqueada_por).collect(),
+                Err(e)=>{
+                    println!("error crea_ciclo {}",e);
+                    return Err(e);
+                }
+            };
+        }
+    }
+
+    pub async fn set_dependencias(id:&str,bloqueada_por:&[String],actor:&str,esperado:Option<i64>,db:&Session)->Result<bool,unreql::Error>{
         let query = r.db("tareas").table("tareas")
         .get(id.to_string())
-        .update(rjson!({
-            "estado":estado.clone(),
-            "update_at":Utils::current_timestamp(),
-        }))
+        .update(
+            r.with_opt(
+                func!(|t| Self::si_coincide(t, esperado, rjson!({
+                    "bloqueada_por":bloqueada_por.to_vec(),
+                    "update_at":Utils::current_timestamp(),
+                }))),
+                UpdateOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Historial::registrar(&res.changes.unwrap_or_default(), actor, "dependencias", db).await;
+                Ok(res.replaced > 0 || res.unchanged > 0)
+            },
+            Err(e)=>{
+                println!("error set_dependencias {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// tareas que bloquean a esta y siguen pendientes (fuera de la papelera)
+    pub async fn bloqueadoras_abiertas(&self,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
+        if self.bloqueada_por.is_empty() {
+            return Ok(vec![]);
+        }
+        let query = r.db("tareas").table("tareas")
+        .get_all(r.args(self.bloqueada_por.clone()))
+        .filter(func!(|t| t.clone().g("estado").eq(false).and(t.has_fields("deleted_at").not())))
+        .exec_to_vec::<_,Tareas>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res)
+            },
+            Err(e)=>{
+                println!("error bloqueadoras_abiertas {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// tareas activas asignadas al usuario en el espacio activo que todavia puede ver,
+    /// las actualizadas mas recientemente primero
+    pub async fn get_asignadas(id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
+        let query = Self::en_espacio(
+            r.db("tareas").table("tareas")
+            .get_all(r.with_opt(id_usuario.to_string(), r.index("asignado_a"))),
+            id_espacio
+        )
+        .filter(func!(|t| t.has_fields("deleted_at").not()))
+        .order_by(r.desc("update_at"))
+        .exec_to_vec::<_,Tareas>(db);
+        let tareas = match query.await {
+            Ok(res)=>res,
+            Err(e)=>{
+                println!("error get_asignadas {}",e);
+                return Err(e);
+            }
+        };
+        let mut res=Vec::with_capacity(tareas.len());
+        for tarea in tareas {
+            if tarea.permiso(id_usuario, id_espacio, db).await?.is_some() {
+                res.push(tarea);
+            }
+        }
+        Ok(res)
+    }
+
+    /// asigna `etapa` a las tareas anteriores a los flujos de trabajo: las completadas
+    /// van al estado final del flujo por defecto y el resto al inicial
+    pub async fn migrar_etapas(db:&Session)->Result<u32,unreql::Error>{
+        let mut migradas=0;
+        for (estado,etapa) in [(true,Flujos::etapa_final_default()),(false,Flujos::etapa_inicial_default())] {
+            let query = r.db("tareas").table("tareas")
+            .filter(func!(|t| t.clone().has_fields("etapa").not().and(t.g("estado").eq(estado))))
+            .update(rjson!({"etapa":etapa}))
+            .exec::<_,WriteStatus>(db);
+            match query.await {
+                Ok(res)=>migradas += res.replaced,
+                Err(e)=>{
+                    println!("error migrar_etapas {}",e);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(migradas)
+    }
+
+    /// las tareas completadas antes de guardar `completada_at` toman su ultima actualizacion
+    pub async fn migrar_completadas(db:&Session)->Result<u32,unreql::Error>{
+        let query = r.db("tareas").table("tareas")
+        .filter(func!(|t| t.clone().has_fields("completada_at").not().and(t.g("estado").eq(true))))
+        .update(func!(|t| rjson!({"completada_at":t.g("update_at")})))
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>Ok(res.replaced),
+            Err(e)=>{
+                println!("error migrar_completadas {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// da una posicion del orden manual a las tareas que no tienen, despues de todas las
+    /// demas y entre ellas las mas nuevas primero, como el listado por `create_at` descendente
+    pub async fn migrar_posiciones(db:&Session)->Result<u32,unreql::Error>{
+        let query = r.db("tareas").table("tareas")
+        .pluck(r.args(["id","create_at","posicion"]))
+        .exec_to_vec::<_,Value>(db);
+        let todas = match query.await {
+            Ok(res)=>res,
+            Err(e)=>{
+                println!("error migrar_posiciones {}",e);
+                return Err(e);
+            }
+        };
+        let ultima=todas.iter().filter_map(|t| t.get("posicion")?.as_str()).max();
+        let mut pendientes:Vec<(i64,&str)>=todas.iter()
+            .filter(|t| t.get("posicion").is_none())
+            .filter_map(|t| Some((t.get("create_at")?.as_i64()?,t.get("id")?.as_str()?)))
+            .collect();
+        if pendientes.is_empty() {
+            return Ok(0);
+        }
+        pendientes.sort_by(|a,b| b.cmp(a));
+        let cambios:Vec<Value>=pendientes.iter()
+            .zip(posiciones_siguientes(ultima, pendientes.len()))
+            .map(|((_,id),posicion)| serde_json::json!({"id":id,"posicion":posicion}))
+            .collect();
+        let query = r.db("tareas").table("tareas")
+        .insert(
+            r.with_opt(
+                Value::Array(cambios),
+                InsertOptions { conflict: Some(Conflict::Update), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.replaced)
+            },
+            Err(e)=>{
+                println!("error migrar_posiciones {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// menor posicion entre las tareas de la lista del usuario o del espacio, `None` si esta vacia
+    pub async fn primera_posicion(id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Option<String>,unreql::Error>{
+        let (indice,clave)=match id_espacio {
+            Some(id_espacio)=>("espacio_posicion",id_espacio.to_string()),
+            None=>("usuario_posicion",id_usuario.to_string()),
+        };
+        let query = r.db("tareas").table("tareas")
+        .between(
+            rjson!([clave.clone(), r.minval()]),
+            rjson!([clave, r.maxval()]),
+            BetweenOptions { index: Some(indice.to_string()), ..Default::default() }
+        )
+        .order_by(r.index(indice))
+        .limit(1)
+        .exec_to_vec::<_,Tareas>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().next().map(|t| t.posicion))
+            },
+            Err(e)=>{
+                println!("error primera_posicion {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// cambia solo la posicion de la tarea; no toca `update_at` porque el contenido no cambia, pero
+    /// si la `version` para que la etag del listado y de la tarea no quede vieja
+    pub async fn mover(id:&str,posicion:&str,actor:&str,esperado:Option<i64>,db:&Session)->Result<bool,unreql::Error>{
+        let query = r.db("tareas").table("tareas")
+        .get(id.to_string())
+        .update(
+            r.with_opt(
+                func!(|t| Self::si_coincide(t, esperado, rjson!({
+                    "posicion":posicion.to_string(),
+                }))),
+                UpdateOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                if res.replaced > 0 {
+                    Historial::registrar(&res.changes.unwrap_or_default(), actor, "mover", db).await;
+                    return Ok(true);
+                }
+                Ok(false)
+            },
+            Err(e)=>{
+                println!("error mover {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// tareas activas en `etapa` dentro del mismo alcance que la tarea (su espacio o la lista de su dueño)
+    pub async fn count_en_etapa(&self,etapa:&str,db:&Session)->Result<u64,unreql::Error>{
+        let (indice,clave)=match self.id_espacio.as_ref() {
+            Some(id_espacio)=>("espacio_create_at",id_espacio.clone()),
+            None=>("usuario_create_at",self.id_usuario.clone()),
+        };
+        let etapa=etapa.to_string();
+        let query = Self::en_espacio(
+            r.db("tareas").table("tareas")
+            .between(
+                rjson!([clave.clone(), r.minval()]),
+                rjson!([clave, r.maxval()]),
+                BetweenOptions { index: Some(indice.to_string()), ..Default::default() }
+            ),
+            self.id_espacio.as_deref()
+        )
+        .filter(func!(|t| t.clone().g("etapa").eq(etapa).and(t.has_fields("deleted_at").not())))
+        .count(())
+        .exec::<_,u64>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res)
+            },
+            Err(e)=>{
+                println!("error count_en_etapa {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// mueve la tarea a `etapa`; `estado` indica si es el estado final del flujo
+    pub async fn change_estado(estado:&bool,etapa:&str,id:&str,actor:&str,esperado:Option<i64>,db:&Session)->Result<bool,unreql::Error>{
+        let query = r.db("tareas").table("tareas")
+        .get(id.to_string())
+        .update(
+            r.with_opt(
+                func!(|t| {
+                    // una tarea que ya estaba completada conserva su fecha de completado
+                    let completada_at=if *estado {
+                        t.clone().g("completada_at").default(Utils::current_timestamp())
+                    }else{
+                        r.expr(Value::Null)
+                    };
+                    Self::si_coincide(t, esperado, rjson!({
+                        "estado":estado.clone(),
+                        "etapa":etapa.to_string(),
+                        "completada_at":completada_at,
+                        "update_at":Utils::current_timestamp(),
+                    }))
+                }),
+                UpdateOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
         .exec::<_,WriteStatus>(db);
         match query.await {
             Ok(res)=>{
                 if res.replaced > 0 {
+                    Historial::registrar(&res.changes.unwrap_or_default(), actor, "estado", db).await;
                     return Ok(true);
                 }
                 Ok(false)
@@ -77,17 +1023,101 @@ impl Tareas {
         }
     }
 
-    pub async fn add_tarea(tarea:&TareasHttp,id_usuario:&str,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
+    /// reemplaza nombre, vencimiento, etiquetas y prioridad; los que vienen vacios se quitan
+    pub async fn editar(id:&str,tarea:&TareasHttp,actor:&str,esperado:Option<i64>,db:&Session)->Result<bool,unreql::Error>{
+        let mut cambios=rjson!({
+            "nombre":tarea.nombre.clone(),
+            "update_at":Utils::current_timestamp(),
+        });
+        if let Some(vence) = tarea.vence {
+            cambios=cambios.merge(rjson!({"vence":vence}));
+        }
+        let etiquetas=tarea.etiquetas_limpias();
+        if !etiquetas.is_empty() {
+            cambios=cambios.merge(rjson!({"etiquetas":etiquetas}));
+        }
+        if let Some(prioridad) = tarea.prioridad {
+            cambios=cambios.merge(rjson!({"prioridad":prioridad}));
+        }
+        let query = r.db("tareas").table("tareas")
+        .get_all(id.to_string())
+        .replace(
+            r.with_opt(
+                func!(|t| Self::si_coincide(t.clone(), esperado, t.without(rjson!(["vence","etiquetas","prioridad"])).merge(cambios))),
+                ReplaceOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                if res.replaced > 0 {
+                    let changes=res.changes.unwrap_or_default();
+                    for change in changes.iter() {
+                        if let Some(new_val) = change.new_val.clone() {
+                            let tarea:Tareas=Deserialize::deserialize(new_val)?;
+                            IndiceBusqueda::write().indexar(&tarea);
+                        }
+                    }
+                    Historial::registrar(&changes, actor, "editar", db).await;
+                    return Ok(true);
+                }
+                Ok(false)
+            },
+            Err(e)=>{
+                println!("error editar {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn add_tarea(tarea:&TareasHttp,id_usuario:&str,id_espacio:Option<&str>,etapa:&str,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
+        Self::insertar_tarea(tarea, None, id_usuario, id_espacio, etapa, db).await
+    }
+
+    /// crea la tarea con el `id` que eligio un cliente CalDAV y guarda su `UID`;
+    /// vacio si ya existe una tarea con ese id
+    pub async fn add_tarea_ical(tarea:&TareasHttp,id:&str,uid_ical:&str,id_usuario:&str,id_espacio:Option<&str>,etapa:&str,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
+        Self::insertar_tarea(tarea, Some((id,uid_ical)), id_usuario, id_espacio, etapa, db).await
+    }
+
+    async fn insertar_tarea(tarea:&TareasHttp,ical:Option<(&str,&str)>,id_usuario:&str,id_espacio:Option<&str>,etapa:&str,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
+        // las tareas nuevas van al principio del orden manual
+        let primera=Self::primera_posicion(id_usuario, id_espacio, db).await?;
+        let posicion=posicion_entre(None, primera.as_deref())
+            .or_else(|| posicion_entre(None, None))
+            .unwrap_or_default();
+        let mut nueva=rjson!({
+            "nombre":tarea.nombre.clone(),
+            "id_usuario":id_usuario.to_string(),
+            "estado":false,
+            "etapa":etapa.to_string(),
+            "posicion":posicion,
+            "create_at":Utils::current_timestamp(),
+            "update_at":Utils::current_timestamp(),
+        });
+        if let Some(id_espacio) = id_espacio {
+            nueva=nueva.merge(rjson!({"id_espacio":id_espacio.to_string()}));
+        }
+        if let Some(vence) = tarea.vence {
+            nueva=nueva.merge(rjson!({"vence":vence}));
+        }
+        let etiquetas=tarea.etiquetas_limpias();
+        if !etiquetas.is_empty() {
+            nueva=nueva.merge(rjson!({"etiquetas":etiquetas}));
+        }
+        if let Some(prioridad) = tarea.prioridad {
+            nueva=nueva.merge(rjson!({"prioridad":prioridad}));
+        }
+        if let Some(id_padre) = tarea.id_padre.clone() {
+            nueva=nueva.merge(rjson!({"id_padre":id_padre}));
+        }
+        if let Some((id,uid_ical)) = ical {
+            nueva=nueva.merge(rjson!({"id":id.to_string(),"uid_ical":uid_ical.to_string()}));
+        }
         let query = r.db("tareas").table("tareas")
         .insert(
             r.with_opt(
-                rjson!({
-                    "nombre":tarea.nombre.clone(),
-                    "id_usuario":id_usuario.to_string(),
-                    "estado":false,
-                    "create_at":Utils::current_timestamp(),
-                    "update_at":Utils::current_timestamp(),
-                }),
+                nueva,
                 InsertOptions {return_changes: Some(true.into()), ..Default::default() }
             )
         )
@@ -96,10 +1126,12 @@ impl Tareas {
             Ok(res)=>{
                 if res.inserted > 0 {
                     let changes = res.changes.ok_or("No hay cambios en la respuesta").unwrap();
+                    Historial::registrar(&changes, id_usuario, "crear", db).await;
                     let mut tareas: Vec<Tareas> = Vec::new();
                     for change in changes {
                         if let Some(new_val) = change.new_val {
                             let tarea: Tareas = Deserialize::deserialize(new_val)?;
+                            IndiceBusqueda::write().indexar(&tarea);
                             tareas.push(tarea);
                         }
                     }
@@ -115,11 +1147,26 @@ impl Tareas {
     }
     
 
-    pub async fn delete_tarea_by_id(id:&str,db:&Session)->Result<bool,unreql::Error>{
-        let query = r.db("tareas").table("tareas").get(id.to_string()).delete(()).exec::<_,WriteStatus>(db);
+    /// mueve la tarea a la papelera
+    pub async fn delete_tarea_by_id(id:&str,actor:&str,esperado:Option<i64>,db:&Session)->Result<bool,unreql::Error>{
+        let query = r.db("tareas").table("tareas")
+        .get_all(id.to_string())
+        .filter(func!(|t| t.has_fields("deleted_at").not()))
+        .update(
+            r.with_opt(
+                func!(|t| Self::si_coincide(t, esperado, rjson!({
+                    "deleted_at":Utils::current_timestamp(),
+                    "update_at":Utils::current_timestamp(),
+                }))),
+                UpdateOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
         match query.await {
             Ok(res)=>{
-                if res.deleted > 0 {
+                if res.replaced > 0 {
+                    IndiceBusqueda::write().eliminar(id);
+                    Historial::registrar(&res.changes.unwrap_or_default(), actor, "papelera", db).await;
                     return Ok(true);
                 }
                 Ok(false)  
@@ -131,23 +1178,234 @@ impl Tareas {
         }
     }
 
-    pub async fn delete_all_tareas_by_usuario(id_usuario:&str,db:&Session)->Result<bool,unreql::Error>{
-        let query = r.db("tareas").table("tareas")
-        .filter(rjson!({
-            "id_usuario":id_usuario.to_string()
-        }))
-        .delete(()).exec::<_,WriteStatus>(db);
+    /// mueve a la papelera todas las tareas activas del usuario en el espacio activo y las devuelve
+    pub async fn delete_all_tareas_by_usuario(id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
+        let query = Self::en_espacio(
+            r.db("tareas").table("tareas")
+            .between(
+                rjson!([id_usuario.to_string(), r.minval()]),
+                rjson!([id_usuario.to_string(), r.maxval()]),
+                BetweenOptions { index: Some("usuario_create_at".to_string()), ..Default::default() }
+            ),
+            id_espacio
+        )
+        .filter(func!(|t| t.has_fields("deleted_at").not()))
+        .update(
+            r.with_opt(
+                func!(|t| Self::con_version(t, rjson!({
+                    "deleted_at":Utils::current_timestamp(),
+                    "update_at":Utils::current_timestamp(),
+                }))),
+                UpdateOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
         match query.await {
             Ok(res)=>{
-                if res.deleted > 0 {
-                    return Ok(true);
+                if res.replaced > 0 {
+                    let changes=res.changes.unwrap_or_default();
+                    {
+                        let mut indice=IndiceBusqueda::write();
+                        for id in changes.iter().filter_map(|c| c.old_val.as_ref()?.get("id")?.as_str()) {
+                            indice.eliminar(id);
+                        }
+                    }
+                    Historial::registrar(&changes, id_usuario, "papelera", db).await;
+                    return Ok(Self::tareas_de_cambios(&changes, true));
                 }
-                Ok(false)  
+                Ok(vec![])
             },
             Err(e)=>{
-                println!("error delete_tarea_by_id {}",e);
+                println!("error delete_all_tareas_by_usuario {}",e);
                 Err(e)
             }         
         }
     }
+
+    /// tareas del usuario en la papelera del espacio activo, las mas recientes primero
+    pub async fn get_papelera_by_usuario(id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Vec<Tareas>,unreql::Error>{
+        let query = Self::en_espacio(
+            r.db("tareas").table("tareas")
+            .between(
+                rjson!([id_usuario.to_string(), r.minval()]),
+                rjson!([id_usuario.to_string(), r.maxval()]),
+                BetweenOptions { index: Some("usuario_deleted_at".to_string()), ..Default::default() }
+            )
+            .order_by(r.index(r.desc("usuario_deleted_at"))),
+            id_espacio
+        )
+        .exec_to_vec::<_,Tareas>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res)
+            },
+            Err(e)=>{
+                println!("error get_papelera_by_usuario {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// saca la tarea de la papelera y la devuelve restaurada
+    pub async fn restore_tarea(id:&str,id_usuario:&str,id_espacio:Option<&str>,db:&Session)->Result<Option<Tareas>,unreql::Error>{
+        let id_usuario=id_usuario.to_string();
+        let duenio=id_usuario.clone();
+        let query = Self::en_espacio(r.db("tareas").table("tareas").get_all(id.to_string()), id_espacio)
+        .filter(func!(|t| t.clone().g("id_usuario").eq(duenio).and(t.has_fields("deleted_at"))))
+        .replace(
+            r.with_opt(
+                func!(|t| Self::con_version(t.clone(), t.without("deleted_at").merge(rjson!({"update_at":Utils::current_timestamp()})))),
+                ReplaceOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                let changes=res.changes.unwrap_or_default();
+                Historial::registrar(&changes, &id_usuario, "restaurar", db).await;
+                let restaurada=Self::tareas_de_cambios(&changes, true).into_iter().next();
+                if let Some(tarea) = restaurada.as_ref() {
+                    IndiceBusqueda::write().indexar(tarea);
+                }
+                Ok(restaurada)
+            },
+            Err(e)=>{
+                println!("error restore_tarea {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// quita las referencias a tareas eliminadas definitivamente: salen de `bloqueada_por` de las
+    /// que bloqueaban y sus subtareas, en la papelera o no, pasan a ser tareas de primer nivel
+    async fn soltar_referencias(ids:&[String],db:&Session)->Result<(),unreql::Error>{
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let query = r.db("tareas").table("tareas")
+        .get_all(r.with_opt(r.args(ids.to_vec()), r.index("id_padre")))
+        .replace(func!(|t| Self::con_version(t.clone(), t.without("id_padre").merge(rjson!({"update_at":Utils::current_timestamp()})))))
+        .exec::<_,WriteStatus>(db);
+        if let Err(e) = query.await {
+            println!("error soltar_referencias subtareas {}",e);
+            return Err(e);
+        }
+        let (bloqueadoras,quitar)=(ids.to_vec(),ids.to_vec());
+        let query = r.db("tareas").table("tareas")
+        .filter(func!(|t| t.g("bloqueada_por").set_intersection(bloqueadoras).count(()).gt(0)))
+        .update(func!(|t| Self::con_version(t.clone(), rjson!({
+            "bloqueada_por":t.g("bloqueada_por").set_difference(quitar.clone()),
+            "update_at":Utils::current_timestamp(),
+        }))))
+        .exec::<_,WriteStatus>(db);
+        if let Err(e) = query.await {
+            println!("error soltar_referencias bloqueada_por {}",e);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// borra los adjuntos, los accesos compartidos, los tiempos y los comentarios de las tareas
+    /// eliminadas en `changes` y quita las referencias que otras tareas tenian a ellas
+    async fn purge_relacionados(changes:&[Change],almacenamiento:&AlmacenamientoAdjuntos,db:&Session){
+        let ids:Vec<String>=changes.iter()
+            .filter_map(|c| c.old_val.as_ref()?.get("id")?.as_str().map(str::to_string))
+            .collect();
+        if let Err(e) = Adjuntos::delete_by_tareas(&ids, almacenamiento, db).await {
+            println!("error purge_relacionados adjuntos {}",e);
+        }
+        if let Err(e) = Compartidos::delete_by_tareas(&ids, db).await {
+            println!("error purge_relacionados compartidos {}",e);
+        }
+        if let Err(e) = Tiempos::delete_by_tareas(&ids, db).await {
+            println!("error purge_relacionados tiempos {}",e);
+        }
+        if let Err(e) = Comentarios::delete_by_tareas(&ids, db).await {
+            println!("error purge_relacionados comentarios {}",e);
+        }
+        if let Err(e) = Self::soltar_referencias(&ids, db).await {
+            println!("error purge_relacionados referencias {}",e);
+        }
+    }
+
+    /// elimina definitivamente una tarea que esta en la papelera y la devuelve como estaba, con
+    /// quienes la veian, que se buscan antes de borrar sus accesos compartidos
+    pub async fn purge_tarea(id:&str,id_usuario:&str,id_espacio:Option<&str>,almacenamiento:&AlmacenamientoAdjuntos,db:&Session)->Result<Option<(Tareas,Vec<String>)>,unreql::Error>{
+        let duenio=id_usuario.to_string();
+        let query = Self::en_espacio(r.db("tareas").table("tareas").get_all(id.to_string()), id_espacio)
+        .filter(func!(|t| t.clone().g("id_usuario").eq(duenio).and(t.has_fields("deleted_at"))))
+        .delete(DeleteOptions {return_changes: Some(true.into()), ..Default::default() })
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                let changes=res.changes.unwrap_or_default();
+                let tareas=Self::tareas_de_cambios(&changes, false);
+                let destinatarios=Self::destinatarios_de(&tareas, db).await;
+                Self::purge_relacionados(&changes, almacenamiento, db).await;
+                Historial::registrar(&changes, id_usuario, "purgar", db).await;
+                Ok(tareas.into_iter().next().map(|tarea| (tarea,destinatarios)))
+            },
+            Err(e)=>{
+                println!("error purge_tarea {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// vacia la papelera del usuario en el espacio activo, devuelve las tareas eliminadas y
+    /// quienes las veian
+    pub async fn purge_papelera_by_usuario(id_usuario:&str,id_espacio:Option<&str>,almacenamiento:&AlmacenamientoAdjuntos,db:&Session)->Result<(Vec<Tareas>,Vec<String>),unreql::Error>{
+        let query = Self::en_espacio(
+            r.db("tareas").table("tareas")
+            .between(
+                rjson!([id_usuario.to_string(), r.minval()]),
+                rjson!([id_usuario.to_string(), r.maxval()]),
+                BetweenOptions { index: Some("usuario_deleted_at".to_string()), ..Default::default() }
+            ),
+            id_espacio
+        )
+        .delete(DeleteOptions {return_changes: Some(true.into()), ..Default::default() })
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                let changes=res.changes.unwrap_or_default();
+                let tareas=Self::tareas_de_cambios(&changes, false);
+                let destinatarios=Self::destinatarios_de(&tareas, db).await;
+                Self::purge_relacionados(&changes, almacenamiento, db).await;
+                Historial::registrar(&changes, id_usuario, "purgar", db).await;
+                Ok((tareas,destinatarios))
+            },
+            Err(e)=>{
+                println!("error purge_papelera_by_usuario {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// elimina definitivamente las tareas que entraron a la papelera antes de `antes_de` y las
+    /// devuelve con quienes las veian
+    pub async fn purge_expired(antes_de:i64,almacenamiento:&AlmacenamientoAdjuntos,db:&Session)->Result<(Vec<Tareas>,Vec<String>),unreql::Error>{
+        let query = r.db("tareas").table("tareas")
+        .between(
+            r.minval(),
+            antes_de,
+            BetweenOptions { index: Some("deleted_at".to_string()), ..Default::default() }
+        )
+        .delete(DeleteOptions {return_changes: Some(true.into()), ..Default::default() })
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                let changes=res.changes.unwrap_or_default();
+                let tareas=Self::tareas_de_cambios(&changes, false);
+                let destinatarios=Self::destinatarios_de(&tareas, db).await;
+                Self::purge_relacionados(&changes, almacenamiento, db).await;
+                Historial::registrar(&changes, ACTOR_SISTEMA, "purgar", db).await;
+                Ok((tareas,destinatarios))
+            },
+            Err(e)=>{
+                println!("error purge_expired {}",e);
+                Err(e)
+            }
+        }
+    }
 }
\ No newline at end of file
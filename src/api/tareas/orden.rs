@@ -0,0 +1,273 @@
+use rand::Rng;
+use rocket::serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// digitos de las posiciones en orden ascii
+const DIGITOS:&[u8]=b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// parte entera de la primera posicion; la letra inicial indica cuantos digitos
+/// enteros siguen (`a`-`z` positivos, `A`-`Z` negativos) y el resto es la fraccion
+const ENTERO_CERO:&[u8]=b"a0";
+
+/// digitos aleatorios que se agregan a cada posicion nueva para que dos movimientos
+/// simultaneos entre los mismos vecinos no terminen en la misma posicion
+const LARGO_AZAR:usize=3;
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct MoverHttp{
+    /// id de la tarea que queda justo antes, `null` para mover al principio
+    #[serde(default)]
+    pub antes:Option<String>,
+    /// id de la tarea que queda justo despues, `null` para mover al final
+    #[serde(default)]
+    pub despues:Option<String>,
+}
+
+fn digito(c:u8)->Option<usize>{
+    DIGITOS.iter().position(|d| *d == c)
+}
+
+fn largo_entero(cabeza:u8)->Option<usize>{
+    match cabeza {
+        b'a'..=b'z'=>Some((cabeza - b'a') as usize + 2),
+        b'A'..=b'Z'=>Some((b'Z' - cabeza) as usize + 2),
+        _=>None
+    }
+}
+
+/// separa la posicion en parte entera y fraccion, `None` si no es una posicion valida
+fn partes(posicion:&[u8])->Option<(&[u8],&[u8])>{
+    let largo=largo_entero(*posicion.first()?)?;
+    if posicion.len() < largo || !posicion[1..].iter().all(|c| digito(*c).is_some()) {
+        return None;
+    }
+    let (entero,fraccion)=posicion.split_at(largo);
+    // el menor entero sin fraccion no deja lugar antes, y la fraccion no termina en cero
+    if entero[0] == b'A' && entero[1..].iter().all(|c| *c == DIGITOS[0]) || fraccion.last() == Some(&DIGITOS[0]) {
+        return None;
+    }
+    Some((entero,fraccion))
+}
+
+/// fraccion estrictamente entre `a` y `b` (sin limite superior si es `None`), sin cero final
+fn punto_medio(a:&[u8],b:Option<&[u8]>)->Vec<u8>{
+    if let Some(b) = b {
+        let comun=(0..b.len())
+            .take_while(|i| a.get(*i).copied().unwrap_or(DIGITOS[0]) == b[*i])
+            .count();
+        if comun > 0 {
+            let mut res=b[..comun].to_vec();
+            res.extend(punto_medio(a.get(comun..).unwrap_or_default(), Some(&b[comun..])));
+            return res;
+        }
+    }
+    let digito_a=a.first().and_then(|c| digito(*c)).unwrap_or(0);
+    let digito_b=b.and_then(|b| b.first()).and_then(|c| digito(*c)).unwrap_or(DIGITOS.len());
+    if digito_b - digito_a > 1 {
+        return vec![DIGITOS[(digito_a + digito_b).div_ceil(2)]];
+    }
+    match b {
+        Some(b) if b.len() > 1=>vec![b[0]],
+        _=>{
+            let mut res=vec![DIGITOS[digito_a]];
+            res.extend(punto_medio(a.get(1..).unwrap_or_default(), None));
+            res
+        }
+    }
+}
+
+/// siguiente entero, `None` si ya es el mayor representable
+fn incrementar(entero:&[u8])->Option<Vec<u8>>{
+    let cabeza=entero[0];
+    let mut digitos=entero[1..].to_vec();
+    for c in digitos.iter_mut().rev() {
+        let d=digito(*c)? + 1;
+        if d < DIGITOS.len() {
+            *c=DIGITOS[d];
+            let mut res=vec![cabeza];
+            res.extend(digitos);
+            return Some(res);
+        }
+        *c=DIGITOS[0];
+    }
+    let cabeza=match cabeza {
+        b'Z'=>return Some(ENTERO_CERO.to_vec()),
+        b'z'=>return None,
+        c=>c + 1,
+    };
+    if cabeza > b'a' {
+        digitos.push(DIGITOS[0]);
+    }else{
+        digitos.pop();
+    }
+    let mut res=vec![cabeza];
+    res.extend(digitos);
+    Some(res)
+}
+
+/// entero anterior, `None` si ya es el menor representable
+fn decrementar(entero:&[u8])->Option<Vec<u8>>{
+    let cabeza=entero[0];
+    let mut digitos=entero[1..].to_vec();
+    let ultimo=DIGITOS[DIGITOS.len() - 1];
+    for c in digitos.iter_mut().rev() {
+        let d=digito(*c)?;
+        if d > 0 {
+            *c=DIGITOS[d - 1];
+            let mut res=vec![cabeza];
+            res.extend(digitos);
+            return Some(res);
+        }
+        *c=ultimo;
+    }
+    let cabeza=match cabeza {
+        b'a'=>return Some(vec![b'Z',ultimo]),
+        b'A'=>return None,
+        c=>c - 1,
+    };
+    if cabeza < b'Z' {
+        digitos.push(ultimo);
+    }else{
+        digitos.pop();
+    }
+    let mut res=vec![cabeza];
+    res.extend(digitos);
+    Some(res)
+}
+
+/// posicion estrictamente entre `a` y `b` (cualquiera puede faltar), ya validadas y `a < b`
+fn generar(a:Option<&[u8]>,b:Option<&[u8]>)->Option<Vec<u8>>{
+    match (a,b) {
+        (None,None)=>Some(ENTERO_CERO.to_vec()),
+        (None,Some(b))=>{
+            let (entero,fraccion)=partes(b)?;
+            decrementar(entero).or_else(|| {
+                let mut res=entero.to_vec();
+                res.extend(punto_medio(b"", Some(fraccion)));
+                Some(res)
+            })
+        },
+        (Some(a),None)=>{
+            let (entero,fraccion)=partes(a)?;
+            incrementar(entero).or_else(|| {
+                let mut res=entero.to_vec();
+                res.extend(punto_medio(fraccion, None));
+                Some(res)
+            })
+        },
+        (Some(a),Some(b))=>{
+            let (entero_a,fraccion_a)=partes(a)?;
+            let (entero_b,fraccion_b)=partes(b)?;
+            let mut res=entero_a.to_vec();
+            if entero_a == entero_b {
+                res.extend(punto_medio(fraccion_a, Some(fraccion_b)));
+                return Some(res);
+            }
+            match incrementar(entero_a) {
+                Some(siguiente) if siguiente.as_slice() < b=>Some(siguiente),
+                _=>{
+                    res.extend(punto_medio(fraccion_a, None));
+                    Some(res)
+                }
+            }
+        }
+    }
+}
+
+/// posicion nueva entre dos vecinos; `None` si alguna no es valida o no estan en orden
+pub fn posicion_entre(antes:Option<&str>,despues:Option<&str>)->Option<String>{
+    let a=antes.map(str::as_bytes);
+    let b=despues.map(str::as_bytes);
+    if a.is_some_and(|a| partes(a).is_none()) || b.is_some_and(|b| partes(b).is_none()) {
+        return None;
+    }
+    if let (Some(a),Some(b)) = (a,b) {
+        if a >= b {
+            return None;
+        }
+    }
+    let mut medio=generar(a, b)?;
+    // si la posicion es prefijo de `b`, agregarle digitos podria pasarse de `b`
+    while let Some(b) = b.filter(|b| b.starts_with(&medio)) {
+        medio=generar(Some(&medio), Some(b))?;
+    }
+    let mut rng=rand::rng();
+    for i in 0..LARGO_AZAR {
+        let desde=if i == LARGO_AZAR - 1 { 1 } else { 0 };
+        medio.push(DIGITOS[rng.random_range(desde..DIGITOS.len())]);
+    }
+    String::from_utf8(medio).ok()
+}
+
+/// `cantidad` posiciones consecutivas despues de `despues_de`, sin azar, para asignar
+/// en bloque el orden de tareas que todavia no tienen posicion
+pub fn posiciones_siguientes(despues_de:Option<&str>,cantidad:usize)->Vec<String>{
+    let mut res:Vec<String>=Vec::with_capacity(cantidad);
+    let mut anterior=despues_de.filter(|p| partes(p.as_bytes()).is_some()).map(str::to_string);
+    for _ in 0..cantidad {
+        let Some(siguiente) = generar(anterior.as_deref().map(str::as_bytes), None).and_then(|p| String::from_utf8(p).ok()) else {
+            break;
+        };
+        res.push(siguiente.clone());
+        anterior=Some(siguiente);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entre(antes:Option<&str>,despues:Option<&str>)->String{
+        let medio=posicion_entre(antes, despues).unwrap();
+        assert!(partes(medio.as_bytes()).is_some(), "{} no es valida", medio);
+        assert!(antes.is_none_or(|a| a < medio.as_str()), "{:?} !< {}", antes, medio);
+        assert!(despues.is_none_or(|b| medio.as_str() < b), "{} !< {:?}", medio, despues);
+        medio
+    }
+
+    #[test]
+    fn queda_entre_los_vecinos() {
+        let primera=entre(None, None);
+        let antes=entre(None, Some(&primera));
+        let despues=entre(Some(&primera), None);
+        entre(Some(&antes), Some(&primera));
+        entre(Some(&primera), Some(&despues));
+        entre(Some("a0"), Some("a1"));
+        entre(Some("a0V"), Some("a0V1"));
+        entre(Some("Zz"), Some("a0"));
+    }
+
+    #[test]
+    fn insertar_siempre_al_principio_no_se_agota() {
+        let mut primera=entre(None, None);
+        for _ in 0..500 {
+            primera=entre(None, Some(&primera));
+        }
+        // y siempre justo despues de la primera
+        let mut ultima=entre(Some(&primera), None);
+        for _ in 0..500 {
+            ultima=entre(Some(&primera), Some(&ultima));
+        }
+    }
+
+    #[test]
+    fn rechaza_posiciones_invalidas_o_desordenadas() {
+        assert!(posicion_entre(Some("a1"), Some("a0")).is_none());
+        assert!(posicion_entre(Some("a1"), Some("a1")).is_none());
+        assert!(posicion_entre(Some("a10"), None).is_none());
+        assert!(posicion_entre(Some("?"), None).is_none());
+        assert!(posicion_entre(None, Some("A00000000000000000000000000")).is_none());
+    }
+
+    #[test]
+    fn siguientes_en_orden_y_sin_azar() {
+        let posiciones=posiciones_siguientes(Some("a0"), 100);
+        assert_eq!(posiciones.len(), 100);
+        assert!(posiciones.windows(2).all(|par| par[0] < par[1]));
+        assert!(posiciones[0].as_str() > "a0");
+        assert_eq!(posiciones, posiciones_siguientes(Some("a0"), 100));
+        assert_eq!(posiciones_siguientes(Some("?"), 1), vec!["a0".to_string()]);
+    }
+}
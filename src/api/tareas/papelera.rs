@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use rocket::{figment::Figment, tokio::time::interval};
+use unreql::Session;
+
+use crate::{api::AlmacenamientoAdjuntos, utils::Utils, ClientesSockets};
+
+use super::Tareas;
+
+const RETENCION_DIAS_DEFAULT:u64=30;
+const INTERVALO_PURGA_SEGS_DEFAULT:u64=3600;
+
+/// configuracion de la papelera, se lee de `Rocket.toml`
+/// (`papelera_retencion_dias` y `papelera_intervalo_purga_segs`)
+#[derive(Debug,Clone,Copy)]
+pub struct ConfigPapelera{
+    pub retencion_dias:u64,
+    pub intervalo_purga_segs:u64,
+}
+
+impl ConfigPapelera {
+    pub fn from_figment(figment:&Figment)->ConfigPapelera{
+        ConfigPapelera {
+            retencion_dias: figment.extract_inner("papelera_retencion_dias").unwrap_or(RETENCION_DIAS_DEFAULT),
+            intervalo_purga_segs: figment.extract_inner::<u64>("papelera_intervalo_purga_segs").unwrap_or(INTERVALO_PURGA_SEGS_DEFAULT).max(1),
+        }
+    }
+
+    pub fn retencion_ms(&self)->i64{
+        (self.retencion_dias * 24 * 60 * 60 * 1000) as i64
+    }
+}
+
+/// tarea en segundo plano que purga periodicamente la papelera vencida
+pub async fn purge_job(config:ConfigPapelera,db:Session,almacenamiento:AlmacenamientoAdjuntos,clients:ClientesSockets){
+    println!("🗑️ purga de papelera cada {}s, retencion {} dias",config.intervalo_purga_segs,config.retencion_dias);
+    let mut intervalo=interval(Duration::from_secs(config.intervalo_purga_segs));
+    loop {
+        intervalo.tick().await;
+        let antes_de=Utils::current_timestamp() - config.retencion_ms();
+        match Tareas::purge_expired(antes_de, &almacenamiento, &db).await {
+            Ok((purgadas,destinatarios))=>{
+                if !purgadas.is_empty() {
+                    println!("🗑️ {} tareas purgadas de la papelera",purgadas.len());
+                    Utils::send_ws_to(&clients, &destinatarios, "purge").await;
+                }
+            },
+            Err(e)=>{
+                println!("error purge_job {}",e);
+            }
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use rocket::serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::utils::{Utils, DIA_MS};
+
+use super::{IndiceBusqueda, Prioridad, Tareas, TareasHttp};
+
+const MINUTO_MS:i64=60 * 1000;
+const HORA_MS:i64=60 * MINUTO_MS;
+/// sin hora, una tarea vence al final del dia
+const FIN_DEL_DIA_MS:i64=DIA_MS - 1;
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct TareaRapidaHttp{
+    /// texto libre, por ejemplo `comprar pan mañana 17:00 #casa !alta`
+    #[validate(length(min = 1, max = 500))]
+    pub texto:String,
+    /// minutos que la zona horaria se adelanta a UTC para interpretar fechas y horas (-840 a 840, por defecto 0)
+    #[serde(default)]
+    pub zona:Option<i64>,
+}
+
+/// lo que se entendio del texto de una alta rapida
+#[derive(Debug,Clone,Default,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Interpretacion{
+    /// el texto sin las partes reconocidas
+    pub nombre:String,
+    /// fecha limite en ms
+    pub vence:Option<i64>,
+    /// `vence` como `AAAA-MM-DD HH:MM` en la zona pedida
+    pub vence_local:Option<String>,
+    pub etiquetas:Vec<String>,
+    pub prioridad:Option<Prioridad>,
+    /// fragmentos del texto que se interpretaron, en el orden en que aparecen
+    pub reconocido:Vec<String>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct TareaRapida{
+    pub interpretacion:Interpretacion,
+    /// tarea creada, vacio con `prueba=true`
+    pub tareas:Vec<Tareas>,
+}
+
+impl Interpretacion {
+    pub fn tarea(&self)->TareasHttp{
+        TareasHttp {
+            nombre: self.nombre.clone(),
+            vence: self.vence,
+            etiquetas: self.etiquetas.clone(),
+            prioridad: self.prioridad,
+            id_padre: None,
+        }
+    }
+}
+
+/// fecha o plazo reconocido en el texto
+enum Cuando{
+    /// dia local, desde epoch
+    Dia(i64),
+    /// cantidad de ms desde ahora
+    Plazo(i64),
+}
+
+fn prioridad(palabra:&str)->Option<Prioridad>{
+    match palabra {
+        "baja"|"low"=>Some(Prioridad::Baja),
+        "media"|"medium"|"normal"=>Some(Prioridad::Media),
+        "alta"|"high"=>Some(Prioridad::Alta),
+        "urgente"|"urgent"=>Some(Prioridad::Urgente),
+        _=>None,
+    }
+}
+
+/// dia de la semana con el lunes en 0
+fn dia_semana(palabra:&str)->Option<i64>{
+    match palabra {
+        "lunes"|"monday"=>Some(0),
+        "martes"|"tuesday"=>Some(1),
+        "miercoles"|"wednesday"=>Some(2),
+        "jueves"|"thursday"=>Some(3),
+        "viernes"|"friday"=>Some(4),
+        "sabado"|"saturday"=>Some(5),
+        "domingo"|"sunday"=>Some(6),
+        _=>None,
+    }
+}
+
+fn cantidad(palabra:&str)->Option<i64>{
+    match palabra {
+        "un"|"una"|"uno"|"a"|"an"|"one"=>Some(1),
+        "dos"|"two"=>Some(2),
+        "tres"|"three"=>Some(3),
+        _=>palabra.parse::<i64>().ok().filter(|n| (1..=1000).contains(n)),
+    }
+}
+
+fn unidad_ms(palabra:&str)->Option<i64>{
+    match palabra {
+        "minuto"|"minutos"|"min"|"mins"|"minute"|"minutes"=>Some(MINUTO_MS),
+        "hora"|"horas"|"h"|"hour"|"hours"=>Some(HORA_MS),
+        "dia"|"dias"|"day"|"days"=>Some(DIA_MS),
+        "semana"|"semanas"|"week"|"weeks"=>Some(7 * DIA_MS),
+        _=>None,
+    }
+}
+
+/// `17:00`, `17h`, `5pm` o `5:30pm` como minutos desde la medianoche
+fn hora(palabra:&str)->Option<i64>{
+    let (texto,meridiano)=if let Some(t) = palabra.strip_suffix("am") {
+        (t,Some(false))
+    }else if let Some(t) = palabra.strip_suffix("pm") {
+        (t,Some(true))
+    }else{
+        (palabra.strip_suffix("hs").or_else(|| palabra.strip_suffix('h')).unwrap_or(palabra),None)
+    };
+    let (horas,minutos)=match texto.split_once(':') {
+        Some((h,m)) if m.len() == 2=>(h.parse::<i64>().ok()?,m.parse::<i64>().ok()?),
+        Some(_)=>return None,
+        None if meridiano.is_some() || texto.len() < palabra.len()=>(texto.parse::<i64>().ok()?,0),
+        None=>return None,
+    };
+    let horas=match meridiano {
+        Some(pm) if (1..=12).contains(&horas)=>horas % 12 + if pm { 12 } else { 0 },
+        Some(_)=>return None,
+        None=>horas,
+    };
+    ((0..24).contains(&horas) && (0..60).contains(&minutos)).then_some(horas * 60 + minutos)
+}
+
+/// `25/12`, `25/12/2026` o `2026-12-25`; sin año es la proxima vez que llegue esa fecha
+fn fecha(palabra:&str,hoy:i64)->Option<i64>{
+    if let [anio,mes,dia] = palabra.split('-').collect::<Vec<_>>()[..] {
+        if anio.len() != 4 {
+            return None;
+        }
+        return Utils::dia_de_fecha(anio.parse().ok()?, mes.parse().ok()?, dia.parse().ok()?);
+    }
+    let partes:Vec<&str>=palabra.split('/').collect();
+    let (dia,mes)=match partes[..] {
+        [dia,mes] | [dia,mes,_]=>(dia.parse::<i64>().ok()?,mes.parse::<i64>().ok()?),
+        _=>return None,
+    };
+    let anio_actual=Utils::fecha_de_dia(hoy)[..4].parse::<i64>().ok()?;
+    match partes.get(2) {
+        Some(anio)=>{
+            let anio=anio.parse::<i64>().ok()?;
+            Utils::dia_de_fecha(if anio < 100 { 2000 + anio } else { anio }, mes, dia)
+        },
+        None=>{
+            let este=Utils::dia_de_fecha(anio_actual, mes, dia)?;
+            if este >= hoy { Some(este) } else { Utils::dia_de_fecha(anio_actual + 1, mes, dia) }
+        }
+    }
+}
+
+/// recorre las palabras del texto buscando fechas, horas, etiquetas y prioridad
+struct Lector<'a>{
+    originales:Vec<&'a str>,
+    /// las mismas palabras normalizadas y sin la puntuacion final
+    palabras:Vec<String>,
+    hoy:i64,
+}
+
+impl Lector<'_> {
+    fn palabra(&self,i:usize)->&str{
+        self.palabras.get(i).map(String::as_str).unwrap_or("")
+    }
+
+    /// fecha que empieza en `i` y cuantas palabras ocupa
+    fn cuando(&self,i:usize)->Option<(Cuando,usize)>{
+        let uno=self.palabra(i);
+        let dos=self.palabra(i + 1);
+        let tres=self.palabra(i + 2);
+        match (uno,dos,tres) {
+            ("hoy"|"today",_,_)=>Some((Cuando::Dia(self.hoy),1)),
+            ("pasado","manana",_)=>Some((Cuando::Dia(self.hoy + 2),2)),
+            ("day","after","tomorrow")=>Some((Cuando::Dia(self.hoy + 2),3)),
+            ("manana"|"tomorrow",_,_)=>Some((Cuando::Dia(self.hoy + 1),1)),
+            ("proxima"|"next","semana"|"week",_) | ("semana","que","viene")=>{
+                Some((Cuando::Dia(self.proximo(0)),if uno == "semana" { 3 } else { 2 }))
+            },
+            ("el"|"este"|"proximo"|"on"|"this"|"next",_,_) if dia_semana(dos).is_some()=>{
+                Some((Cuando::Dia(self.proximo(dia_semana(dos)?)),2))
+            },
+            ("en"|"in",_,_)=>{
+                Some((Cuando::Plazo(cantidad(dos)? * unidad_ms(tres)?),3))
+            },
+            ("dentro","de",_)=>{
+                Some((Cuando::Plazo(cantidad(tres)? * unidad_ms(self.palabra(i + 3))?),4))
+            },
+            _=>{
+                if let Some(dia) = dia_semana(uno) {
+                    return Some((Cuando::Dia(self.proximo(dia)),1));
+                }
+                fecha(uno, self.hoy).map(|dia| (Cuando::Dia(dia),1))
+            }
+        }
+    }
+
+    /// hora que empieza en `i` y cuantas palabras ocupa; un numero solo cuenta como hora
+    /// despues de `a las` o `at`
+    fn hora(&self,i:usize)->Option<(i64,usize)>{
+        let (prefijo,siguiente)=match (self.palabra(i),self.palabra(i + 1)) {
+            ("a","las"|"la")=>(2,self.palabra(i + 2)),
+            ("at"|"@",_)=>(1,self.palabra(i + 1)),
+            _=>(0,self.palabra(i)),
+        };
+        let meridiano=self.palabra(i + prefijo + 1);
+        if matches!(meridiano,"am"|"pm") {
+            if let Some(minutos) = hora(&format!("{}{}",siguiente,meridiano)) {
+                return Some((minutos,prefijo + 2));
+            }
+        }
+        if let Some(minutos) = hora(siguiente) {
+            return Some((minutos,prefijo + 1));
+        }
+        if prefijo > 0 {
+            let horas=siguiente.parse::<i64>().ok().filter(|h| (0..24).contains(h))?;
+            return Some((horas * 60,prefijo + 1));
+        }
+        None
+    }
+
+    /// proximo dia de la semana `dia` despues de hoy
+    fn proximo(&self,dia:i64)->i64{
+        let hoy=(self.hoy + 3).rem_euclid(7);
+        let faltan=(dia - hoy).rem_euclid(7);
+        self.hoy + if faltan == 0 { 7 } else { faltan }
+    }
+}
+
+/// interpreta una alta rapida en español o ingles. Se toma la primera fecha y la primera hora
+/// que aparecen; lo que no se reconoce queda en el nombre
+pub fn interpretar(texto:&str,ahora:i64,zona_ms:i64)->Interpretacion{
+    let originales:Vec<&str>=texto.split_whitespace().collect();
+    let palabras=originales.iter()
+        .map(|p| IndiceBusqueda::normalizar(p).trim_end_matches([',', ';', '.']).to_string())
+        .collect();
+    let lector=Lector { originales, palabras, hoy: (ahora + zona_ms).div_euclid(DIA_MS) };
+
+    let mut res=Interpretacion::default();
+    let mut nombre:Vec<&str>=Vec::new();
+    let mut cuando:Option<Cuando>=None;
+    let mut minutos:Option<i64>=None;
+    let mut i=0;
+    while i < lector.originales.len() {
+        let original=lector.originales[i];
+        let palabra=lector.palabra(i);
+        let mut usadas=1;
+        if palabra.len() > 1 && palabra.starts_with('#') {
+            let etiqueta=original[1..].trim_end_matches([',', ';', '.']).to_lowercase();
+            if !res.etiquetas.contains(&etiqueta) {
+                res.etiquetas.push(etiqueta);
+            }
+        }else if let Some(p) = palabra.strip_prefix('!').and_then(prioridad).filter(|_| res.prioridad.is_none()) {
+            res.prioridad=Some(p);
+        }else if let Some((c,n)) = lector.cuando(i).filter(|_| cuando.is_none()) {
+            cuando=Some(c);
+            usadas=n;
+        }else if let Some((m,n)) = lector.hora(i).filter(|_| minutos.is_none() && !matches!(cuando,Some(Cuando::Plazo(_)))) {
+            minutos=Some(m);
+            usadas=n;
+        }else{
+            nombre.push(original);
+            i += 1;
+            continue;
+        }
+        res.reconocido.push(lector.originales[i..i + usadas].join(" "));
+        i += usadas;
+    }
+
+    res.vence=match (cuando,minutos) {
+        (Some(Cuando::Plazo(ms)),_)=>Some(ahora + ms),
+        (Some(Cuando::Dia(dia)),Some(m))=>Some(dia * DIA_MS + m * MINUTO_MS - zona_ms),
+        (Some(Cuando::Dia(dia)),None)=>Some(dia * DIA_MS + FIN_DEL_DIA_MS - zona_ms),
+        (None,Some(m))=>{
+            // una hora sola es hoy, o mañana si ya paso
+            let hoy=lector.hoy * DIA_MS + m * MINUTO_MS - zona_ms;
+            Some(if hoy > ahora { hoy } else { hoy + DIA_MS })
+        },
+        (None,None)=>None,
+    };
+    res.vence_local=res.vence.map(|vence| {
+        let local=vence + zona_ms;
+        let minuto=local.rem_euclid(DIA_MS) / MINUTO_MS;
+        format!("{} {:02}:{:02}",Utils::fecha_de_dia(local.div_euclid(DIA_MS)),minuto / 60,minuto % 60)
+    });
+    res.nombre=nombre.join(" ");
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// lunes 2026-10-19 a las 10:00 UTC
+    fn lunes()->(i64,i64){
+        let dia=Utils::dia_de_fecha(2026, 10, 19).unwrap();
+        (dia,dia * DIA_MS + 10 * HORA_MS)
+    }
+
+    #[test]
+    fn reconoce_fecha_hora_etiquetas_y_prioridad() {
+        let (dia,ahora)=lunes();
+        let res=interpretar("comprar pan mañana 17:00 #Casa !alta", ahora, 0);
+        assert_eq!(res.nombre, "comprar pan");
+        assert_eq!(res.vence, Some((dia + 1) * DIA_MS + 17 * HORA_MS));
+        assert_eq!(res.vence_local.as_deref(), Some("2026-10-20 17:00"));
+        assert_eq!(res.etiquetas, vec!["casa"]);
+        assert_eq!(res.prioridad, Some(Prioridad::Alta));
+        assert_eq!(res.reconocido, vec!["mañana","17:00","#Casa","!alta"]);
+    }
+
+    #[test]
+    fn fechas_relativas_y_dias_de_la_semana() {
+        let (dia,ahora)=lunes();
+        let vence=|texto:&str| interpretar(texto, ahora, 0).vence;
+        assert_eq!(vence("llamar en 2 horas"), Some(ahora + 2 * HORA_MS));
+        assert_eq!(vence("llamar dentro de una semana"), Some(ahora + 7 * DIA_MS));
+        assert_eq!(vence("reunion el viernes"), Some((dia + 4) * DIA_MS + FIN_DEL_DIA_MS));
+        // el mismo dia de la semana es la semana que viene
+        assert_eq!(vence("reunion lunes"), Some((dia + 7) * DIA_MS + FIN_DEL_DIA_MS));
+        assert_eq!(vence("meeting next week"), Some((dia + 7) * DIA_MS + FIN_DEL_DIA_MS));
+        assert_eq!(vence("pasado mañana"), Some((dia + 2) * DIA_MS + FIN_DEL_DIA_MS));
+    }
+
+    #[test]
+    fn fechas_absolutas() {
+        let (_,ahora)=lunes();
+        let dia_de=|texto:&str| interpretar(texto, ahora, 0).vence.map(|v| Utils::fecha_de_dia(v.div_euclid(DIA_MS)));
+        assert_eq!(dia_de("pagar 25/12").as_deref(), Some("2026-12-25"));
+        // una fecha sin año que ya paso es la del año siguiente
+        assert_eq!(dia_de("pagar 01/01").as_deref(), Some("2027-01-01"));
+        assert_eq!(dia_de("pagar 2027-03-05").as_deref(), Some("2027-03-05"));
+        assert_eq!(dia_de("pagar 31/02"), None);
+    }
+
+    #[test]
+    fn hora_sola_y_zona() {
+        let (dia,ahora)=lunes();
+        // las 9 ya pasaron, es mañana
+        assert_eq!(interpretar("correr a las 9", ahora, 0).vence, Some((dia + 1) * DIA_MS + 9 * HORA_MS));
+        assert_eq!(interpretar("cenar at 8pm", ahora, 0).vence, Some(dia * DIA_MS + 20 * HORA_MS));
+        // en UTC-3 mañana termina tres horas despues
+        let res=interpretar("entregar mañana", ahora, -3 * HORA_MS);
+        assert_eq!(res.vence, Some((dia + 1) * DIA_MS + FIN_DEL_DIA_MS + 3 * HORA_MS));
+        assert_eq!(res.vence_local.as_deref(), Some("2026-10-20 23:59"));
+    }
+
+    #[test]
+    fn lo_que_no_reconoce_queda_en_el_nombre() {
+        let (_,ahora)=lunes();
+        let res=interpretar("leer capitulo 42 ! # !rapido", ahora, 0);
+        assert_eq!(res.nombre, "leer capitulo 42 ! # !rapido");
+        assert_eq!(res.vence, None);
+        assert!(res.reconocido.is_empty());
+        // solo se toma la primera fecha
+        let res=interpretar("hoy mover a mañana", ahora, 0);
+        assert_eq!(res.nombre, "mover a mañana");
+    }
+}
@@ -0,0 +1,359 @@
+use rocket::serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::utils::{Utils, DIA_MS};
+
+use super::{validar, Prioridad, Tareas, TareasHttp};
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde",rename_all="snake_case")]
+pub enum AccionTodoTxt{
+    Crear,
+    Actualizar,
+    SinCambios,
+    Omitir,
+}
+
+/// resultado de una linea del archivo
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct LineaTodoTxt{
+    /// numero de linea en el archivo, desde 1
+    pub linea:usize,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub nombre:Option<String>,
+    pub accion:AccionTodoTxt,
+    /// id de la tarea creada o actualizada; las creadas no lo traen en la prueba
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub id:Option<String>,
+    #[serde(default,skip_serializing_if="Vec::is_empty")]
+    pub errores:Vec<String>,
+    #[serde(default,skip_serializing_if="Vec::is_empty")]
+    pub avisos:Vec<String>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct ResultadoTodoTxt{
+    pub prueba:bool,
+    pub creadas:usize,
+    pub actualizadas:usize,
+    pub sin_cambios:usize,
+    pub omitidas:usize,
+    pub lineas:Vec<LineaTodoTxt>,
+}
+
+/// tarea leida de una linea: `id` viene de la etiqueta `id:` que escribe `GET /tareas/todo.txt`
+#[derive(Debug,Clone)]
+pub struct TareaTodoTxt{
+    pub id:Option<String>,
+    pub tarea:TareasHttp,
+    pub completada:bool,
+}
+
+/// letra de prioridad de todo.txt, `A` la mas alta
+fn letra_prioridad(prioridad:Prioridad)->char{
+    match prioridad {
+        Prioridad::Urgente=>'A',
+        Prioridad::Alta=>'B',
+        Prioridad::Media=>'C',
+        Prioridad::Baja=>'D',
+    }
+}
+
+fn prioridad_de_letra(letra:&str)->Option<Prioridad>{
+    match letra {
+        "A"=>Some(Prioridad::Urgente),
+        "B"=>Some(Prioridad::Alta),
+        "C"=>Some(Prioridad::Media),
+        "D"=>Some(Prioridad::Baja),
+        _=>None,
+    }
+}
+
+fn fecha_todo_txt(ms:i64)->String{
+    Utils::fecha_de_dia(ms.div_euclid(DIA_MS))
+}
+
+/// dia de una fecha `AAAA-MM-DD`
+fn dia_todo_txt(texto:&str)->Option<i64>{
+    let numero=|desde:usize,hasta:usize| texto.get(desde..hasta).filter(|v| v.bytes().all(|b| b.is_ascii_digit()))?.parse::<i64>().ok();
+    if texto.len() != 10 || texto.get(4..5) != Some("-") || texto.get(7..8) != Some("-") {
+        return None;
+    }
+    Utils::dia_de_fecha(numero(0,4)?, numero(5,7)?, numero(8,10)?)
+}
+
+/// `(A)` al principio de una tarea pendiente
+fn prioridad_inicial(palabra:&str)->Option<&str>{
+    palabra.strip_prefix('(')?.strip_suffix(')').filter(|letra| letra.len() == 1 && letra.bytes().all(|b| b.is_ascii_uppercase()))
+}
+
+/// linea de la tarea: `x`, fechas de completado y creacion, `(A)`, nombre, `+etiqueta`, `@contexto`,
+/// `due:` e `id:`. Las etiquetas que empiezan con `@` son contextos, el resto proyectos
+pub fn linea_todo_txt(tarea:&Tareas)->String{
+    let mut partes:Vec<String>=Vec::new();
+    if tarea.estado {
+        partes.push("x".to_string());
+        // la fecha de creacion solo se puede escribir despues de la de completado
+        if let Some(completada_at) = tarea.completada_at {
+            partes.push(fecha_todo_txt(completada_at));
+            partes.push(fecha_todo_txt(tarea.create_at));
+        }
+    }else{
+        if let Some(prioridad) = tarea.prioridad {
+            partes.push(format!("({})",letra_prioridad(prioridad)));
+        }
+        partes.push(fecha_todo_txt(tarea.create_at));
+    }
+    partes.extend(tarea.nombre.split_whitespace().map(str::to_string));
+    for etiqueta in tarea.etiquetas.iter() {
+        let etiqueta=etiqueta.split_whitespace().collect::<Vec<_>>().join("_");
+        if etiqueta.starts_with('@') {
+            partes.push(etiqueta);
+        }else{
+            partes.push(format!("+{}",etiqueta));
+        }
+    }
+    if let Some(vence) = tarea.vence {
+        partes.push(format!("due:{}",fecha_todo_txt(vence)));
+    }
+    if let (true,Some(prioridad)) = (tarea.estado,tarea.prioridad) {
+        partes.push(format!("pri:{}",letra_prioridad(prioridad)));
+    }
+    partes.push(format!("id:{}",tarea.id));
+    partes.join(" ")
+}
+
+/// archivo con una tarea por linea, las pendientes primero en el orden manual
+pub fn todo_txt(tareas:&[Tareas])->String{
+    let mut ordenadas:Vec<&Tareas>=tareas.iter().collect();
+    ordenadas.sort_by(|a,b| (a.estado,&a.posicion,&a.id).cmp(&(b.estado,&b.posicion,&b.id)));
+    let mut texto=String::new();
+    for tarea in ordenadas {
+        texto.push_str(&linea_todo_txt(tarea));
+        texto.push('\n');
+    }
+    texto
+}
+
+/// lee una linea que no esta vacia; los avisos son lo que no se pudo pasar al modelo de tareas.
+/// Las etiquetas `clave:valor` que no son `due:`, `pri:` ni `id:` quedan en el nombre
+pub fn leer_linea_todo_txt(linea:&str)->(Result<TareaTodoTxt,Vec<String>>,Vec<String>){
+    let mut avisos=Vec::new();
+    let mut palabras=linea.split_whitespace().peekable();
+    let mut completada=false;
+    let mut prioridad=None;
+    if palabras.peek() == Some(&"x") {
+        palabras.next();
+        completada=true;
+        // fechas de completado y de creacion, que se toman de la base
+        for _ in 0..2 {
+            if palabras.peek().and_then(|p| dia_todo_txt(p)).is_some() {
+                palabras.next();
+            }
+        }
+        // algunos clientes dejan la prioridad despues de las fechas en vez de pasarla a `pri:`
+        if let Some(letra) = palabras.peek().and_then(|p| prioridad_inicial(p)) {
+            prioridad=prioridad_de_letra(letra);
+            palabras.next();
+        }
+    }else{
+        if let Some(letra) = palabras.peek().and_then(|p| prioridad_inicial(p)) {
+            prioridad=prioridad_de_letra(letra);
+            if prioridad.is_none() {
+                avisos.push(format!("prioridad ({}) no existe, solo de A a D",letra));
+            }
+            palabras.next();
+        }
+        if palabras.peek().and_then(|p| dia_todo_txt(p)).is_some() {
+            palabras.next();
+        }
+    }
+
+    let mut errores=Vec::new();
+    let mut id=None;
+    let mut nombre:Vec<&str>=Vec::new();
+    let mut etiquetas=Vec::new();
+    let mut vence=None;
+    for palabra in palabras {
+        if let Some(proyecto) = palabra.strip_prefix('+').filter(|p| !p.is_empty()) {
+            etiquetas.push(proyecto.to_string());
+        }else if palabra.len() > 1 && palabra.starts_with('@') {
+            etiquetas.push(palabra.to_string());
+        }else if let Some(fecha) = palabra.strip_prefix("due:") {
+            match dia_todo_txt(fecha) {
+                Some(dia)=>vence=Some(dia * DIA_MS + DIA_MS - 1),
+                None=>errores.push(format!("due:{} no es una fecha AAAA-MM-DD",fecha)),
+            }
+        }else if let Some(letra) = palabra.strip_prefix("pri:") {
+            match prioridad_de_letra(letra) {
+                Some(p)=>prioridad=Some(p),
+                None=>avisos.push(format!("prioridad {} no existe, solo de A a D",letra)),
+            }
+        }else if let Some(valor) = palabra.strip_prefix("id:").filter(|v| !v.is_empty()) {
+            if id.is_some() {
+                errores.push("mas de una etiqueta id:".to_string());
+            }
+            id=Some(valor.to_string());
+        }else{
+            nombre.push(palabra);
+        }
+    }
+
+    let tarea=validar(TareasHttp {
+        nombre: nombre.join(" "),
+        vence,
+        etiquetas,
+        prioridad,
+        id_padre: None,
+    });
+    match tarea {
+        Ok(tarea) if errores.is_empty()=>(Ok(TareaTodoTxt { id, tarea, completada }),avisos),
+        Ok(_)=>(Err(errores),avisos),
+        Err(e)=>{
+            errores.extend(e);
+            (Err(errores),avisos)
+        }
+    }
+}
+
+/// lineas no vacias con su numero desde 1
+pub fn leer_todo_txt(texto:&str)->Vec<(usize,&str)>{
+    texto.trim_start_matches('\u{feff}')
+        .lines()
+        .enumerate()
+        .map(|(indice,linea)| (indice + 1,linea.trim()))
+        .filter(|(_,linea)| !linea.is_empty())
+        .collect()
+}
+
+impl TareaTodoTxt {
+    /// campos con que queda `tarea` despues de aplicar la linea, o `None` si no cambia ninguno.
+    /// Se compara contra la linea que se escribiria de la tarea guardada, asi lo que todo.txt no
+    /// representa (la hora del vencimiento, espacios en las etiquetas) no cuenta como cambio
+    pub fn cambios(&self,tarea:&Tareas)->Option<TareasHttp>{
+        let actual=match leer_linea_todo_txt(&linea_todo_txt(tarea)) {
+            (Ok(actual),_)=>actual.tarea,
+            (Err(_),_)=>TareasHttp::default(),
+        };
+        let leida=&self.tarea;
+        let mut res=TareasHttp {
+            nombre: tarea.nombre.clone(),
+            vence: tarea.vence,
+            etiquetas: tarea.etiquetas.clone(),
+            prioridad: tarea.prioridad,
+            id_padre: None,
+        };
+        let mut cambia=false;
+        if leida.nombre != actual.nombre {
+            res.nombre=leida.nombre.clone();
+            cambia=true;
+        }
+        if leida.vence != actual.vence {
+            res.vence=leida.vence;
+            cambia=true;
+        }
+        if leida.etiquetas_limpias() != actual.etiquetas_limpias() {
+            res.etiquetas=leida.etiquetas.clone();
+            cambia=true;
+        }
+        if leida.prioridad != actual.prioridad {
+            res.prioridad=leida.prioridad;
+            cambia=true;
+        }
+        if cambia { Some(res) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn tarea(campos:Value)->Tareas{
+        let mut base=json!({
+            "id":"t1",
+            "nombre":"tarea",
+            "id_usuario":"u",
+            "estado":false,
+            "create_at":0,
+            "update_at":0,
+        });
+        base.as_object_mut().unwrap().extend(campos.as_object().unwrap().clone());
+        serde_json::from_value(base).unwrap()
+    }
+
+    fn dia()->i64{
+        Utils::dia_de_fecha(2026, 10, 19).unwrap()
+    }
+
+    #[test]
+    fn escribe_pendientes_y_completadas() {
+        let pendiente=tarea(json!({"id":"a","nombre":"Pagar  luz","prioridad":"urgente","etiquetas":["casa","@tel","mi lista"],"vence":dia() * DIA_MS + 36_000_000}));
+        assert_eq!(linea_todo_txt(&pendiente), "(A) 1970-01-01 Pagar luz +casa @tel +mi_lista due:2026-10-19 id:a");
+
+        let hecha=tarea(json!({"id":"c","nombre":"Hecha","estado":true,"completada_at":dia() * DIA_MS,"prioridad":"alta","posicion":"a"}));
+        assert_eq!(linea_todo_txt(&hecha), "x 2026-10-19 1970-01-01 Hecha pri:B id:c");
+
+        let otra=tarea(json!({"id":"b","nombre":"Otra","posicion":"b"}));
+        assert_eq!(todo_txt(&[hecha, otra, pendiente]), "(A) 1970-01-01 Pagar luz +casa @tel +mi_lista due:2026-10-19 id:a\n1970-01-01 Otra id:b\nx 2026-10-19 1970-01-01 Hecha pri:B id:c\n");
+    }
+
+    #[test]
+    fn ida_y_vuelta_sin_cambios() {
+        let tareas=[
+            tarea(json!({"id":"a","nombre":"Pagar luz","prioridad":"urgente","etiquetas":["casa","@tel"],"vence":dia() * DIA_MS + 36_000_000})),
+            tarea(json!({"id":"c","nombre":"Hecha","estado":true,"completada_at":dia() * DIA_MS,"prioridad":"baja"})),
+            tarea(json!({"id":"d","nombre":"Sin fechas"})),
+        ];
+        for t in tareas.iter() {
+            let (leida,avisos)=leer_linea_todo_txt(&linea_todo_txt(t));
+            let leida=leida.ok().unwrap();
+            assert!(avisos.is_empty());
+            assert_eq!(leida.id.as_deref(), Some(t.id.as_str()));
+            assert_eq!(leida.completada, t.estado);
+            assert_eq!(leida.tarea.nombre, t.nombre);
+            assert_eq!(leida.tarea.prioridad, t.prioridad);
+            // la hora del vencimiento no se escribe y no cuenta como cambio
+            assert!(leida.cambios(t).is_none());
+        }
+    }
+
+    #[test]
+    fn detecta_cambios() {
+        let guardada=tarea(json!({"id":"a","nombre":"Pagar luz","etiquetas":["casa"],"vence":dia() * DIA_MS}));
+        let (leida,_)=leer_linea_todo_txt("(B) Pagar agua +casa due:2026-10-20 id:a");
+        let cambios=leida.ok().unwrap().cambios(&guardada).unwrap();
+        assert_eq!(cambios.nombre, "Pagar agua");
+        assert_eq!(cambios.vence, Some((dia() + 2) * DIA_MS - 1));
+        assert_eq!(cambios.etiquetas, ["casa"]);
+        assert_eq!(cambios.prioridad, Some(Prioridad::Alta));
+    }
+
+    #[test]
+    fn errores_y_avisos() {
+        let (leida,avisos)=leer_linea_todo_txt("(Z) Llamar due:manana id:a id:b");
+        let errores=leida.err().unwrap();
+        assert_eq!(avisos, ["prioridad (Z) no existe, solo de A a D"]);
+        assert!(errores.contains(&"due:manana no es una fecha AAAA-MM-DD".to_string()));
+        assert!(errores.contains(&"mas de una etiqueta id:".to_string()));
+
+        let (leida,_)=leer_linea_todo_txt("x 2026-10-19 +casa");
+        assert!(leida.is_err());
+
+        // la prioridad despues de las fechas de una completada se respeta
+        let (leida,avisos)=leer_linea_todo_txt("x 2026-10-19 (C) Listo clave:valor");
+        let leida=leida.ok().unwrap();
+        assert!(avisos.is_empty());
+        assert!(leida.completada);
+        assert_eq!(leida.tarea.prioridad, Some(Prioridad::Media));
+        assert_eq!(leida.tarea.nombre, "Listo clave:valor");
+    }
+
+    #[test]
+    fn numera_lineas_no_vacias() {
+        assert_eq!(leer_todo_txt("\u{feff}uno\r\n\n  \n dos \n"), [(1, "uno"), (4, "dos")]);
+    }
+}
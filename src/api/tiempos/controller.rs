@@ -0,0 +1,527 @@
+use rocket::{http::Status, response::status::{self, Custom}, serde::json::Json, State};
+use unreql::Session;
+use validator::Validate;
+
+use crate::{api::{AdduserResponse, Tareas}, utils::Utils, ClientesSockets, JwtGuard};
+
+use super::{ResumenQuery, TiempoEdit, TiempoHttp, Tiempos, TiemposTarea, TotalDia};
+
+/// manda a todos los clientes del usuario el temporizador que tiene corriendo, `null` si ninguno
+async fn avisar_temporizador(id_usuario:&str,db:&Session,clients:&ClientesSockets){
+    match Tiempos::en_curso(id_usuario, db).await {
+        Ok(en_curso)=>{
+            let data=serde_json::to_value(en_curso).unwrap_or_default();
+            Utils::send_ws_data_to(clients, &[id_usuario.to_string()], "timer", Some(data)).await;
+        },
+        Err(e)=>{
+            println!("error avisar_temporizador {}",e);
+        }
+    }
+}
+
+/// 404 si el usuario no puede ver la tarea
+async fn tarea_visible(id:&str,userClaims:&JwtGuard,db:&Session)->Result<Tareas,status::Custom<Json<AdduserResponse>>>{
+    match Tareas::get_visible(id,&userClaims.id_usuario,userClaims.id_espacio.as_deref(),db).await {
+        Ok(Some(tarea))=>Ok(tarea),
+        Ok(None)=>{
+            Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/tareas/{id}/tiempos/iniciar",
+    responses(
+        (status = 201, description = "Temporizador iniciado; el que tuviera corriendo el usuario se detiene", body = Tiempos),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tiempos"
+)]
+#[post("/<id>/tiempos/iniciar")]
+pub async fn iniciar_temporizador(db:&State<Session>,id:&str,userClaims:JwtGuard,clients:&State<ClientesSockets>)->Result<Custom<Json<Tiempos>>,status::Custom<Json<AdduserResponse>>>{
+    let tarea=tarea_visible(id,&userClaims,db).await?;
+
+    match Tiempos::iniciar(&tarea.id,&userClaims.id_usuario,db).await {
+        Ok(Some(res))=>{
+            avisar_temporizador(&userClaims.id_usuario, db, clients).await;
+            Ok(
+                Custom(
+                    Status::Created,
+                    Json(res)
+                )
+            )
+        },
+        Ok(None)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/tiempos/detener",
+    responses(
+        (status = 200, description = "Entrada del temporizador detenido", body = Tiempos),
+        (status = 404, description = "El usuario no tiene un temporizador corriendo", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tiempos"
+)]
+#[post("/detener")]
+pub async fn detener_temporizador(db:&State<Session>,userClaims:JwtGuard,clients:&State<ClientesSockets>)->Result<Custom<Json<Tiempos>>,status::Custom<Json<AdduserResponse>>>{
+    match Tiempos::detener(&userClaims.id_usuario,db).await {
+        Ok(Some(res))=>{
+            avisar_temporizador(&userClaims.id_usuario, db, clients).await;
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Ok(None)=>{
+            Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/tiempos/actual",
+    responses(
+        (status = 200, description = "Temporizador corriendo del usuario, `null` si no tiene", body = Option<Tiempos>),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tiempos"
+)]
+#[get("/actual")]
+pub async fn get_temporizador(userClaims:JwtGuard,db:&State<Session>)->Result<Custom<Json<Option<Tiempos>>>,status::Custom<Json<AdduserResponse>>>{
+    match Tiempos::en_curso(&userClaims.id_usuario,db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/tareas/{id}/tiempos",
+    responses(
+        (status = 200, description = "Entradas de tiempo de la tarea y su total", body = TiemposTarea),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tiempos"
+)]
+#[get("/<id>/tiempos")]
+pub async fn get_tiempos_tarea(userClaims:JwtGuard,db:&State<Session>,id:&str)->Result<Custom<Json<TiemposTarea>>,status::Custom<Json<AdduserResponse>>>{
+    let tarea=tarea_visible(id,&userClaims,db).await?;
+
+    match Tiempos::get_by_tarea(&tarea.id,db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/tareas/{id}/tiempos",
+    responses(
+        (status = 201, description = "Entrada de tiempo cargada", body = Tiempos),
+        (status = 400, description = "Datos invalidos o fin anterior al inicio", body = AdduserResponse),
+        (status = 404, description = "Tarea no encontrada", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la tarea")
+    ),
+    request_body=TiempoHttp,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tiempos"
+)]
+#[post("/<id>/tiempos",data="<tiempo>")]
+pub async fn add_tiempo(db:&State<Session>,id:&str,userClaims:JwtGuard,tiempo:Json<TiempoHttp>)->Result<Custom<Json<Tiempos>>,status::Custom<Json<AdduserResponse>>>{
+    if !tiempo.is_valid() {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse { error: 3 }
+                )
+            )
+        );
+    }
+    let tarea=tarea_visible(id,&userClaims,db).await?;
+
+    match Tiempos::add(&tiempo,&tarea.id,&userClaims.id_usuario,db).await {
+        Ok(Some(res))=>{
+            Ok(
+                Custom(
+                    Status::Created,
+                    Json(res)
+                )
+            )
+        },
+        Ok(None)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/tiempos/{id}",
+    responses(
+        (status = 200, description = "Entrada de tiempo editada", body = Tiempos),
+        (status = 400, description = "Datos invalidos o fin anterior al inicio", body = AdduserResponse),
+        (status = 404, description = "Entrada no encontrada o no es del usuario", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la entrada de tiempo")
+    ),
+    request_body=TiempoEdit,
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tiempos"
+)]
+#[put("/<id>",data="<tiempo>")]
+pub async fn edit_tiempo(db:&State<Session>,id:&str,userClaims:JwtGuard,tiempo:Json<TiempoEdit>,clients:&State<ClientesSockets>)->Result<Custom<Json<Tiempos>>,status::Custom<Json<AdduserResponse>>>{
+    if tiempo.validate().is_err() {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse { error: 3 }
+                )
+            )
+        );
+    }
+    let anterior=match Tiempos::get_by_id(id,db).await {
+        Ok(Some(anterior)) if anterior.id_usuario == userClaims.id_usuario=>anterior,
+        Ok(_)=>{
+            return Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            );
+        },
+        Err(e)=>{
+            return Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            );
+        }
+    };
+
+    match Tiempos::edit(id,&tiempo,&userClaims.id_usuario,db).await {
+        Ok(Some(res))=>{
+            if anterior.fin.is_none() {
+                avisar_temporizador(&userClaims.id_usuario, db, clients).await;
+            }
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Ok(None)=>{
+            Err(
+                Custom(
+                    Status::BadRequest,
+                    Json(
+                        AdduserResponse{
+                            error:3
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/tiempos/{id}",
+    responses(
+        (status = 200, description = "Entrada de tiempo eliminada", body = String),
+        (status = 404, description = "Entrada no encontrada o no es del usuario", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ("id" =String, Path, description = "id de la entrada de tiempo")
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tiempos"
+)]
+#[delete("/<id>")]
+pub async fn delete_tiempo(db:&State<Session>,id:&str,userClaims:JwtGuard,clients:&State<ClientesSockets>)->Result<Custom<String>,status::Custom<Json<AdduserResponse>>>{
+    match Tiempos::delete(id,&userClaims.id_usuario,db).await {
+        Ok(Some(eliminado))=>{
+            if eliminado.fin.is_none() {
+                avisar_temporizador(&userClaims.id_usuario, db, clients).await;
+            }
+            Ok(
+                Custom(
+                    Status::Ok,
+                    "Ok ".to_string()
+                )
+            )
+        },
+        Ok(None)=>{
+            Err(
+                Custom(
+                    Status::NotFound,
+                    Json(
+                        AdduserResponse{
+                            error:1
+                        }
+                    )
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/tiempos/resumen",
+    responses(
+        (status = 200, description = "Totales del usuario por dia y por tarea, solo los dias con tiempo", body = Vec<TotalDia>),
+        (status = 400, description = "Rango invalido, de mas de 366 dias o zona fuera de rango", body = AdduserResponse),
+        (status = 500, description = "Error interno del servidor", body = AdduserResponse),
+        (status = 401, description = "No autorizado")
+    ),
+    params(
+        ResumenQuery
+    ),
+    security(
+        ("jwt_token" = [])
+    ),
+    tag="tiempos"
+)]
+#[get("/resumen?<params..>")]
+pub async fn get_resumen_tiempos(userClaims:JwtGuard,db:&State<Session>,params:ResumenQuery)->Result<Custom<Json<Vec<TotalDia>>>,status::Custom<Json<AdduserResponse>>>{
+    let Some((desde,hasta,zona)) = params.rango() else {
+        return Err(
+            Custom(
+                Status::BadRequest,
+                Json(
+                    AdduserResponse{
+                        error:3
+                    }
+                )
+            )
+        );
+    };
+
+    match Tiempos::resumen(&userClaims.id_usuario,desde,hasta,zona,db).await {
+        Ok(res)=>{
+            Ok(
+                Custom(
+                    Status::Ok,
+                    Json(res)
+                )
+            )
+        },
+        Err(e)=>{
+            Err(
+                Custom(
+                    Status::InternalServerError,
+                    Json(
+                        AdduserResponse{
+                            error:2
+                        }
+                    )
+                )
+            )
+        }
+    }
+}
@@ -0,0 +1,518 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rocket::{serde::{Deserialize, Serialize}, FromForm};
+use serde_json::Value;
+use unreql::{cmd::options::{BetweenOptions, Conflict, DeleteOptions, InsertOptions, ReplaceOptions}, func, r, rjson, types::WriteStatus, Command, Session};
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+use crate::utils::{Utils, DIA_MS};
+
+const RESUMEN_DIAS_DEFAULT:i64=7;
+const RESUMEN_DIAS_MAX:i64=366;
+
+/// campos de la tabla con los que se construyen los indices compuestos
+const INDICES_TIEMPOS:[(&str,[&str;2]);2]=[
+    ("tarea_inicio",["id_tarea","inicio"]),
+    ("usuario_inicio",["id_usuario","inicio"]),
+];
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct TiempoHttp{
+    /// inicio en milisegundos desde epoch
+    pub inicio:i64,
+    /// fin en milisegundos desde epoch, posterior a `inicio`
+    pub fin:i64,
+    #[serde(default)]
+    #[validate(length(max = 500))]
+    pub nota:Option<String>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema,Validate)]
+#[serde(crate="rocket::serde")]
+pub struct TiempoEdit{
+    #[serde(default)]
+    pub inicio:Option<i64>,
+    /// en una entrada que esta corriendo, detiene el temporizador
+    #[serde(default)]
+    pub fin:Option<i64>,
+    #[serde(default)]
+    #[validate(length(max = 500))]
+    pub nota:Option<String>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct Tiempos{
+    pub id:String,
+    pub id_tarea:String,
+    pub id_usuario:String,
+    pub inicio:i64,
+    /// `null` mientras el temporizador esta corriendo
+    #[serde(default)]
+    pub fin:Option<i64>,
+    #[serde(default)]
+    pub nota:Option<String>,
+    /// igual a `id_usuario` solo mientras corre, asi el indice `en_curso` no incluye las detenidas
+    #[serde(default,skip_serializing)]
+    pub en_curso:Option<String>,
+    pub create_at:i64,
+    pub update_at:i64,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct TiemposTarea{
+    /// milisegundos registrados en la tarea por todos los usuarios, incluido lo que lleva
+    /// un temporizador corriendo
+    pub total_ms:i64,
+    /// entradas en orden de inicio
+    pub entradas:Vec<Tiempos>,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct TotalTarea{
+    pub id_tarea:String,
+    pub total_ms:i64,
+}
+
+#[derive(Debug,Clone,Deserialize,Serialize,ToSchema)]
+#[serde(crate="rocket::serde")]
+pub struct TotalDia{
+    /// `AAAA-MM-DD` en la zona pedida
+    pub fecha:String,
+    pub total_ms:i64,
+    pub tareas:Vec<TotalTarea>,
+}
+
+#[derive(Debug,Clone,FromForm,IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ResumenQuery{
+    /// inicio del rango en milisegundos, por defecto 7 dias antes de `hasta`
+    pub desde:Option<i64>,
+    /// fin del rango en milisegundos, por defecto ahora
+    pub hasta:Option<i64>,
+    /// minutos que la zona horaria se adelanta a UTC para cortar los dias (-840 a 840, por defecto 0)
+    pub zona:Option<i64>,
+}
+
+impl TiempoHttp {
+    pub fn is_valid(&self)->bool{
+        self.validate().is_ok() && self.inicio < self.fin
+    }
+}
+
+impl ResumenQuery {
+    /// `(desde, hasta, zona)` o `None` si el rango o la zona no son validos
+    pub fn rango(&self)->Option<(i64,i64,i64)>{
+        let (desde,hasta)=Utils::rango_dias(self.desde, self.hasta, RESUMEN_DIAS_DEFAULT, RESUMEN_DIAS_MAX)?;
+        Some((desde,hasta,Utils::zona_valida(self.zona)?))
+    }
+}
+
+impl Tiempos {
+    pub fn duracion(&self,ahora:i64)->i64{
+        (self.fin.unwrap_or(ahora) - self.inicio).max(0)
+    }
+
+    pub async fn create_indexes(db:&Session)->Result<(),unreql::Error>{
+        Utils::create_table_if_missing("tiempos", db).await?;
+        Utils::create_table_if_missing("tiempos_en_curso", db).await?;
+        let existentes=r.db("tareas").table("tiempos")
+        .index_list()
+        .exec::<_,Vec<String>>(db)
+        .await?;
+        for (nombre,[grupo,campo]) in INDICES_TIEMPOS {
+            if existentes.iter().any(|i| i == nombre) {
+                continue;
+            }
+            let query = r.db("tareas").table("tiempos")
+            .index_create(r.args((
+                nombre,
+                func!(|t| [t.clone().g(grupo), t.g(campo)])
+            )))
+            .exec::<_,Value>(db);
+            if let Err(e) = query.await {
+                println!("error create_indexes tiempos {} {}",nombre,e);
+                return Err(e);
+            }
+        }
+        if !existentes.iter().any(|i| i == "en_curso") {
+            let query = r.db("tareas").table("tiempos")
+            .index_create("en_curso")
+            .exec::<_,Value>(db);
+            if let Err(e) = query.await {
+                println!("error create_indexes tiempos en_curso {}",e);
+                return Err(e);
+            }
+        }
+        r.db("tareas").table("tiempos").index_wait(()).exec::<_,Value>(db).await?;
+        Ok(())
+    }
+
+    pub async fn get_by_id(id:&str,db:&Session)->Result<Option<Tiempos>,unreql::Error>{
+        let query = r.db("tareas").table("tiempos")
+        .get(id.to_string())
+        .exec_to_vec::<_,Tiempos>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().next())
+            },
+            Err(e)=>{
+                println!("error get_by_id tiempos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// temporizador que esta corriendo para el usuario, si hay uno
+    pub async fn en_curso(id_usuario:&str,db:&Session)->Result<Option<Tiempos>,unreql::Error>{
+        let query = r.db("tareas").table("tiempos")
+        .get_all(r.with_opt(id_usuario.to_string(), r.index("en_curso")))
+        .exec_to_vec::<_,Tiempos>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().max_by_key(|t| t.inicio))
+            },
+            Err(e)=>{
+                println!("error en_curso tiempos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// detiene el temporizador del usuario; devuelve la entrada detenida
+    pub async fn detener(id_usuario:&str,db:&Session)->Result<Option<Tiempos>,unreql::Error>{
+        let ahora=Utils::current_timestamp();
+        let query = r.db("tareas").table("tiempos")
+        .get_all(r.with_opt(id_usuario.to_string(), r.index("en_curso")))
+        .replace(
+            r.with_opt(
+                func!(|t| t.without("en_curso").merge(rjson!({"fin":ahora,"update_at":ahora}))),
+                ReplaceOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                let mut detenidas=vec![];
+                for change in res.changes.unwrap_or_default() {
+                    if let Some(new_val) = change.new_val {
+                        let tiempo: Tiempos = Deserialize::deserialize(new_val)?;
+                        detenidas.push(tiempo);
+                    }
+                }
+                Ok(detenidas.into_iter().max_by_key(|t| t.inicio))
+            },
+            Err(e)=>{
+                println!("error detener tiempos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// detiene la entrada `id` si sigue corriendo
+    async fn detener_entrada(id:&str,ahora:i64,db:&Session)->Result<(),unreql::Error>{
+        let query = r.db("tareas").table("tiempos")
+        .get_all(id.to_string())
+        .replace(func!(|t| r.branch(
+            t.clone().has_fields("en_curso"),
+            t.clone().without("en_curso").merge(rjson!({"fin":ahora,"update_at":ahora})),
+            t
+        )))
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(_)=>Ok(()),
+            Err(e)=>{
+                println!("error detener_entrada tiempos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// apunta el documento del usuario en `tiempos_en_curso` a la entrada `id_tiempo` y devuelve
+    /// a cual apuntaba antes. El reemplazo es atomico, asi cada entrada la desplaza una sola
+    /// peticion aunque se inicien varios temporizadores a la vez
+    async fn apuntar_en_curso(id_usuario:&str,id_tiempo:&str,db:&Session)->Result<Option<String>,unreql::Error>{
+        let query = r.db("tareas").table("tiempos_en_curso")
+        .insert(
+            r.with_opt(
+                rjson!({
+                    "id":id_usuario.to_string(),
+                    "id_tiempo":id_tiempo.to_string(),
+                }),
+                InsertOptions { conflict: Some(Conflict::Replace), return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.changes.unwrap_or_default().into_iter()
+                    .filter_map(|c| c.old_val?.get("id_tiempo")?.as_str().map(str::to_string))
+                    .next())
+            },
+            Err(e)=>{
+                println!("error apuntar_en_curso tiempos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// inicia un temporizador en la tarea y detiene el que tuviera corriendo el usuario: el que
+    /// desplaza en `tiempos_en_curso`, asi de dos inicios simultaneos queda corriendo uno solo
+    pub async fn iniciar(id_tarea:&str,id_usuario:&str,db:&Session)->Result<Option<Tiempos>,unreql::Error>{
+        let ahora=Utils::current_timestamp();
+        let Some(nueva) = Self::insertar(rjson!({
+            "id_tarea":id_tarea.to_string(),
+            "id_usuario":id_usuario.to_string(),
+            "inicio":ahora,
+            "en_curso":id_usuario.to_string(),
+            "create_at":ahora,
+            "update_at":ahora,
+        }), db).await? else {
+            return Ok(None);
+        };
+        if let Some(anterior) = Self::apuntar_en_curso(id_usuario, &nueva.id, db).await? {
+            if anterior != nueva.id {
+                Self::detener_entrada(&anterior, ahora, db).await?;
+            }
+        }
+        Ok(Some(nueva))
+    }
+
+    /// deja apuntada en `tiempos_en_curso` la entrada corriendo mas reciente de cada usuario y
+    /// detiene las demas, para los temporizadores iniciados antes de que existiera esa tabla
+    pub async fn migrar_en_curso(db:&Session)->Result<u32,unreql::Error>{
+        let query = r.db("tareas").table("tiempos")
+        .filter(func!(|t| t.has_fields("en_curso")))
+        .exec_to_vec::<_,Tiempos>(db);
+        let corriendo=match query.await {
+            Ok(res)=>res,
+            Err(e)=>{
+                println!("error migrar_en_curso tiempos {}",e);
+                return Err(e);
+            }
+        };
+        let mut por_usuario:HashMap<String,Vec<Tiempos>>=HashMap::new();
+        for tiempo in corriendo {
+            por_usuario.entry(tiempo.id_usuario.clone()).or_default().push(tiempo);
+        }
+        let ahora=Utils::current_timestamp();
+        let mut detenidas=0;
+        for (id_usuario,mut tiempos) in por_usuario {
+            let existente=r.db("tareas").table("tiempos_en_curso")
+            .get(id_usuario.clone())
+            .exec_to_vec::<_,Value>(db)
+            .await?;
+            if existente.into_iter().any(|v| !v.is_null()) {
+                continue;
+            }
+            tiempos.sort_by_key(|t| t.inicio);
+            let Some(ultima) = tiempos.pop() else {
+                continue;
+            };
+            Self::apuntar_en_curso(&id_usuario, &ultima.id, db).await?;
+            for tiempo in tiempos {
+                Self::detener_entrada(&tiempo.id, ahora, db).await?;
+                detenidas += 1;
+            }
+        }
+        Ok(detenidas)
+    }
+
+    /// entrada cargada a mano, ya terminada
+    pub async fn add(tiempo:&TiempoHttp,id_tarea:&str,id_usuario:&str,db:&Session)->Result<Option<Tiempos>,unreql::Error>{
+        let ahora=Utils::current_timestamp();
+        Self::insertar(rjson!({
+            "id_tarea":id_tarea.to_string(),
+            "id_usuario":id_usuario.to_string(),
+            "inicio":tiempo.inicio,
+            "fin":tiempo.fin,
+            "nota":tiempo.nota.clone(),
+            "create_at":ahora,
+            "update_at":ahora,
+        }), db).await
+    }
+
+    async fn insertar(nueva:Command,db:&Session)->Result<Option<Tiempos>,unreql::Error>{
+        let query = r.db("tareas").table("tiempos")
+        .insert(
+            r.with_opt(
+                nueva,
+                InsertOptions {return_changes: Some(true.into()), ..Default::default() }
+            )
+        )
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                for change in res.changes.unwrap_or_default() {
+                    if let Some(new_val) = change.new_val {
+                        let tiempo: Tiempos = Deserialize::deserialize(new_val)?;
+                        return Ok(Some(tiempo));
+                    }
+                }
+                Ok(None)
+            },
+            Err(e)=>{
+                println!("error insertar tiempos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// aplica la edicion a una entrada propia; `None` si no existe, no es del usuario o el
+    /// resultado dejaria el fin antes del inicio
+    pub async fn edit(id:&str,edicion:&TiempoEdit,id_usuario:&str,db:&Session)->Result<Option<Tiempos>,unreql::Error>{
+        let Some(mut tiempo) = Self::get_by_id(id, db).await?.filter(|t| t.id_usuario == id_usuario) else {
+            return Ok(None);
+        };
+        if let Some(inicio) = edicion.inicio {
+            tiempo.inicio=inicio;
+        }
+        if let Some(fin) = edicion.fin {
+            tiempo.fin=Some(fin);
+            tiempo.en_curso=None;
+        }
+        if edicion.nota.is_some() {
+            tiempo.nota=edicion.nota.clone();
+        }
+        let fin=tiempo.fin.unwrap_or_else(Utils::current_timestamp);
+        if tiempo.inicio >= fin {
+            return Ok(None);
+        }
+        tiempo.update_at=Utils::current_timestamp();
+
+        let cambios=rjson!({
+            "inicio":tiempo.inicio,
+            "nota":tiempo.nota.clone(),
+            "update_at":tiempo.update_at,
+        });
+        let seleccion=r.db("tareas").table("tiempos").get(id.to_string());
+        let query = match tiempo.fin {
+            // con fin la entrada deja de estar en curso
+            Some(fin)=>seleccion.replace(func!(|t| t.without("en_curso").merge(cambios.merge(rjson!({"fin":fin}))))),
+            None=>seleccion.update(cambios),
+        }
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(_)=>{
+                Ok(Some(tiempo))
+            },
+            Err(e)=>{
+                println!("error edit tiempos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// borra una entrada propia; devuelve la borrada
+    pub async fn delete(id:&str,id_usuario:&str,db:&Session)->Result<Option<Tiempos>,unreql::Error>{
+        let id_usuario=id_usuario.to_string();
+        let query = r.db("tareas").table("tiempos")
+        .get_all(id.to_string())
+        .filter(func!(|t| t.g("id_usuario").eq(id_usuario)))
+        .delete(DeleteOptions {return_changes: Some(true.into()), ..Default::default() })
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                for change in res.changes.unwrap_or_default() {
+                    if let Some(old_val) = change.old_val {
+                        let tiempo: Tiempos = Deserialize::deserialize(old_val)?;
+                        return Ok(Some(tiempo));
+                    }
+                }
+                Ok(None)
+            },
+            Err(e)=>{
+                println!("error delete tiempos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn get_by_tarea(id_tarea:&str,db:&Session)->Result<TiemposTarea,unreql::Error>{
+        let query = r.db("tareas").table("tiempos")
+        .between(
+            rjson!([id_tarea.to_string(), r.minval()]),
+            rjson!([id_tarea.to_string(), r.maxval()]),
+            BetweenOptions { index: Some("tarea_inicio".to_string()), ..Default::default() }
+        )
+        .order_by(r.index("tarea_inicio"))
+        .exec_to_vec::<_,Tiempos>(db);
+        match query.await {
+            Ok(entradas)=>{
+                let ahora=Utils::current_timestamp();
+                let total_ms=entradas.iter().map(|t| t.duracion(ahora)).sum();
+                Ok(TiemposTarea { total_ms, entradas })
+            },
+            Err(e)=>{
+                println!("error get_by_tarea tiempos {}",e);
+                Err(e)
+            }
+        }
+    }
+
+    /// totales del usuario por dia entre `desde` y `hasta`; las entradas que cruzan la
+    /// medianoche se reparten entre los dias
+    pub async fn resumen(id_usuario:&str,desde:i64,hasta:i64,zona:i64,db:&Session)->Result<Vec<TotalDia>,unreql::Error>{
+        // el indice es por inicio, asi que se descartan aparte las que terminaron antes de `desde`
+        let query = r.db("tareas").table("tiempos")
+        .between(
+            rjson!([id_usuario.to_string(), r.minval()]),
+            rjson!([id_usuario.to_string(), hasta]),
+            BetweenOptions { index: Some("usuario_inicio".to_string()), ..Default::default() }
+        )
+        .filter(func!(|t| t.clone().has_fields("fin").not().or(t.g("fin").gt(desde))))
+        .exec_to_vec::<_,Tiempos>(db);
+        let entradas=match query.await {
+            Ok(res)=>res,
+            Err(e)=>{
+                println!("error resumen tiempos {}",e);
+                return Err(e);
+            }
+        };
+
+        let ahora=Utils::current_timestamp();
+        let desplazamiento=zona * 60 * 1000;
+        let mut dias:BTreeMap<i64,BTreeMap<String,i64>>=BTreeMap::new();
+        for tiempo in entradas {
+            let mut inicio=tiempo.inicio.max(desde) + desplazamiento;
+            let fin=tiempo.fin.unwrap_or(ahora).min(hasta) + desplazamiento;
+            while inicio < fin {
+                let dia=inicio.div_euclid(DIA_MS);
+                let corte=fin.min((dia + 1) * DIA_MS);
+                *dias.entry(dia).or_default().entry(tiempo.id_tarea.clone()).or_default() += corte - inicio;
+                inicio=corte;
+            }
+        }
+        Ok(dias.into_iter()
+            .map(|(dia,tareas)| TotalDia {
+                fecha: Utils::fecha_de_dia(dia),
+                total_ms: tareas.values().sum(),
+                tareas: tareas.into_iter().map(|(id_tarea,total_ms)| TotalTarea { id_tarea, total_ms }).collect(),
+            })
+            .collect())
+    }
+
+    pub async fn delete_by_tareas(ids_tareas:&[String],db:&Session)->Result<u32,unreql::Error>{
+        if ids_tareas.is_empty() {
+            return Ok(0);
+        }
+        let ids=ids_tareas.to_vec();
+        let query = r.db("tareas").table("tiempos")
+        .filter(func!(|t| r.expr(ids).contains(t.g("id_tarea"))))
+        .delete(DeleteOptions::default())
+        .exec::<_,WriteStatus>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.deleted)
+            },
+            Err(e)=>{
+                println!("error delete_by_tareas tiempos {}",e);
+                Err(e)
+            }
+        }
+    }
+}
@@ -44,6 +44,21 @@ impl Usuarios {
         }
     }
 
+    pub async fn get_by_id(id:&str,db:&Session)->Result<Option<Usuarios>,unreql::Error>{
+        let query = r.db("tareas").table("usuarios")
+        .get(id.to_string())
+        .exec_to_vec::<_,Usuarios>(db);
+        match query.await {
+            Ok(res)=>{
+                Ok(res.into_iter().next())
+            },
+            Err(e)=>{
+                println!("error get_by_id usuarios {}",e);
+                Err(e)
+            }
+        }
+    }
+
     pub async fn add(user:&UsuariosHttp,db:&Session)->Result<bool,unreql::Error>{
         let password_hash=match Utils::hash_password_argon2d(&user.password){
             Ok(res)=>res,
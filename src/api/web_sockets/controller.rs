@@ -1,9 +1,14 @@
-use std::{collections::HashMap, sync::{Arc}};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use rocket::{futures::{lock::Mutex, select, FutureExt, SinkExt, StreamExt}, tokio::sync::broadcast::{channel, error::RecvError, Sender}, State};
+use rocket::{futures::{select, FutureExt, SinkExt, StreamExt}, tokio::sync::broadcast::{channel, error::RecvError}, State};
 use ws::{Config, Message, WebSocket};
 
-use crate::{utils::Utils, ClientesSockets};
+use jsonwebtoken::{decode, DecodingKey, Validation};
+
+use crate::{utils::{Claims, Utils}, ClientesSockets};
+
+/// id de cada conexion, un usuario puede tener varias abiertas (pestañas o dispositivos)
+static SIGUIENTE_CONEXION:AtomicU64=AtomicU64::new(0);
 
 #[utoipa::path(
     get,  // WebSocket generalmente usa GET
@@ -19,7 +24,7 @@ use crate::{utils::Utils, ClientesSockets};
 )]
 #[get("/<token>")]
 pub async fn connect_ws(token: &str, ws: WebSocket, clients: &State<ClientesSockets>) -> ws::Channel<'static> {
-    let clientes: Arc<Mutex<HashMap<String, Sender<String>>>> = clients.inner().clone();
+    let clientes: ClientesSockets = clients.inner().clone();
     let config = Config {
         max_message_size: Some(1024),
         max_frame_size: Some(1024),
@@ -34,7 +39,8 @@ pub async fn connect_ws(token: &str, ws: WebSocket, clients: &State<ClientesSock
             ws.channel(move |mut stream| {
                 Box::pin(async move {
                     let (tx, mut rx) = channel(100);
-                    clientes.lock().await.insert(id_usuario.clone().to_string(), tx.clone());
+                    let id_conexion = SIGUIENTE_CONEXION.fetch_add(1, Ordering::Relaxed);
+                    clientes.lock().await.entry(id_usuario.clone()).or_default().push((id_conexion, tx.clone()));
                     let count = clientes.lock().await.len();
                     println!("connectes {}", count);
                     let mut stream = stream.fuse();
@@ -81,7 +87,16 @@ pub async fn connect_ws(token: &str, ws: WebSocket, clients: &State<ClientesSock
                             },
                         }//select
                     }//loop
-                    clientes.lock().await.remove(&id_usuario.clone().to_string());
+                    {
+                        // solo se quita esta conexion, las otras del usuario siguen abiertas
+                        let mut clientes = clientes.lock().await;
+                        if let Some(conexiones) = clientes.get_mut(&id_usuario) {
+                            conexiones.retain(|(id, _)| *id != id_conexion);
+                            if conexiones.is_empty() {
+                                clientes.remove(&id_usuario);
+                            }
+                        }
+                    }
                     let count = clientes.lock().await.len();
                     println!("conn closed {} clients", count);
                     Ok(())
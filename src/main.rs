@@ -2,9 +2,9 @@
 
 use std::{collections::HashMap, sync::Arc};
 
-use api::{AdduserResponse, Tareas, Usuarios};
+use api::{AccionImportacion, AccionTodoTxt, AdduserResponse, AdjuntoCrudo, AdjuntoUpload, Adjuntos, AlmacenamientoAdjuntos, AlmacenamientoLocal, AristaDependencia, AsignacionHttp, Calendarios, CambioCampo, ClaveCaldav, ColumnaTablero, ComentarioEdit, Comentarios, ComentariosHttp, ComentariosPage, CompartidoEdit, Compartidos, CompartidosHttp, CompartidosList, ConfigAdjuntos, ConfigCaldav, ConfigIdempotencia, ConfigPapelera, CredencialesCaldav, DependenciasHttp, EspacioUsuario, Espacios, EspaciosHttp, Estadisticas, EstadoFlujo, EtapaHttp, FilaImportacion, Flujos, FlujosHttp, GrafoDependencias, Historial, Idempotencia, InstanciarHttp, Interpretacion, Invitaciones, InvitacionesHttp, LineaTodoTxt, LoteHttp, LoteResultado, MiembroEdit, Miembros, MoverHttp, NodoDependencia, OperacionLote, PeriodoEstadisticas, Permiso, PlantillaDesdeTarea, PlantillaTarea, Plantillas, PlantillasHttp, Prioridad, ResultadoImportacion, ResultadoOperacion, ResultadoTodoTxt, Rol, SuscripcionCalendario, Tablero, TareaEncontrada, TareaRapida, TareaRapidaHttp, Tareas, TareasPage, TiempoEdit, TiempoHttp, Tiempos, TiemposTarea, TotalDia, TotalTarea, TransicionFlujo, Usuarios, HEADER_IDEMPOTENCIA};
 use jsonwebtoken::{decode, DecodingKey, Validation};
-use rocket::{fairing::{AdHoc, Fairing, Info, Kind}, futures::lock::Mutex, http::{Header, Status}, request::{self, FromRequest, Outcome}, routes, tokio::sync::broadcast::Sender, Request, Response};
+use rocket::{fairing::{AdHoc, Fairing, Info, Kind}, futures::lock::Mutex, http::{Header, Status}, request::{self, FromRequest, Outcome}, routes, tokio::sync::broadcast::Sender, Request, Response, State};
 use rocket_cors::{AllowedHeaders, AllowedMethods, AllowedOrigins, CorsOptions, Method};
 use unreql::{cmd::connect::Options, r, Session};
 use utils::Claims;
@@ -37,16 +37,147 @@ const API_URL: &str = "/v1/api";
         api::add_usuario,
         api::login,
         api::get_all_tareas,
+        api::search_tareas,
         api::get_tarea_by_id,
         api::add_tarea,
+        api::add_tarea_rapida,
+        api::clonar_tarea,
+        api::batch_tareas,
         api::change_estado,
         api::delete_tarea_by_id,
         api::delete_tareas_by_id_user,
+        api::get_papelera,
+        api::restore_tarea,
+        api::purge_tarea,
+        api::purge_papelera,
+        api::get_historial_tarea,
+        api::get_asignadas,
+        api::asignar_tarea,
+        api::get_dependencias,
+        api::set_dependencias,
+        api::change_etapa,
+        api::mover_tarea,
+        api::get_tablero,
+        api::get_estadisticas,
+        api::exportar_tareas,
+        api::importar_tareas,
+        api::get_todo_txt,
+        api::put_todo_txt,
+        api::get_comentarios,
+        api::add_comentario,
+        api::edit_comentario,
+        api::delete_comentario,
+        api::get_adjuntos,
+        api::add_adjunto_raw,
+        api::add_adjunto,
+        api::download_adjunto,
+        api::delete_adjunto,
+        api::get_compartidos,
+        api::add_compartido,
+        api::change_permiso,
+        api::delete_compartido,
+        api::get_espacios,
+        api::add_espacio,
+        api::get_miembros_espacio,
+        api::change_rol_miembro,
+        api::delete_miembro_espacio,
+        api::get_invitaciones_espacio,
+        api::add_invitacion,
+        api::get_invitaciones,
+        api::aceptar_invitacion,
+        api::delete_invitacion,
+        api::get_flujo,
+        api::set_flujo,
+        api::iniciar_temporizador,
+        api::detener_temporizador,
+        api::get_temporizador,
+        api::get_tiempos_tarea,
+        api::add_tiempo,
+        api::edit_tiempo,
+        api::delete_tiempo,
+        api::get_resumen_tiempos,
+        api::get_plantillas,
+        api::get_plantilla,
+        api::add_plantilla,
+        api::add_plantilla_desde_tarea,
+        api::instanciar_plantilla,
+        api::delete_plantilla,
+        api::renovar_token_calendario,
+        api::revocar_token_calendario,
+        api::renovar_clave_caldav,
+        api::revocar_clave_caldav,
+        api::get_calendario,
         api::connect_ws,
     ),
     components(
         schemas(
             Tareas,
+            TareasPage,
+            TareaEncontrada,
+            TareaRapidaHttp,
+            TareaRapida,
+            ResultadoImportacion,
+            FilaImportacion,
+            AccionImportacion,
+            ResultadoTodoTxt,
+            LineaTodoTxt,
+            AccionTodoTxt,
+            Interpretacion,
+            Prioridad,
+            AsignacionHttp,
+            DependenciasHttp,
+            GrafoDependencias,
+            NodoDependencia,
+            AristaDependencia,
+            Historial,
+            CambioCampo,
+            Comentarios,
+            ComentariosHttp,
+            ComentarioEdit,
+            ComentariosPage,
+            Adjuntos,
+            AdjuntoUpload,
+            AdjuntoCrudo,
+            Compartidos,
+            CompartidosHttp,
+            CompartidoEdit,
+            CompartidosList,
+            Permiso,
+            Espacios,
+            EspaciosHttp,
+            EspacioUsuario,
+            Miembros,
+            MiembroEdit,
+            Invitaciones,
+            InvitacionesHttp,
+            Rol,
+            Flujos,
+            FlujosHttp,
+            EstadoFlujo,
+            TransicionFlujo,
+            EtapaHttp,
+            MoverHttp,
+            LoteHttp,
+            OperacionLote,
+            ResultadoOperacion,
+            LoteResultado,
+            Tablero,
+            Estadisticas,
+            PeriodoEstadisticas,
+            ColumnaTablero,
+            Tiempos,
+            TiempoHttp,
+            TiempoEdit,
+            TiemposTarea,
+            TotalDia,
+            TotalTarea,
+            Plantillas,
+            PlantillasHttp,
+            PlantillaTarea,
+            PlantillaDesdeTarea,
+            InstanciarHttp,
+            SuscripcionCalendario,
+            ClaveCaldav,
             Usuarios,
             AdduserResponse
         )
@@ -98,8 +229,15 @@ impl Fairing for SecurityHeaders{
 
 pub struct JwtGuard{
     pub id_usuario:String,   
+    /// espacio activo, elegido con el header `X-Espacio`; `None` para las tareas personales
+    pub id_espacio:Option<String>,
+    /// rol del usuario en el espacio activo
+    pub rol:Option<Rol>,
 }
 
+/// header con el id del espacio activo
+pub const HEADER_ESPACIO: &str = "X-Espacio";
+
 #[rocket::async_trait]
 impl <'r> FromRequest<'r> for JwtGuard {
     type Error = ();
@@ -115,7 +253,22 @@ impl <'r> FromRequest<'r> for JwtGuard {
             if !token.is_empty() {
                 if let Ok(claims) = decode::<Claims>(token, &DecodingKey::from_secret(key), &Validation::default()) {
                     println!("✅ Claims válidos: {:?}", claims);
-                    return Outcome::Success(JwtGuard { id_usuario: claims.claims.sub });
+                    let id_usuario=claims.claims.sub;
+                    let Some(id_espacio) = request.headers().get_one(HEADER_ESPACIO) else {
+                        return Outcome::Success(JwtGuard { id_usuario, id_espacio: None, rol: None });
+                    };
+                    let Outcome::Success(db) = request.guard::<&State<Session>>().await else {
+                        return Outcome::Error((Status::InternalServerError,()));
+                    };
+                    // solo los miembros pueden operar dentro de un espacio
+                    return match Espacios::rol_de(id_espacio, &id_usuario, db).await {
+                        Ok(Some(rol))=>Outcome::Success(JwtGuard { id_usuario, id_espacio: Some(id_espacio.to_string()), rol: Some(rol) }),
+                        Ok(None)=>{
+                            println!("❌ Usuario sin acceso al espacio {}", id_espacio);
+                            Outcome::Error((Status::Forbidden,()))
+                        },
+                        Err(e)=>Outcome::Error((Status::InternalServerError,())),
+                    };
                 } else {
                     println!("❌ Error al decodificar JWT");
                 }
@@ -132,7 +285,8 @@ impl <'r> FromRequest<'r> for JwtGuard {
     
 }
 
-pub type ClientesSockets = Arc<Mutex<HashMap<String,Sender<String>>>>;
+/// conexiones abiertas por usuario, cada una con su id para quitarla al cerrarse
+pub type ClientesSockets = Arc<Mutex<HashMap<String,Vec<(u64,Sender<String>)>>>>;
 
 #[rocket::main]
 async fn main()->Result<(),rocket::Error> {    
@@ -145,6 +299,93 @@ async fn main()->Result<(),rocket::Error> {
     .await
     .expect("error conn rdb");
 
+    Tareas::create_indexes(&conn)
+    .await
+    .expect("error indices tareas");
+
+    Historial::create_indexes(&conn)
+    .await
+    .expect("error indices tareas_historial");
+
+    Comentarios::create_indexes(&conn)
+    .await
+    .expect("error indices comentarios");
+
+    Adjuntos::create_indexes(&conn)
+    .await
+    .expect("error indices adjuntos");
+
+    Compartidos::create_indexes(&conn)
+    .await
+    .expect("error indices compartidos");
+
+    Espacios::create_indexes(&conn)
+    .await
+    .expect("error indices espacios");
+
+    Flujos::create_indexes(&conn)
+    .await
+    .expect("error indices flujos");
+
+    Idempotencia::create_indexes(&conn)
+    .await
+    .expect("error indices idempotencia");
+
+    Tiempos::create_indexes(&conn)
+    .await
+    .expect("error indices tiempos");
+
+    Plantillas::create_indexes(&conn)
+    .await
+    .expect("error indices plantillas");
+
+    Calendarios::create_indexes(&conn)
+    .await
+    .expect("error indices calendarios");
+
+    CredencialesCaldav::create_indexes(&conn)
+    .await
+    .expect("error indices credenciales caldav");
+
+    let migradas=Tareas::migrar_etapas(&conn)
+    .await
+    .expect("error migrando etapas");
+    if migradas > 0 {
+        println!("🗂️ {} tareas migradas a etapas del flujo",migradas);
+    }
+
+    let ordenadas=Tareas::migrar_posiciones(&conn)
+    .await
+    .expect("error migrando posiciones");
+    if ordenadas > 0 {
+        println!("↕️ {} tareas con posicion inicial en el orden manual",ordenadas);
+    }
+
+    let completadas=Tareas::migrar_completadas(&conn)
+    .await
+    .expect("error migrando fechas de completado");
+    if completadas > 0 {
+        println!("✅ {} tareas completadas con fecha de completado inicial",completadas);
+    }
+
+    let detenidos=Tiempos::migrar_en_curso(&conn)
+    .await
+    .expect("error migrando temporizadores en curso");
+    if detenidos > 0 {
+        println!("⏱️ {} temporizadores duplicados detenidos",detenidos);
+    }
+
+    let config_adjuntos=ConfigAdjuntos::from_figment(&rocket::Config::figment());
+    println!("📎 adjuntos en {}",config_adjuntos.dir.display());
+    let almacenamiento:AlmacenamientoAdjuntos=Arc::new(AlmacenamientoLocal::new(config_adjuntos.dir.clone()));
+
+    let config_idempotencia=ConfigIdempotencia::from_figment(&rocket::Config::figment());
+
+    let indexadas=Tareas::load_search_index(&conn)
+    .await
+    .expect("error indice de busqueda");
+    println!("🔎 {} tareas en el indice de busqueda",indexadas);
+
 
     let allowed_methods: AllowedMethods = vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"]
         .into_iter()
@@ -154,7 +395,11 @@ async fn main()->Result<(),rocket::Error> {
     let allowed_headers = AllowedHeaders::some(&[
         "Authorization",
         "Content-Type",
+        HEADER_ESPACIO,
         "Origin",
+        "If-Match",
+        "If-None-Match",
+        HEADER_IDEMPOTENCIA,
     ]);
     let allowed_origins:AllowedOrigins=AllowedOrigins::some_exact(&[
         "http://localhost",
@@ -166,6 +411,7 @@ async fn main()->Result<(),rocket::Error> {
         allowed_origins,
         allowed_methods,
         allowed_headers,
+        expose_headers: ["ETag".to_string()].into_iter().collect(),
         allow_credentials: true,
         max_age: Some(600),
         ..Default::default()
@@ -174,11 +420,23 @@ async fn main()->Result<(),rocket::Error> {
     rocket::build()
     .manage(conn.clone())
     .manage(Arc::new(Mutex::new(HashMap::new())) as ClientesSockets)
+    .manage(config_adjuntos)
+    .manage(config_idempotencia)
+    .manage(almacenamiento)
     .attach(cors.to_cors().unwrap())
     .attach(SecurityHeaders)
-    .attach(AdHoc::on_liftoff("On Launch", |_|{
+    .attach(AdHoc::on_liftoff("On Launch", |rocket|{
         Box::pin(async move{
-
+            let config=ConfigPapelera::from_figment(rocket.figment());
+            if let (Some(db),Some(almacenamiento),Some(clients)) = (rocket.state::<Session>(),rocket.state::<AlmacenamientoAdjuntos>(),rocket.state::<ClientesSockets>()) {
+                rocket::tokio::spawn(api::purge_job(config, db.clone(), almacenamiento.clone(), clients.clone()));
+            }
+            if let (Some(db),Some(config)) = (rocket.state::<Session>(),rocket.state::<ConfigIdempotencia>()) {
+                rocket::tokio::spawn(api::purge_idempotencia_job(*config, db.clone()));
+            }
+            if let (Some(config),Some(db),Some(clients)) = (ConfigCaldav::from_figment(rocket.figment()),rocket.state::<Session>(),rocket.state::<ClientesSockets>()) {
+                rocket::tokio::spawn(api::caldav_job(config, db.clone(), clients.clone()));
+            }
         })
     }))
     .attach(AdHoc::on_shutdown("On Shutdown", |_| {
@@ -193,11 +451,90 @@ async fn main()->Result<(),rocket::Error> {
     ])
     .mount(format!("{}/tareas",API_URL), routes![
         api::get_all_tareas,
+        api::search_tareas,
         api::get_tarea_by_id,
         api::add_tarea,
+        api::add_tarea_rapida,
+        api::clonar_tarea,
+        api::add_plantilla_desde_tarea,
+        api::batch_tareas,
         api::change_estado,
         api::delete_tarea_by_id,
         api::delete_tareas_by_id_user,
+        api::get_papelera,
+        api::restore_tarea,
+        api::purge_tarea,
+        api::purge_papelera,
+        api::get_historial_tarea,
+        api::get_asignadas,
+        api::asignar_tarea,
+        api::get_dependencias,
+        api::set_dependencias,
+        api::change_etapa,
+        api::mover_tarea,
+        api::get_tablero,
+        api::get_estadisticas,
+        api::exportar_tareas,
+        api::importar_tareas,
+        api::get_todo_txt,
+        api::put_todo_txt,
+        api::get_comentarios,
+        api::add_comentario,
+        api::get_adjuntos,
+        api::add_adjunto,
+        api::add_adjunto_raw,
+        api::download_adjunto,
+        api::delete_adjunto,
+        api::iniciar_temporizador,
+        api::get_tiempos_tarea,
+        api::add_tiempo,
+    ])
+    .mount(format!("{}/comentarios",API_URL), routes![
+        api::edit_comentario,
+        api::delete_comentario,
+    ])
+    .mount(format!("{}/compartidos",API_URL), routes![
+        api::get_compartidos,
+        api::add_compartido,
+        api::change_permiso,
+        api::delete_compartido,
+    ])
+    .mount(format!("{}/espacios",API_URL), routes![
+        api::get_espacios,
+        api::add_espacio,
+        api::get_miembros_espacio,
+        api::change_rol_miembro,
+        api::delete_miembro_espacio,
+        api::get_invitaciones_espacio,
+        api::add_invitacion,
+        api::get_invitaciones,
+        api::aceptar_invitacion,
+        api::delete_invitacion,
+    ])
+    .mount(format!("{}/flujo",API_URL), routes![
+        api::get_flujo,
+        api::set_flujo,
+    ])
+    .mount(format!("{}/tiempos",API_URL), routes![
+        api::detener_temporizador,
+        api::get_temporizador,
+        api::get_resumen_tiempos,
+        api::edit_tiempo,
+        api::delete_tiempo,
+    ])
+    .mount(format!("{}/plantillas",API_URL), routes![
+        api::get_plantillas,
+        api::get_plantilla,
+        api::add_plantilla,
+        api::instanciar_plantilla,
+        api::delete_plantilla,
+    ])
+    .mount(format!("{}/calendario",API_URL), routes![
+        api::renovar_token_calendario,
+        api::revocar_token_calendario,
+        api::renovar_clave_caldav,
+        api::revocar_clave_caldav,
+        api::get_calendario,
     ])
     .mount(format!("{}/ws",API_URL), routes![
         api::connect_ws,
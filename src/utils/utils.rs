@@ -6,6 +6,8 @@ use argon2::{
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use rand::{distr::Alphanumeric, Rng};
 use rocket::{futures::lock::Mutex, serde::{Deserialize, Serialize}, tokio::sync::broadcast::Sender, State};
+use sha2::{Digest, Sha256};
+use unreql::{r, Session};
 
 use crate::ClientesSockets;
 
@@ -22,8 +24,16 @@ pub struct Claims{
 #[serde(crate="rocket::serde")]
 pub struct WSNotifications{
     event:String,
+    /// datos del evento, solo en los que los mandan
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    data:Option<serde_json::Value>,
 }
 
+/// milisegundos de un dia
+pub const DIA_MS:i64=24 * 60 * 60 * 1000;
+/// desplazamiento maximo de la zona horaria en minutos (UTC-14 a UTC+14)
+pub const ZONA_MAX:i64=14 * 60;
+
 pub struct Utils{
 
 }
@@ -36,6 +46,57 @@ impl Utils {
         .unwrap()
         .as_millis() as i64
     }
+
+    /// fecha `AAAA-MM-DD` de un numero de dias desde epoch
+    pub fn fecha_de_dia(dias:i64)->String{
+        let z=dias + 719468;
+        let era=z.div_euclid(146097);
+        let doe=z.rem_euclid(146097);
+        let yoe=(doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy=doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp=(5 * doy + 2) / 153;
+        let dia=doy - (153 * mp + 2) / 5 + 1;
+        let mes=if mp < 10 { mp + 3 } else { mp - 9 };
+        let anio=yoe + era * 400 + if mes <= 2 { 1 } else { 0 };
+        format!("{:04}-{:02}-{:02}",anio,mes,dia)
+    }
+
+    /// zona horaria en minutos que se adelanta a UTC, 0 si no se manda; `None` si pasa de `ZONA_MAX`
+    pub fn zona_valida(zona:Option<i64>)->Option<i64>{
+        Some(zona.unwrap_or(0)).filter(|zona| zona.abs() <= ZONA_MAX)
+    }
+
+    /// `(desde, hasta)` de una consulta por dias: `hasta` es ahora y `desde` `dias_default` antes
+    /// si no se mandan; `None` si el rango esta al reves o abarca mas de `dias_max`
+    pub fn rango_dias(desde:Option<i64>,hasta:Option<i64>,dias_default:i64,dias_max:i64)->Option<(i64,i64)>{
+        let hasta=hasta.unwrap_or_else(Self::current_timestamp);
+        let desde=desde.unwrap_or(hasta - dias_default * DIA_MS);
+        if desde >= hasta || hasta - desde > dias_max * DIA_MS {
+            return None;
+        }
+        Some((desde,hasta))
+    }
+
+    /// numero de dias desde epoch de una fecha, `None` si no existe
+    pub fn dia_de_fecha(anio:i64,mes:i64,dia:i64)->Option<i64>{
+        let dias_mes=match mes {
+            1|3|5|7|8|10|12=>31,
+            4|6|9|11=>30,
+            2 if anio % 4 == 0 && (anio % 100 != 0 || anio % 400 == 0)=>29,
+            2=>28,
+            _=>return None,
+        };
+        if dia < 1 || dia > dias_mes {
+            return None;
+        }
+        let anio=if mes <= 2 { anio - 1 } else { anio };
+        let era=anio.div_euclid(400);
+        let yoe=anio.rem_euclid(400);
+        let mp=if mes > 2 { mes - 3 } else { mes + 9 };
+        let doy=(153 * mp + 2) / 5 + dia - 1;
+        let doe=yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        Some(era * 146097 + doe - 719468)
+    }
     
     fn get_argon2d_config() -> Argon2<'static> {
         Argon2::new(
@@ -113,9 +174,74 @@ impl Utils {
             .collect()
     }
 
-    pub async fn send_ws(clients:&ClientesSockets,event:&str){
+    /// cadena alfanumerica al azar de exactamente `largo` caracteres, para tokens
+    pub fn generar_token(largo: usize) -> String {
+        rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(largo)
+            .map(char::from)
+            .collect()
+    }
+
+    /// crea la tabla en la base `tareas` si todavia no existe
+    pub async fn create_table_if_missing(tabla:&str,db:&Session)->Result<(),unreql::Error>{
+        let tablas=r.db("tareas").table_list().exec::<_,Vec<String>>(db).await?;
+        if !tablas.iter().any(|t| t == tabla) {
+            println!("creando tabla {}",tabla);
+            r.db("tareas").table_create(tabla.to_string()).exec::<_,serde_json::Value>(db).await?;
+        }
+        Ok(())
+    }
+
+    /// sha-256 en hex, estable entre procesos y versiones a diferencia de `DefaultHasher`
+    pub fn sha256_hex(datos:&[u8])->String{
+        Sha256::digest(datos).iter().map(|b| format!("{:02x}",b)).collect()
+    }
+
+    /// json con las claves de cada objeto ordenadas, para que el mismo valor se hashee igual
+    /// sin importar el orden en que llegaron los campos
+    pub fn json_canonico(valor:&serde_json::Value)->String{
+        match valor {
+            serde_json::Value::Object(campos)=>{
+                let mut claves:Vec<&String>=campos.keys().collect();
+                claves.sort();
+                let campos:Vec<String>=claves.into_iter()
+                    .map(|clave| format!("{}:{}",serde_json::Value::from(clave.as_str()),Self::json_canonico(&campos[clave])))
+                    .collect();
+                format!("{{{}}}",campos.join(","))
+            },
+            serde_json::Value::Array(valores)=>{
+                format!("[{}]",valores.iter().map(Self::json_canonico).collect::<Vec<_>>().join(","))
+            },
+            _=>valor.to_string(),
+        }
+    }
+
+    /// codifica un valor json como cursor opaco (hex) para paginacion
+    pub fn encode_cursor(valor:&serde_json::Value)->String{
+        valor.to_string().bytes().map(|b| format!("{:02x}",b)).collect()
+    }
+
+    pub fn decode_cursor(cursor:&str)->Option<serde_json::Value>{
+        if !cursor.len().is_multiple_of(2) {
+            return None;
+        }
+        let bytes=(0..cursor.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(cursor.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// envia el evento solo a los usuarios indicados
+    pub async fn send_ws_to(clients:&ClientesSockets,ids_usuarios:&[String],event:&str){
+        Self::send_ws_data_to(clients, ids_usuarios, event, None).await;
+    }
+
+    /// igual que `send_ws_to` pero el evento lleva `data`
+    pub async fn send_ws_data_to(clients:&ClientesSockets,ids_usuarios:&[String],event:&str,data:Option<serde_json::Value>){
         let my_objects:Vec<WSNotifications>=vec![
-            WSNotifications{event:event.to_string()}
+            WSNotifications{event:event.to_string(),data}
         ];
         let json_string = match serde_json::to_string(&my_objects) {
             Ok(json)=>json,
@@ -124,14 +250,47 @@ impl Utils {
                 return;
             }        
         };
-        println!("Intentando enviar msg a todos los clientes ........");
-        for (user_id,sender) in clients.lock().await.iter(){
+        for (user_id,senders) in clients.lock().await.iter().filter(|(id,_)| ids_usuarios.contains(id)){
             println!("enviando msg a {}",user_id);
-            match sender.send(json_string.clone()) {
-                Ok(_)=>println!("msg enviado {}",user_id)
-                ,Err(e)=>println!("Err send msg {:?}",e)
-                
+            for (_,sender) in senders {
+                match sender.send(json_string.clone()) {
+                    Ok(_)=>println!("msg enviado {}",user_id)
+                    ,Err(e)=>println!("Err send msg {:?}",e)
+                    
+                }
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn sha256_en_hex() {
+        assert_eq!(Utils::sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn json_canonico_ordena_claves() {
+        let a=json!({"b":1,"a":{"d":[{"y":true,"x":null}],"c":"t\"x"}});
+        let b:serde_json::Value=serde_json::from_str(r#"{"a":{"c":"t\"x","d":[{"x":null,"y":true}]},"b":1}"#).unwrap();
+        assert_eq!(Utils::json_canonico(&a), r#"{"a":{"c":"t\"x","d":[{"x":null,"y":true}]},"b":1}"#);
+        assert_eq!(Utils::json_canonico(&a), Utils::json_canonico(&b));
+    }
+
+    #[test]
+    fn zona_y_rango_de_dias() {
+        assert_eq!(Utils::zona_valida(None), Some(0));
+        assert_eq!(Utils::zona_valida(Some(-ZONA_MAX)), Some(-840));
+        assert_eq!(Utils::zona_valida(Some(ZONA_MAX + 1)), None);
+
+        let hasta=100 * DIA_MS;
+        assert_eq!(Utils::rango_dias(None, Some(hasta), 7, 366), Some((93 * DIA_MS,hasta)));
+        assert_eq!(Utils::rango_dias(Some(0), Some(hasta), 7, 100), Some((0,hasta)));
+        assert_eq!(Utils::rango_dias(Some(0), Some(hasta), 7, 99), None);
+        assert_eq!(Utils::rango_dias(Some(hasta), Some(hasta), 7, 366), None);
+    }
+}